@@ -10,7 +10,13 @@ fn benchmark_cache_operations(c: &mut Criterion) {
     // Benchmark single sink update
     group.bench_function("single_sink_update", |b| {
         let cache = AudioCache::new();
-        let sink = SinkInfo { id: 1, name: "Test".to_string(), volume: 0.5, muted: false };
+        let sink = SinkInfo {
+            id: 1,
+            name: "Test".to_string(),
+            volume: 0.5,
+            pipewire_id: 1,
+            ..Default::default()
+        };
 
         b.iter(|| {
             cache.update_sink(black_box("Test".to_string()), black_box(sink.clone()));
@@ -23,10 +29,11 @@ fn benchmark_cache_operations(c: &mut Criterion) {
         let app = AppInfo {
             display_name: "Firefox".to_string(),
             binary_name: "firefox".to_string(),
+            stream_names: vec!["firefox".to_string()],
             current_sink: "Media".to_string(),
             active: true,
             sink_input_ids: vec![1, 2, 3],
-            inactive_since: None,
+            ..Default::default()
         };
 
         b.iter(|| {
@@ -44,7 +51,13 @@ fn benchmark_cache_operations(c: &mut Criterion) {
             for i in 0..size {
                 cache.update_sink(
                     format!("Sink_{i}"),
-                    SinkInfo { id: i as u32, name: format!("Sink_{i}"), volume: 0.5, muted: false },
+                    SinkInfo {
+                        id: i as u32,
+                        name: format!("Sink_{i}"),
+                        volume: 0.5,
+                        pipewire_id: i as u32,
+                        ..Default::default()
+                    },
                 );
 
                 if i < size / 2 {
@@ -53,10 +66,11 @@ fn benchmark_cache_operations(c: &mut Criterion) {
                         AppInfo {
                             display_name: format!("App_{i}"),
                             binary_name: format!("app_{i}"),
+                            stream_names: vec![format!("app_{i}")],
                             current_sink: "Game".to_string(),
                             active: true,
                             sink_input_ids: vec![i as u32],
-                            inactive_since: None,
+                            ..Default::default()
                         },
                     );
                 }
@@ -85,7 +99,13 @@ fn benchmark_concurrent_access(c: &mut Criterion) {
             for i in 0..100 {
                 cache_write.update_sink(
                     format!("Sink_{i}"),
-                    SinkInfo { id: i, name: format!("Sink_{i}"), volume: 0.5, muted: false },
+                    SinkInfo {
+                        id: i,
+                        name: format!("Sink_{i}"),
+                        volume: 0.5,
+                        pipewire_id: i,
+                        ..Default::default()
+                    },
                 );
             }
         });
@@ -131,7 +151,13 @@ fn benchmark_concurrent_access(c: &mut Criterion) {
                     let cache_write = cache_clone.write().await;
                     cache_write.update_sink(
                         format!("Sink_{i}"),
-                        SinkInfo { id: i, name: format!("Sink_{i}"), volume: 0.5, muted: false },
+                        SinkInfo {
+                            id: i,
+                            name: format!("Sink_{i}"),
+                            volume: 0.5,
+                            pipewire_id: i,
+                            ..Default::default()
+                        },
                     );
                 });
                 handles.push(handle);
@@ -168,12 +194,13 @@ fn benchmark_memory_operations(c: &mut Criterion) {
                 AppInfo {
                     display_name: format!("InactiveApp_{i}"),
                     binary_name: format!("inactive_{i}"),
+                    stream_names: vec![format!("inactive_{i}")],
                     current_sink: "Game".to_string(),
                     active: false,
-                    sink_input_ids: vec![],
                     inactive_since: Some(
                         std::time::Instant::now() - std::time::Duration::from_secs(400),
                     ),
+                    ..Default::default()
                 },
             );
         }
@@ -185,10 +212,11 @@ fn benchmark_memory_operations(c: &mut Criterion) {
                 AppInfo {
                     display_name: format!("ActiveApp_{i}"),
                     binary_name: format!("active_{i}"),
+                    stream_names: vec![format!("active_{i}")],
                     current_sink: "Media".to_string(),
                     active: true,
                     sink_input_ids: vec![i],
-                    inactive_since: None,
+                    ..Default::default()
                 },
             );
         }
@@ -1,24 +1,14 @@
-use std::process::Command;
+use std::path::PathBuf;
+use std::sync::Arc;
 use tracing::debug;
 
-/// Trait for executing system commands - allows for mocking in tests
-pub trait CommandExecutor: Send + Sync {
-    fn execute(&self, program: &str, args: &[&str]) -> std::io::Result<std::process::Output>;
-    fn execute_shell(&self, cmd: &str) -> std::io::Result<std::process::Output>;
-}
-
-/// Real command executor for production
-pub struct SystemCommandExecutor;
-
-impl CommandExecutor for SystemCommandExecutor {
-    fn execute(&self, program: &str, args: &[&str]) -> std::io::Result<std::process::Output> {
-        Command::new(program).args(args).output()
-    }
+use crate::cache::NewStream;
+pub use crate::command::{CommandExecutor, SystemCommandExecutor};
+use crate::config::{expand_path, CacheConfig};
+use crate::sink_inputs::SinkInput;
 
-    fn execute_shell(&self, cmd: &str) -> std::io::Result<std::process::Output> {
-        Command::new("sh").arg("-c").arg(cmd).output()
-    }
-}
+/// Last app ID segments that name a flavor of the app rather than the app
+const GENERIC_ID_SEGMENTS: &[&str] = &["app", "application", "client", "desktop", "gui"];
 
 /// Configuration for app name detection
 pub struct AppNameConfig {
@@ -28,6 +18,8 @@ pub struct AppNameConfig {
     pub max_parent_depth: usize,
     /// Prefixes that indicate we should use application.name instead
     pub fallback_prefixes: Vec<String>,
+    /// Directories searched for the `.desktop` files of sandboxed apps
+    pub desktop_dirs: Vec<PathBuf>,
 }
 
 impl Default for AppNameConfig {
@@ -40,23 +32,39 @@ impl Default for AppNameConfig {
             ],
             max_parent_depth: 3,
             fallback_prefixes: vec!["steam_app".to_string()],
+            desktop_dirs: [
+                "~/.local/share/flatpak/exports/share/applications",
+                "/var/lib/flatpak/exports/share/applications",
+                "/var/lib/snapd/desktop/applications",
+                "~/.local/share/applications",
+                "/usr/share/applications",
+            ]
+            .into_iter()
+            .map(expand_path)
+            .collect(),
         }
     }
 }
 
 /// App name detector with configurable behavior
 pub struct AppNameDetector {
-    executor: Box<dyn CommandExecutor>,
+    executor: Arc<dyn CommandExecutor>,
     config: AppNameConfig,
 }
 
+impl Default for AppNameDetector {
+    fn default() -> Self {
+        Self::new_system()
+    }
+}
+
 impl AppNameDetector {
-    pub fn new(executor: Box<dyn CommandExecutor>, config: AppNameConfig) -> Self {
+    pub fn new(executor: Arc<dyn CommandExecutor>, config: AppNameConfig) -> Self {
         Self { executor, config }
     }
 
     pub fn new_system() -> Self {
-        Self::new(Box::new(SystemCommandExecutor), AppNameConfig::default())
+        Self::new(Arc::new(SystemCommandExecutor), AppNameConfig::default())
     }
 
     /// Extract binary name from a full path
@@ -102,6 +110,33 @@ impl AppNameDetector {
         }
     }
 
+    /// PID of the process owning the focused window, via xdotool
+    pub fn active_window_pid(&self) -> Option<u32> {
+        let output =
+            self.executor.execute_shell("xdotool getactivewindow getwindowpid 2>/dev/null").ok()?;
+
+        if !output.status.success() {
+            return None;
+        }
+
+        String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+    }
+
+    /// Whether `pid` is `ancestor` or one of its children, up to the configured depth
+    pub fn is_same_or_child_of(&self, pid: u32, ancestor: u32) -> bool {
+        let mut current_pid = pid;
+        for _ in 0..=self.config.max_parent_depth {
+            if current_pid == ancestor {
+                return true;
+            }
+            match self.get_parent_pid(current_pid) {
+                Some(ppid) => current_pid = ppid,
+                None => return false,
+            }
+        }
+        false
+    }
+
     /// Check if a window title should trigger fallback to application.name
     pub fn should_use_fallback(&self, title: &str) -> bool {
         self.config.fallback_prefixes.iter().any(|prefix| title.starts_with(prefix))
@@ -135,6 +170,63 @@ impl AppNameDetector {
         None
     }
 
+    /// App ID of a Flatpak or Snap sandboxed process
+    ///
+    /// Taken from the stream's `application.id` if it is a reverse-DNS ID, otherwise
+    /// from the `FLATPAK_ID` or `SNAP_NAME` the sandbox sets in the process environment.
+    pub fn sandbox_app_id(&self, application_id: Option<&str>, pid: Option<u32>) -> Option<String> {
+        if let Some(id) = application_id.filter(|id| is_reverse_dns_id(id)) {
+            return Some(id.to_string());
+        }
+
+        let environ = format!("/proc/{}/environ", pid?);
+        let output = self.executor.execute("cat", &[&environ]).ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let environ = String::from_utf8_lossy(&output.stdout);
+        let variables: Vec<&str> = environ.split('\0').collect();
+        ["FLATPAK_ID=", "SNAP_NAME="].iter().find_map(|name| {
+            variables
+                .iter()
+                .find_map(|variable| variable.strip_prefix(name))
+                .filter(|id| !id.is_empty())
+                .map(str::to_string)
+        })
+    }
+
+    /// Friendly name for a sandboxed app, from the `Name` in its exported `.desktop` file
+    ///
+    /// Falls back to the meaningful end of the ID, so `org.mozilla.firefox` becomes
+    /// "Firefox" and `com.spotify.Client` becomes "Spotify".
+    pub fn sandbox_app_name(&self, app_id: &str) -> String {
+        // Snap exports its desktop files as <snap>_<app>.desktop
+        let file_names = [format!("{app_id}.desktop"), format!("{app_id}_{app_id}.desktop")];
+        for dir in &self.config.desktop_dirs {
+            for file_name in &file_names {
+                let Ok(contents) = std::fs::read_to_string(dir.join(file_name)) else {
+                    continue;
+                };
+                if let Some(name) = desktop_entry_name(&contents) {
+                    debug!("Found name {} for {} in {}", name, app_id, file_name);
+                    return name;
+                }
+            }
+        }
+
+        name_from_app_id(app_id)
+    }
+
+    /// Friendly name of the app if the stream comes from a Flatpak or Snap sandbox
+    pub fn sandboxed_app_name(
+        &self,
+        application_id: Option<&str>,
+        pid: Option<u32>,
+    ) -> Option<String> {
+        let app_id = self.sandbox_app_id(application_id, pid)?;
+        Some(self.sandbox_app_name(&app_id))
+    }
+
     /// Determine the best display name for an app
     pub fn determine_display_name(
         &self,
@@ -149,22 +241,141 @@ impl AppNameDetector {
             }
         }
 
-        // Priority 2: Application name if it's not generic
+        // Priority 2: Friendly name of a sandboxed app, whose binary name is often a wrapper
+        let binary_name = binary_path.map(|path| self.extract_binary_name(path));
+        let app_id = [Some(application_name), binary_name.as_deref()]
+            .into_iter()
+            .flatten()
+            .find(|name| is_reverse_dns_id(name));
+        if let Some(name) = self.sandboxed_app_name(app_id, pid) {
+            return name;
+        }
+
+        // Priority 3: Application name if it's not generic
         if !application_name.is_empty() && !is_generic_app_name(application_name) {
             return application_name.to_string();
         }
 
-        // Priority 3: Binary name as fallback
-        if let Some(path) = binary_path {
-            let binary_name = self.extract_binary_name(path);
-            if !binary_name.is_empty() {
-                return capitalize_first_letter(&binary_name);
-            }
+        // Priority 4: Binary name as fallback
+        if let Some(binary_name) = binary_name.filter(|name| !name.is_empty()) {
+            return capitalize_first_letter(&binary_name);
         }
 
         // Last resort: use application name as-is
         application_name.to_string()
     }
+
+    /// The stream as the PipeWire monitor adds it to its app when there's no window
+    /// title to go by
+    ///
+    /// The app is named from the configured identity properties, with sandboxed apps
+    /// grouped by their app ID. Returns None for a stream the track lists leave out.
+    pub fn new_stream(
+        &self,
+        config: &CacheConfig,
+        input: &SinkInput,
+        current_sink: String,
+    ) -> Option<NewStream> {
+        let app_name =
+            config.app_identity(|key| input.property(key).map(str::to_string)).unwrap_or_default();
+        let binary_name = stream_binary_name(input);
+        let parent_name =
+            self.sandboxed_app_name(input.property("application.id"), input.process_id());
+        let (app_key, display_name) = choose_app_name(
+            None,
+            parent_name.as_deref(),
+            &app_name,
+            binary_name.as_deref(),
+            config.capitalize_binary_names,
+        );
+        let binary_name = binary_name.unwrap_or_else(|| app_name.clone());
+        if !config.should_track(&[&app_key, &binary_name, &app_name]) {
+            debug!("Not tracking {} ({})", app_key, binary_name);
+            return None;
+        }
+
+        let stream_label =
+            input.property("media.name").and_then(|name| stream_label(name, &app_name));
+        Some(NewStream {
+            app_key,
+            display_name,
+            binary_name,
+            stream_name: app_name,
+            sink_input_id: input.serial(),
+            current_sink,
+            media_role: input.media_role().map(str::to_string),
+            stream_label,
+            stream_volume: input.volume,
+            corked: input.corked,
+            window_title: None,
+        })
+    }
+}
+
+/// Name of the binary playing a stream, without its path or wrapper suffixes
+pub fn stream_binary_name(input: &SinkInput) -> Option<String> {
+    let binary_path = input.property("application.process.binary")?;
+    let extracted = binary_path
+        .split('/')
+        .next_back()
+        .unwrap_or(binary_path)
+        .trim_end_matches("-bin")
+        .trim_end_matches(".exe");
+    (!extracted.is_empty()).then(|| extracted.to_string())
+}
+
+/// Cache key and display name for a stream, best source first
+///
+/// 1. Window title from X11/Wayland (most accurate)
+/// 2. Ultimate parent process name (groups e.g. Discord's Chromium and WEBRTC streams)
+/// 3. Binary name for WebRTC streams
+/// 4. application.name if it's not generic
+/// 5. Binary name
+/// 6. application.name as a last resort
+///
+/// Names taken from a process or binary get their first letter capitalized for display
+/// only if `capitalize` is set. The key is always capitalized, so routing rules keyed on
+/// it keep matching whichever way the option is set.
+pub fn choose_app_name(
+    window_title: Option<&str>,
+    parent_name: Option<&str>,
+    app_name: &str,
+    binary_name: Option<&str>,
+    capitalize: bool,
+) -> (String, String) {
+    let is_webrtc = app_name.contains("WEBRTC") || app_name.contains("WebRTC");
+    let is_generic =
+        app_name.is_empty() || app_name.contains("wine") || app_name.contains("preloader");
+
+    let (name, from_process) = match (window_title, parent_name, binary_name) {
+        (Some(title), _, _) => (title, false),
+        (None, Some(parent), _) => (parent, true),
+        (None, None, Some(binary)) if is_webrtc || is_generic => (binary, true),
+        _ => (app_name, false),
+    };
+
+    if !from_process {
+        return (name.to_string(), name.to_string());
+    }
+    let key = capitalize_first_letter(name);
+    let display_name = if capitalize { key.clone() } else { name.to_string() };
+    (key, display_name)
+}
+
+/// Stream `media.name` values that say nothing about what's playing
+const GENERIC_MEDIA_NAMES: &[&str] =
+    &["playback", "playback stream", "audio stream", "audiostream", "output", "sound"];
+
+/// Label for a stream from its `media.name`, if that tells it apart from its app
+///
+/// Browsers report the tab or video title here; many other apps repeat their own
+/// name or use a generic one, which makes no useful label.
+pub fn stream_label(media_name: &str, app_name: &str) -> Option<String> {
+    let media_name = media_name.trim();
+    let uninformative = media_name.is_empty()
+        || media_name.eq_ignore_ascii_case(app_name)
+        || GENERIC_MEDIA_NAMES.iter().any(|generic| media_name.eq_ignore_ascii_case(generic));
+    (!uninformative).then(|| media_name.to_string())
 }
 
 /// Check if an application name is generic/unhelpful
@@ -177,8 +388,49 @@ fn is_generic_app_name(name: &str) -> bool {
         || name == "wine-preloader"
 }
 
+/// Whether `name` is a reverse-DNS app ID such as `org.mozilla.firefox`
+pub fn is_reverse_dns_id(name: &str) -> bool {
+    let segments: Vec<&str> = name.split('.').collect();
+    segments.len() >= 3
+        && segments[0].chars().all(|c| c.is_ascii_lowercase())
+        && segments.iter().all(|segment| {
+            !segment.is_empty()
+                && !segment.chars().all(|c| c.is_ascii_digit())
+                && segment.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        })
+}
+
+/// Untranslated `Name` of the `[Desktop Entry]` group in a `.desktop` file
+fn desktop_entry_name(contents: &str) -> Option<String> {
+    let mut in_entry = false;
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_entry = line == "[Desktop Entry]";
+        } else if let Some(name) = line.strip_prefix("Name=").filter(|_| in_entry) {
+            let name = name.trim();
+            if !name.is_empty() {
+                return Some(name.to_string());
+            }
+        }
+    }
+    None
+}
+
+/// Readable name from an app ID, skipping trailing segments like `Client`
+fn name_from_app_id(app_id: &str) -> String {
+    let segments: Vec<&str> = app_id.split('.').collect();
+    let name = segments
+        .iter()
+        .rev()
+        .find(|segment| {
+            !GENERIC_ID_SEGMENTS.iter().any(|generic| segment.eq_ignore_ascii_case(generic))
+        })
+        .unwrap_or(&app_id);
+    capitalize_first_letter(name)
+}
+
 /// Capitalize the first letter of a string
-fn capitalize_first_letter(s: &str) -> String {
+pub(crate) fn capitalize_first_letter(s: &str) -> String {
     let mut chars = s.chars();
     match chars.next() {
         None => String::new(),
@@ -196,11 +448,16 @@ mod tests {
     struct MockCommandExecutor {
         ps_responses: HashMap<u32, u32>,     // PID -> Parent PID
         window_titles: HashMap<u32, String>, // PID -> Window Title
+        environments: HashMap<u32, String>,  // PID -> NUL-separated environment
     }
 
     impl MockCommandExecutor {
         fn new() -> Self {
-            Self { ps_responses: HashMap::new(), window_titles: HashMap::new() }
+            Self {
+                ps_responses: HashMap::new(),
+                window_titles: HashMap::new(),
+                environments: HashMap::new(),
+            }
         }
 
         fn with_parent(mut self, pid: u32, ppid: u32) -> Self {
@@ -212,6 +469,11 @@ mod tests {
             self.window_titles.insert(pid, title);
             self
         }
+
+        fn with_environment(mut self, pid: u32, variables: &[&str]) -> Self {
+            self.environments.insert(pid, variables.join("\0"));
+            self
+        }
     }
 
     impl CommandExecutor for MockCommandExecutor {
@@ -228,6 +490,18 @@ mod tests {
                 }
             }
 
+            if program == "cat" {
+                let pid = args[0].trim_start_matches("/proc/").trim_end_matches("/environ");
+                if let Some(environ) = pid.parse().ok().and_then(|pid| self.environments.get(&pid))
+                {
+                    return Ok(std::process::Output {
+                        status: std::process::ExitStatus::from_raw(0),
+                        stdout: environ.as_bytes().to_vec(),
+                        stderr: Vec::new(),
+                    });
+                }
+            }
+
             Ok(std::process::Output {
                 status: std::process::ExitStatus::from_raw(1),
                 stdout: Vec::new(),
@@ -275,7 +549,7 @@ mod tests {
     fn test_get_parent_pid() {
         let executor = MockCommandExecutor::new().with_parent(1234, 5678).with_parent(5678, 1);
 
-        let detector = AppNameDetector::new(Box::new(executor), AppNameConfig::default());
+        let detector = AppNameDetector::new(Arc::new(executor), AppNameConfig::default());
 
         assert_eq!(detector.get_parent_pid(1234), Some(5678));
         assert_eq!(detector.get_parent_pid(5678), None); // PID 1 is filtered out
@@ -288,7 +562,7 @@ mod tests {
             .with_window(1234, "Firefox".to_string())
             .with_window(5678, "Default IME".to_string());
 
-        let detector = AppNameDetector::new(Box::new(executor), AppNameConfig::default());
+        let detector = AppNameDetector::new(Arc::new(executor), AppNameConfig::default());
 
         assert_eq!(detector.get_window_title(1234), Some("Firefox".to_string()));
         assert_eq!(detector.get_window_title(5678), None); // Ignored title
@@ -309,7 +583,7 @@ mod tests {
     fn test_find_window_title_in_tree_direct() {
         let executor = MockCommandExecutor::new().with_window(1234, "Discord".to_string());
 
-        let detector = AppNameDetector::new(Box::new(executor), AppNameConfig::default());
+        let detector = AppNameDetector::new(Arc::new(executor), AppNameConfig::default());
 
         assert_eq!(detector.find_window_title_in_tree(1234), Some("Discord".to_string()));
     }
@@ -320,7 +594,7 @@ mod tests {
             .with_parent(782169, 782029) // Discord audio -> Discord main
             .with_window(782029, "Discord".to_string());
 
-        let detector = AppNameDetector::new(Box::new(executor), AppNameConfig::default());
+        let detector = AppNameDetector::new(Arc::new(executor), AppNameConfig::default());
 
         assert_eq!(detector.find_window_title_in_tree(782169), Some("Discord".to_string()));
     }
@@ -332,7 +606,7 @@ mod tests {
             .with_window(1234, "Default IME".to_string())
             .with_window(5678, "Discord".to_string());
 
-        let detector = AppNameDetector::new(Box::new(executor), AppNameConfig::default());
+        let detector = AppNameDetector::new(Arc::new(executor), AppNameConfig::default());
 
         assert_eq!(detector.find_window_title_in_tree(1234), Some("Discord".to_string()));
     }
@@ -341,7 +615,7 @@ mod tests {
     fn test_find_window_title_steam_app() {
         let executor = MockCommandExecutor::new().with_window(1234, "steam_app_359320".to_string());
 
-        let detector = AppNameDetector::new(Box::new(executor), AppNameConfig::default());
+        let detector = AppNameDetector::new(Arc::new(executor), AppNameConfig::default());
 
         assert_eq!(detector.find_window_title_in_tree(1234), None);
     }
@@ -350,7 +624,7 @@ mod tests {
     fn test_determine_display_name_window_priority() {
         let executor = MockCommandExecutor::new().with_window(1234, "Discord".to_string());
 
-        let detector = AppNameDetector::new(Box::new(executor), AppNameConfig::default());
+        let detector = AppNameDetector::new(Arc::new(executor), AppNameConfig::default());
 
         assert_eq!(
             detector.determine_display_name(
@@ -385,7 +659,7 @@ mod tests {
     #[test]
     fn test_determine_display_name_wine() {
         let executor = MockCommandExecutor::new();
-        let detector = AppNameDetector::new(Box::new(executor), AppNameConfig::default());
+        let detector = AppNameDetector::new(Arc::new(executor), AppNameConfig::default());
 
         // Wine should be skipped, Elite Dangerous should be used
         assert_eq!(
@@ -428,10 +702,9 @@ mod tests {
             .with_parent(5, 6)
             .with_window(6, "TooDeep".to_string());
 
-        let mut config = AppNameConfig::default();
-        config.max_parent_depth = 3;
+        let config = AppNameConfig { max_parent_depth: 3, ..Default::default() };
 
-        let detector = AppNameDetector::new(Box::new(executor), config);
+        let detector = AppNameDetector::new(Arc::new(executor), config);
 
         // Should stop at depth 3, not reach PID 6
         assert_eq!(detector.find_window_title_in_tree(1), None);
@@ -444,7 +717,7 @@ mod tests {
             .with_parent(782169, 782029) // Audio process -> Main Discord
             .with_window(782029, "Discord".to_string());
 
-        let detector = AppNameDetector::new(Box::new(executor), AppNameConfig::default());
+        let detector = AppNameDetector::new(Arc::new(executor), AppNameConfig::default());
 
         let result = detector.determine_display_name(
             "WEBRTC VoiceEngine",
@@ -460,7 +733,7 @@ mod tests {
         // Simulate Steam game: window has steam_app prefix
         let executor = MockCommandExecutor::new().with_window(1234, "steam_app_359320".to_string());
 
-        let detector = AppNameDetector::new(Box::new(executor), AppNameConfig::default());
+        let detector = AppNameDetector::new(Arc::new(executor), AppNameConfig::default());
 
         let result = detector.determine_display_name(
             "Elite Dangerous",
@@ -471,4 +744,137 @@ mod tests {
         // Should use application name since window title is steam_app
         assert_eq!(result, "Elite Dangerous");
     }
+
+    /// Detector looking for desktop files only in `dir`
+    fn sandbox_detector(executor: MockCommandExecutor, dir: &std::path::Path) -> AppNameDetector {
+        let config = AppNameConfig { desktop_dirs: vec![dir.to_path_buf()], ..Default::default() };
+        AppNameDetector::new(Arc::new(executor), config)
+    }
+
+    #[test]
+    fn test_is_reverse_dns_id() {
+        assert!(is_reverse_dns_id("org.mozilla.firefox"));
+        assert!(is_reverse_dns_id("com.spotify.Client"));
+        assert!(is_reverse_dns_id("com.discordapp.Discord"));
+        assert!(!is_reverse_dns_id("firefox"));
+        assert!(!is_reverse_dns_id("python3.11"));
+        assert!(!is_reverse_dns_id("libpipewire.so.0"));
+        assert!(!is_reverse_dns_id("Firefox.Web.Browser"));
+        assert!(!is_reverse_dns_id("org..firefox"));
+    }
+
+    #[test]
+    fn test_reverse_dns_id_maps_to_desktop_file_name() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("org.mozilla.firefox.desktop"),
+            "[Desktop Entry]\nName[de]=Feuerfuchs\nName=Firefox Web Browser\nExec=firefox\n\n\
+             [Desktop Action new-window]\nName=New Window\n",
+        )
+        .unwrap();
+        let detector = sandbox_detector(MockCommandExecutor::new(), dir.path());
+
+        assert_eq!(
+            detector.sandboxed_app_name(Some("org.mozilla.firefox"), None).as_deref(),
+            Some("Firefox Web Browser")
+        );
+        // The ID can also arrive as the application.name
+        assert_eq!(
+            detector.determine_display_name("org.mozilla.firefox", Some("/app/bin/bwrap"), None),
+            "Firefox Web Browser"
+        );
+    }
+
+    #[test]
+    fn test_sandbox_id_read_from_process_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("spotify_spotify.desktop"),
+            "[Desktop Entry]\nName=Spotify\n",
+        )
+        .unwrap();
+        let executor = MockCommandExecutor::new()
+            .with_environment(100, &["HOME=/home/user", "FLATPAK_ID=com.discordapp.Discord"])
+            .with_environment(200, &["SNAP_NAME=spotify", "SNAP=/snap/spotify/1"])
+            .with_environment(300, &["HOME=/home/user"]);
+        let detector = sandbox_detector(executor, dir.path());
+
+        assert_eq!(
+            detector.sandbox_app_id(None, Some(100)).as_deref(),
+            Some("com.discordapp.Discord")
+        );
+        assert_eq!(detector.sandboxed_app_name(None, Some(200)).as_deref(), Some("Spotify"));
+        // Neither sandboxed nor readable
+        assert_eq!(detector.sandbox_app_id(Some("Firefox"), Some(300)), None);
+        assert_eq!(detector.sandbox_app_id(None, Some(400)), None);
+        assert_eq!(detector.sandbox_app_id(None, None), None);
+    }
+
+    #[test]
+    fn test_sandbox_name_falls_back_to_app_id() {
+        let dir = tempfile::tempdir().unwrap();
+        // A desktop file without a usable name is skipped
+        std::fs::write(dir.path().join("org.gnome.Lollypop.desktop"), "[Desktop Entry]\nName=\n")
+            .unwrap();
+        let detector = sandbox_detector(MockCommandExecutor::new(), dir.path());
+
+        assert_eq!(detector.sandbox_app_name("org.mozilla.firefox"), "Firefox");
+        assert_eq!(detector.sandbox_app_name("com.spotify.Client"), "Spotify");
+        assert_eq!(detector.sandbox_app_name("org.gnome.Lollypop"), "Lollypop");
+    }
+
+    #[test]
+    fn test_unsandboxed_app_names_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let detector = sandbox_detector(MockCommandExecutor::new(), dir.path());
+
+        assert_eq!(
+            detector.determine_display_name(
+                "WEBRTC VoiceEngine",
+                Some("/usr/bin/discord"),
+                Some(1)
+            ),
+            "Discord"
+        );
+        assert_eq!(detector.determine_display_name("Firefox", None, Some(1)), "Firefox");
+    }
+
+    #[test]
+    fn test_choose_app_name_preserves_casing_of_proper_names() {
+        // Window titles and application.name are shown as reported either way
+        for capitalize in [true, false] {
+            assert_eq!(
+                choose_app_name(Some("iTunes"), Some("wine"), "", Some("itunes"), capitalize),
+                ("iTunes".to_string(), "iTunes".to_string())
+            );
+            assert_eq!(
+                choose_app_name(None, None, "iTunes", Some("itunes"), capitalize),
+                ("iTunes".to_string(), "iTunes".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_choose_app_name_capitalization_keeps_key_stable() {
+        let binary = |capitalize| {
+            choose_app_name(None, None, "wine64-preloader", Some("foobar2000"), capitalize)
+        };
+        assert_eq!(binary(true), ("Foobar2000".to_string(), "Foobar2000".to_string()));
+        assert_eq!(binary(false), ("Foobar2000".to_string(), "foobar2000".to_string()));
+
+        let parent = choose_app_name(None, Some("discord"), "WEBRTC VoiceEngine", None, false);
+        assert_eq!(parent, ("Discord".to_string(), "discord".to_string()));
+    }
+
+    #[test]
+    fn test_stream_label_keeps_only_informative_media_names() {
+        assert_eq!(
+            stream_label(" Lo-fi beats to relax to - YouTube ", "Firefox").as_deref(),
+            Some("Lo-fi beats to relax to - YouTube")
+        );
+        assert_eq!(stream_label("firefox", "Firefox"), None);
+        assert_eq!(stream_label("Playback", "Spotify"), None);
+        assert_eq!(stream_label("AudioStream", "Chromium"), None);
+        assert_eq!(stream_label("", "Firefox"), None);
+    }
 }
@@ -0,0 +1,293 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::process::Output;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+use crate::command::{CommandExecutor, SystemCommandExecutor};
+use crate::sink_inputs::{parse_sink_inputs, SinkInput};
+
+/// A node whose volume or mute state can be changed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Node {
+    Sink(u32),
+    SinkInput(u32),
+}
+
+/// A sink as listed by the audio server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SinkEntry {
+    pub id: u32,
+    pub name: String,
+}
+
+/// Operations the controller needs from the audio server
+#[async_trait]
+pub trait PipeWireBackend: Send + Sync {
+    async fn set_volume(&self, node: Node, percent: u32) -> Result<()>;
+
+    async fn set_mute(&self, node: Node, muted: bool) -> Result<()>;
+
+    /// Move a stream to the sink with the given name
+    async fn move_sink_input(&self, sink_input_id: u32, sink_name: &str) -> Result<()>;
+
+    async fn list_sink_inputs(&self) -> Result<Vec<SinkInput>>;
+
+    /// Make sure a sink named `sink_name` exists that plays to every one of `targets`
+    ///
+    /// Returns the id of the module loaded for it, or None if the sink already existed.
+    async fn combine_sinks(&self, sink_name: &str, targets: &[String]) -> Result<Option<u32>>;
+
+    async fn list_sinks(&self) -> Result<Vec<SinkEntry>>;
+
+    /// Create a sink named `sink_name` that plays to the default output device
+    /// through a loopback named `loopback_name`
+    ///
+    /// Returns the ids of the modules loaded for it, for [`Self::unload_module`].
+    async fn create_virtual_sink(
+        &self,
+        sink_name: &str,
+        description: &str,
+        loopback_name: &str,
+    ) -> Result<Vec<u32>>;
+
+    async fn unload_module(&self, module_id: u32) -> Result<()>;
+
+    /// Version of the PipeWire server
+    async fn server_version(&self) -> Result<String>;
+
+    /// Name of the sink new streams play to unless something moves them
+    async fn default_sink(&self) -> Result<String>;
+
+    /// Ids of streams as they appear, for backends that can report them
+    ///
+    /// The daemon follows these when the PipeWire monitor is off. pactl can only be
+    /// polled, so the default is None.
+    fn subscribe_new_streams(&self) -> Option<broadcast::Receiver<u32>> {
+        None
+    }
+}
+
+/// Backend that talks to pipewire-pulse through the `pactl` CLI
+pub struct PactlBackend {
+    executor: Arc<dyn CommandExecutor>,
+}
+
+impl Default for PactlBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PactlBackend {
+    pub fn new() -> Self {
+        Self::with_executor(Arc::new(SystemCommandExecutor))
+    }
+
+    /// Create a backend that runs pactl through a custom executor
+    pub fn with_executor(executor: Arc<dyn CommandExecutor>) -> Self {
+        Self { executor }
+    }
+
+    /// Run pactl without blocking the async runtime, failing if it exits unsuccessfully
+    async fn pactl(&self, args: &[&str]) -> Result<Output> {
+        let executor = self.executor.clone();
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        let output = tokio::task::spawn_blocking(move || {
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            executor.execute("pactl", &args)
+        })
+        .await??;
+
+        if !output.status.success() {
+            return Err(anyhow!(
+                "pactl command failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+        Ok(output)
+    }
+
+    /// Load a module, returning its id
+    async fn load_module(&self, args: &[String]) -> Result<u32> {
+        let args: Vec<&str> =
+            std::iter::once("load-module").chain(args.iter().map(String::as_str)).collect();
+        let output = self.pactl(&args).await?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        stdout.trim().parse().map_err(|_| anyhow!("Unexpected load-module output: {}", stdout))
+    }
+}
+
+#[async_trait]
+impl PipeWireBackend for PactlBackend {
+    async fn set_volume(&self, node: Node, percent: u32) -> Result<()> {
+        let (command, id) = match node {
+            Node::Sink(id) => ("set-sink-volume", id),
+            Node::SinkInput(id) => ("set-sink-input-volume", id),
+        };
+        self.pactl(&[command, &id.to_string(), &format!("{percent}%")]).await?;
+        Ok(())
+    }
+
+    async fn set_mute(&self, node: Node, muted: bool) -> Result<()> {
+        let (command, id) = match node {
+            Node::Sink(id) => ("set-sink-mute", id),
+            Node::SinkInput(id) => ("set-sink-input-mute", id),
+        };
+        self.pactl(&[command, &id.to_string(), if muted { "1" } else { "0" }]).await?;
+        Ok(())
+    }
+
+    async fn move_sink_input(&self, sink_input_id: u32, sink_name: &str) -> Result<()> {
+        self.pactl(&["move-sink-input", &sink_input_id.to_string(), sink_name]).await?;
+        Ok(())
+    }
+
+    async fn list_sink_inputs(&self) -> Result<Vec<SinkInput>> {
+        let output = self.pactl(&["list", "sink-inputs"]).await?;
+        Ok(parse_sink_inputs(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    async fn combine_sinks(&self, sink_name: &str, targets: &[String]) -> Result<Option<u32>> {
+        if self.list_sinks().await?.iter().any(|sink| sink.name == sink_name) {
+            return Ok(None);
+        }
+        let module_id = self
+            .load_module(&[
+                "module-combine-sink".to_string(),
+                format!("sink_name={sink_name}"),
+                format!("slaves={}", targets.join(",")),
+            ])
+            .await?;
+        Ok(Some(module_id))
+    }
+
+    async fn list_sinks(&self) -> Result<Vec<SinkEntry>> {
+        let output = self.pactl(&["list", "sinks", "short"]).await?;
+        Ok(parse_sinks_short(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    async fn create_virtual_sink(
+        &self,
+        sink_name: &str,
+        description: &str,
+        loopback_name: &str,
+    ) -> Result<Vec<u32>> {
+        let null_sink = self.load_module(&null_sink_module_args(sink_name, description)).await?;
+        match self.load_module(&loopback_module_args(sink_name, loopback_name)).await {
+            Ok(loopback) => Ok(vec![null_sink, loopback]),
+            Err(e) => {
+                // Don't leave a sink behind that plays nowhere
+                let _ = self.unload_module(null_sink).await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn unload_module(&self, module_id: u32) -> Result<()> {
+        self.pactl(&["unload-module", &module_id.to_string()]).await?;
+        Ok(())
+    }
+
+    async fn server_version(&self) -> Result<String> {
+        let output = self.pactl(&["info"]).await?;
+        parse_server_version(&String::from_utf8_lossy(&output.stdout))
+            .ok_or_else(|| anyhow!("No server version in pactl info"))
+    }
+
+    async fn default_sink(&self) -> Result<String> {
+        let output = self.pactl(&["info"]).await?;
+        parse_default_sink(&String::from_utf8_lossy(&output.stdout))
+            .ok_or_else(|| anyhow!("No default sink in pactl info"))
+    }
+}
+
+/// Suffix of the loopbacks the daemon creates, and the only one looked for by default
+pub const DEFAULT_LOOPBACK_SUFFIX: &str = "_to_Speaker";
+
+/// Names a loopback of `sink_name` may have, one per suffix, in the order given
+pub fn loopback_node_names(sink_name: &str, suffixes: &[String]) -> Vec<String> {
+    suffixes.iter().map(|suffix| format!("{sink_name}{suffix}")).collect()
+}
+
+/// Prefix of the combine sinks that duplicate an app to several sinks
+pub const COMBINED_SINK_PREFIX: &str = "combined_";
+
+/// Name of the combine sink that feeds every one of `targets`
+pub fn combined_sink_name(targets: &[String]) -> String {
+    format!("{COMBINED_SINK_PREFIX}{}", targets.join("_"))
+}
+
+/// Whether a sink is one of the combine sinks made by [`combined_sink_name`]
+pub fn is_combined_sink(node_name: &str) -> bool {
+    node_name.starts_with(COMBINED_SINK_PREFIX)
+}
+
+/// `pactl load-module` arguments for the null sink behind a virtual sink
+pub fn null_sink_module_args(sink_name: &str, description: &str) -> Vec<String> {
+    vec![
+        "module-null-sink".to_string(),
+        format!("sink_name={sink_name}"),
+        format!("sink_properties=device.description={}", quote_module_value(description)),
+    ]
+}
+
+/// `pactl load-module` arguments for the loopback playing a virtual sink to the
+/// default output, named `loopback_name` for the controller to find it by
+pub fn loopback_module_args(sink_name: &str, loopback_name: &str) -> Vec<String> {
+    vec![
+        "module-loopback".to_string(),
+        format!("source={sink_name}.monitor"),
+        "source_dont_move=true".to_string(),
+        format!("sink_input_properties=node.name={loopback_name}"),
+    ]
+}
+
+/// Quote a module property value so spaces and quotes survive
+fn quote_module_value(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Parse the output of `pactl list sinks short`
+pub fn parse_sinks_short(output: &str) -> Vec<SinkEntry> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let id = fields.next()?.parse().ok()?;
+            let name = fields.next()?.to_string();
+            Some(SinkEntry { id, name })
+        })
+        .collect()
+}
+
+/// Extract the server version from `pactl info` output
+///
+/// pipewire-pulse reports itself as `Server Name: PulseAudio (on PipeWire 1.0.5)`
+/// while `Server Version` is the emulated PulseAudio version, so the former wins.
+pub fn parse_server_version(output: &str) -> Option<String> {
+    let field =
+        |name: &str| output.lines().find_map(|line| line.trim().strip_prefix(name)).map(str::trim);
+
+    if let Some(version) = field("Server Name:")
+        .and_then(|server| server.split_once("on PipeWire "))
+        .map(|(_, rest)| rest.trim_end_matches(')').trim())
+    {
+        return Some(version.to_string());
+    }
+
+    field("Server Version:").filter(|version| !version.is_empty()).map(str::to_string)
+}
+
+/// Extract the default sink's name from `pactl info` output
+///
+/// Without any output device pipewire-pulse reports `@DEFAULT_SINK@` or nothing at
+/// all, neither of which names a sink.
+pub fn parse_default_sink(output: &str) -> Option<String> {
+    output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Default Sink:"))
+        .map(str::trim)
+        .filter(|name| !name.is_empty() && !name.starts_with('@'))
+        .map(str::to_string)
+}
@@ -1,38 +1,265 @@
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, watch, Mutex as AsyncMutex, Notify};
+use tracing::{debug, info, warn};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::backend::{loopback_node_names, DEFAULT_LOOPBACK_SUFFIX};
+use crate::config::{AppSettings, Config, RoutingConfig, StaleRulePolicy};
+use crate::events::{Event, EventKind, EventLog};
+use crate::latency::{LatencyStats, LatencySummary, Operation};
+use crate::volume::sanitize_volume;
+
+/// Default limit, in bytes, for app and stream names stored in the cache
+pub const DEFAULT_MAX_NAME_LENGTH: usize = 128;
+
+/// How long pactl/wpctl get before a call is abandoned
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How long a D-Bus route waits before refreshing clients once more
+pub const DEFAULT_ROUTE_REFRESH_DELAY: Duration = Duration::from_millis(300);
+
+const ELLIPSIS: &str = "…";
+
+/// Shortest rule key, in characters, allowed to match an app name by prefix
+pub const FUZZY_MIN_RULE_LENGTH: usize = 4;
+
+/// Sinks remembered per app for [`AudioCache::recent_sinks`]
+pub const RECENT_SINKS_LEN: usize = 5;
+
+/// How many sink events a slow subscriber may fall behind before missing some
+///
+/// Sized for a volume slider being dragged, which changes the state many times a second.
+const SINK_EVENT_CAPACITY: usize = 128;
+
+/// A physical output device appearing or going away, or a sink's state changing
+#[derive(Debug, Clone, PartialEq)]
+pub enum SinkEvent {
+    Added {
+        sink_name: String,
+        display_name: String,
+    },
+    Removed {
+        sink_name: String,
+    },
+    /// Any field of a cached sink changed; `present` is false once it's gone
+    StateChanged {
+        sink_name: String,
+        volume: f32,
+        muted: bool,
+        present: bool,
+    },
+}
+
+/// Shorten a name to at most `max_len` bytes, ending it with an ellipsis
+///
+/// The cut is made on a character boundary, so the result is always valid UTF-8.
+pub fn truncate_name(name: &str, max_len: usize) -> String {
+    if name.len() <= max_len {
+        return name.to_string();
+    }
+    let mut end = max_len.saturating_sub(ELLIPSIS.len());
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{ELLIPSIS}", &name[..end])
+}
+
+/// Sinks named by a routing rule value such as `"Game"` or `"Recording, Headphones"`
+///
+/// Entries are trimmed, empty ones dropped and duplicates removed, keeping the first
+/// occurrence so the primary sink stays first.
+pub fn parse_sink_targets(value: &str) -> Vec<String> {
+    let mut targets: Vec<String> = Vec::new();
+    for target in value.split(',').map(str::trim).filter(|target| !target.is_empty()) {
+        if !targets.iter().any(|existing| existing == target) {
+            targets.push(target.to_string());
+        }
+    }
+    targets
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct SinkInfo {
     pub id: u32,
     pub name: String,
     pub volume: f32,
     pub muted: bool,
     pub pipewire_id: u32, // Add pipewire_id field for D-Bus
+    #[serde(default)]
+    pub applied_percent: u32, // Percentage last sent to pactl/wpctl
+    #[serde(default)]
+    pub node_name: String, // The node.name pactl knows it by, empty when that is `name`
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl SinkInfo {
+    /// The name to give pactl for this sink
+    ///
+    /// Sinks matched by description have a generated node name, not the configured one.
+    pub fn pactl_name(&self) -> &str {
+        if self.node_name.is_empty() {
+            &self.name
+        } else {
+            &self.node_name
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AppInfo {
     pub display_name: String,
     pub binary_name: String,
     pub stream_names: Vec<String>, // The actual PipeWire stream names (e.g., ["game.exe", "WEBRTC VoiceEngine"])
     pub current_sink: String,
+    #[serde(default)]
+    pub current_sinks: Vec<String>, // Every target of an app duplicated to several sinks; empty when it only plays on current_sink
+    #[serde(default)]
+    pub stream_labels: Vec<(u32, String)>, // (sink_input_id, media.name) of streams that report one, oldest first
+    #[serde(default)]
+    pub window_title: Option<String>, // Title of the app's window, for apps that have one
     pub active: bool,
-    pub sink_input_ids: Vec<u32>,
-    pub pipewire_id: u32, // Add pipewire_id field for D-Bus
+    #[serde(default)]
+    pub playing: bool, // Some stream is actually playing, not just open but corked
+    #[serde(default)]
+    pub muted: bool, // The app's own streams are muted, whatever its sink's state
+    pub sink_input_ids: Vec<u32>, // Serials of the app's streams, not pactl indexes
+    pub pipewire_id: u32,         // Add pipewire_id field for D-Bus
+    #[serde(default)]
+    pub media_role: Option<String>, // PipeWire media.role of the app's streams (e.g. "Game", "Music")
+    #[serde(default)]
+    pub volume: Option<f32>, // Average volume of the app's streams, None until PipeWire reports one
     #[serde(skip)]
     #[allow(dead_code)] // Used for TTL tracking but not directly read
     pub inactive_since: Option<std::time::Instant>,
 }
 
+/// Equal when everything but `inactive_since` matches, which isn't shared with clients
+impl PartialEq for AppInfo {
+    fn eq(&self, other: &Self) -> bool {
+        // Destructured so a new field can't be left out by accident
+        let Self {
+            display_name,
+            binary_name,
+            stream_names,
+            current_sink,
+            current_sinks,
+            stream_labels,
+            window_title,
+            active,
+            playing,
+            muted,
+            sink_input_ids,
+            pipewire_id,
+            media_role,
+            volume,
+            inactive_since: _,
+        } = self;
+        *display_name == other.display_name
+            && *binary_name == other.binary_name
+            && *stream_names == other.stream_names
+            && *current_sink == other.current_sink
+            && *current_sinks == other.current_sinks
+            && *stream_labels == other.stream_labels
+            && *window_title == other.window_title
+            && *active == other.active
+            && *playing == other.playing
+            && *muted == other.muted
+            && *sink_input_ids == other.sink_input_ids
+            && *pipewire_id == other.pipewire_id
+            && *media_role == other.media_role
+            && *volume == other.volume
+    }
+}
+
+impl AppInfo {
+    /// Name to show for the app
+    ///
+    /// With `per_stream_labels` this is the `media.name` of its newest labelled
+    /// stream, such as a browser tab's title, falling back to `display_name`.
+    pub fn label(&self, per_stream_labels: bool) -> &str {
+        match self.stream_labels.last() {
+            Some((_, label)) if per_stream_labels => label,
+            _ => &self.display_name,
+        }
+    }
+}
+
+/// A stream seen for an app, see [`AudioCache::add_stream`]
+#[derive(Debug, Default)]
+pub struct NewStream {
+    pub app_key: String,
+    pub display_name: String,
+    pub binary_name: String,
+    pub stream_name: String, // The name the stream itself reports
+    pub sink_input_id: u32,
+    pub current_sink: String,
+    pub media_role: Option<String>,
+    pub stream_label: Option<String>,
+    pub stream_volume: Option<f32>,
+    pub corked: bool,
+    pub window_title: Option<String>,
+}
+
+/// Shared daemon state
+///
+/// Each map is locked independently. To stay deadlock free, methods never call into
+/// one map while holding a guard (`get`, `get_mut`, an iterator or a `retain` closure)
+/// into another; they collect what they need, drop the guard, then touch the next map.
 #[derive(Debug)]
 pub struct AudioCache {
     generation: AtomicU64,
+    writers: AtomicUsize, // Changes to sinks or apps in progress, see read_consistent
     pub sinks: DashMap<String, SinkInfo>,
     pub apps: DashMap<String, AppInfo>,
     pub routing_rules: DashMap<String, String>,
+    rule_learned: Arc<Notify>, // Notified when auto-routing remembers a target as a rule
+    learned_rules: DashMap<String, String>, // Rules auto-routing remembered that aren't saved yet
     pub remembered_apps: DashMap<String, String>, // app -> last sink
+    recent_sinks: DashMap<String, VecDeque<String>>, // app -> sinks it was routed to, newest first
+    recent_sinks_changed: Arc<Notify>, // Notified when an app's recent sinks change or are dropped
+    pub physical_sinks: DashMap<String, String>, // Hardware sink node name -> display name
+    sink_labels: DashMap<String, String>, // Sink -> label shown in place of its name
+    stream_volumes: DashMap<u32, f32>, // Sink input -> volume PipeWire reported
+    stream_corked: DashMap<u32, bool>, // Sink input -> whether its app paused it
+    sink_members: DashMap<String, HashSet<String>>, // sink -> apps whose current_sink it is
+    sink_locks: DashMap<String, Arc<AsyncMutex<()>>>, // Serializes volume/mute changes per sink
+    sink_discovered: DashMap<String, (u32, Instant)>, // sink -> PipeWire id and when it appeared
+    pins: DashMap<String, String>, // app -> sink it's routed to whenever it appears, kept through cleanup
+    pins_changed: Arc<Notify>,     // Notified when a pin is added or removed, for saving
+    app_settings: DashMap<String, AppSettings>, // app -> volume and mute its new streams get
+    app_settings_changed: Arc<Notify>, // Notified when an app's saved volume or mute changes
+    auto_muted: DashSet<String>,   // Apps muted by auto_mute_on_inactive while idle, never saved
+    paused: AtomicBool,
+    auto_routing: AtomicBool, // Route new streams by rule or default sink
+    follow_focus: AtomicBool, // Move the app with window focus to focus_sink
+    focus_sink: Option<String>,
+    routing: RoutingConfig, // Rules TEST_RULE resolves against, beyond the runtime ones
+    sink_order: HashMap<String, u32>, // Virtual sink -> position in the panel
+    resumed: Arc<Notify>,
+    changes: watch::Sender<u64>,
+    ready: watch::Sender<bool>, // Set once the monitor's first full scan is in the cache
+    sink_events: broadcast::Sender<SinkEvent>,
+    max_name_length: usize,
+    per_stream_labels: bool, // Show apps by their newest stream's media.name
+    persist_sink_labels: bool, // Save sink labels set at runtime with the app mappings
+    labels_to_save: DashMap<String, String>, // Sink -> label set at runtime, while persisting them
+    labels_changed: Arc<Notify>, // Notified when a label to save is set or cleared
+    configured_labels: HashMap<String, String>, // Sink -> label the config gives it
+    command_timeout: Duration,
+    volume_ramp: Duration, // Loopback volume changes are stepped over this long, zero to jump
+    loopback_suffixes: Vec<String>, // A sink's loopbacks are named the sink plus one of these
+    route_refresh_delay: Duration, // Wait before the refresh after a D-Bus route, zero to skip it
+    default_volumes: DashMap<String, f32>, // Reset volume per sink, configured or imported
+    prior_mutes: Mutex<Option<HashMap<String, bool>>>, // Mute states to restore once a solo or mute-all ends
+    server_version: OnceLock<String>,                  // PipeWire version, detected once at startup
+    default_sink: Mutex<Option<String>>, // Sink PipeWire plays new streams to, as last queried
+    events: EventLog,                    // Recent stream, route and sink events for EVENTS
+    latency: LatencyStats,               // How long cache updates, snapshots and routes take
+    dropped_updates: Arc<AtomicU64>,     // Monitor updates lost because the cache worker was gone
+    graph_nodes: Arc<DashMap<u32, GraphNode>>, // Streams the PipeWire monitor tracks, by node id
 }
 
 impl Default for AudioCache {
@@ -45,84 +272,1710 @@ impl AudioCache {
     pub fn new() -> Self {
         Self {
             generation: AtomicU64::new(0),
+            writers: AtomicUsize::new(0),
             sinks: DashMap::new(),
             apps: DashMap::new(),
             routing_rules: DashMap::new(),
+            rule_learned: Arc::new(Notify::new()),
+            learned_rules: DashMap::new(),
             remembered_apps: DashMap::new(),
+            pins: DashMap::new(),
+            pins_changed: Arc::new(Notify::new()),
+            app_settings: DashMap::new(),
+            app_settings_changed: Arc::new(Notify::new()),
+            auto_muted: DashSet::new(),
+            recent_sinks: DashMap::new(),
+            recent_sinks_changed: Arc::new(Notify::new()),
+            physical_sinks: DashMap::new(),
+            sink_labels: DashMap::new(),
+            stream_volumes: DashMap::new(),
+            stream_corked: DashMap::new(),
+            sink_members: DashMap::new(),
+            sink_locks: DashMap::new(),
+            sink_discovered: DashMap::new(),
+            paused: AtomicBool::new(false),
+            auto_routing: AtomicBool::new(true),
+            follow_focus: AtomicBool::new(false),
+            focus_sink: None,
+            routing: Config::default().routing,
+            sink_order: HashMap::new(),
+            resumed: Arc::new(Notify::new()),
+            changes: watch::channel(0).0,
+            ready: watch::channel(false).0,
+            sink_events: broadcast::channel(SINK_EVENT_CAPACITY).0,
+            max_name_length: DEFAULT_MAX_NAME_LENGTH,
+            per_stream_labels: false,
+            persist_sink_labels: false,
+            labels_to_save: DashMap::new(),
+            labels_changed: Arc::new(Notify::new()),
+            configured_labels: HashMap::new(),
+            command_timeout: DEFAULT_COMMAND_TIMEOUT,
+            volume_ramp: Duration::ZERO,
+            loopback_suffixes: vec![DEFAULT_LOOPBACK_SUFFIX.to_string()],
+            route_refresh_delay: DEFAULT_ROUTE_REFRESH_DELAY,
+            default_volumes: DashMap::new(),
+            prior_mutes: Mutex::new(None),
+            server_version: OnceLock::new(),
+            default_sink: Mutex::new(None),
+            events: EventLog::default(),
+            latency: LatencyStats::default(),
+            dropped_updates: Arc::default(),
+            graph_nodes: Arc::default(),
+        }
+    }
+
+    /// Limit the length of app and stream names, capped at what shared memory can hold
+    #[allow(dead_code)] // The daemon passes the configured limit, test-daemon doesn't
+    pub fn with_max_name_length(mut self, max_name_length: usize) -> Self {
+        // Shared memory stores name lengths in a single byte
+        self.max_name_length = max_name_length.clamp(ELLIPSIS.len(), u8::MAX as usize);
+        self
+    }
+
+    /// Show apps by the `media.name` of their newest stream, see [`AppInfo::label`]
+    #[allow(dead_code)] // The daemon passes the configured setting, test-daemon doesn't
+    pub fn with_per_stream_labels(mut self, enabled: bool) -> Self {
+        self.per_stream_labels = enabled;
+        self
+    }
+
+    /// Keep the last `size` events for debugging, none if 0
+    #[allow(dead_code)] // The daemon passes the configured size, test-daemon doesn't
+    pub fn with_event_log_size(mut self, size: usize) -> Self {
+        self.events = EventLog::new(size);
+        self
+    }
+
+    /// Add an event to the log of recent events
+    pub fn record_event(&self, kind: EventKind) {
+        self.events.record(kind);
+    }
+
+    /// The last `count` logged events, oldest first
+    pub fn recent_events(&self, count: usize) -> Vec<Event> {
+        self.events.recent(count)
+    }
+
+    /// Add a sample to the latency histogram of `operation`
+    pub fn record_latency(&self, operation: Operation, elapsed: Duration) {
+        self.latency.record(operation, elapsed);
+    }
+
+    /// Latency percentiles of recent cache updates, snapshots and routes, by operation
+    pub fn latency_summaries(&self) -> BTreeMap<&'static str, LatencySummary> {
+        self.latency.summaries()
+    }
+
+    /// Labels the config shows sinks under in place of their node names
+    #[allow(dead_code)] // The daemon passes the configured labels, test-daemon doesn't
+    pub fn with_sink_labels(mut self, labels: impl IntoIterator<Item = (String, String)>) -> Self {
+        for (sink_name, label) in labels {
+            let label = self.limit_name(label);
+            self.sink_labels.insert(sink_name.clone(), label.clone());
+            self.configured_labels.insert(sink_name, label);
         }
+        self
+    }
+
+    /// Labels saved with the app mappings, which win over the configured ones
+    ///
+    /// They're saved again along with the labels set later, until set back to the
+    /// configured label.
+    #[allow(dead_code)] // The daemon passes the saved labels, test-daemon doesn't
+    pub fn with_saved_sink_labels(
+        self,
+        labels: impl IntoIterator<Item = (String, String)>,
+    ) -> Self {
+        for (sink_name, label) in labels {
+            let label = self.limit_name(label);
+            self.sink_labels.insert(sink_name.clone(), label.clone());
+            self.labels_to_save.insert(sink_name, label);
+        }
+        self
+    }
+
+    /// Positions of the virtual sinks in the panel, see [`crate::config::Config::sink_order`]
+    #[allow(dead_code)] // The daemon passes the configured order, test-daemon doesn't
+    pub fn with_sink_order(mut self, order: impl IntoIterator<Item = (String, u32)>) -> Self {
+        self.sink_order = order.into_iter().collect();
+        self
+    }
+
+    /// Position of a virtual sink in the panel, None for other sinks
+    pub fn sink_order(&self, sink_name: &str) -> Option<u32> {
+        self.sink_order.get(sink_name).copied()
+    }
+
+    /// Names of the cached virtual sinks in panel order, ties broken by name
+    #[allow(dead_code)] // Used by the D-Bus service
+    pub fn ordered_virtual_sinks(&self) -> Vec<String> {
+        let mut sinks: Vec<(u32, String)> = self
+            .sinks
+            .iter()
+            .filter_map(|entry| Some((self.sink_order(entry.key())?, entry.key().clone())))
+            .collect();
+        sinks.sort();
+        sinks.into_iter().map(|(_, name)| name).collect()
+    }
+
+    /// Save sink labels set at runtime along with the app mappings
+    #[allow(dead_code)] // The daemon passes the configured setting, test-daemon doesn't
+    pub fn with_persisted_sink_labels(mut self, enabled: bool) -> Self {
+        self.persist_sink_labels = enabled;
+        self
+    }
+
+    /// Labels set with [`Self::set_sink_label`] that are to be saved, by sink
+    #[allow(dead_code)] // Used by the daemon
+    pub fn labels_to_save(&self) -> BTreeMap<String, String> {
+        self.labels_to_save
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect()
+    }
+
+    /// Notified each time a label to save is set or cleared
+    #[allow(dead_code)] // Used by the daemon
+    pub fn labels_changed(&self) -> Arc<Notify> {
+        self.labels_changed.clone()
+    }
+
+    /// Sink the app with window focus is moved to while following focus
+    #[allow(dead_code)] // The daemon passes the configured sink, test-daemon doesn't
+    pub fn with_focus_sink(mut self, focus_sink: Option<String>) -> Self {
+        self.focus_sink = focus_sink;
+        self
+    }
+
+    /// Routing config that [`Self::test_rule`] resolves targets with
+    #[allow(dead_code)] // The daemon passes the configured routing, test-daemon doesn't
+    pub fn with_routing(mut self, routing: RoutingConfig) -> Self {
+        self.routing = routing;
+        self
+    }
+
+    /// Deadline for each external command run on behalf of the IPC and D-Bus handlers
+    #[allow(dead_code)] // The daemon passes the configured timeout, test-daemon doesn't
+    pub fn with_command_timeout(mut self, command_timeout: Duration) -> Self {
+        self.command_timeout = command_timeout;
+        self
+    }
+
+    pub fn command_timeout(&self) -> Duration {
+        self.command_timeout
+    }
+
+    /// Step loopback volume changes over `volume_ramp` instead of jumping, to avoid clicks
+    #[allow(dead_code)] // The daemon passes the configured duration, test-daemon doesn't
+    pub fn with_volume_ramp(mut self, volume_ramp: Duration) -> Self {
+        self.volume_ramp = volume_ramp;
+        self
+    }
+
+    pub fn volume_ramp(&self) -> Duration {
+        self.volume_ramp
+    }
+
+    /// Look for a sink's loopbacks by these suffixes rather than only `_to_Speaker`
+    #[allow(dead_code)] // The daemon passes the configured suffixes, test-daemon doesn't
+    pub fn with_loopback_suffixes(mut self, suffixes: Vec<String>) -> Self {
+        self.loopback_suffixes = suffixes;
+        self
+    }
+
+    /// Node names of the streams that play `sink_name` to an output device
+    pub fn loopback_node_names(&self, sink_name: &str) -> Vec<String> {
+        loopback_node_names(sink_name, &self.loopback_suffixes)
+    }
+
+    /// Node name to give the loopback the daemon creates for `sink_name`, by the first suffix
+    pub fn loopback_name(&self, sink_name: &str) -> String {
+        let suffix = self.loopback_suffixes.first().map_or(DEFAULT_LOOPBACK_SUFFIX, String::as_str);
+        format!("{sink_name}{suffix}")
+    }
+
+    /// Refresh D-Bus clients again `delay` after routing an app, for PipeWire to settle
+    ///
+    /// The controller already checks where the streams ended up before a route returns,
+    /// so zero skips the extra refresh.
+    #[allow(dead_code)] // The daemon passes the configured delay, test-daemon doesn't
+    pub fn with_route_refresh_delay(mut self, delay: Duration) -> Self {
+        self.route_refresh_delay = delay;
+        self
+    }
+
+    #[allow(dead_code)] // Used by the D-Bus service
+    pub fn route_refresh_delay(&self) -> Duration {
+        self.route_refresh_delay
+    }
+
+    /// Volumes sinks are reset to, for sinks that don't use the full 1.0
+    #[allow(dead_code)] // The daemon passes the configured defaults, test-daemon doesn't
+    pub fn with_default_volumes(mut self, default_volumes: HashMap<String, f32>) -> Self {
+        self.default_volumes = default_volumes.into_iter().collect();
+        self
+    }
+
+    /// Volume a reset puts the sink back to
+    pub fn default_volume(&self, sink_name: &str) -> f32 {
+        self.default_volumes.get(sink_name).map_or(1.0, |volume| *volume).clamp(0.0, 1.0)
+    }
+
+    /// `name` as the cache stores it, truncated to the configured length
+    pub fn limit_name(&self, name: String) -> String {
+        if name.len() <= self.max_name_length {
+            return name;
+        }
+        let truncated = truncate_name(&name, self.max_name_length);
+        warn!("Truncated {} byte name to {:?}", name.len(), truncated);
+        truncated
+    }
+
+    /// Stop periodic work such as shared memory writes until `resume` is called
+    #[allow(dead_code)] // Used by the IPC and D-Bus handlers
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Restart periodic work, waking the task waiting on `resume_signal`
+    #[allow(dead_code)] // Used by the IPC and D-Bus handlers
+    pub fn resume(&self) {
+        if self.paused.swap(false, Ordering::SeqCst) {
+            self.resumed.notify_one();
+        }
+    }
+
+    #[allow(dead_code)] // Used by the shared memory writer
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Turn automatic routing of new streams on or off at runtime
+    pub fn set_auto_routing(&self, enabled: bool) {
+        self.auto_routing.store(enabled, Ordering::SeqCst);
+    }
+
+    #[allow(dead_code)] // Used by the PipeWire monitor
+    pub fn auto_routing_enabled(&self) -> bool {
+        self.auto_routing.load(Ordering::SeqCst)
+    }
+
+    #[allow(dead_code)] // Used by the focus follower and IPC
+    pub fn focus_sink(&self) -> Option<&str> {
+        self.focus_sink.as_deref()
+    }
+
+    /// Turn following window focus on or off at runtime
+    #[allow(dead_code)] // Used by IPC
+    pub fn set_follow_focus(&self, enabled: bool) {
+        self.follow_focus.store(enabled, Ordering::SeqCst);
+    }
+
+    #[allow(dead_code)] // Used by the focus follower
+    pub fn follow_focus_enabled(&self) -> bool {
+        self.follow_focus.load(Ordering::SeqCst)
+    }
+
+    /// Start out with pins restored from the app mappings
+    #[allow(dead_code)] // The daemon passes the saved pins, test-daemon doesn't
+    pub fn with_pins(self, pins: impl IntoIterator<Item = (String, String)>) -> Self {
+        for (app_name, sink_name) in pins {
+            self.pins.insert(app_name, sink_name);
+        }
+        self
+    }
+
+    /// Route `app_name` to `sink_name` whenever it appears, until [`Self::unpin_app`]
+    ///
+    /// Unlike a routing rule a pin outlives the app being cleaned up, and it applies
+    /// even while auto-routing is disabled.
+    pub fn pin_app(&self, app_name: &str, sink_name: &str) {
+        self.pins.insert(app_name.to_string(), sink_name.to_string());
+        self.pins_changed.notify_one();
+        self.increment_generation();
+    }
+
+    /// Remove an app's pin, returning false if it had none
+    pub fn unpin_app(&self, app_name: &str) -> bool {
+        let removed = self.pins.remove(app_name).is_some();
+        if removed {
+            self.pins_changed.notify_one();
+            self.increment_generation();
+        }
+        removed
+    }
+
+    /// Every pin, by app name
+    #[allow(dead_code)] // Used by the daemon
+    pub fn pins(&self) -> BTreeMap<String, String> {
+        self.pins.iter().map(|entry| (entry.key().clone(), entry.value().clone())).collect()
+    }
+
+    /// Notified each time a pin is added or removed, so they can be saved
+    #[allow(dead_code)] // Used by the daemon
+    pub fn pins_changed(&self) -> Arc<Notify> {
+        self.pins_changed.clone()
+    }
+
+    /// Start out with app volumes and mutes restored from the app mappings
+    #[allow(dead_code)] // The daemon passes the saved settings, test-daemon doesn't
+    pub fn with_app_settings(
+        self,
+        settings: impl IntoIterator<Item = (String, AppSettings)>,
+    ) -> Self {
+        for (app_name, app_settings) in settings {
+            self.app_settings.insert(app_name, app_settings);
+        }
+        self
+    }
+
+    /// Remember the volume set for an app, to give its streams when it comes back
+    pub fn record_app_volume(&self, app_name: &str, volume: f32) {
+        let mut settings = self.app_settings.entry(app_name.to_string()).or_default();
+        if settings.volume != Some(volume) {
+            settings.volume = Some(volume);
+            self.app_settings_changed.notify_one();
+        }
+    }
+
+    /// Remember whether an app was muted, to mute its streams when it comes back
+    pub fn record_app_mute(&self, app_name: &str, muted: bool) {
+        // A mute set by hand replaces an auto-mute, which then leaves the app alone
+        self.auto_muted.remove(app_name);
+        let mut settings = self.app_settings.entry(app_name.to_string()).or_default();
+        if settings.muted != Some(muted) {
+            settings.muted = Some(muted);
+            self.app_settings_changed.notify_one();
+        }
+    }
+
+    /// Mark an idle app as auto-muted, unless it is muted already
+    ///
+    /// Returns false for an app that isn't cached or is muted, by hand or by an earlier
+    /// auto-mute, so [`Self::end_auto_mute`] never unmutes what the user muted.
+    #[allow(dead_code)] // Used by the PipeWire monitor
+    pub fn begin_auto_mute(&self, app_name: &str) -> bool {
+        if !self.apps.get(app_name).is_some_and(|app| !app.muted) {
+            return false;
+        }
+        self.auto_muted.insert(app_name.to_string())
+    }
+
+    /// Clear an app's auto-mute, returning whether it had one
+    #[allow(dead_code)] // Used by the PipeWire monitor
+    pub fn end_auto_mute(&self, app_name: &str) -> bool {
+        self.auto_muted.remove(app_name).is_some()
+    }
+
+    /// Whether an app is muted by `auto_mute_on_inactive`
+    pub fn is_auto_muted(&self, app_name: &str) -> bool {
+        self.auto_muted.contains(app_name)
+    }
+
+    /// Volume and mute saved for an app, if either was ever set
+    pub fn saved_app_settings(&self, app_name: &str) -> Option<AppSettings> {
+        self.app_settings.get(app_name).map(|settings| *settings)
+    }
+
+    /// Every app's saved volume and mute, by app name
+    #[allow(dead_code)] // Used by the daemon
+    pub fn app_settings(&self) -> BTreeMap<String, AppSettings> {
+        self.app_settings.iter().map(|entry| (entry.key().clone(), *entry.value())).collect()
+    }
+
+    /// Notified each time an app's saved volume or mute changes, so they can be saved
+    #[allow(dead_code)] // Used by the daemon
+    pub fn app_settings_changed(&self) -> Arc<Notify> {
+        self.app_settings_changed.clone()
+    }
+
+    /// Sink an app is pinned to, by its name or else its binary name
+    fn pinned_sink(&self, app_name: &str) -> Option<String> {
+        if let Some(sink_name) = self.pins.get(app_name) {
+            return Some(sink_name.clone());
+        }
+        let binary_name = self.apps.get(app_name).map(|app| app.binary_name.clone())?;
+        self.pins.get(&binary_name).map(|sink_name| sink_name.clone())
+    }
+
+    /// Sink a new stream of `app_name` should be moved to, if any
+    ///
+    /// A pinned app always goes to its pinned sink. Otherwise resolves the target
+    /// with [`Self::resolve_target`]; an app that had no rule of its own then has the
+    /// result remembered as its rule. Returns `None` while auto-routing is disabled
+    /// or no rule applies.
+    #[allow(dead_code)] // Used by the PipeWire monitor
+    pub fn auto_route_target(&self, app_name: &str, routing: &RoutingConfig) -> Option<String> {
+        if let Some(sink_name) = self.pinned_sink(app_name) {
+            return Some(sink_name);
+        }
+        if !self.auto_routing_enabled() {
+            return None;
+        }
+
+        if let Some((_, sink_name)) = self.app_rule(app_name, routing) {
+            return Some(sink_name);
+        }
+        let sink_name = self.resolve_target(app_name, routing)?;
+        self.routing_rules.insert(app_name.to_string(), sink_name.clone());
+        self.learned_rules.insert(app_name.to_string(), sink_name.clone());
+        self.rule_learned.notify_one();
+        Some(sink_name)
+    }
+
+    /// Notified each time [`Self::auto_route_target`] remembers a rule, so it can be saved
+    #[allow(dead_code)] // Used by the daemon
+    pub fn rule_learned(&self) -> Arc<Notify> {
+        self.rule_learned.clone()
+    }
+
+    /// The (app, sink) rules remembered since the last call, which are to be saved
+    #[allow(dead_code)] // Used by the daemon
+    pub fn take_learned_rules(&self) -> Vec<(String, String)> {
+        let apps: Vec<String> = self.learned_rules.iter().map(|rule| rule.key().clone()).collect();
+        apps.into_iter().filter_map(|app| self.learned_rules.remove(&app)).collect()
+    }
+
+    /// Sink an app's streams belong on, taking the most specific matching rule
+    ///
+    /// Precedence, highest first:
+    /// 0. a pin for the app's name or binary name
+    /// 1. a routing rule for the app's display name
+    /// 2. a routing rule for its binary name
+    /// 3. a routing rule for its window title
+    /// 4. with `fuzzy_matching`, the longest rule that is a prefix of either name
+    /// 5. a role rule for the media role its streams declare
+    /// 6. the default sink, if `route_unmatched_to_default` is set
+    ///
+    /// Returns `None` when nothing matches, leaving the app where PipeWire put it.
+    #[allow(dead_code)] // Used by the PipeWire monitor
+    pub fn resolve_target(&self, app_name: &str, routing: &RoutingConfig) -> Option<String> {
+        self.matching_rule(app_name, routing).map(|(_, sink_name)| sink_name)
+    }
+
+    /// Which rule [`Self::resolve_target`] would take for `app_name` with the
+    /// configured routing, and the sink it names, moving nothing
+    pub fn test_rule(&self, app_name: &str) -> RuleTest {
+        let (rule, sink) = self.matching_rule(app_name, &self.routing).unzip();
+        RuleTest { app_name: app_name.to_string(), rule, sink }
+    }
+
+    /// [`Self::resolve_target`] along with the kind of rule that matched
+    fn matching_rule(
+        &self,
+        app_name: &str,
+        routing: &RoutingConfig,
+    ) -> Option<(RuleMatch, String)> {
+        if let Some(sink_name) = self.pinned_sink(app_name) {
+            return Some((RuleMatch::Pinned, sink_name));
+        }
+        if let Some(matched) = self.app_rule(app_name, routing) {
+            return Some(matched);
+        }
+
+        let media_role = self.apps.get(app_name).and_then(|app| app.media_role.clone());
+        if let Some(sink_name) = media_role.as_deref().and_then(|role| routing.role_sink(role)) {
+            return Some((RuleMatch::Role, sink_name.to_string()));
+        }
+
+        Some(routing.default_sink.clone())
+            .filter(|sink_name| routing.route_unmatched_to_default && !sink_name.is_empty())
+            .map(|sink_name| (RuleMatch::Default, sink_name))
+    }
+
+    /// Routing rule for the app's display name, else its binary name, else its window
+    /// title, else a fuzzy match
+    fn app_rule(&self, app_name: &str, routing: &RoutingConfig) -> Option<(RuleMatch, String)> {
+        if let Some(sink_name) = self.routing_rules.get(app_name) {
+            return Some((RuleMatch::Exact, sink_name.clone()));
+        }
+
+        let (binary_name, window_title) = self
+            .apps
+            .get(app_name)
+            .map(|app| (app.binary_name.clone(), app.window_title.clone()))
+            .unzip();
+        if let Some(sink_name) = binary_name.as_ref().and_then(|name| self.routing_rules.get(name))
+        {
+            return Some((RuleMatch::Binary, sink_name.clone()));
+        }
+        // Wine and Proton games all run as wine64-preloader, their window tells them apart
+        if let Some(sink_name) =
+            window_title.flatten().and_then(|title| self.routing_rules.get(&title))
+        {
+            return Some((RuleMatch::Window, sink_name.clone()));
+        }
+
+        if !routing.fuzzy_matching {
+            return None;
+        }
+        let names: Vec<String> = std::iter::once(app_name)
+            .chain(binary_name.as_deref())
+            .map(str::to_lowercase)
+            .collect();
+        self.routing_rules
+            .iter()
+            .filter(|rule| rule.key().chars().count() >= FUZZY_MIN_RULE_LENGTH)
+            .filter(|rule| {
+                let key = rule.key().to_lowercase();
+                names.iter().any(|name| name.starts_with(&key))
+            })
+            // Longest key is the most specific; ties go to the first key alphabetically
+            .max_by(|a, b| a.key().len().cmp(&b.key().len()).then_with(|| b.key().cmp(a.key())))
+            .map(|rule| (RuleMatch::Fuzzy, rule.value().clone()))
+    }
+
+    /// Notified on `resume`, so a waiter doesn't need to hold the cache lock
+    #[allow(dead_code)] // Used by the shared memory writer
+    pub fn resume_signal(&self) -> Arc<Notify> {
+        self.resumed.clone()
+    }
+
+    /// Lock to hold while changing a sink's volume or mute
+    ///
+    /// Keeps concurrent IPC and D-Bus requests for one sink from interleaving, so the
+    /// cache ends up with whichever value was applied last.
+    pub fn sink_lock(&self, sink_name: &str) -> Arc<AsyncMutex<()>> {
+        self.sink_locks.entry(sink_name.to_string()).or_default().clone()
+    }
+
+    /// Record the PipeWire server version; only the first call has any effect
+    #[allow(dead_code)] // Set by the daemon at startup, never by test-daemon
+    pub fn set_server_version(&self, version: String) {
+        let _ = self.server_version.set(version);
+    }
+
+    pub fn server_version(&self) -> Option<&str> {
+        self.server_version.get().map(String::as_str)
+    }
+
+    /// Record the system's default sink, bumping the generation if it changed
+    ///
+    /// Returns whether it changed.
+    pub fn set_default_sink(&self, sink_name: &str) -> bool {
+        {
+            let mut default_sink = self.default_sink.lock().unwrap_or_else(|e| e.into_inner());
+            if default_sink.as_deref() == Some(sink_name) {
+                return false;
+            }
+            *default_sink = Some(sink_name.to_string());
+        }
+        self.increment_generation();
+        true
+    }
+
+    /// The system's default sink, None until it was first queried
+    pub fn default_sink(&self) -> Option<String> {
+        self.default_sink.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Record a hardware sink, announcing it if it wasn't known yet
+    #[allow(dead_code)] // Used by the PipeWire monitor
+    pub fn add_physical_sink(&self, sink_name: &str, display_name: &str) -> bool {
+        let added =
+            self.physical_sinks.insert(sink_name.to_string(), display_name.to_string()).is_none();
+        if added {
+            // Nobody listening is fine, the sink is still cached
+            let _ = self.sink_events.send(SinkEvent::Added {
+                sink_name: sink_name.to_string(),
+                display_name: display_name.to_string(),
+            });
+        }
+        added
+    }
+
+    /// Forget a hardware sink, announcing its removal if it was known
+    #[allow(dead_code)] // Used by the PipeWire monitor
+    pub fn remove_physical_sink(&self, sink_name: &str) -> bool {
+        let removed = self.physical_sinks.remove(sink_name).is_some();
+        if removed {
+            let _ = self.sink_events.send(SinkEvent::Removed { sink_name: sink_name.to_string() });
+        }
+        removed
+    }
+
+    /// Announce a sink's current volume and mute state to subscribers
+    fn announce_sink_state(&self, sink_name: &str, volume: f32, muted: bool, present: bool) {
+        let _ = self.sink_events.send(SinkEvent::StateChanged {
+            sink_name: sink_name.to_string(),
+            volume,
+            muted,
+            present,
+        });
+    }
+
+    /// Receive an event each time a hardware sink is added or removed, or any
+    /// sink's state changes
+    #[allow(dead_code)] // Used by the D-Bus service
+    pub fn subscribe_sink_events(&self) -> broadcast::Receiver<SinkEvent> {
+        self.sink_events.subscribe()
     }
 
     pub fn increment_generation(&self) {
-        self.generation.fetch_add(1, Ordering::SeqCst);
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        // Concurrent bumps may arrive out of order, only ever publish the newest
+        self.changes.send_if_modified(|latest| {
+            let newer = generation > *latest;
+            if newer {
+                *latest = generation;
+            }
+            newer
+        });
+    }
+
+    /// Receive the latest generation each time the cache changes
+    #[allow(dead_code)] // Used by the D-Bus service
+    pub fn subscribe_changes(&self) -> watch::Receiver<u64> {
+        self.changes.subscribe()
+    }
+
+    /// Record that the first full scan of PipeWire is in the cache
+    ///
+    /// Bumps the generation so clients refresh. Returns false if the cache was
+    /// already ready, in which case nothing happens.
+    #[allow(dead_code)] // Used by the PipeWire monitor
+    pub fn mark_ready(&self) -> bool {
+        if !self.ready.send_if_modified(|ready| !std::mem::replace(ready, true)) {
+            return false;
+        }
+        self.increment_generation();
+        true
+    }
+
+    #[allow(dead_code)] // Used by the D-Bus service
+    pub fn is_ready(&self) -> bool {
+        *self.ready.borrow()
+    }
+
+    /// Receive `true` once the first full scan of PipeWire is in the cache
+    #[allow(dead_code)] // Used by the D-Bus service
+    pub fn subscribe_ready(&self) -> watch::Receiver<bool> {
+        self.ready.subscribe()
+    }
+
+    /// Counter the PipeWire monitor bumps for each update it couldn't deliver
+    #[allow(dead_code)] // Used by the PipeWire monitor
+    pub fn dropped_update_counter(&self) -> Arc<AtomicU64> {
+        self.dropped_updates.clone()
+    }
+
+    /// Streams the PipeWire monitor tracks, which it keeps up to date for DUMP_GRAPH
+    pub fn graph_nodes(&self) -> Arc<DashMap<u32, GraphNode>> {
+        self.graph_nodes.clone()
+    }
+
+    /// Monitor updates lost so far, nonzero once the cache worker has stopped
+    #[allow(dead_code)] // Used by IPC
+    pub fn dropped_updates(&self) -> u64 {
+        self.dropped_updates.load(Ordering::Relaxed)
     }
 
     pub fn get_generation(&self) -> u64 {
         self.generation.load(Ordering::SeqCst)
     }
 
+    /// Mark a change to sinks or apps as in progress until the guard is dropped
+    ///
+    /// Taken before the change and held past its generation bump, so
+    /// [`Self::read_consistent`] never sees the change without the bump.
+    fn begin_write(&self) -> WriteGuard<'_> {
+        self.writers.fetch_add(1, Ordering::SeqCst);
+        WriteGuard(&self.writers)
+    }
+
+    /// Run `read` against sinks and apps as of one generation, returned with it
+    ///
+    /// Changes only need a shared lock on the cache, so this is a seqlock: it waits
+    /// out changes in progress and reads again if one landed meanwhile. Changes that
+    /// don't bump the generation themselves are made under the exclusive lock.
+    pub fn read_consistent<T>(&self, read: impl Fn(&Self) -> T) -> (u64, T) {
+        loop {
+            let generation = self.get_generation();
+            if self.writers.load(Ordering::SeqCst) == 0 {
+                let value = read(self);
+                if self.writers.load(Ordering::SeqCst) == 0 && self.get_generation() == generation {
+                    return (generation, value);
+                }
+            }
+            std::thread::yield_now();
+        }
+    }
+
+    /// The name pactl knows the cached sink `sink_name` by
+    pub fn pactl_sink_name(&self, sink_name: &str) -> String {
+        self.sinks
+            .get(sink_name)
+            .map_or_else(|| sink_name.to_string(), |sink| sink.pactl_name().to_string())
+    }
+
+    /// The cache's name for the sink pactl calls `node_name`, or `node_name` itself
+    pub fn sink_for_node(&self, node_name: &str) -> String {
+        self.sinks
+            .iter()
+            .find(|sink| sink.pactl_name() == node_name)
+            .map_or_else(|| node_name.to_string(), |sink| sink.key().clone())
+    }
+
+    /// Store a sink's latest state
+    ///
+    /// Re-storing an identical sink, as the periodic volume poll mostly does, leaves
+    /// the generation alone so clients aren't told about a change that didn't happen.
     pub fn update_sink(&self, name: String, info: SinkInfo) {
-        self.sinks.insert(name, info);
+        // A new PipeWire id means the sink was recreated, e.g. by a module reload
+        let recreated =
+            !self.sink_discovered.get(&name).is_some_and(|entry| entry.0 == info.pipewire_id);
+        if recreated {
+            self.sink_discovered.insert(name.clone(), (info.pipewire_id, Instant::now()));
+        }
+        let (unchanged, state_changed) = match self.sinks.get(&name) {
+            Some(old) => (*old == info, old.volume != info.volume || old.muted != info.muted),
+            None => {
+                self.record_event(EventKind::SinkAdded { sink: name.clone() });
+                (false, true)
+            }
+        };
+        if unchanged {
+            return;
+        }
+        let (volume, muted) = (info.volume, info.muted);
+        let _write = self.begin_write();
+        self.sinks.insert(name.clone(), info);
+        self.increment_generation();
+        if state_changed {
+            self.announce_sink_state(&name, volume, muted, true);
+        }
+    }
+
+    /// Forget a sink that went away
+    ///
+    /// Returns false if the sink is not cached.
+    #[allow(dead_code)] // Used by the PipeWire monitor
+    pub fn remove_sink(&self, name: &str) -> bool {
+        let _write = self.begin_write();
+        let Some((_, sink)) = self.sinks.remove(name) else {
+            return false;
+        };
+        self.record_event(EventKind::SinkRemoved { sink: name.to_string() });
+        self.increment_generation();
+        self.announce_sink_state(name, sink.volume, sink.muted, false);
+        true
+    }
+
+    /// How long each sink has existed under its current PipeWire id
+    pub fn sink_uptimes(&self) -> HashMap<String, Duration> {
+        self.sink_discovered.iter().map(|entry| (entry.key().clone(), entry.1.elapsed())).collect()
+    }
+
+    /// Label of every sink that has one, by sink name
+    #[allow(dead_code)] // Used by the D-Bus service
+    pub fn sink_labels(&self) -> HashMap<String, String> {
+        self.sink_labels.iter().map(|entry| (entry.key().clone(), entry.value().clone())).collect()
+    }
+
+    /// Restore the sinks apps were recently routed to, newest first
+    #[allow(dead_code)] // The daemon passes the saved app mappings, test-daemon doesn't
+    pub fn with_recent_sinks(
+        self,
+        recent: impl IntoIterator<Item = (String, Vec<String>)>,
+    ) -> Self {
+        for (app_name, sinks) in recent {
+            let sinks: VecDeque<String> = sinks.into_iter().take(RECENT_SINKS_LEN).collect();
+            if !sinks.is_empty() {
+                self.recent_sinks.insert(app_name, sinks);
+            }
+        }
+        self
+    }
+
+    /// Sinks an app was last routed to, newest first and without repeats
+    #[allow(dead_code)] // Used by the D-Bus service
+    pub fn recent_sinks(&self, app_name: &str) -> Vec<String> {
+        self.recent_sinks
+            .get(app_name)
+            .map(|sinks| sinks.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Note a successful route, moving `sink_name` to the front of the app's recent sinks
+    ///
+    /// Only the newest [`RECENT_SINKS_LEN`] sinks are kept.
+    pub fn record_recent_sink(&self, app_name: &str, sink_name: &str) {
+        let mut sinks = self.recent_sinks.entry(app_name.to_string()).or_default();
+        if sinks.front().map(String::as_str) == Some(sink_name) {
+            return;
+        }
+        sinks.retain(|sink| sink != sink_name);
+        sinks.push_front(sink_name.to_string());
+        sinks.truncate(RECENT_SINKS_LEN);
+        self.recent_sinks_changed.notify_one();
+    }
+
+    /// Every app's recent sinks, newest first, by app name
+    #[allow(dead_code)] // Used by the daemon
+    pub fn all_recent_sinks(&self) -> BTreeMap<String, Vec<String>> {
+        self.recent_sinks
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().iter().cloned().collect()))
+            .collect()
+    }
+
+    /// Notified each time a route changes an app's recent sinks, or they're dropped
+    /// along with the app, so they can be saved
+    #[allow(dead_code)] // Used by the daemon
+    pub fn recent_sinks_changed(&self) -> Arc<Notify> {
+        self.recent_sinks_changed.clone()
+    }
+
+    /// Label to show for a sink, its name unless one was configured or set
+    pub fn sink_label(&self, sink_name: &str) -> String {
+        self.sink_labels.get(sink_name).map_or_else(|| sink_name.to_string(), |l| l.clone())
+    }
+
+    /// Show a sink under `label` without renaming its PipeWire node
+    ///
+    /// Routing keeps using the sink's name. Returns false if the sink already had
+    /// this label, otherwise bumps the generation. With `persist_sink_labels` the
+    /// daemon saves the label along with the app mappings, or drops the saved one
+    /// once the sink is given its configured label again.
+    pub fn set_sink_label(&self, sink_name: &str, label: String) -> bool {
+        let label = self.limit_name(label);
+        if self.sink_labels.get(sink_name).is_some_and(|current| *current == label) {
+            return false;
+        }
+        if self.persist_sink_labels {
+            if self.configured_labels.get(sink_name) == Some(&label) {
+                self.labels_to_save.remove(sink_name);
+            } else {
+                self.labels_to_save.insert(sink_name.to_string(), label.clone());
+            }
+            self.labels_changed.notify_one();
+        }
+        self.sink_labels.insert(sink_name.to_string(), label);
+        self.increment_generation();
+        true
+    }
+
+    /// Record a volume that was applied to a sink, along with the percentage sent to PipeWire
+    ///
+    /// Returns false if the sink is not cached.
+    pub fn record_applied_volume(
+        &self,
+        sink_name: &str,
+        volume: f32,
+        applied_percent: u32,
+    ) -> bool {
+        let _write = self.begin_write();
+        let (changed, muted) = match self.sinks.get_mut(sink_name) {
+            Some(mut sink) => {
+                let changed = sink.volume != volume;
+                sink.volume = volume;
+                sink.applied_percent = applied_percent;
+                (changed, sink.muted)
+            }
+            None => return false,
+        };
+        self.increment_generation();
+        if changed {
+            self.announce_sink_state(sink_name, volume, muted, true);
+        }
+        true
+    }
+
+    /// Record a mute state that was applied to a sink
+    ///
+    /// Returns false if the sink is not cached.
+    pub fn record_applied_mute(&self, sink_name: &str, muted: bool) -> bool {
+        self.apply_mutes(&[(sink_name.to_string(), muted)]) == 1
+    }
+
+    /// Mute every sink except `target`, which is unmuted
+    ///
+    /// The mute states from before the first solo or mute-all are kept, so soloing
+    /// another sink while one is already soloed still restores the original states
+    /// on `unsolo`. Returns the resulting mute state of every sink, or None if
+    /// `target` is unknown.
+    pub fn solo_sink(&self, target: &str) -> Option<Vec<(String, bool)>> {
+        if !self.sinks.contains_key(target) {
+            return None;
+        }
+        Some(self.mute_all_but(Some(target)))
+    }
+
+    /// End a solo, restoring the mute states from before it started
+    ///
+    /// Returns the restored mute states, empty if no sink was soloed.
+    pub fn unsolo(&self) -> Vec<(String, bool)> {
+        self.restore_prior_mutes().unwrap_or_default()
+    }
+
+    /// Mute every sink, keeping the states from before for `unmute_all`
+    ///
+    /// As with `solo_sink`, states stashed by an earlier solo or mute-all are kept,
+    /// so `unmute_all` during a solo goes back to how things were before the solo.
+    /// Returns the resulting mute state of every sink.
+    pub fn mute_all(&self) -> Vec<(String, bool)> {
+        self.mute_all_but(None)
+    }
+
+    /// Undo `mute_all` or a solo, restoring the mute states from before it
+    ///
+    /// With nothing to restore every sink is unmuted. Returns the resulting mute
+    /// state of every sink changed.
+    pub fn unmute_all(&self) -> Vec<(String, bool)> {
+        self.restore_prior_mutes().unwrap_or_else(|| {
+            let mut changes: Vec<(String, bool)> =
+                self.sinks.iter().map(|entry| (entry.key().clone(), false)).collect();
+            changes.sort();
+            self.apply_mutes(&changes);
+            changes
+        })
+    }
+
+    /// Mute every sink except `unmuted`, stashing the current states unless some are
+    fn mute_all_but(&self, unmuted: Option<&str>) -> Vec<(String, bool)> {
+        let current: HashMap<String, bool> =
+            self.sinks.iter().map(|entry| (entry.key().clone(), entry.value().muted)).collect();
+        let mut prior_mutes = self.prior_mutes.lock().unwrap_or_else(|e| e.into_inner());
+        if prior_mutes.is_none() {
+            *prior_mutes = Some(current.clone());
+        }
+
+        let mut changes: Vec<(String, bool)> = current
+            .into_keys()
+            .map(|name| {
+                let muted = unmuted != Some(name.as_str());
+                (name, muted)
+            })
+            .collect();
+        changes.sort();
+        self.apply_mutes(&changes);
+        changes
+    }
+
+    /// Apply and clear the stashed mute states, None if nothing was stashed
+    fn restore_prior_mutes(&self) -> Option<Vec<(String, bool)>> {
+        let prior_mutes = self.prior_mutes.lock().unwrap_or_else(|e| e.into_inner()).take()?;
+
+        let mut changes: Vec<(String, bool)> =
+            prior_mutes.into_iter().filter(|(name, _)| self.sinks.contains_key(name)).collect();
+        changes.sort();
+        self.apply_mutes(&changes);
+        Some(changes)
+    }
+
+    /// Set the mute state of each listed sink, returning how many were cached
+    fn apply_mutes(&self, mutes: &[(String, bool)]) -> usize {
+        let mut found = 0;
+        let mut changed = Vec::new();
+        let _write = self.begin_write();
+        for (name, muted) in mutes {
+            if let Some(mut sink) = self.sinks.get_mut(name) {
+                found += 1;
+                if sink.muted != *muted {
+                    sink.muted = *muted;
+                    changed.push((name, sink.volume, *muted));
+                }
+            }
+        }
         self.increment_generation();
+        for (name, volume, muted) in changed {
+            self.announce_sink_state(name, volume, muted, true);
+        }
+        found
     }
 
-    pub fn update_app(&self, name: String, info: AppInfo) {
+    /// Store an app's latest state
+    ///
+    /// Re-storing an identical app, as repeated stream events often do, leaves the
+    /// generation alone.
+    pub fn update_app(&self, name: String, mut info: AppInfo) {
+        // Keep oversized names from reaching D-Bus and shared memory
+        let name = self.limit_name(name);
+        info.display_name = self.limit_name(info.display_name);
+        info.stream_names = info.stream_names.into_iter().map(|s| self.limit_name(s)).collect();
+        info.stream_labels = info
+            .stream_labels
+            .into_iter()
+            .map(|(id, label)| (id, self.limit_name(label)))
+            .collect();
+
         // Remember the app's sink assignment
         if info.active {
             self.remembered_apps.insert(name.clone(), info.current_sink.clone());
         }
 
-        self.apps.insert(name, info);
+        if self.apps.get(&name).is_some_and(|old| *old == info) {
+            return;
+        }
+
+        let new_sink = info.current_sink.clone();
+        let _write = self.begin_write();
+        if let Some(old) = self.apps.insert(name.clone(), info) {
+            self.unindex_app(&name, &old.current_sink);
+        }
+        self.index_app(&name, &new_sink);
         self.increment_generation();
     }
 
+    /// Move a stream to `app_key` after its app changed the name it reports
+    ///
+    /// Browsers and Electron apps rename their streams once they are set up, which would
+    /// otherwise leave the stream's id with the app it started out as. If it was the only
+    /// stream of that app, the app itself is renamed to `app_key`, keeping its sink,
+    /// volume and recent sinks, or merged into `app_key` if that app already exists.
+    /// Otherwise only the stream moves. Returns the app that had the stream, if any other
+    /// app than `app_key` did.
+    #[allow(dead_code)] // Used by the PipeWire monitor
+    pub fn reassign_stream(&self, sink_input_id: u32, app_key: &str) -> Option<String> {
+        let app_key = self.limit_name(app_key.to_string());
+        let (owner, only_stream) = self.apps.iter().find_map(|entry| {
+            let app = entry.value();
+            (*entry.key() != app_key && app.sink_input_ids.contains(&sink_input_id))
+                .then(|| (entry.key().clone(), app.sink_input_ids == [sink_input_id]))
+        })?;
+        let _write = self.begin_write();
+
+        if !only_stream {
+            if let Some(mut app) = self.apps.get_mut(&owner) {
+                app.sink_input_ids.retain(|id| *id != sink_input_id);
+                app.stream_labels.retain(|(id, _)| *id != sink_input_id);
+            }
+            self.refresh_app_streams(&owner);
+            self.increment_generation();
+            return Some(owner);
+        }
+
+        let (_, old) = self.apps.remove(&owner)?;
+        self.unindex_app(&owner, &old.current_sink);
+        if let Some((_, recent)) = self.recent_sinks.remove(&owner) {
+            self.recent_sinks.entry(app_key.clone()).or_insert(recent);
+            self.recent_sinks_changed.notify_one();
+        }
+        let merged = match self.apps.get_mut(&app_key) {
+            Some(mut app) => {
+                app.sink_input_ids.extend(old.sink_input_ids.iter().copied());
+                app.stream_labels.extend(old.stream_labels.iter().cloned());
+                for stream_name in &old.stream_names {
+                    if !app.stream_names.contains(stream_name) {
+                        app.stream_names.push(stream_name.clone());
+                    }
+                }
+                app.active = true;
+                app.inactive_since = None;
+                true
+            }
+            None => false,
+        };
+        if merged {
+            self.refresh_app_streams(&app_key);
+            self.increment_generation();
+        } else {
+            self.update_app(app_key, old);
+        }
+        Some(owner)
+    }
+
+    /// Add a stream to its app, creating the app if it isn't cached yet
+    ///
+    /// A stream another app had is moved over first, see [`Self::reassign_stream`].
+    /// Returns the key the app is cached under, which may be truncated, and whether
+    /// the app was playing before the stream joined it.
+    pub fn add_stream(&self, stream: NewStream) -> (String, bool) {
+        let NewStream {
+            app_key,
+            display_name,
+            binary_name,
+            stream_name,
+            sink_input_id,
+            current_sink,
+            media_role,
+            stream_label,
+            stream_volume,
+            corked,
+            window_title,
+        } = stream;
+        let app_key = self.limit_name(app_key);
+        let _write = self.begin_write();
+
+        // The stream's app reported a new name, move the stream rather than
+        // leaving its id with an app of the old name
+        if let Some(previous_key) = self.reassign_stream(sink_input_id, &app_key) {
+            info!("Stream {} of {} now belongs to {}", sink_input_id, previous_key, app_key);
+        }
+        let known_stream =
+            self.apps.get(&app_key).is_some_and(|app| app.sink_input_ids.contains(&sink_input_id));
+        if !known_stream {
+            self.record_event(EventKind::StreamAdded { app: app_key.clone(), sink_input_id });
+        }
+        let was_playing = self.apps.get(&app_key).is_some_and(|app| app.playing);
+        if let Some(mut app) = self.apps.get_mut(&app_key) {
+            if !app.sink_input_ids.contains(&sink_input_id) {
+                app.sink_input_ids.push(sink_input_id);
+            }
+            // Add stream name if not already present
+            if !app.stream_names.contains(&stream_name) {
+                app.stream_names.push(stream_name);
+            }
+            // Mark as active and clear inactive timestamp
+            app.active = true;
+            app.inactive_since = None;
+            // Update display name if we have a better one
+            if !display_name.is_empty() && display_name != app_key {
+                app.display_name = display_name;
+            }
+            if media_role.is_some() {
+                app.media_role = media_role;
+            }
+            if window_title.is_some() {
+                app.window_title = window_title;
+            }
+            // Update sink if it's different (in case of multiple streams)
+            if app.current_sink != current_sink && app.current_sink != "Unknown" {
+                debug!("App {} has streams in multiple sinks", app_key);
+            }
+        } else {
+            // App doesn't exist yet, create it with minimal info
+            let app_info = AppInfo {
+                display_name,
+                binary_name,
+                stream_names: vec![stream_name],
+                current_sink,
+                window_title,
+                active: true,
+                sink_input_ids: vec![sink_input_id],
+                pipewire_id: sink_input_id, // Use sink_input_id as pipewire_id
+                media_role,
+                ..Default::default()
+            };
+            self.update_app(app_key.clone(), app_info);
+        }
+        if let Some(label) = stream_label {
+            self.set_stream_label(&app_key, sink_input_id, label);
+        }
+        // Seed the app's level from what PipeWire already plays it at
+        if let Some(volume) = stream_volume {
+            self.set_stream_volume(&app_key, sink_input_id, volume);
+        }
+        self.set_stream_corked(&app_key, sink_input_id, corked);
+        (app_key, was_playing)
+    }
+
+    /// Change the sink an app is on, keeping the sink membership index in sync
+    ///
+    /// Returns false if the app is not cached.
+    #[allow(dead_code)] // Used by the controller when routing
+    pub fn set_app_sink(&self, name: &str, sink_name: &str) -> bool {
+        self.set_app_sinks(name, &[sink_name.to_string()])
+    }
+
+    /// Like [`Self::set_app_sink`] for an app duplicated to several sinks
+    ///
+    /// The first sink becomes the app's `current_sink`; `current_sinks` lists them all
+    /// when there is more than one. Returns false for an unknown app or no sinks.
+    pub fn set_app_sinks(&self, name: &str, sink_names: &[String]) -> bool {
+        let Some(primary) = sink_names.first() else {
+            return false;
+        };
+        let _write = self.begin_write();
+        let old_sink = match self.apps.get_mut(name) {
+            Some(mut app) => {
+                app.current_sinks =
+                    if sink_names.len() > 1 { sink_names.to_vec() } else { Vec::new() };
+                std::mem::replace(&mut app.current_sink, primary.clone())
+            }
+            None => return false,
+        };
+        self.unindex_app(name, &old_sink);
+        self.index_app(name, primary);
+        self.increment_generation();
+        true
+    }
+
+    /// Record that an app's streams were muted or unmuted
+    ///
+    /// Returns false if the app is not cached.
+    pub fn set_app_mute(&self, name: &str, muted: bool) -> bool {
+        let _write = self.begin_write();
+        match self.apps.get_mut(name) {
+            Some(app) if app.muted == muted => return true,
+            Some(mut app) => app.muted = muted,
+            None => return false,
+        }
+        self.increment_generation();
+        true
+    }
+
+    /// Names of the apps currently on a sink, sorted for stable output
+    pub fn apps_for_sink(&self, sink_name: &str) -> Vec<String> {
+        let mut apps: Vec<String> = self
+            .sink_members
+            .get(sink_name)
+            .map(|members| members.iter().cloned().collect())
+            .unwrap_or_default();
+        apps.sort();
+        apps
+    }
+
+    /// How many entries each of the cache's collections holds
+    pub fn stats(&self) -> CacheStats {
+        let active_apps = self.apps.iter().filter(|entry| entry.value().active).count();
+        CacheStats {
+            sinks: self.sinks.len(),
+            active_apps,
+            inactive_apps: self.apps.len() - active_apps,
+            routing_rules: self.routing_rules.len(),
+            remembered_apps: self.remembered_apps.len(),
+            indexed_apps: self.sink_members.iter().map(|members| members.len()).sum(),
+        }
+    }
+
+    fn index_app(&self, name: &str, sink_name: &str) {
+        self.sink_members.entry(sink_name.to_string()).or_default().insert(name.to_string());
+    }
+
+    fn unindex_app(&self, name: &str, sink_name: &str) {
+        if let Some(mut members) = self.sink_members.get_mut(sink_name) {
+            members.remove(name);
+        }
+        self.sink_members.remove_if(sink_name, |_, members| members.is_empty());
+    }
+
     #[allow(dead_code)] // May be used for D-Bus state retrieval
     pub fn get_snapshot(&self) -> CacheSnapshot {
         CacheSnapshot {
             generation: self.get_generation(),
             sinks: self.sinks.iter().map(|r| (r.key().clone(), r.value().clone())).collect(),
-            apps: self.apps.iter().map(|r| (r.key().clone(), r.value().clone())).collect(),
+            apps: self
+                .apps
+                .iter()
+                .map(|r| {
+                    let mut app = r.value().clone();
+                    app.display_name = self.app_label(&app).to_string();
+                    (r.key().clone(), app)
+                })
+                .collect(),
         }
     }
 
-    #[allow(dead_code)] // Used by cleanup task in main.rs
+    /// Name to show for `app`, honoring the per-stream labels setting
+    pub fn app_label<'a>(&self, app: &'a AppInfo) -> &'a str {
+        app.label(self.per_stream_labels)
+    }
+
+    /// Label a stream of `app_name` with its `media.name`, replacing any earlier label
+    ///
+    /// Returns false if the app isn't cached. Doesn't bump the generation; the
+    /// caller does once it's done updating the app.
+    #[allow(dead_code)] // Used by the PipeWire monitor
+    pub fn set_stream_label(&self, app_name: &str, sink_input_id: u32, label: String) -> bool {
+        let label = self.limit_name(label);
+        let Some(mut app) = self.apps.get_mut(app_name) else {
+            return false;
+        };
+        app.stream_labels.retain(|(id, _)| *id != sink_input_id);
+        app.stream_labels.push((sink_input_id, label));
+        true
+    }
+
+    /// Record the volume PipeWire reports for one of `app_name`'s streams
+    ///
+    /// The app's volume becomes the average over its streams with a known volume.
+    /// Returns false if the app isn't cached. Doesn't bump the generation; the
+    /// caller does once it's done updating the app.
+    #[allow(dead_code)] // Used by the PipeWire monitor
+    pub fn set_stream_volume(&self, app_name: &str, sink_input_id: u32, volume: f32) -> bool {
+        self.stream_volumes.insert(sink_input_id, volume.clamp(0.0, 1.5));
+        self.refresh_app_streams(app_name)
+    }
+
+    /// Record whether one of `app_name`'s streams is corked, paused by the app
+    ///
+    /// The app is playing while any of its streams isn't known to be corked. Returns
+    /// false if the app isn't cached. Like [`Self::set_stream_volume`], leaves bumping
+    /// the generation to the caller.
+    #[allow(dead_code)] // Used by the PipeWire monitor
+    pub fn set_stream_corked(&self, app_name: &str, sink_input_id: u32, corked: bool) -> bool {
+        self.stream_corked.insert(sink_input_id, corked);
+        self.refresh_app_streams(app_name)
+    }
+
+    /// Drop what was recorded about a stream that went away, updating its app
+    #[allow(dead_code)] // Used by the PipeWire monitor
+    pub fn forget_stream(&self, app_name: &str, sink_input_id: u32) {
+        self.stream_volumes.remove(&sink_input_id);
+        self.stream_corked.remove(&sink_input_id);
+        self.refresh_app_streams(app_name);
+    }
+
+    /// Set an app's volume to the average of its streams' known volumes, and whether
+    /// it is playing from their corked states
+    fn refresh_app_streams(&self, app_name: &str) -> bool {
+        let Some(stream_ids) = self.apps.get(app_name).map(|app| app.sink_input_ids.clone()) else {
+            return false;
+        };
+        let volumes: Vec<f32> =
+            stream_ids.iter().filter_map(|id| self.stream_volumes.get(id).map(|v| *v)).collect();
+        let average =
+            (!volumes.is_empty()).then(|| volumes.iter().sum::<f32>() / volumes.len() as f32);
+        let playing =
+            stream_ids.iter().any(|id| !self.stream_corked.get(id).is_some_and(|corked| *corked));
+        match self.apps.get_mut(app_name) {
+            Some(mut app) => {
+                app.playing = playing;
+                // An app whose streams all went away keeps showing its last level
+                if average.is_some() {
+                    app.volume = average;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The routing rules, remembered apps and default volumes, for [`Self::import_routing`]
+    pub fn export_routing(&self) -> RoutingExport {
+        let pairs = |map: &DashMap<String, String>| {
+            map.iter().map(|entry| (entry.key().clone(), entry.value().clone())).collect()
+        };
+        RoutingExport {
+            rules: pairs(&self.routing_rules),
+            remembered_apps: pairs(&self.remembered_apps),
+            default_volumes: self
+                .default_volumes
+                .iter()
+                .map(|entry| (entry.key().clone(), *entry.value()))
+                .collect(),
+        }
+    }
+
+    /// Apply an export, merging it with the current setup or replacing it
+    ///
+    /// Every sink a rule or default volume names must exist, virtual or hardware;
+    /// otherwise nothing is applied and the unknown sinks are returned, sorted.
+    /// Remembered apps are only history, those on sinks that don't exist are skipped.
+    /// Volumes are clamped to 0.0 - 1.0 and non-finite ones dropped.
+    pub fn import_routing(&self, data: RoutingExport, mode: ImportMode) -> Result<(), Vec<String>> {
+        let exists = |sink: &str| self.sink_exists(sink);
+        let mut unknown: Vec<String> = data
+            .rules
+            .values()
+            .flat_map(|sink| parse_sink_targets(sink))
+            .chain(data.default_volumes.keys().cloned())
+            .filter(|sink| !exists(sink))
+            .collect();
+        if !unknown.is_empty() {
+            unknown.sort();
+            unknown.dedup();
+            return Err(unknown);
+        }
+
+        if mode == ImportMode::Replace {
+            self.routing_rules.clear();
+            self.remembered_apps.clear();
+            self.default_volumes.clear();
+        }
+        for (app, sink) in data.rules {
+            self.routing_rules.insert(app, sink);
+        }
+        for (app, sink) in data.remembered_apps.into_iter().filter(|(_, sink)| exists(sink)) {
+            self.remembered_apps.insert(app, sink);
+        }
+        for (sink, volume) in data.default_volumes {
+            if let Some(volume) = sanitize_volume(volume) {
+                self.default_volumes.insert(sink, volume);
+            }
+        }
+        self.increment_generation();
+        Ok(())
+    }
+
+    /// Whether a virtual or hardware sink of this name is in the cache
+    fn sink_exists(&self, sink_name: &str) -> bool {
+        self.sinks.contains_key(sink_name) || self.physical_sinks.contains_key(sink_name)
+    }
+
+    /// Routing rules naming a sink that isn't in the cache, as sorted (app, rule) pairs
+    pub fn dangling_rules(&self) -> Vec<(String, String)> {
+        let rules: Vec<(String, String)> = self
+            .routing_rules
+            .iter()
+            .map(|rule| (rule.key().clone(), rule.value().clone()))
+            .collect();
+        // Not while iterating the rules, the sinks are separate maps
+        let mut dangling: Vec<(String, String)> = rules
+            .into_iter()
+            .filter(|(_, rule)| {
+                parse_sink_targets(rule).iter().any(|sink_name| !self.sink_exists(sink_name))
+            })
+            .collect();
+        dangling.sort();
+        dangling
+    }
+
+    /// Log each of [`Self::dangling_rules`] and deal with it as `policy` says
+    ///
+    /// Only rules naming one of `removed_sinks`, virtual sinks taken out of the
+    /// config, are pruned or redirected; any other sink may only be missing for now,
+    /// like an unplugged device. Returns those rules as they were. Redirected rules
+    /// point at `default_sink`, unless that is missing as well, which keeps them.
+    #[allow(dead_code)] // Used by the daemon
+    pub fn reconcile_routing_rules(
+        &self,
+        policy: StaleRulePolicy,
+        default_sink: &str,
+        removed_sinks: &[String],
+    ) -> Vec<(String, String)> {
+        let dangling: Vec<(String, String)> = self
+            .dangling_rules()
+            .into_iter()
+            .filter(|(app_name, rule)| {
+                let removed =
+                    parse_sink_targets(rule).iter().any(|sink| removed_sinks.contains(sink));
+                if !removed {
+                    debug!("Keeping routing rule {} -> {} until its sink is back", app_name, rule);
+                }
+                removed
+            })
+            .collect();
+        let redirect = policy == StaleRulePolicy::Redirect && self.sink_exists(default_sink);
+        if policy == StaleRulePolicy::Redirect && !redirect && !dangling.is_empty() {
+            warn!("Not redirecting stale routing rules, {} doesn't exist either", default_sink);
+        }
+        for (app_name, rule) in &dangling {
+            warn!("Routing rule {} -> {} names a sink that doesn't exist", app_name, rule);
+            if policy == StaleRulePolicy::Prune {
+                self.routing_rules.remove(app_name);
+                self.remembered_apps.remove(app_name);
+            } else if redirect {
+                self.routing_rules.insert(app_name.clone(), default_sink.to_string());
+                self.remembered_apps.insert(app_name.clone(), default_sink.to_string());
+            }
+        }
+        if !dangling.is_empty() && (policy == StaleRulePolicy::Prune || redirect) {
+            self.increment_generation();
+        }
+        dangling
+    }
+
+    /// Every app the daemon knows of, running or not, sorted by name
+    ///
+    /// Persisted app mappings are mirrored into `routing_rules`, so an app that
+    /// only has a saved mapping shows up with `has_rule` set.
+    pub fn known_apps(&self) -> Vec<KnownApp> {
+        let mut known: BTreeMap<String, KnownApp> = BTreeMap::new();
+
+        // Each map is collected in turn so no two guards are held at once
+        let running: Vec<(String, String)> = self
+            .apps
+            .iter()
+            .filter(|r| r.value().active)
+            .map(|r| (r.key().clone(), r.value().current_sink.clone()))
+            .collect();
+        let remembered: Vec<(String, String)> =
+            self.remembered_apps.iter().map(|r| (r.key().clone(), r.value().clone())).collect();
+        let rules: Vec<(String, String)> =
+            self.routing_rules.iter().map(|r| (r.key().clone(), r.value().clone())).collect();
+
+        for (name, sink) in rules {
+            let app = known_entry(&mut known, name);
+            app.has_rule = true;
+            app.sink = Some(sink);
+        }
+        for (name, sink) in remembered {
+            let app = known_entry(&mut known, name);
+            app.remembered = true;
+            app.sink.get_or_insert(sink);
+        }
+        for (name, sink) in running {
+            let app = known_entry(&mut known, name);
+            app.running = true;
+            app.sink = Some(sink);
+        }
+
+        known.into_values().collect()
+    }
+
+    /// Full record of one cached app, or None if it isn't cached
+    pub fn app_record(&self, name: &str) -> Option<AppRecord> {
+        let app = self.apps.get(name)?.clone();
+        let volume = self.sinks.get(&app.current_sink).map(|sink| sink.volume);
+        Some(AppRecord {
+            name: name.to_string(),
+            inactive_seconds: app.inactive_since.map(|since| since.elapsed().as_secs()),
+            display_name: self.app_label(&app).to_string(),
+            binary_name: app.binary_name,
+            stream_names: app.stream_names,
+            stream_labels: app.stream_labels,
+            window_title: app.window_title,
+            current_sink: app.current_sink,
+            current_sinks: app.current_sinks,
+            active: app.active,
+            playing: app.playing,
+            muted: app.muted,
+            sink_input_ids: app.sink_input_ids,
+            pipewire_id: app.pipewire_id,
+            media_role: app.media_role,
+            volume,
+            stream_volume: app.volume,
+        })
+    }
+
+    #[allow(dead_code)] // Run by the daemon's cleanup task, test-daemon has none
     pub fn cleanup_inactive_apps(&self, ttl_seconds: u64) -> usize {
         let now = std::time::Instant::now();
         let ttl = std::time::Duration::from_secs(ttl_seconds);
-        let mut removed_count = 0;
+        let expired = |app: &AppInfo| {
+            !app.active && app.inactive_since.is_some_and(|since| now.duration_since(since) > ttl)
+        };
 
-        // Use retain to remove items in-place (more efficient than collect + remove)
-        self.apps.retain(|name, app| {
-            // Keep active apps
-            if app.active {
-                return true;
-            }
+        // Collect candidates first; no other map is touched while iterating `apps`
+        let candidates: Vec<String> = self
+            .apps
+            .iter()
+            .filter(|entry| expired(entry.value()))
+            .map(|entry| entry.key().clone())
+            .collect();
 
+        let mut removed = 0;
+        let _write = self.begin_write();
+        for name in candidates {
             // Keep apps with routing rules
-            if self.routing_rules.contains_key(name) {
-                return true;
+            if self.routing_rules.contains_key(&name) {
+                continue;
             }
 
-            // Check if inactive app has expired
-            if let Some(inactive_since) = app.inactive_since {
-                if now.duration_since(inactive_since) > ttl {
-                    // Remove from remembered apps too
-                    self.remembered_apps.remove(name);
-                    removed_count += 1;
-                    return false; // Remove this app
-                }
-            }
+            // The app may have become active again since it was collected
+            let Some((name, app)) = self.apps.remove_if(&name, |_, app| expired(app)) else {
+                continue;
+            };
 
-            true // Keep this app
-        });
+            // Remove from remembered apps too
+            self.remembered_apps.remove(&name);
+            if self.recent_sinks.remove(&name).is_some() {
+                self.recent_sinks_changed.notify_one();
+            }
+            self.unindex_app(&name, &app.current_sink);
+            removed += 1;
+        }
 
-        if removed_count > 0 {
+        if removed > 0 {
             self.increment_generation();
         }
 
-        removed_count
+        removed
+    }
+}
+
+/// A change to sinks or apps in progress, see [`AudioCache::begin_write`]
+struct WriteGuard<'a>(&'a AtomicUsize);
+
+impl Drop for WriteGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
     }
 }
 
+fn known_entry(known: &mut BTreeMap<String, KnownApp>, name: String) -> &mut KnownApp {
+    known.entry(name.clone()).or_insert_with(|| KnownApp { name, ..Default::default() })
+}
+
+/// A stream node the PipeWire monitor tracks, with the app it resolved it to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GraphNode {
+    pub id: u32,
+    pub serial: u32, // object.serial, the sink input id pactl knows it by
+    pub app_name: Option<String>,
+}
+
+/// An app seen live, remembered from a previous run, or named by a routing rule
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KnownApp {
+    pub name: String,
+    pub running: bool,
+    pub remembered: bool,
+    pub has_rule: bool,
+    pub sink: Option<String>, // Current sink if running, else the rule's or last used sink
+}
+
+/// Sizes of the cache's collections, see [`AudioCache::stats`]
+///
+/// Nothing evicts remembered apps, so this is where a cache that keeps growing
+/// shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub sinks: usize,
+    pub active_apps: usize,
+    pub inactive_apps: usize,
+    pub routing_rules: usize,
+    pub remembered_apps: usize,
+    pub indexed_apps: usize, // Entries of the sink -> apps index
+}
+
+/// Which precedence tier of [`AudioCache::resolve_target`] picked a sink
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleMatch {
+    Pinned,  // Pin for the app's name or binary
+    Exact,   // Rule for the app's own name
+    Binary,  // Rule for its binary name
+    Window,  // Rule for its window title
+    Fuzzy,   // Rule that is a prefix of either name
+    Role,    // Role rule for its media.role
+    Default, // The default sink for unmatched apps
+}
+
+/// Outcome of [`AudioCache::test_rule`]; both are None when nothing matches
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleTest {
+    pub app_name: String,
+    pub rule: Option<RuleMatch>,
+    pub sink: Option<String>,
+}
+
+/// Routing rules, remembered apps and default volumes, for moving a setup elsewhere
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RoutingExport {
+    #[serde(default)]
+    pub rules: BTreeMap<String, String>, // App -> sink, or comma-separated sinks
+    #[serde(default)]
+    pub remembered_apps: BTreeMap<String, String>, // App -> last sink
+    #[serde(default)]
+    pub default_volumes: BTreeMap<String, f32>, // Sink -> volume its reset restores
+}
+
+/// How [`AudioCache::import_routing`] treats what is already configured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportMode {
+    Merge,   // Imported entries win, others stay
+    Replace, // Only the imported entries remain
+}
+
+/// Everything cached about one app
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AppRecord {
+    pub name: String,
+    pub display_name: String,
+    pub binary_name: String,
+    pub stream_names: Vec<String>,
+    pub stream_labels: Vec<(u32, String)>,
+    #[serde(default)]
+    pub window_title: Option<String>,
+    pub current_sink: String,
+    pub current_sinks: Vec<String>,
+    pub active: bool,
+    #[serde(default)]
+    pub playing: bool,
+    #[serde(default)]
+    pub muted: bool,
+    pub sink_input_ids: Vec<u32>,
+    pub pipewire_id: u32,
+    pub media_role: Option<String>,
+    pub inactive_seconds: Option<u64>, // Time since its last stream went away, None while active
+    pub volume: Option<f32>,           // Volume of current_sink, if that sink is cached
+    #[serde(default)]
+    pub stream_volume: Option<f32>, // Average volume of the app's own streams, if known
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CacheSnapshot {
     pub generation: u64,
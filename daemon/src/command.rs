@@ -0,0 +1,92 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// Trait for executing system commands - allows for mocking in tests
+pub trait CommandExecutor: Send + Sync {
+    fn execute(&self, program: &str, args: &[&str]) -> std::io::Result<std::process::Output>;
+    #[allow(dead_code)] // Only used by the app name detector
+    fn execute_shell(&self, cmd: &str) -> std::io::Result<std::process::Output>;
+}
+
+/// Real command executor for production
+pub struct SystemCommandExecutor;
+
+impl CommandExecutor for SystemCommandExecutor {
+    fn execute(&self, program: &str, args: &[&str]) -> std::io::Result<std::process::Output> {
+        Command::new(program).args(args).output()
+    }
+
+    fn execute_shell(&self, cmd: &str) -> std::io::Result<std::process::Output> {
+        Command::new("sh").arg("-c").arg(cmd).output()
+    }
+}
+
+/// Runs commands through another executor, logging each one and how it exited
+///
+/// Entries are logged at info level, so what was sent to pactl and wpctl can be
+/// followed without debug logging. With [`Self::with_file`] they are also appended
+/// to a file of their own.
+pub struct TracingExecutor {
+    inner: Arc<dyn CommandExecutor>,
+    file: Option<Mutex<File>>,
+}
+
+#[allow(dead_code)] // Only the daemon turns tracing on
+impl TracingExecutor {
+    pub fn new(inner: Arc<dyn CommandExecutor>) -> Self {
+        Self { inner, file: None }
+    }
+
+    /// Also append every entry to the file at `path`, creating it if needed
+    pub fn with_file(mut self, path: &Path) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        self.file = Some(Mutex::new(file));
+        Ok(self)
+    }
+
+    fn trace(&self, command: &str, result: &std::io::Result<Output>) {
+        let outcome = match result {
+            Ok(output) => output.status.to_string(),
+            Err(e) => format!("failed to start: {e}"),
+        };
+        info!("Ran {}: {}", command, outcome);
+
+        let Some(file) = &self.file else {
+            return;
+        };
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut file = file.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = writeln!(file, "{} {}: {}", timestamp.as_secs(), command, outcome) {
+            warn!("Failed to write command trace: {}", e);
+        }
+    }
+}
+
+impl CommandExecutor for TracingExecutor {
+    fn execute(&self, program: &str, args: &[&str]) -> std::io::Result<Output> {
+        let result = self.inner.execute(program, args);
+        let command =
+            std::iter::once(program).chain(args.iter().copied()).map(quote).collect::<Vec<_>>();
+        self.trace(&command.join(" "), &result);
+        result
+    }
+
+    fn execute_shell(&self, cmd: &str) -> std::io::Result<Output> {
+        let result = self.inner.execute_shell(cmd);
+        self.trace(&format!("sh -c {}", quote(cmd)), &result);
+        result
+    }
+}
+
+/// An argument as it would be typed in a shell, quoted if it has to be
+fn quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'') {
+        return arg.to_string();
+    }
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
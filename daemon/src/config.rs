@@ -1,22 +1,115 @@
 use anyhow::Result;
+use nix::unistd::Uid;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use tracing::{debug, info};
 
+use crate::backend::DEFAULT_LOOPBACK_SUFFIX;
+use crate::cache::{
+    ImportMode, RoutingExport, DEFAULT_COMMAND_TIMEOUT, DEFAULT_MAX_NAME_LENGTH,
+    DEFAULT_ROUTE_REFRESH_DELAY,
+};
+use crate::events::DEFAULT_EVENT_LOG_SIZE;
+use crate::volume::sanitize_volume;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub cache: CacheConfig,
     pub routing: RoutingConfig,
     pub performance: PerformanceConfig,
     pub virtual_sinks: Vec<VirtualSink>,
+    #[serde(default)]
+    pub auto_create_sinks: bool, // Create missing virtual_sinks on startup, removing them on exit
+    #[serde(default)]
+    pub persist_sink_labels: bool, // Keep sink labels set over IPC or D-Bus across restarts
+    #[serde(default)]
+    pub restrict_ipc_control: bool, // Only the daemon's own user may change state over IPC
+    #[serde(default)]
+    pub rescan_on_resume: bool, // Rescan PipeWire and reapply routing after the system wakes
+    #[serde(default)]
+    pub trace_commands: bool, // Log every command the daemon runs and its exit status at info level
+    #[serde(default = "default_loopback_target_suffixes")]
+    pub loopback_target_suffixes: Vec<String>, // A sink's loopbacks are named the sink plus one of these
+    #[serde(default)]
+    pub match_sinks_by: SinkMatch, // Which node property virtual_sinks names are compared with
+    #[serde(default)]
+    pub paths: PathsConfig,
+}
+
+/// How PipeWire sinks are recognized as one of the configured virtual sinks
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SinkMatch {
+    #[default]
+    NodeName, // node.name equals the virtual sink's name
+    Description, // node.description does, for sinks whose node.name is generated
+    Either,      // Either one does, node.name first
+}
+
+fn default_loopback_target_suffixes() -> Vec<String> {
+    vec![DEFAULT_LOOPBACK_SUFFIX.to_string()]
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CacheConfig {
     pub update_interval_ms: u64,
     pub max_remembered_apps: usize,
+    #[serde(default = "default_max_name_length")]
+    pub max_name_length: usize, // Bytes; longer app names are truncated with an ellipsis
+    #[serde(default)]
+    pub track_denylist: Vec<String>, // Apps never added to the cache, by app or binary name
+    #[serde(default)]
+    pub track_allowlist: Vec<String>, // If not empty, the only apps added to the cache
+    #[serde(default = "default_capitalize_binary_names")]
+    pub capitalize_binary_names: bool, // Show "Firefox" for an app only known by its binary "firefox"
+    #[serde(default)]
+    pub per_stream_labels: bool, // Show apps by their newest stream's media.name, e.g. a tab title
+    #[serde(default = "default_app_identity_properties")]
+    pub app_identity_properties: Vec<String>, // Stream properties naming the app, first set one wins
+    #[serde(default = "default_event_log_size")]
+    pub event_log_size: usize, // Recent events kept for the EVENTS command, 0 to keep none
+}
+
+impl CacheConfig {
+    /// Whether a stream known by any of `names` should be tracked
+    ///
+    /// Names are compared case-insensitively. The denylist wins over the allowlist,
+    /// and an empty allowlist allows every app.
+    pub fn should_track(&self, names: &[&str]) -> bool {
+        let listed = |list: &[String]| {
+            list.iter().any(|entry| names.iter().any(|name| entry.eq_ignore_ascii_case(name)))
+        };
+        !listed(&self.track_denylist)
+            && (self.track_allowlist.is_empty() || listed(&self.track_allowlist))
+    }
+
+    /// Name of the app behind a stream, from the first `app_identity_properties`
+    /// entry that `property` finds a non-blank value for
+    pub fn app_identity(&self, property: impl Fn(&str) -> Option<String>) -> Option<String> {
+        self.app_identity_properties
+            .iter()
+            .filter_map(|key| property(key))
+            .find(|value| !value.trim().is_empty())
+    }
+}
+
+fn default_max_name_length() -> usize {
+    DEFAULT_MAX_NAME_LENGTH
+}
+
+fn default_capitalize_binary_names() -> bool {
+    true
+}
+
+fn default_event_log_size() -> usize {
+    DEFAULT_EVENT_LOG_SIZE
+}
+
+fn default_app_identity_properties() -> Vec<String> {
+    vec!["application.name".to_string(), "node.description".to_string()]
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,12 +117,124 @@ pub struct RoutingConfig {
     pub enable_auto_routing: bool,
     pub default_sink: String,
     pub rules: HashMap<String, String>,
+    #[serde(default)]
+    pub role_rules: HashMap<String, String>, // media.role -> sink, for apps without a rule
+    #[serde(default)]
+    pub auto_mute_on_inactive: Vec<String>, // Apps muted while every stream they have is paused
+    #[serde(default)]
+    pub fuzzy_matching: bool, // Let a rule match apps whose name it is a prefix of
+    #[serde(default)]
+    pub route_unmatched_to_default: bool, // Move streams no rule matches to default_sink
+    #[serde(default)]
+    pub focus_sink: Option<String>, // Sink the app with window focus is moved to
+    #[serde(default)]
+    pub follow_focus: bool, // Start out following focus; SET_FOLLOW_FOCUS toggles it
+    #[serde(default)]
+    pub stale_rules: StaleRulePolicy, // What becomes of saved rules whose virtual sink was removed
+}
+
+/// What happens to saved routing rules whose sink no longer exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StaleRulePolicy {
+    #[default]
+    Keep, // Only log them, in case the sink comes back
+    Prune,    // Forget them, leaving the apps where they play
+    Redirect, // Point them at default_sink instead
+}
+
+impl RoutingConfig {
+    /// Sink configured for a media role, matched case-insensitively
+    pub fn role_sink(&self, media_role: &str) -> Option<&str> {
+        self.role_rules
+            .iter()
+            .find(|(role, _)| role.eq_ignore_ascii_case(media_role))
+            .map(|(_, sink_name)| sink_name.as_str())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PerformanceConfig {
     pub event_debounce_ms: u64,
     pub max_events_per_second: u32,
+    #[serde(default = "default_command_timeout_ms")]
+    pub command_timeout_ms: u64, // Deadline for each pactl/wpctl call
+    #[serde(default = "default_snapshot_interval_min_ms")]
+    pub snapshot_interval_min_ms: u64, // Shared memory polling while the cache changes
+    #[serde(default = "default_snapshot_interval_max_ms")]
+    pub snapshot_interval_max_ms: u64, // Longest shared memory polling interval when idle
+    #[serde(default = "default_cleanup_interval_min_ms")]
+    pub cleanup_interval_min_ms: u64, // Inactive app cleanup while apps come and go
+    #[serde(default = "default_cleanup_interval_max_ms")]
+    pub cleanup_interval_max_ms: u64, // Longest inactive app cleanup interval when idle
+    #[serde(default)]
+    pub volume_ramp_ms: u64, // Step loopback volume changes over this long, 0 to jump
+    #[serde(default = "default_route_refresh_delay_ms")]
+    pub route_refresh_delay_ms: u64, // Refresh D-Bus clients this long after a route, 0 to skip
+    #[serde(default = "default_pactl_poll_interval_ms")]
+    pub pactl_poll_interval_ms: u64, // Poll pactl this often if PipeWire can't be used, 0 to fail
+}
+
+fn default_command_timeout_ms() -> u64 {
+    DEFAULT_COMMAND_TIMEOUT.as_millis() as u64
+}
+
+fn default_route_refresh_delay_ms() -> u64 {
+    DEFAULT_ROUTE_REFRESH_DELAY.as_millis() as u64
+}
+
+fn default_pactl_poll_interval_ms() -> u64 {
+    1000
+}
+
+fn default_snapshot_interval_min_ms() -> u64 {
+    50
+}
+
+fn default_snapshot_interval_max_ms() -> u64 {
+    500
+}
+
+fn default_cleanup_interval_min_ms() -> u64 {
+    15_000
+}
+
+fn default_cleanup_interval_max_ms() -> u64 {
+    60_000
+}
+
+/// Where the daemon keeps its files; unset entries use the per-user defaults
+///
+/// Values may start with `~` and contain `$VAR` or `${VAR}`, see [`expand_path`].
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PathsConfig {
+    #[serde(default)]
+    pub socket: Option<String>,
+    #[serde(default)]
+    pub shm: Option<String>,
+    #[serde(default)]
+    pub mappings_dir: Option<String>,
+    #[serde(default)]
+    pub command_trace: Option<String>, // Also append traced commands here
+}
+
+impl PathsConfig {
+    pub fn socket_path(&self) -> Option<PathBuf> {
+        self.socket.as_deref().map(expand_path)
+    }
+
+    pub fn shm_path(&self) -> Option<PathBuf> {
+        self.shm.as_deref().map(expand_path)
+    }
+
+    pub fn command_trace_path(&self) -> Option<PathBuf> {
+        self.command_trace.as_deref().map(expand_path)
+    }
+
+    /// App mappings file inside the configured directory
+    pub fn mappings_file(&self) -> Option<PathBuf> {
+        self.mappings_dir.as_deref().map(|dir| expand_path(dir).join(MAPPINGS_FILE_NAME))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -37,51 +242,216 @@ pub struct VirtualSink {
     pub name: String,
     pub display_name: String,
     pub icon: String,
+    #[serde(default)]
+    pub default_volume: Option<f32>, // Volume RESET_VOLUME restores, 1.0 if unset
+    #[serde(default)]
+    pub order: Option<u32>, // Position in the panel, lowest first; its place in the list if unset
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            cache: CacheConfig { update_interval_ms: 100, max_remembered_apps: 50 },
+            cache: CacheConfig {
+                update_interval_ms: 100,
+                max_remembered_apps: 50,
+                max_name_length: DEFAULT_MAX_NAME_LENGTH,
+                track_denylist: Vec::new(),
+                track_allowlist: Vec::new(),
+                capitalize_binary_names: true,
+                per_stream_labels: false,
+                app_identity_properties: default_app_identity_properties(),
+                event_log_size: default_event_log_size(),
+            },
             routing: RoutingConfig {
                 enable_auto_routing: true,
                 default_sink: "Game".to_string(),
                 rules: HashMap::new(),
+                role_rules: HashMap::new(),
+                auto_mute_on_inactive: Vec::new(),
+                fuzzy_matching: false,
+                route_unmatched_to_default: false,
+                focus_sink: None,
+                follow_focus: false,
+                stale_rules: StaleRulePolicy::Keep,
+            },
+            performance: PerformanceConfig {
+                event_debounce_ms: 50,
+                max_events_per_second: 100,
+                command_timeout_ms: default_command_timeout_ms(),
+                snapshot_interval_min_ms: default_snapshot_interval_min_ms(),
+                snapshot_interval_max_ms: default_snapshot_interval_max_ms(),
+                cleanup_interval_min_ms: default_cleanup_interval_min_ms(),
+                cleanup_interval_max_ms: default_cleanup_interval_max_ms(),
+                volume_ramp_ms: 0,
+                route_refresh_delay_ms: default_route_refresh_delay_ms(),
+                pactl_poll_interval_ms: default_pactl_poll_interval_ms(),
             },
-            performance: PerformanceConfig { event_debounce_ms: 50, max_events_per_second: 100 },
             virtual_sinks: vec![
                 VirtualSink {
                     name: "Game".to_string(),
                     display_name: "Game".to_string(),
                     icon: "applications-games-symbolic".to_string(),
+                    default_volume: None,
+                    order: None,
                 },
                 VirtualSink {
                     name: "Chat".to_string(),
                     display_name: "Chat".to_string(),
                     icon: "user-available-symbolic".to_string(),
+                    default_volume: None,
+                    order: None,
                 },
                 VirtualSink {
                     name: "Media".to_string(),
                     display_name: "Media".to_string(),
                     icon: "applications-multimedia-symbolic".to_string(),
+                    default_volume: None,
+                    order: None,
                 },
             ],
+            auto_create_sinks: false,
+            persist_sink_labels: false,
+            restrict_ipc_control: false,
+            rescan_on_resume: false,
+            trace_commands: false,
+            loopback_target_suffixes: default_loopback_target_suffixes(),
+            match_sinks_by: SinkMatch::default(),
+            paths: PathsConfig::default(),
         }
     }
 }
 
+/// File app mappings are persisted to, inside the config directory
+const MAPPINGS_FILE_NAME: &str = "app-mappings.toml";
+
+/// Whether a config or mappings file is JSON rather than TOML, going by its extension
+fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
+
+/// Parse a config or mappings file's contents in the format its extension names
+fn parse_file<T: DeserializeOwned>(path: &Path, contents: &str) -> Result<T> {
+    if is_json(path) {
+        Ok(serde_json::from_str(contents)?)
+    } else {
+        Ok(toml::from_str(contents)?)
+    }
+}
+
+/// The user's home directory, from `$HOME`, else guessed from `$USER`
+fn home_dir(env: &impl Fn(&str) -> Option<String>) -> String {
+    env("HOME")
+        .or_else(|| env("USER").map(|user| format!("/home/{user}")))
+        .unwrap_or_else(|| "/tmp".to_string())
+}
+
+/// Value of an environment variable, with the XDG base directory defaults applied
+fn env_value(name: &str, env: &impl Fn(&str) -> Option<String>) -> Option<String> {
+    match env(name) {
+        Some(value) if !value.is_empty() => Some(value),
+        _ => match name {
+            "HOME" => Some(home_dir(env)),
+            "XDG_CONFIG_HOME" => Some(format!("{}/.config", home_dir(env))),
+            "XDG_RUNTIME_DIR" => Some(format!("/run/user/{}", Uid::current())),
+            _ => None,
+        },
+    }
+}
+
+/// Expand a leading `~` and any `$VAR` or `${VAR}` in a user-provided path
+///
+/// `XDG_CONFIG_HOME` and `XDG_RUNTIME_DIR` fall back to their XDG defaults when unset.
+/// Other unset variables are left in place, so the mistake shows up in the path.
+pub fn expand_path(path: &str) -> PathBuf {
+    expand_path_with(path, |name| std::env::var(name).ok())
+}
+
+/// [`expand_path`] with variables looked up by `env` instead of in the environment
+pub fn expand_path_with(path: &str, env: impl Fn(&str) -> Option<String>) -> PathBuf {
+    let mut expanded = String::with_capacity(path.len());
+    let mut rest = path;
+    if rest == "~" || rest.starts_with("~/") {
+        expanded.push_str(&home_dir(&env));
+        rest = &rest[1..];
+    }
+
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let (name, consumed) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        match env_value(name, &env).filter(|_| !name.is_empty()) {
+            Some(value) => expanded.push_str(&value),
+            None => expanded.push_str(&rest[dollar..dollar + 1 + consumed]),
+        }
+        rest = &after[consumed..];
+    }
+    expanded.push_str(rest);
+    PathBuf::from(expanded)
+}
+
 impl Config {
+    /// Position of each virtual sink in the panel: its configured `order`, or else its
+    /// index in `virtual_sinks`
+    pub fn sink_order(&self) -> Vec<(String, u32)> {
+        self.virtual_sinks
+            .iter()
+            .enumerate()
+            .map(|(index, sink)| (sink.name.clone(), sink.order.unwrap_or(index as u32)))
+            .collect()
+    }
+
+    /// The virtual sink a PipeWire sink is, going by `match_sinks_by`
+    ///
+    /// The cache knows the sink by the configured name, whichever property matched.
+    pub fn find_virtual_sink(
+        &self,
+        node_name: &str,
+        description: Option<&str>,
+    ) -> Option<&VirtualSink> {
+        let by_name = || self.virtual_sinks.iter().find(|sink| sink.name == node_name);
+        let by_description = || {
+            let description = description.filter(|description| !description.is_empty())?;
+            self.virtual_sinks.iter().find(|sink| sink.name == description)
+        };
+        match self.match_sinks_by {
+            SinkMatch::NodeName => by_name(),
+            SinkMatch::Description => by_description(),
+            SinkMatch::Either => by_name().or_else(by_description),
+        }
+    }
+
+    /// Load the config from a JSON file if its extension is `.json`, TOML otherwise
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
-        if path.as_ref().exists() {
+        let path = path.as_ref();
+        if path.exists() {
             let contents = fs::read_to_string(path)?;
-            let config: Config = toml::from_str(&contents)?;
-            Ok(config)
+            parse_file(path, &contents)
         } else {
             Ok(Self::default())
         }
     }
 }
 
+/// Volume and mute last set for an app through the daemon, restored when it reappears
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct AppSettings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub volume: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub muted: Option<bool>,
+}
+
 /// Structure for persisting app-to-sink mappings
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct AppMappings {
@@ -89,52 +459,76 @@ pub struct AppMappings {
     pub mappings: HashMap<String, String>,
     #[serde(default)]
     pub version: u32,
+    #[serde(default)]
+    pub sink_labels: HashMap<String, String>, // Sink -> label set at runtime
+    #[serde(default)]
+    pub recent_sinks: HashMap<String, Vec<String>>, // App -> sinks it was routed to, newest first
+    #[serde(default)]
+    pub default_volumes: HashMap<String, f32>, // Sink -> reset volume from an imported setup
+    #[serde(default)]
+    pub pins: HashMap<String, String>, // App -> sink it's pinned to with PIN
+    #[serde(default)]
+    pub app_settings: HashMap<String, AppSettings>, // App -> volume and mute set over IPC or D-Bus
+    #[serde(default)]
+    pub virtual_sinks: Vec<String>, // Virtual sinks configured at the last start, to spot removed ones
+    #[serde(skip)]
+    file: Option<PathBuf>, // Where `save` writes, the default config file if None
 }
 
 impl AppMappings {
-    /// Get the default config directory path
+    /// Get the default config directory path, under `$XDG_CONFIG_HOME`
     pub fn config_dir() -> Result<PathBuf> {
-        let home = std::env::var("HOME")
-            .or_else(|_| std::env::var("USER").map(|user| format!("/home/{user}")))
-            .unwrap_or_else(|_| "/tmp".to_string());
-
-        let config_dir = PathBuf::from(home).join(".config").join("pipewire-volume-mixer");
-        Ok(config_dir)
+        Ok(expand_path("$XDG_CONFIG_HOME/pipewire-volume-mixer"))
     }
 
     /// Get the default config file path
     pub fn config_file() -> Result<PathBuf> {
-        Ok(Self::config_dir()?.join("app-mappings.toml"))
+        Ok(Self::config_dir()?.join(MAPPINGS_FILE_NAME))
     }
 
     /// Load app mappings from disk
     pub fn load() -> Result<Self> {
-        let config_file = Self::config_file()?;
+        Self::load_from(Self::config_file()?)
+    }
+
+    /// Load app mappings from a specific file, which later saves also go to
+    ///
+    /// Like the config, the file is JSON if its extension is `.json` and TOML otherwise.
+    pub fn load_from<P: Into<PathBuf>>(config_file: P) -> Result<Self> {
+        let config_file = config_file.into();
 
-        if config_file.exists() {
+        let mut mappings = if config_file.exists() {
             let contents = fs::read_to_string(&config_file)?;
-            let mappings: AppMappings = toml::from_str(&contents)?;
+            let mappings: AppMappings = parse_file(&config_file, &contents)?;
             info!("Loaded {} app mappings from {:?}", mappings.mappings.len(), config_file);
-            Ok(mappings)
+            mappings
         } else {
             info!("No existing app mappings file at {:?}, using defaults", config_file);
-            Ok(Self::default())
-        }
+            Self::default()
+        };
+        mappings.file = Some(config_file);
+        Ok(mappings)
     }
 
     /// Save app mappings to disk
     pub fn save(&self) -> Result<()> {
-        let config_dir = Self::config_dir()?;
-        let config_file = Self::config_file()?;
+        let config_file = match &self.file {
+            Some(file) => file.clone(),
+            None => Self::config_file()?,
+        };
 
         // Create config directory if it doesn't exist
-        if !config_dir.exists() {
-            fs::create_dir_all(&config_dir)?;
+        if let Some(config_dir) = config_file.parent().filter(|dir| !dir.exists()) {
+            fs::create_dir_all(config_dir)?;
             info!("Created config directory: {:?}", config_dir);
         }
 
-        // Serialize to TOML
-        let contents = toml::to_string_pretty(self)?;
+        // Keep the format the file was loaded in
+        let contents = if is_json(&config_file) {
+            serde_json::to_string_pretty(self)?
+        } else {
+            toml::to_string_pretty(self)?
+        };
 
         // Write to file
         fs::write(&config_file, contents)?;
@@ -152,6 +546,26 @@ impl AppMappings {
         Ok(())
     }
 
+    /// Save the rules and default volumes of an imported setup to disk
+    ///
+    /// Only what the import brought is added, so configured default volumes stay in
+    /// the config file. Replacing drops the saved rules and volumes first.
+    pub fn import_and_save(&mut self, export: &RoutingExport, mode: ImportMode) -> Result<()> {
+        if mode == ImportMode::Replace {
+            self.mappings.clear();
+            self.default_volumes.clear();
+        }
+        self.mappings.extend(export.rules.clone());
+        self.default_volumes.extend(
+            export
+                .default_volumes
+                .iter()
+                .filter_map(|(sink, volume)| Some((sink.clone(), sanitize_volume(*volume)?))),
+        );
+        self.version += 1;
+        self.save()
+    }
+
     /// Get a mapping for an app
     #[allow(dead_code)]
     pub fn get(&self, app_name: &str) -> Option<&String> {
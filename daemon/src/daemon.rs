@@ -0,0 +1,710 @@
+use anyhow::{Context, Result};
+use nix::unistd::Uid;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, watch, Notify, RwLock};
+use tracing::{debug, error, info, warn};
+
+use crate::app_name_detector::AppNameDetector;
+use crate::backend::PipeWireBackend;
+use crate::cache::{AudioCache, ImportMode, RoutingExport, SinkEvent};
+use crate::command::{CommandExecutor, SystemCommandExecutor, TracingExecutor};
+use crate::config::{AppMappings, Config, RoutingConfig, StaleRulePolicy};
+use crate::dbus_service::start_dbus_service;
+use crate::focus::FocusFollower;
+use crate::ipc::{default_socket_path, import_routing, IpcServer};
+use crate::pipewire_controller::PipeWireController;
+use crate::pipewire_monitor::PipeWireMonitor;
+use crate::resume::ResumeWatcher;
+use crate::schedule::AdaptiveInterval;
+use crate::shared_memory::{default_shm_path, SharedMemoryWriter};
+
+/// Seconds an inactive app is kept before the cleanup task drops it
+const INACTIVE_APP_TTL_SECONDS: u64 = 300;
+
+/// How long to wait before trying again to set up unavailable shared memory
+pub const SHM_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Number of cleanup runs without app changes before the cleanup interval backs off
+const CLEANUP_IDLE_TICKS: u32 = 4;
+
+/// How often the default sink is queried without the PipeWire monitor, besides
+/// whenever a sink comes or goes
+const DEFAULT_SINK_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Configures a [`Daemon`] before it is built
+///
+/// By default the daemon talks to the audio server through pactl, registers on the
+/// session bus and monitors PipeWire. The IPC socket and shared memory file come from
+/// the `[paths]` config, falling back to the per-user defaults.
+pub struct DaemonBuilder {
+    config: Config,
+    socket_path: Option<PathBuf>,
+    shm_path: Option<PathBuf>,
+    backend: Option<Box<dyn PipeWireBackend>>,
+    app_mappings: Option<AppMappings>,
+    dbus: bool,
+    monitor: bool,
+    shared_memory: bool,
+}
+
+impl DaemonBuilder {
+    pub fn with_socket_path<P: Into<PathBuf>>(mut self, socket_path: P) -> Self {
+        self.socket_path = Some(socket_path.into());
+        self
+    }
+
+    /// Publish cache snapshots to shared memory at this path
+    pub fn with_shm_path<P: Into<PathBuf>>(mut self, shm_path: P) -> Self {
+        self.shm_path = Some(shm_path.into());
+        self
+    }
+
+    /// Use a custom backend instead of pactl, e.g. a fake in tests
+    pub fn with_backend(mut self, backend: Box<dyn PipeWireBackend>) -> Self {
+        self.backend = Some(backend);
+        self
+    }
+
+    /// Start from these app mappings instead of loading them from disk
+    pub fn with_app_mappings(mut self, app_mappings: AppMappings) -> Self {
+        self.app_mappings = Some(app_mappings);
+        self
+    }
+
+    /// Whether to register the D-Bus service on the session bus
+    pub fn with_dbus(mut self, enabled: bool) -> Self {
+        self.dbus = enabled;
+        self
+    }
+
+    /// Whether to watch PipeWire for sinks and streams
+    pub fn with_monitor(mut self, enabled: bool) -> Self {
+        self.monitor = enabled;
+        self
+    }
+
+    /// Whether to publish cache snapshots to shared memory
+    pub fn with_shared_memory(mut self, enabled: bool) -> Self {
+        self.shared_memory = enabled;
+        self
+    }
+
+    pub fn build(self) -> Daemon {
+        let config = self.config;
+        let app_mappings = self.app_mappings.unwrap_or_else(|| {
+            let loaded = match config.paths.mappings_file() {
+                Some(file) => AppMappings::load_from(file),
+                None => AppMappings::load(),
+            };
+            match loaded {
+                Ok(mappings) => {
+                    info!("Loaded {} app mappings from disk", mappings.mappings.len());
+                    mappings
+                }
+                Err(e) => {
+                    error!("Failed to load app mappings: {}", e);
+                    AppMappings::default()
+                }
+            }
+        });
+
+        let cache = AudioCache::new()
+            .with_max_name_length(config.cache.max_name_length)
+            .with_per_stream_labels(config.cache.per_stream_labels)
+            .with_event_log_size(config.cache.event_log_size)
+            .with_sink_labels(
+                config
+                    .virtual_sinks
+                    .iter()
+                    .map(|sink| (sink.name.clone(), sink.display_name.clone())),
+            )
+            .with_saved_sink_labels(app_mappings.sink_labels.clone())
+            .with_persisted_sink_labels(config.persist_sink_labels)
+            .with_sink_order(config.sink_order())
+            .with_recent_sinks(app_mappings.recent_sinks.clone())
+            .with_pins(app_mappings.pins.clone())
+            .with_app_settings(app_mappings.app_settings.clone())
+            .with_focus_sink(config.routing.focus_sink.clone())
+            .with_routing(config.routing.clone())
+            .with_command_timeout(Duration::from_millis(config.performance.command_timeout_ms))
+            .with_volume_ramp(Duration::from_millis(config.performance.volume_ramp_ms))
+            .with_loopback_suffixes(config.loopback_target_suffixes.clone())
+            .with_route_refresh_delay(Duration::from_millis(
+                config.performance.route_refresh_delay_ms,
+            ))
+            .with_default_volumes(
+                config
+                    .virtual_sinks
+                    .iter()
+                    .filter_map(|sink| Some((sink.name.clone(), sink.default_volume?)))
+                    .chain(app_mappings.default_volumes.clone())
+                    .collect(),
+            );
+        cache.set_auto_routing(config.routing.enable_auto_routing);
+        cache.set_follow_focus(config.routing.follow_focus);
+        for (app_name, sink_name) in &app_mappings.mappings {
+            cache.remembered_apps.insert(app_name.clone(), sink_name.clone());
+            cache.routing_rules.insert(app_name.clone(), sink_name.clone());
+            debug!("Restored mapping: {} -> {}", app_name, sink_name);
+        }
+        let cache = Arc::new(RwLock::new(cache));
+
+        // One executor for the controller and the monitor, so tracing covers both
+        let executor: Arc<dyn CommandExecutor> = if config.trace_commands {
+            Arc::new(command_tracer(&config))
+        } else {
+            Arc::new(SystemCommandExecutor)
+        };
+        let controller = match self.backend {
+            Some(backend) => PipeWireController::with_backend(cache.clone(), backend),
+            None => PipeWireController::with_executor(cache.clone(), executor.clone()),
+        };
+        let controller = Arc::new(controller.with_cache_config(config.cache.clone()));
+
+        let socket_path = self
+            .socket_path
+            .or_else(|| config.paths.socket_path())
+            .unwrap_or_else(|| default_socket_path().into());
+        let shm_path = self.shared_memory.then(|| {
+            self.shm_path.or_else(|| config.paths.shm_path()).unwrap_or_else(default_shm_path)
+        });
+
+        Daemon {
+            config,
+            cache,
+            controller,
+            executor,
+            app_mappings: Arc::new(RwLock::new(app_mappings)),
+            socket_path,
+            shm_path,
+            dbus: self.dbus,
+            monitor: self.monitor,
+            shutdown: watch::channel(false).0,
+            created_modules: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+/// Executor logging each command the daemon runs, to the trace file too if one is set
+fn command_tracer(config: &Config) -> TracingExecutor {
+    let tracer = || TracingExecutor::new(Arc::new(SystemCommandExecutor));
+    let Some(path) = config.paths.command_trace_path() else {
+        return tracer();
+    };
+    match tracer().with_file(&path) {
+        Ok(tracer) => {
+            info!("Tracing commands to {}", path.display());
+            tracer
+        }
+        Err(e) => {
+            warn!("Failed to open command trace {}, logging only: {}", path.display(), e);
+            tracer()
+        }
+    }
+}
+
+/// The daemon's services around one shared cache
+pub struct Daemon {
+    config: Config,
+    cache: Arc<RwLock<AudioCache>>,
+    controller: Arc<PipeWireController>,
+    executor: Arc<dyn CommandExecutor>, // Runs the monitor's commands, traced with trace_commands
+    app_mappings: Arc<RwLock<AppMappings>>,
+    socket_path: PathBuf,
+    shm_path: Option<PathBuf>, // None with shared memory disabled
+    dbus: bool,
+    monitor: bool,
+    shutdown: watch::Sender<bool>,
+    created_modules: Mutex<Vec<u32>>, // Modules of virtual sinks this daemon created, unloaded on exit
+}
+
+impl Daemon {
+    pub fn builder(config: Config) -> DaemonBuilder {
+        DaemonBuilder {
+            config,
+            socket_path: None,
+            shm_path: None,
+            backend: None,
+            app_mappings: None,
+            dbus: true,
+            monitor: true,
+            shared_memory: true,
+        }
+    }
+
+    pub fn cache(&self) -> &Arc<RwLock<AudioCache>> {
+        &self.cache
+    }
+
+    pub fn controller(&self) -> &Arc<PipeWireController> {
+        &self.controller
+    }
+
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    /// Routing rules, remembered apps and default volumes as JSON, for [`Self::import_config`]
+    pub async fn export_config(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.cache.read().await.export_routing())?)
+    }
+
+    /// Apply a setup from [`Self::export_config`] and save it with the app mappings
+    ///
+    /// Nothing is applied if the data doesn't parse or names a sink that doesn't exist.
+    pub async fn import_config(&self, data: &str, mode: ImportMode) -> Result<()> {
+        let data: RoutingExport = serde_json::from_str(data).context("Invalid routing export")?;
+        import_routing(&self.cache, Some(&*self.app_mappings), data, mode).await
+    }
+
+    /// Run every service until [`Self::shutdown`] is called or PipeWire monitoring fails
+    pub async fn run(&self) -> Result<()> {
+        // Remember the server version for diagnostics
+        match self.controller.query_server_version().await {
+            Ok(version) => {
+                info!("Connected to PipeWire {}", version);
+                self.cache.read().await.set_server_version(version);
+            }
+            Err(e) => warn!("Could not determine PipeWire version: {}", e),
+        }
+
+        // Kept alive for as long as the daemon runs
+        let _dbus_connection = if self.dbus {
+            let connection = start_dbus_service(
+                self.cache.clone(),
+                self.controller.clone(),
+                self.app_mappings.clone(),
+            )
+            .await?;
+            info!("D-Bus service started on org.gnome.PipewireVolumeMixer");
+            Some(connection)
+        } else {
+            None
+        };
+
+        let control_uid = self.config.restrict_ipc_control.then(|| Uid::current().as_raw());
+        let ipc_server =
+            IpcServer::bind(self.cache.clone(), self.controller.clone(), &self.socket_path)?
+                .with_control_uid(control_uid)
+                .with_app_mappings(self.app_mappings.clone());
+        let mut tasks = vec![tokio::spawn(async move {
+            if let Err(e) = ipc_server.run().await {
+                error!("IPC server error: {}", e);
+            }
+        })];
+
+        let performance = &self.config.performance;
+        if let Some(shm_path) = &self.shm_path {
+            let intervals = (
+                Duration::from_millis(performance.snapshot_interval_min_ms),
+                Duration::from_millis(performance.snapshot_interval_max_ms),
+            );
+            tasks.push(tokio::spawn(run_shared_memory(
+                self.cache.clone(),
+                shm_path.clone(),
+                intervals,
+            )));
+        }
+
+        tasks.push(tokio::spawn(cleanup_inactive_apps(
+            self.cache.clone(),
+            AdaptiveInterval::new(
+                Duration::from_millis(performance.cleanup_interval_min_ms),
+                Duration::from_millis(performance.cleanup_interval_max_ms),
+                CLEANUP_IDLE_TICKS,
+            ),
+        )));
+
+        // Changes made over IPC, D-Bus or by routing are saved to the app mappings
+        let (pins_changed, settings_changed, recent_changed, rule_learned, labels_changed) = {
+            let cache = self.cache.read().await;
+            (
+                cache.pins_changed(),
+                cache.app_settings_changed(),
+                cache.recent_sinks_changed(),
+                cache.rule_learned(),
+                cache.labels_changed(),
+            )
+        };
+        tasks.push(tokio::spawn(save_on_change(
+            self.cache.clone(),
+            self.app_mappings.clone(),
+            pins_changed,
+            "pins",
+            |cache| {
+                let pins = cache.pins().into_iter().collect();
+                move |app_mappings: &mut AppMappings| app_mappings.pins = pins
+            },
+        )));
+        tasks.push(tokio::spawn(save_on_change(
+            self.cache.clone(),
+            self.app_mappings.clone(),
+            settings_changed,
+            "app volumes and mutes",
+            |cache| {
+                let settings = cache.app_settings().into_iter().collect();
+                move |app_mappings: &mut AppMappings| app_mappings.app_settings = settings
+            },
+        )));
+        // Recent sinks of apps the cache cleaned up are dropped from the file too
+        tasks.push(tokio::spawn(save_on_change(
+            self.cache.clone(),
+            self.app_mappings.clone(),
+            recent_changed,
+            "recent sinks",
+            |cache| {
+                let recent = cache.all_recent_sinks().into_iter().collect();
+                move |app_mappings: &mut AppMappings| app_mappings.recent_sinks = recent
+            },
+        )));
+        // Saved rules the cache has since dropped, e.g. as stale, are removed at the same time
+        tasks.push(tokio::spawn(save_on_change(
+            self.cache.clone(),
+            self.app_mappings.clone(),
+            rule_learned,
+            "learned routing rules",
+            |cache| {
+                let kept: HashSet<String> =
+                    cache.routing_rules.iter().map(|rule| rule.key().clone()).collect();
+                let learned = cache.take_learned_rules();
+                move |app_mappings: &mut AppMappings| {
+                    app_mappings.mappings.retain(|app_name, _| kept.contains(app_name));
+                    app_mappings.mappings.extend(learned);
+                }
+            },
+        )));
+        // Only with `persist_sink_labels`, else no label is ever marked to save
+        tasks.push(tokio::spawn(save_on_change(
+            self.cache.clone(),
+            self.app_mappings.clone(),
+            labels_changed,
+            "sink labels",
+            |cache| {
+                let labels = cache.labels_to_save().into_iter().collect();
+                move |app_mappings: &mut AppMappings| app_mappings.sink_labels = labels
+            },
+        )));
+        // The monitor follows the default sink through PipeWire's metadata instead
+        if !self.monitor {
+            tasks.push(tokio::spawn(track_default_sink(
+                self.cache.clone(),
+                self.controller.clone(),
+            )));
+        }
+        tasks.push(tokio::spawn(reconcile_stale_rules(
+            self.cache.clone(),
+            self.app_mappings.clone(),
+            self.config.routing.clone(),
+            self.config.virtual_sinks.iter().map(|sink| sink.name.clone()).collect(),
+        )));
+
+        // Runs whenever a focus sink is configured, so IPC can turn following on later
+        if let Some(focus_sink) = &self.config.routing.focus_sink {
+            tasks.push(tokio::spawn(
+                FocusFollower::new(
+                    self.cache.clone(),
+                    self.controller.clone(),
+                    AppNameDetector::new_system(),
+                    focus_sink.clone(),
+                )
+                .run(),
+            ));
+        }
+
+        if self.config.rescan_on_resume {
+            tasks.push(tokio::spawn(
+                ResumeWatcher::new(self.controller.clone(), self.config.routing.clone()).run(),
+            ));
+        }
+
+        // Created before monitoring starts, so the monitor finds them like any other sink
+        if self.config.auto_create_sinks {
+            match self.controller.create_missing_sinks(&self.config.virtual_sinks).await {
+                Ok(module_ids) => self.created_modules.lock().unwrap().extend(module_ids),
+                Err(e) => error!("Could not check for missing virtual sinks: {}", e),
+            }
+        }
+
+        // Backends that announce streams themselves stand in for the monitor, as in tests
+        if !self.monitor {
+            if let Some(new_streams) = self.controller.subscribe_new_streams() {
+                tasks.push(tokio::spawn(follow_new_streams(
+                    self.controller.clone(),
+                    self.config.routing.clone(),
+                    new_streams,
+                )));
+            }
+        }
+
+        let result = if self.monitor {
+            info!("Starting PipeWire monitoring");
+            // Errors fall through to the teardown below, which removes created sinks
+            match PipeWireMonitor::new(
+                self.cache.clone(),
+                self.config.clone(),
+                self.controller.clone(),
+            ) {
+                Ok(monitor) => {
+                    monitor
+                        .with_executor(self.executor.clone())
+                        .run(self.shutdown_requested())
+                        .await
+                }
+                Err(e) => Err(e),
+            }
+        } else {
+            self.shutdown_requested().await;
+            Ok(())
+        };
+
+        info!("Shutting down");
+        for task in tasks {
+            task.abort();
+        }
+        // Only what this daemon loaded; sinks the user set up themselves stay
+        let created_modules = std::mem::take(&mut *self.created_modules.lock().unwrap());
+        if !created_modules.is_empty() {
+            info!("Removing {} modules of created virtual sinks", created_modules.len());
+            self.controller.unload_modules(&created_modules).await;
+        }
+        self.controller.unload_combined_sinks().await;
+        let _ = std::fs::remove_file(&self.socket_path);
+        if let Some(shm_path) = &self.shm_path {
+            let _ = std::fs::remove_file(shm_path);
+        }
+
+        result
+    }
+
+    /// Ids of the modules loaded for virtual sinks this daemon created, unloaded on shutdown
+    pub fn created_modules(&self) -> Vec<u32> {
+        self.created_modules.lock().unwrap().clone()
+    }
+
+    /// Ask a running daemon to stop; `run` returns once its services are torn down
+    pub fn shutdown(&self) {
+        self.shutdown.send_replace(true);
+    }
+
+    async fn shutdown_requested(&self) {
+        let mut shutdown = self.shutdown.subscribe();
+        // The sender lives as long as `self`, so this only returns once it's set
+        let _ = shutdown.wait_for(|requested| *requested).await;
+    }
+}
+
+/// Publish snapshots to shared memory, waiting for it to become available if need be
+///
+/// Shared memory is optional: while it can't be set up, D-Bus and IPC clients are
+/// unaffected and setup is retried every [`SHM_RETRY_INTERVAL`].
+///
+/// `intervals` bounds how often the writer polls the cache, see
+/// [`SharedMemoryWriter::with_intervals`].
+async fn run_shared_memory(
+    cache: Arc<RwLock<AudioCache>>,
+    path: PathBuf,
+    (min, max): (Duration, Duration),
+) {
+    loop {
+        match SharedMemoryWriter::with_path(cache.clone(), &path) {
+            Ok(writer) => {
+                let writer = writer.with_intervals(min, max);
+                if let Err(e) = writer.run().await {
+                    error!("Shared memory writer error: {}", e);
+                }
+                return;
+            }
+            Err(e) => {
+                warn!(
+                    "Continuing without shared memory, retrying in {}s: {}",
+                    SHM_RETRY_INTERVAL.as_secs(),
+                    e
+                );
+                tokio::time::sleep(SHM_RETRY_INTERVAL).await;
+            }
+        }
+    }
+}
+
+/// Add each stream the backend announces to the cache and auto-route it
+async fn follow_new_streams(
+    controller: Arc<PipeWireController>,
+    routing: RoutingConfig,
+    mut new_streams: broadcast::Receiver<u32>,
+) {
+    loop {
+        match new_streams.recv().await {
+            // Each stream settles on its own, so a burst of them isn't routed one by one
+            Ok(sink_input_id) => {
+                let controller = controller.clone();
+                let routing = routing.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = controller.adopt_stream(sink_input_id, &routing).await {
+                        warn!("Could not take in new stream {}: {}", sink_input_id, e);
+                    }
+                });
+            }
+            // The next rescan still adds them to the cache, only unrouted
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("Missed {} new streams", missed);
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Save the app mappings each time `changed` is notified
+///
+/// `read` gathers what changed from the cache and returns the update to make to the
+/// app mappings, which are only locked once the cache no longer is. `what` names the
+/// data in the error logged if saving fails.
+async fn save_on_change<F: FnOnce(&mut AppMappings)>(
+    cache: Arc<RwLock<AudioCache>>,
+    app_mappings: Arc<RwLock<AppMappings>>,
+    changed: Arc<Notify>,
+    what: &str,
+    read: impl Fn(&AudioCache) -> F,
+) {
+    loop {
+        changed.notified().await;
+        let update = read(&*cache.read().await);
+        let mut app_mappings = app_mappings.write().await;
+        update(&mut app_mappings);
+        app_mappings.version += 1;
+        if let Err(e) = app_mappings.save() {
+            error!("Failed to save {}: {}", what, e);
+        }
+    }
+}
+
+/// Keep the cache's default sink up to date while the PipeWire monitor is off
+///
+/// pactl has no way to wait for the default to change, so it's asked again every
+/// [`DEFAULT_SINK_POLL_INTERVAL`] and right away when a sink appears or disappears.
+async fn track_default_sink(cache: Arc<RwLock<AudioCache>>, controller: Arc<PipeWireController>) {
+    let mut sink_events = cache.read().await.subscribe_sink_events();
+    let mut failing = false;
+    loop {
+        match controller.query_default_sink().await {
+            Ok(sink_name) => {
+                failing = false;
+                if cache.read().await.set_default_sink(&sink_name) {
+                    info!("Default sink is now {}", sink_name);
+                }
+            }
+            // Usually no output device at all, which tends to last a while
+            Err(e) if !failing => {
+                warn!("Could not determine the default sink: {}", e);
+                failing = true;
+            }
+            Err(e) => debug!("Could not determine the default sink: {}", e),
+        }
+
+        let poll = tokio::time::sleep(DEFAULT_SINK_POLL_INTERVAL);
+        tokio::pin!(poll);
+        loop {
+            tokio::select! {
+                _ = &mut poll => break,
+                event = sink_events.recv() => match event {
+                    Ok(SinkEvent::Added { .. })
+                    | Ok(SinkEvent::Removed { .. })
+                    | Err(broadcast::error::RecvError::Lagged(_)) => break,
+                    Ok(SinkEvent::StateChanged { .. }) => {}
+                    Err(broadcast::error::RecvError::Closed) => return,
+                },
+            }
+        }
+    }
+}
+
+/// Deal with routing rules naming virtual sinks removed from the config as
+/// `routing.stale_rules` says
+///
+/// Runs once the first scan is in, as every sink looks missing before. Sinks that
+/// are only missing, such as an unplugged device, keep their rules. Pruned and
+/// redirected rules are saved to the app mappings so they stay that way after a
+/// restart, along with the configured sinks to compare the next start with.
+async fn reconcile_stale_rules(
+    cache: Arc<RwLock<AudioCache>>,
+    app_mappings: Arc<RwLock<AppMappings>>,
+    routing: RoutingConfig,
+    virtual_sinks: Vec<String>,
+) {
+    let mut ready = cache.read().await.subscribe_ready();
+    if ready.wait_for(|ready| *ready).await.is_err() {
+        return;
+    }
+    let removed: Vec<String> = {
+        let app_mappings = app_mappings.read().await;
+        let saved = &app_mappings.virtual_sinks;
+        saved.iter().filter(|sink| !virtual_sinks.contains(sink)).cloned().collect()
+    };
+
+    // Each stale app with its rule after reconciling, None once pruned
+    let rules: Vec<(String, Option<String>)> = {
+        let cache = cache.read().await;
+        let stale =
+            cache.reconcile_routing_rules(routing.stale_rules, &routing.default_sink, &removed);
+        stale
+            .into_iter()
+            .map(|(app_name, _)| {
+                let rule = cache.routing_rules.get(&app_name).map(|rule| rule.clone());
+                (app_name, rule)
+            })
+            .collect()
+    };
+
+    let mut app_mappings = app_mappings.write().await;
+    let changed = !rules.is_empty() && routing.stale_rules != StaleRulePolicy::Keep;
+    if !changed && app_mappings.virtual_sinks == virtual_sinks {
+        return;
+    }
+    if changed {
+        for (app_name, rule) in rules {
+            match rule {
+                Some(sink_name) => app_mappings.mappings.insert(app_name, sink_name),
+                None => app_mappings.mappings.remove(&app_name),
+            };
+        }
+    }
+    app_mappings.virtual_sinks = virtual_sinks;
+    app_mappings.version += 1;
+    if let Err(e) = app_mappings.save() {
+        error!("Failed to save app mappings: {}", e);
+    }
+}
+
+/// Periodically drop apps that have been inactive for longer than the TTL
+///
+/// Runs back off along `schedule` while the cache generation stays the same, so an
+/// idle desktop is checked rarely and a burst of apps coming and going is cleaned
+/// up promptly.
+async fn cleanup_inactive_apps(cache: Arc<RwLock<AudioCache>>, mut schedule: AdaptiveInterval) {
+    let mut last_generation = cache.read().await.get_generation();
+    loop {
+        tokio::time::sleep(schedule.current()).await;
+
+        let generation = cache.read().await.get_generation();
+        schedule.tick(generation != last_generation);
+
+        // First do a quick check if there are any inactive apps at all
+        let inactive_count =
+            cache.read().await.apps.iter().filter(|entry| !entry.value().active).count();
+
+        // Only run cleanup if there are inactive apps
+        if inactive_count > 0 {
+            debug!("Running cleanup for {} inactive apps", inactive_count);
+            let removed = cache.read().await.cleanup_inactive_apps(INACTIVE_APP_TTL_SECONDS);
+            if removed > 0 {
+                info!("Cleaned up {} inactive apps after 5 minute TTL", removed);
+            } else {
+                debug!("No apps exceeded TTL yet");
+            }
+        }
+        // Our own removals aren't activity
+        last_generation = cache.read().await.get_generation();
+    }
+}
@@ -1,20 +1,89 @@
 use anyhow::Result;
-use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::RwLock;
-use tracing::{debug, error, info};
-use zbus::{dbus_interface, Connection, SignalContext};
-
-use crate::cache::AudioCache;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, watch, RwLock};
+use tracing::{debug, error, info, warn};
+use zbus::{dbus_interface, Connection, DBusError, SignalContext};
+
+use crate::cache::{parse_sink_targets, AudioCache, SinkEvent};
 use crate::config::AppMappings;
 use crate::pipewire_controller::PipeWireController;
+use crate::volume::linear_to_db;
+
+/// Error replies of the D-Bus methods, named so clients can tell the causes apart
+///
+/// Methods still return their `success` argument for clients written before these
+/// existed; it is always true, as failures come back as one of these instead.
+#[derive(Debug, DBusError)]
+#[dbus_error(prefix = "org.gnome.PipewireVolumeMixer.Error")]
+pub enum MethodError {
+    #[dbus_error(zbus_error)]
+    ZBus(zbus::Error),
+    /// No sink of that name is known
+    UnknownSink(String),
+    /// No app of that name is known
+    UnknownApp(String),
+    /// The app has no streams to act on
+    NoActiveStreams(String),
+    /// An argument is out of range or empty
+    InvalidArgs(String),
+    /// PipeWire or pactl refused or didn't answer
+    Failed(String),
+}
+
+impl MethodError {
+    /// Log a backend failure and turn it into a reply
+    fn failed(action: &str, err: anyhow::Error) -> Self {
+        error!("Failed to {}: {:#}", action, err);
+        Self::Failed(format!("Failed to {action}: {err:#}"))
+    }
+}
+
+/// Apps clients asked to hear about with AppStateChanged
+pub type WatchedApps = Arc<Mutex<HashSet<String>>>;
+
+/// What AppStateChanged reports about an app
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppState {
+    pub app_name: String,
+    pub current_sink: String, // Empty once the app has left the cache
+    pub active: bool,
+    pub volume: f64, // -1 until PipeWire has reported a level for the app
+    pub muted: bool,
+}
+
+impl AppState {
+    fn of(cache: &AudioCache, app_name: &str) -> Option<Self> {
+        let app = cache.apps.get(app_name)?;
+        Some(Self {
+            app_name: app_name.to_string(),
+            current_sink: app.current_sink.clone(),
+            active: app.active,
+            volume: app.volume.map_or(-1.0, f64::from),
+            muted: app.muted,
+        })
+    }
+
+    /// Sent once for an app that left the cache
+    fn gone(app_name: &str) -> Self {
+        Self {
+            app_name: app_name.to_string(),
+            current_sink: String::new(),
+            active: false,
+            volume: -1.0,
+            muted: false,
+        }
+    }
+}
 
 /// D-Bus service for the PipeWire Volume Mixer
 pub struct DBusService {
     cache: Arc<RwLock<AudioCache>>,
     controller: Arc<PipeWireController>,
-    generation: Arc<RwLock<u32>>,
     app_mappings: Arc<RwLock<AppMappings>>,
+    watched_apps: WatchedApps,
 }
 
 impl DBusService {
@@ -23,23 +92,49 @@ impl DBusService {
         controller: Arc<PipeWireController>,
         app_mappings: Arc<RwLock<AppMappings>>,
     ) -> Self {
-        Self { cache, controller, generation: Arc::new(RwLock::new(0)), app_mappings }
+        Self { cache, controller, app_mappings, watched_apps: WatchedApps::default() }
+    }
+
+    /// The apps watched with WatchApp, shared with whatever sends AppStateChanged
+    pub fn watched_apps(&self) -> WatchedApps {
+        self.watched_apps.clone()
     }
 
     /// Convert sinks to D-Bus HashMap
-    async fn sinks_to_hashmap(
-        &self,
+    fn sinks_to_hashmap(
+        cache: &AudioCache,
     ) -> Result<HashMap<String, HashMap<String, zbus::zvariant::Value<'static>>>> {
-        let cache = self.cache.read().await;
         let mut map = HashMap::new();
+        let uptimes = cache.sink_uptimes();
+        let labels = cache.sink_labels();
 
         for entry in cache.sinks.iter() {
             let (name, sink) = entry.pair();
             let mut sink_map = HashMap::new();
+            let label = labels.get(name).unwrap_or(name);
+            sink_map.insert(
+                "display_name".to_string(),
+                zbus::zvariant::Value::Str(label.clone().into()),
+            );
             sink_map
                 .insert("pipewire_id".to_string(), zbus::zvariant::Value::U32(sink.pipewire_id));
             sink_map.insert("volume".to_string(), zbus::zvariant::Value::F64(sink.volume as f64));
+            sink_map.insert(
+                "volume_db".to_string(),
+                zbus::zvariant::Value::F64(linear_to_db(sink.volume) as f64),
+            );
+            sink_map.insert(
+                "applied_percent".to_string(),
+                zbus::zvariant::Value::U32(sink.applied_percent),
+            );
             sink_map.insert("muted".to_string(), zbus::zvariant::Value::Bool(sink.muted));
+            // Only virtual sinks have a place in the panel
+            if let Some(order) = cache.sink_order(name) {
+                sink_map.insert("order".to_string(), zbus::zvariant::Value::U32(order));
+            }
+            let uptime = uptimes.get(name).copied().unwrap_or_default();
+            sink_map
+                .insert("uptime_seconds".to_string(), zbus::zvariant::Value::U64(uptime.as_secs()));
 
             map.insert(name.clone(), sink_map);
         }
@@ -48,10 +143,9 @@ impl DBusService {
     }
 
     /// Convert applications to D-Bus HashMap
-    async fn apps_to_hashmap(
-        &self,
+    fn apps_to_hashmap(
+        cache: &AudioCache,
     ) -> Result<HashMap<String, HashMap<String, zbus::zvariant::Value<'static>>>> {
-        let cache = self.cache.read().await;
         let mut map = HashMap::new();
 
         for entry in cache.apps.iter() {
@@ -59,7 +153,7 @@ impl DBusService {
             let mut app_map = HashMap::new();
             app_map.insert(
                 "display_name".to_string(),
-                zbus::zvariant::Value::Str(app.display_name.clone().into()),
+                zbus::zvariant::Value::Str(cache.app_label(app).to_string().into()),
             );
             app_map.insert(
                 "current_sink".to_string(),
@@ -67,6 +161,22 @@ impl DBusService {
             );
             app_map.insert("pipewire_id".to_string(), zbus::zvariant::Value::U32(app.pipewire_id));
             app_map.insert("active".to_string(), zbus::zvariant::Value::Bool(app.active));
+            app_map.insert("playing".to_string(), zbus::zvariant::Value::Bool(app.playing));
+            app_map.insert("muted".to_string(), zbus::zvariant::Value::Bool(app.muted));
+            app_map.insert(
+                "media_role".to_string(),
+                zbus::zvariant::Value::Str(app.media_role.clone().unwrap_or_default().into()),
+            );
+            if let Some(title) = &app.window_title {
+                app_map.insert(
+                    "window_title".to_string(),
+                    zbus::zvariant::Value::Str(title.clone().into()),
+                );
+            }
+            // Left out until PipeWire has reported a level for the app
+            if let Some(volume) = app.volume {
+                app_map.insert("volume".to_string(), zbus::zvariant::Value::F64(volume as f64));
+            }
 
             map.insert(name.clone(), app_map);
         }
@@ -74,11 +184,64 @@ impl DBusService {
         Ok(map)
     }
 
-    /// Increment generation counter
-    async fn increment_generation(&self) -> u32 {
-        let mut gen = self.generation.write().await;
-        *gen += 1;
-        *gen
+    /// Fail with [`MethodError::UnknownSink`] unless the sink is in the cache
+    async fn require_sink(&self, sink_name: &str) -> Result<(), MethodError> {
+        if self.cache.read().await.sinks.contains_key(sink_name) {
+            Ok(())
+        } else {
+            warn!("D-Bus: Unknown sink {}", sink_name);
+            Err(MethodError::UnknownSink(format!("Unknown sink: {sink_name}")))
+        }
+    }
+
+    /// Route an app and save the choice, everything RouteApplication does before signalling
+    pub async fn route_and_save(&self, app_name: &str, sink_name: &str) -> Result<(), MethodError> {
+        let targets = parse_sink_targets(sink_name);
+        if targets.is_empty() {
+            return Err(MethodError::InvalidArgs("No sink to route to".to_string()));
+        }
+        for target in &targets {
+            self.require_sink(target).await?;
+        }
+
+        // Apply to PipeWire first
+        self.controller
+            .route_app(app_name, sink_name)
+            .await
+            .map_err(|e| MethodError::failed("route application", e))?;
+
+        // Controller already updated the cache with the actual result, and its recent sinks
+        self.cache.read().await.routing_rules.insert(app_name.to_string(), sink_name.to_string());
+
+        // Save mapping to disk for persistence
+        {
+            let mut mappings = self.app_mappings.write().await;
+            if let Err(e) = mappings.update_and_save(app_name.to_string(), sink_name.to_string()) {
+                error!("Failed to save app mapping to disk: {}", e);
+                // Don't fail the routing operation if save fails
+            } else {
+                debug!("Saved mapping {} -> {} to disk", app_name, sink_name);
+            }
+        }
+
+        // StateChanged is emitted by the coalescing task once the generation moves
+        self.increment_generation().await;
+        Ok(())
+    }
+
+    /// Refresh once PipeWire has settled after a route, see [`AudioCache::route_refresh_delay`]
+    pub async fn refresh_after_route(&self) {
+        let delay = self.cache.read().await.route_refresh_delay();
+        if delay.is_zero() {
+            return;
+        }
+        tokio::time::sleep(delay).await;
+        self.refresh_state().await;
+    }
+
+    /// Increment the cache generation, which schedules a StateChanged signal
+    async fn increment_generation(&self) {
+        self.cache.read().await.increment_generation();
     }
 
     /// Get current timestamp
@@ -95,7 +258,7 @@ impl DBusService {
     /// Get all sinks
     #[dbus_interface(property)]
     async fn sinks(&self) -> HashMap<String, HashMap<String, zbus::zvariant::Value<'static>>> {
-        self.sinks_to_hashmap().await.unwrap_or_else(|e| {
+        Self::sinks_to_hashmap(&*self.cache.read().await).unwrap_or_else(|e| {
             error!("Failed to convert sinks: {}", e);
             HashMap::new()
         })
@@ -106,7 +269,7 @@ impl DBusService {
     async fn applications(
         &self,
     ) -> HashMap<String, HashMap<String, zbus::zvariant::Value<'static>>> {
-        self.apps_to_hashmap().await.unwrap_or_else(|e| {
+        Self::apps_to_hashmap(&*self.cache.read().await).unwrap_or_else(|e| {
             error!("Failed to convert apps: {}", e);
             HashMap::new()
         })
@@ -115,7 +278,25 @@ impl DBusService {
     /// Get generation counter
     #[dbus_interface(property)]
     async fn generation(&self) -> u32 {
-        *self.generation.read().await
+        self.cache.read().await.get_generation() as u32
+    }
+
+    /// PipeWire server version, empty if it could not be determined
+    #[dbus_interface(property)]
+    async fn server_version(&self) -> String {
+        self.cache.read().await.server_version().unwrap_or_default().to_string()
+    }
+
+    /// Sink new streams play to by default, empty until it's known
+    #[dbus_interface(property)]
+    async fn default_sink(&self) -> String {
+        self.cache.read().await.default_sink().unwrap_or_default()
+    }
+
+    /// Whether the first full scan of PipeWire has finished
+    #[dbus_interface(property)]
+    async fn ready(&self) -> bool {
+        self.cache.read().await.is_ready()
     }
 
     /// Get last update timestamp
@@ -125,45 +306,114 @@ impl DBusService {
     }
 
     /// Set sink volume
-    async fn set_sink_volume(&self, sink_name: String, volume: f64) -> bool {
+    pub async fn set_sink_volume(
+        &self,
+        sink_name: String,
+        volume: f64,
+    ) -> Result<bool, MethodError> {
         debug!("D-Bus: Setting volume for sink {} to {}", sink_name, volume);
-
-        // Update cache optimistically
-        {
-            let cache = self.cache.write().await;
-            if let Some(mut sink) = cache.sinks.get_mut(&sink_name) {
-                sink.volume = volume as f32;
-            };
+        if !volume.is_finite() {
+            error!("Refusing to set sink {} to volume {}", sink_name, volume);
+            return Err(MethodError::InvalidArgs(format!("Volume must be finite, got {volume}")));
         }
+        self.require_sink(&sink_name).await?;
 
-        // Apply to PipeWire
-        if let Err(e) = self.controller.set_sink_volume(&sink_name, volume as f32).await {
-            error!("Failed to set sink volume: {}", e);
-            return false;
-        }
+        // Clamped before narrowing, so a huge value can't become infinite
+        let volume = volume.clamp(0.0, 1.0) as f32;
+        // Apply to PipeWire; the controller updates the cache under the sink's lock
+        self.controller
+            .set_sink_volume(&sink_name, volume)
+            .await
+            .map_err(|e| MethodError::failed("set sink volume", e))?;
 
-        true
+        Ok(true)
     }
 
     /// Set sink mute state
-    async fn set_sink_mute(&self, sink_name: String, muted: bool) -> bool {
+    pub async fn set_sink_mute(&self, sink_name: String, muted: bool) -> Result<bool, MethodError> {
         debug!("D-Bus: Setting mute for sink {} to {}", sink_name, muted);
+        self.require_sink(&sink_name).await?;
 
-        // Update cache optimistically
-        {
-            let cache = self.cache.write().await;
-            if let Some(mut sink) = cache.sinks.get_mut(&sink_name) {
-                sink.muted = muted;
-            };
+        // Apply to PipeWire; the controller updates the cache under the sink's lock
+        self.controller
+            .set_sink_mute(&sink_name, muted)
+            .await
+            .map_err(|e| MethodError::failed("set sink mute", e))?;
+
+        Ok(true)
+    }
+
+    /// Reset a sink to its default volume and unmute it
+    async fn reset_sink_volume(&self, sink_name: String) -> Result<bool, MethodError> {
+        debug!("D-Bus: Resetting volume for sink {}", sink_name);
+        self.require_sink(&sink_name).await?;
+
+        self.controller
+            .reset_sink_volume(&sink_name)
+            .await
+            .map_err(|e| MethodError::failed("reset sink volume", e))?;
+
+        Ok(true)
+    }
+
+    /// Mute one app's streams without muting the sink it shares with others
+    async fn set_app_mute(&self, app_name: String, muted: bool) -> Result<bool, MethodError> {
+        debug!("D-Bus: Setting mute for app {} to {}", app_name, muted);
+        if !self.cache.read().await.apps.contains_key(&app_name) {
+            return Err(MethodError::UnknownApp(format!("Unknown app: {app_name}")));
         }
 
-        // Apply to PipeWire
-        if let Err(e) = self.controller.set_sink_mute(&sink_name, muted).await {
-            error!("Failed to set sink mute: {}", e);
-            return false;
+        match self.controller.set_app_mute(&app_name, muted).await {
+            Ok(0) => {
+                warn!("App {} has no streams to mute", app_name);
+                Err(MethodError::NoActiveStreams(format!("{app_name} has no streams to mute")))
+            }
+            Ok(_) => Ok(true),
+            Err(e) => Err(MethodError::failed("set app mute", e)),
         }
+    }
 
-        true
+    /// Set one app's stream volume without touching the sink it shares with others
+    ///
+    /// The volume is remembered and given to the app's streams when it comes back.
+    pub async fn set_app_volume(&self, app_name: String, volume: f64) -> Result<bool, MethodError> {
+        debug!("D-Bus: Setting volume for app {} to {}", app_name, volume);
+        if !volume.is_finite() {
+            error!("Refusing to set app {} to volume {}", app_name, volume);
+            return Err(MethodError::InvalidArgs(format!("Volume must be finite, got {volume}")));
+        }
+        if !self.cache.read().await.apps.contains_key(&app_name) {
+            return Err(MethodError::UnknownApp(format!("Unknown app: {app_name}")));
+        }
+
+        // Clamped before narrowing, so a huge value can't become infinite
+        let volume = volume.clamp(0.0, 1.0) as f32;
+        match self.controller.set_app_volume(&app_name, volume).await {
+            Ok(0) => {
+                warn!("App {} has no streams to set the volume of", app_name);
+                Err(MethodError::NoActiveStreams(format!("{app_name} has no active streams")))
+            }
+            Ok(_) => Ok(true),
+            Err(e) => Err(MethodError::failed("set app volume", e)),
+        }
+    }
+
+    /// Show a sink under a new label, leaving its PipeWire node name alone
+    pub async fn set_sink_display_name(
+        &self,
+        sink_name: String,
+        display_name: String,
+    ) -> Result<bool, MethodError> {
+        debug!("D-Bus: Labeling sink {} as {}", sink_name, display_name);
+
+        self.require_sink(&sink_name).await?;
+        if display_name.trim().is_empty() {
+            error!("Cannot give sink {} an empty label", sink_name);
+            return Err(MethodError::InvalidArgs("The label must not be empty".to_string()));
+        }
+        // The generation bump schedules StateChanged, and the daemon saves the label
+        self.cache.read().await.set_sink_label(&sink_name, display_name);
+        Ok(true)
     }
 
     /// Route application to a sink
@@ -172,74 +422,143 @@ impl DBusService {
         #[zbus(signal_context)] ctx: SignalContext<'_>,
         app_name: String,
         sink_name: String,
-    ) -> bool {
+    ) -> Result<bool, MethodError> {
         debug!("D-Bus: Routing app {} to sink {}", app_name, sink_name);
 
-        // Apply to PipeWire first
-        if let Err(e) = self.controller.route_app(&app_name, &sink_name).await {
-            error!("Failed to route application: {}", e);
-            return false;
-        }
-
-        // Controller already updated the cache with the actual result
-
-        // Save mapping to disk for persistence
-        {
-            let mut mappings = self.app_mappings.write().await;
-            if let Err(e) = mappings.update_and_save(app_name.clone(), sink_name.clone()) {
-                error!("Failed to save app mapping to disk: {}", e);
-                // Don't fail the routing operation if save fails
-            } else {
-                debug!("Saved mapping {} -> {} to disk", app_name, sink_name);
-            }
-        }
+        self.route_and_save(&app_name, &sink_name).await?;
 
         // Emit the ApplicationRouted signal
         if let Err(e) = Self::application_routed(&ctx, &app_name, &sink_name).await {
             error!("Failed to emit ApplicationRouted signal: {}", e);
         }
 
-        // Emit property changed signal for Applications
-        let new_gen = self.increment_generation().await;
-        if let Err(e) = Self::state_changed(&ctx, new_gen).await {
-            error!("Failed to emit StateChanged signal: {}", e);
-        }
+        self.refresh_after_route().await;
 
-        // Wait a bit and refresh to ensure cache is in sync with PipeWire
-        tokio::time::sleep(std::time::Duration::from_millis(300)).await;
-        self.refresh_state().await;
-
-        true
+        Ok(true)
     }
 
     /// Force refresh of state
     async fn refresh_state(&self) {
         debug!("D-Bus: Refreshing state");
-        let _ = self.increment_generation().await;
+        self.increment_generation().await;
     }
 
-    /// Get full state as a single HashMap
-    async fn get_full_state(&self) -> HashMap<String, zbus::zvariant::Value<'static>> {
-        let mut state = HashMap::new();
+    /// Get full state as a single HashMap, taken from one generation of the cache
+    pub async fn get_full_state(&self) -> HashMap<String, zbus::zvariant::Value<'static>> {
+        let (generation, (sinks, apps)) = self.cache.read().await.read_consistent(|cache| {
+            (
+                Self::sinks_to_hashmap(cache).unwrap_or_default(),
+                Self::apps_to_hashmap(cache).unwrap_or_default(),
+            )
+        });
 
-        state.insert("sinks".to_string(), zbus::zvariant::Value::from(self.sinks().await));
-        state.insert(
-            "applications".to_string(),
-            zbus::zvariant::Value::from(self.applications().await),
-        );
-        state.insert("generation".to_string(), zbus::zvariant::Value::U32(self.generation().await));
-        state.insert(
-            "last_update".to_string(),
-            zbus::zvariant::Value::U32(self.last_update().await),
-        );
+        let mut state = HashMap::new();
+        state.insert("sinks".to_string(), zbus::zvariant::Value::from(sinks));
+        state.insert("applications".to_string(), zbus::zvariant::Value::from(apps));
+        state.insert("generation".to_string(), zbus::zvariant::Value::U32(generation as u32));
+        state.insert("last_update".to_string(), zbus::zvariant::Value::U32(Self::get_timestamp()));
 
         state
     }
 
+    /// Mute every sink except this one until unsolo is called
+    async fn solo_sink(&self, sink_name: String) -> Result<bool, MethodError> {
+        debug!("D-Bus: Soloing sink {}", sink_name);
+        self.require_sink(&sink_name).await?;
+        self.controller
+            .solo_sink(&sink_name)
+            .await
+            .map_err(|e| MethodError::failed("solo sink", e))?;
+        Ok(true)
+    }
+
+    /// Restore the mute states from before the last solo
+    async fn unsolo(&self) -> Result<bool, MethodError> {
+        debug!("D-Bus: Ending solo");
+        self.controller.unsolo().await.map_err(|e| MethodError::failed("end solo", e))?;
+        Ok(true)
+    }
+
+    /// Mute every virtual sink at once, for a panic-mute key
+    async fn mute_all(&self) -> Result<bool, MethodError> {
+        debug!("D-Bus: Muting all sinks");
+        self.controller.mute_all().await.map_err(|e| MethodError::failed("mute all sinks", e))?;
+        Ok(true)
+    }
+
+    /// Restore the mute states from before mute_all or a solo
+    async fn unmute_all(&self) -> Result<bool, MethodError> {
+        debug!("D-Bus: Unmuting all sinks");
+        self.controller
+            .unmute_all()
+            .await
+            .map_err(|e| MethodError::failed("unmute all sinks", e))?;
+        Ok(true)
+    }
+
+    /// Rebuild sinks and apps from PipeWire, for when ids changed after a suspend
+    async fn rescan(&self) -> Result<bool, MethodError> {
+        debug!("D-Bus: Rescanning PipeWire");
+        self.controller.rescan().await.map_err(|e| MethodError::failed("rescan PipeWire", e))?;
+        Ok(true)
+    }
+
+    /// Turn automatic routing of new streams on or off
+    async fn set_auto_routing(&self, enabled: bool) {
+        info!("D-Bus: Setting auto-routing to {}", enabled);
+        self.cache.read().await.set_auto_routing(enabled);
+    }
+
+    /// Stop shared memory updates while no client needs them
+    async fn pause(&self) {
+        info!("D-Bus: Pausing monitoring");
+        self.cache.read().await.pause();
+    }
+
+    /// Restart shared memory updates, publishing a fresh snapshot immediately
+    async fn resume(&self) {
+        info!("D-Bus: Resuming monitoring");
+        self.cache.read().await.resume();
+    }
+
+    /// Send AppStateChanged whenever this app changes, until unwatched
+    pub async fn watch_app(&self, app_name: String) {
+        debug!("D-Bus: Watching app {}", app_name);
+        self.watched_apps.lock().unwrap_or_else(|e| e.into_inner()).insert(app_name);
+    }
+
+    /// Stop sending AppStateChanged for this app
+    pub async fn unwatch_app(&self, app_name: String) {
+        debug!("D-Bus: No longer watching app {}", app_name);
+        self.watched_apps.lock().unwrap_or_else(|e| e.into_inner()).remove(&app_name);
+    }
+
+    /// Sinks an app was recently routed to, newest first, for quick-switch buttons
+    pub async fn get_recent_sinks(&self, app_name: String) -> Vec<String> {
+        debug!("D-Bus: Getting recent sinks for app {}", app_name);
+        self.cache.read().await.recent_sinks(&app_name)
+    }
+
+    /// Names of the virtual sinks in the order the panel should show them
+    pub async fn get_virtual_sinks(&self) -> Vec<String> {
+        debug!("D-Bus: Getting virtual sinks");
+        self.cache.read().await.ordered_virtual_sinks()
+    }
+
+    /// Get the names of the apps currently routed to a sink
+    pub async fn get_apps_for_sink(&self, sink_name: String) -> Vec<String> {
+        debug!("D-Bus: Getting apps for sink {}", sink_name);
+        self.cache.read().await.apps_for_sink(&sink_name)
+    }
+
     /// Signal: State changed
     #[dbus_interface(signal)]
     async fn state_changed(ctx: &SignalContext<'_>, generation: u32) -> zbus::Result<()>;
 
+    /// Signal: The first full scan of PipeWire is in the cache, sent once
+    #[dbus_interface(signal)]
+    async fn initial_ready(ctx: &SignalContext<'_>, generation: u32) -> zbus::Result<()>;
+
     /// Signal: Sink volume changed
     #[dbus_interface(signal)]
     async fn sink_volume_changed(
@@ -256,6 +575,16 @@ impl DBusService {
         muted: bool,
     ) -> zbus::Result<()>;
 
+    /// Signal: Sink volume, mute state or presence changed, carrying all of them
+    #[dbus_interface(signal)]
+    async fn sink_state_changed(
+        ctx: &SignalContext<'_>,
+        sink_name: &str,
+        volume: f64,
+        muted: bool,
+        present: bool,
+    ) -> zbus::Result<()>;
+
     /// Signal: Application routed
     #[dbus_interface(signal)]
     async fn application_routed(
@@ -264,6 +593,29 @@ impl DBusService {
         sink_name: &str,
     ) -> zbus::Result<()>;
 
+    /// Signal: Output device connected
+    #[dbus_interface(signal)]
+    async fn sink_added(
+        ctx: &SignalContext<'_>,
+        sink_name: &str,
+        display_name: &str,
+    ) -> zbus::Result<()>;
+
+    /// Signal: Output device disconnected
+    #[dbus_interface(signal)]
+    async fn sink_removed(ctx: &SignalContext<'_>, sink_name: &str) -> zbus::Result<()>;
+
+    /// Signal: A watched app's routing, activity, volume or mute state changed
+    #[dbus_interface(signal)]
+    async fn app_state_changed(
+        ctx: &SignalContext<'_>,
+        app_name: &str,
+        current_sink: &str,
+        active: bool,
+        volume: f64,
+        muted: bool,
+    ) -> zbus::Result<()>;
+
     /// Signal: Applications changed
     #[dbus_interface(signal)]
     async fn apps_changed(
@@ -281,7 +633,19 @@ pub async fn start_dbus_service(
 ) -> Result<Connection> {
     info!("Starting D-Bus service");
 
+    let (changes, app_changes, sink_events, ready) = {
+        let cache = cache.read().await;
+        (
+            cache.subscribe_changes(),
+            cache.subscribe_changes(),
+            cache.subscribe_sink_events(),
+            cache.subscribe_ready(),
+        )
+    };
+    let generation_cache = cache.clone();
+    let app_cache = cache.clone();
     let service = DBusService::new(cache, controller, app_mappings);
+    let watched_apps = service.watched_apps();
 
     let connection = Connection::session().await?;
 
@@ -291,19 +655,214 @@ pub async fn start_dbus_service(
     // Request the bus name
     connection.request_name("org.gnome.PipewireVolumeMixer").await?;
 
+    // Emit StateChanged for cache changes, at most once per window
+    let signal_connection = connection.clone();
+    tokio::spawn(coalesce_changes(changes, STATE_CHANGED_WINDOW, move |generation| {
+        let connection = signal_connection.clone();
+        async move {
+            if let Err(e) = emit_state_changed(&connection, generation as u32).await {
+                error!("Failed to emit StateChanged signal: {}", e);
+            }
+        }
+    }));
+
+    // Tell clients once the first scan is complete, without waiting for the
+    // coalescing window, so they don't keep showing an empty mixer
+    let signal_connection = connection.clone();
+    tokio::spawn(announce_ready(ready, move || {
+        let connection = signal_connection.clone();
+        let cache = generation_cache.clone();
+        async move {
+            let generation = cache.read().await.get_generation() as u32;
+            if let Err(e) = emit_initial_ready(&connection, generation).await {
+                error!("Failed to emit InitialReady signal: {}", e);
+            }
+        }
+    }));
+
+    // Announce output devices as they come and go, and sink state changes
+    let signal_connection = connection.clone();
+    tokio::spawn(forward_sink_events(sink_events, move |event| {
+        let connection = signal_connection.clone();
+        async move {
+            if let Err(e) = emit_sink_event(&connection, &event).await {
+                error!("Failed to emit sink event signal: {}", e);
+            }
+        }
+    }));
+
+    // Tell clients about the apps they watch, without them following every change
+    let signal_connection = connection.clone();
+    tokio::spawn(forward_app_changes(
+        app_changes,
+        app_cache,
+        watched_apps,
+        STATE_CHANGED_WINDOW,
+        move |state| {
+            let connection = signal_connection.clone();
+            async move {
+                if let Err(e) = emit_app_state_changed(&connection, &state).await {
+                    error!("Failed to emit AppStateChanged signal: {}", e);
+                }
+            }
+        },
+    ));
+
     info!("D-Bus service started successfully");
 
     Ok(connection)
 }
 
+/// Minimum time between two StateChanged signals
+pub const STATE_CHANGED_WINDOW: Duration = Duration::from_millis(50);
+
+/// Call `emit` with the latest generation at most once per `window`
+///
+/// The first change opens a window; every change arriving within it is folded
+/// into a single call carrying the newest generation. Runs until the cache is dropped.
+pub async fn coalesce_changes<F, Fut>(
+    mut changes: watch::Receiver<u64>,
+    window: Duration,
+    mut emit: F,
+) where
+    F: FnMut(u64) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    while changes.changed().await.is_ok() {
+        tokio::time::sleep(window).await;
+        let generation = *changes.borrow_and_update();
+        debug!("Emitting coalesced StateChanged for generation {}", generation);
+        emit(generation).await;
+    }
+}
+
+/// Call `emit` once the cache becomes ready, or right away if it already is
+///
+/// Returns without calling `emit` if the cache is dropped before that.
+pub async fn announce_ready<F, Fut>(mut ready: watch::Receiver<bool>, emit: F)
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    if ready.wait_for(|ready| *ready).await.is_ok() {
+        emit().await;
+    }
+}
+
+/// Call `emit` for each sink event until the cache is dropped
+///
+/// Events missed by falling behind are skipped; the next full state refresh
+/// picks up the current devices and sink states.
+pub async fn forward_sink_events<F, Fut>(mut events: broadcast::Receiver<SinkEvent>, mut emit: F)
+where
+    F: FnMut(SinkEvent) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    loop {
+        match events.recv().await {
+            Ok(event) => emit(event).await,
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("Dropped {} sink events", missed);
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Call `emit` for each watched app whose state changed, at most once per `window`
+///
+/// Each app is compared with what was last sent for it, so changes to other apps
+/// send nothing. An app leaving the cache is sent once more, inactive on no sink.
+/// Runs until the cache is dropped.
+pub async fn forward_app_changes<F, Fut>(
+    mut changes: watch::Receiver<u64>,
+    cache: Arc<RwLock<AudioCache>>,
+    watched: WatchedApps,
+    window: Duration,
+    mut emit: F,
+) where
+    F: FnMut(AppState) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut sent: HashMap<String, AppState> = HashMap::new();
+    while changes.changed().await.is_ok() {
+        tokio::time::sleep(window).await;
+        changes.borrow_and_update();
+
+        let watched = watched.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        // Watching an app again starts over from whatever state it is in then
+        sent.retain(|app_name, _| watched.contains(app_name));
+        let states: Vec<(String, Option<AppState>)> = {
+            let cache = cache.read().await;
+            watched
+                .into_iter()
+                .map(|app_name| {
+                    let state = AppState::of(&cache, &app_name);
+                    (app_name, state)
+                })
+                .collect()
+        };
+        for (app_name, state) in states {
+            // A watched app that hasn't appeared yet isn't news
+            let state = match (state, sent.get(&app_name)) {
+                (Some(state), previous) if previous != Some(&state) => state,
+                (None, Some(previous)) if *previous != AppState::gone(&app_name) => {
+                    AppState::gone(&app_name)
+                }
+                _ => continue,
+            };
+            sent.insert(app_name, state.clone());
+            emit(state).await;
+        }
+    }
+}
+
+/// Helper to emit AppStateChanged for a watched app
+pub async fn emit_app_state_changed(connection: &Connection, state: &AppState) -> Result<()> {
+    let ctx = SignalContext::new(connection, "/org/gnome/PipewireVolumeMixer")?;
+    DBusService::app_state_changed(
+        &ctx,
+        &state.app_name,
+        &state.current_sink,
+        state.active,
+        state.volume,
+        state.muted,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Helper to emit SinkAdded, SinkRemoved or SinkStateChanged for a sink event
+pub async fn emit_sink_event(connection: &Connection, event: &SinkEvent) -> Result<()> {
+    let ctx = SignalContext::new(connection, "/org/gnome/PipewireVolumeMixer")?;
+    match event {
+        SinkEvent::Added { sink_name, display_name } => {
+            DBusService::sink_added(&ctx, sink_name, display_name).await?
+        }
+        SinkEvent::Removed { sink_name } => DBusService::sink_removed(&ctx, sink_name).await?,
+        SinkEvent::StateChanged { sink_name, volume, muted, present } => {
+            DBusService::sink_state_changed(&ctx, sink_name, *volume as f64, *muted, *present)
+                .await?
+        }
+    }
+    Ok(())
+}
+
 /// Helper to emit state change signals
-#[allow(dead_code)]
 pub async fn emit_state_changed(connection: &Connection, generation: u32) -> Result<()> {
     let ctx = SignalContext::new(connection, "/org/gnome/PipewireVolumeMixer")?;
     DBusService::state_changed(&ctx, generation).await?;
     Ok(())
 }
 
+/// Helper to emit StateChanged followed by InitialReady for the first full state
+pub async fn emit_initial_ready(connection: &Connection, generation: u32) -> Result<()> {
+    let ctx = SignalContext::new(connection, "/org/gnome/PipewireVolumeMixer")?;
+    DBusService::state_changed(&ctx, generation).await?;
+    DBusService::initial_ready(&ctx, generation).await?;
+    Ok(())
+}
+
 /// Helper to emit sink volume changed signal
 #[allow(dead_code)]
 pub async fn emit_sink_volume_changed(
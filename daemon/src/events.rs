@@ -0,0 +1,91 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Events kept for the `EVENTS` command unless configured otherwise
+pub const DEFAULT_EVENT_LOG_SIZE: usize = 200;
+
+/// Something that happened to a stream, route or sink
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum EventKind {
+    #[allow(dead_code)] // Recorded by the PipeWire monitor
+    StreamAdded {
+        app: String,
+        sink_input_id: u32,
+    },
+    #[allow(dead_code)] // Recorded by the PipeWire monitor
+    StreamRemoved {
+        app: String,
+        sink_input_id: u32,
+    },
+    RouteApplied {
+        app: String,
+        sink: String,
+    },
+    RouteFailed {
+        app: String,
+        sink: String,
+        error: String,
+    },
+    VolumeChanged {
+        sink: String,
+        volume: f32,
+    },
+    SinkAdded {
+        sink: String,
+    },
+    SinkRemoved {
+        sink: String,
+    },
+}
+
+/// An event with the time it was recorded
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Event {
+    pub timestamp_ms: u64, // Milliseconds since the Unix epoch
+    #[serde(flatten)]
+    pub kind: EventKind,
+}
+
+/// Ring buffer of the most recent events, dropping the oldest once full
+#[derive(Debug)]
+pub struct EventLog {
+    capacity: usize,
+    events: Mutex<VecDeque<Event>>,
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_EVENT_LOG_SIZE)
+    }
+}
+
+impl EventLog {
+    /// A log holding up to `capacity` events; 0 records nothing
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, events: Mutex::new(VecDeque::with_capacity(capacity)) }
+    }
+
+    pub fn record(&self, kind: EventKind) {
+        if self.capacity == 0 {
+            return;
+        }
+        let timestamp_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_millis() as u64)
+            .unwrap_or_default();
+        let mut events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back(Event { timestamp_ms, kind });
+    }
+
+    /// The last `count` events, oldest first
+    pub fn recent(&self, count: usize) -> Vec<Event> {
+        let events = self.events.lock().unwrap_or_else(|e| e.into_inner());
+        events.iter().skip(events.len().saturating_sub(count)).cloned().collect()
+    }
+}
@@ -0,0 +1,133 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{debug, error, info};
+
+use crate::app_name_detector::AppNameDetector;
+use crate::cache::AudioCache;
+use crate::pipewire_controller::PipeWireController;
+use crate::sink_inputs::SinkInput;
+
+/// How often the focused window is checked while following focus
+pub const FOCUS_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Cached app playing audio from the process that owns the focused window
+///
+/// `apps` pairs each cached app with its sink input ids. A stream matches when it
+/// comes from the focused process or one of its children, as with browsers that play
+/// audio from a helper process. Returns None if no window is focused or the focused
+/// app isn't playing anything.
+pub fn focused_app(
+    detector: &AppNameDetector,
+    inputs: &[SinkInput],
+    apps: &[(String, Vec<u32>)],
+) -> Option<String> {
+    let focused_pid = detector.active_window_pid()?;
+    let stream_ids: Vec<u32> = inputs
+        .iter()
+        .filter(|input| {
+            input.process_id().is_some_and(|pid| detector.is_same_or_child_of(pid, focused_pid))
+        })
+        .map(SinkInput::serial)
+        .collect();
+
+    apps.iter()
+        .find(|(_, sink_input_ids)| sink_input_ids.iter().any(|id| stream_ids.contains(id)))
+        .map(|(name, _)| name.clone())
+}
+
+/// Moves the app with window focus to the focus sink while follow focus is on
+///
+/// The app that had focus before goes back to the sink it was on when it got focus.
+pub struct FocusFollower {
+    cache: Arc<RwLock<AudioCache>>,
+    controller: Arc<PipeWireController>,
+    detector: Arc<AppNameDetector>,
+    focus_sink: String,
+    focused: Option<(String, String)>, // App on the focus sink and the sink it came from
+}
+
+impl FocusFollower {
+    pub fn new(
+        cache: Arc<RwLock<AudioCache>>,
+        controller: Arc<PipeWireController>,
+        detector: AppNameDetector,
+        focus_sink: String,
+    ) -> Self {
+        Self { cache, controller, detector: Arc::new(detector), focus_sink, focused: None }
+    }
+
+    /// Follow focus until the task is aborted, idling while follow focus is off
+    pub async fn run(mut self) {
+        info!("Following window focus to sink {}", self.focus_sink);
+        loop {
+            tokio::time::sleep(FOCUS_POLL_INTERVAL).await;
+            let target = if self.cache.read().await.follow_focus_enabled() {
+                self.find_focused_app().await
+            } else {
+                None
+            };
+            self.focus(target).await;
+        }
+    }
+
+    async fn find_focused_app(&self) -> Option<String> {
+        let inputs = match self.controller.list_sink_inputs().await {
+            Ok(inputs) => inputs,
+            Err(e) => {
+                debug!("Could not list streams to follow focus: {}", e);
+                return None;
+            }
+        };
+        let apps: Vec<(String, Vec<u32>)> = self
+            .cache
+            .read()
+            .await
+            .apps
+            .iter()
+            .filter(|entry| entry.value().active)
+            .map(|entry| (entry.key().clone(), entry.value().sink_input_ids.clone()))
+            .collect();
+
+        // xdotool and ps are run synchronously
+        let detector = self.detector.clone();
+        tokio::task::spawn_blocking(move || focused_app(&detector, &inputs, &apps))
+            .await
+            .unwrap_or(None)
+    }
+
+    /// Give the focus sink to `target`, returning the previous app to its sink
+    async fn focus(&mut self, target: Option<String>) {
+        if self.focused.as_ref().map(|(app, _)| app) == target.as_ref() {
+            return;
+        }
+
+        if let Some((app_name, prior_sink)) = self.focused.take() {
+            debug!("{} lost focus, moving it back to {}", app_name, prior_sink);
+            if let Err(e) = self.controller.route_app(&app_name, &prior_sink).await {
+                // Most likely it stopped playing
+                debug!("Could not move {} back to {}: {}", app_name, prior_sink, e);
+            }
+        }
+
+        let Some(app_name) = target else {
+            return;
+        };
+        // An app duplicated to several sinks goes back to all of them
+        let Some(prior_sink) = self.cache.read().await.apps.get(&app_name).map(|app| {
+            if app.current_sinks.is_empty() {
+                app.current_sink.clone()
+            } else {
+                app.current_sinks.join(",")
+            }
+        }) else {
+            return;
+        };
+        match self.controller.route_app(&app_name, &self.focus_sink).await {
+            Ok(()) => info!("{} has focus, moved it to {}", app_name, self.focus_sink),
+            Err(e) => error!("Failed to move focused app {}: {}", app_name, e),
+        }
+        // Remembered even on failure, so it isn't retried on every poll
+        self.focused = Some((app_name, prior_sink));
+    }
+}
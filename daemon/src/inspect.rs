@@ -0,0 +1,147 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+
+use crate::cache::{AppInfo, AudioCache, GraphNode, SinkInfo};
+
+/// Point-in-time view of the daemon's state, as returned by `DUMP_STATE`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StateDump {
+    pub generation: u64,
+    pub sinks: BTreeMap<String, SinkInfo>,
+    pub apps: BTreeMap<String, AppInfo>,
+    pub routing_rules: BTreeMap<String, String>,
+}
+
+impl StateDump {
+    pub fn from_cache(cache: &AudioCache) -> Self {
+        Self {
+            generation: cache.get_generation(),
+            sinks: cache.sinks.iter().map(|r| (r.key().clone(), r.value().clone())).collect(),
+            apps: cache.apps.iter().map(|r| (r.key().clone(), r.value().clone())).collect(),
+            routing_rules: cache
+                .routing_rules
+                .iter()
+                .map(|r| (r.key().clone(), r.value().clone()))
+                .collect(),
+        }
+    }
+}
+
+/// What the daemon makes of the PipeWire graph, as returned by `DUMP_GRAPH`
+///
+/// Unlike `pw-dump` this shows the daemon's view: which streams the monitor tracks,
+/// the app each was resolved to, and the cache app its serial ended up in.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraphDump {
+    pub generation: u64,
+    pub nodes: Vec<DumpedNode>,
+    pub sinks: BTreeMap<String, SinkInfo>,
+    pub physical_sinks: BTreeMap<String, String>, // Node name -> display name
+    pub apps: BTreeMap<String, AppInfo>,
+}
+
+/// A tracked stream in a [`GraphDump`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DumpedNode {
+    #[serde(flatten)]
+    pub node: GraphNode,
+    pub cache_app: Option<String>, // App whose sink inputs include the serial, if any
+}
+
+impl GraphDump {
+    pub fn from_cache(cache: &AudioCache) -> Self {
+        let mut nodes: Vec<GraphNode> =
+            cache.graph_nodes().iter().map(|entry| entry.value().clone()).collect();
+        nodes.sort_by_key(|node| node.id);
+        let apps: BTreeMap<String, AppInfo> =
+            cache.apps.iter().map(|r| (r.key().clone(), r.value().clone())).collect();
+        let nodes = nodes
+            .into_iter()
+            .map(|node| {
+                let cache_app = apps
+                    .iter()
+                    .find(|(_, app)| app.sink_input_ids.contains(&node.serial))
+                    .map(|(name, _)| name.clone());
+                DumpedNode { node, cache_app }
+            })
+            .collect();
+        Self {
+            generation: cache.get_generation(),
+            nodes,
+            sinks: cache.sinks.iter().map(|r| (r.key().clone(), r.value().clone())).collect(),
+            physical_sinks: cache
+                .physical_sinks
+                .iter()
+                .map(|r| (r.key().clone(), r.value().clone()))
+                .collect(),
+            apps,
+        }
+    }
+}
+
+/// Ask a running daemon for its current state over the IPC socket
+pub async fn query_daemon(socket_path: &str) -> Result<StateDump> {
+    let stream = UnixStream::connect(socket_path)
+        .await
+        .with_context(|| format!("Failed to connect to daemon at {socket_path}"))?;
+    let (reader, mut writer) = stream.into_split();
+
+    writer.write_all(b"DUMP_STATE\n").await?;
+
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+
+    match line.trim_end().split_once(' ') {
+        Some(("OK", json)) => serde_json::from_str(json).context("Invalid state from daemon"),
+        Some(("ERROR", msg)) => bail!("Daemon returned an error: {msg}"),
+        _ => bail!("Unexpected response from daemon: {}", line.trim_end()),
+    }
+}
+
+/// Render a state dump as human-readable tables
+pub fn format_table(state: &StateDump) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "Generation: {}", state.generation);
+
+    let _ = writeln!(out, "\nSinks ({}):", state.sinks.len());
+    let _ = writeln!(out, "  {:<20} {:>6} {:>7}  MUTED", "NAME", "ID", "VOLUME");
+    for (name, sink) in &state.sinks {
+        let _ = writeln!(
+            out,
+            "  {:<20} {:>6} {:>6.0}%  {}",
+            name,
+            sink.pipewire_id,
+            sink.volume * 100.0,
+            if sink.muted { "yes" } else { "no" }
+        );
+    }
+
+    let _ = writeln!(out, "\nApps ({}):", state.apps.len());
+    let _ = writeln!(out, "  {:<24} {:<12} {:<8} STREAMS", "NAME", "SINK", "ACTIVE");
+    for (name, app) in &state.apps {
+        let _ = writeln!(
+            out,
+            "  {:<24} {:<12} {:<8} {}",
+            name,
+            app.current_sink,
+            if app.active { "yes" } else { "no" },
+            app.stream_names.join(", ")
+        );
+    }
+
+    let _ = writeln!(out, "\nRouting rules ({}):", state.routing_rules.len());
+    for (app, sink) in &state.routing_rules {
+        let _ = writeln!(out, "  {app} -> {sink}");
+    }
+
+    out
+}
+
+/// Render a state dump as pretty-printed JSON
+pub fn format_json(state: &StateDump) -> Result<String> {
+    Ok(serde_json::to_string_pretty(state)?)
+}
@@ -1,32 +1,151 @@
 use anyhow::{bail, Context, Result};
+use nix::sys::socket::{getsockopt, sockopt::PeerCredentials};
 use nix::unistd::Uid;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::net::{UnixListener, UnixStream};
 use tokio::sync::RwLock;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
+
+use crate::cache::{parse_sink_targets, AudioCache, ImportMode, RoutingExport};
+use crate::config::AppMappings;
+use crate::inspect::{GraphDump, StateDump};
+use crate::ipc_binary::{
+    read_frame, write_frame, Request, Response, BINARY_HANDSHAKE, MAX_FRAME_LEN,
+};
+use crate::pipewire_controller::PipeWireController;
+use crate::volume::{db_to_linear, linear_to_db, sanitize_volume};
+
+/// Version of the line protocol, bumped whenever commands or replies change
+pub const PROTOCOL_VERSION: u32 = 3;
+
+/// Longest command line accepted, in bytes; longer ones close the connection
+pub const MAX_LINE_LEN: usize = 8 * 1024;
+
+/// Longest IMPORT line accepted, in bytes. It carries a whole EXPORT, so it may be as
+/// long as a binary frame rather than [`MAX_LINE_LEN`]
+pub const MAX_IMPORT_LEN: usize = MAX_FRAME_LEN;
+
+/// Commands that only read state, which clients of any user may run
+const READ_ONLY_COMMANDS: &[&str] = &[
+    "PING",
+    "VERSION",
+    "HEALTH",
+    "GET_VOLUME",
+    "GET_DEFAULT_SINK",
+    "GET_APP",
+    "TEST_RULE",
+    "LIST_KNOWN_APPS",
+    "DUMP_STATE",
+    "DUMP_GRAPH",
+    "EVENTS",
+    "METRICS",
+    "EXPORT",
+];
+
+/// Failure categories, sent as `ERROR <code> <message>` so clients can branch on the code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IpcError {
+    UnknownCommand(String),
+    BadArgs(String),
+    UnknownSink(String),
+    UnknownApp(String),
+    NoActiveStreams(String),
+    Backend(String),
+    PermissionDenied(String),
+}
+
+impl IpcError {
+    /// Stable identifier clients can match on
+    pub fn code(&self) -> &'static str {
+        match self {
+            IpcError::UnknownCommand(_) => "UNKNOWN_COMMAND",
+            IpcError::BadArgs(_) => "BAD_ARGS",
+            IpcError::UnknownSink(_) => "UNKNOWN_SINK",
+            IpcError::UnknownApp(_) => "UNKNOWN_APP",
+            IpcError::NoActiveStreams(_) => "NO_ACTIVE_STREAMS",
+            IpcError::Backend(_) => "BACKEND",
+            IpcError::PermissionDenied(_) => "PERMISSION_DENIED",
+        }
+    }
+}
+
+impl fmt::Display for IpcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IpcError::UnknownCommand(msg)
+            | IpcError::BadArgs(msg)
+            | IpcError::UnknownSink(msg)
+            | IpcError::UnknownApp(msg)
+            | IpcError::NoActiveStreams(msg)
+            | IpcError::Backend(msg)
+            | IpcError::PermissionDenied(msg) => f.write_str(msg),
+        }
+    }
+}
 
-use crate::cache::AudioCache;
+impl std::error::Error for IpcError {}
+
+/// Code for a failed command; errors that were not classified are backend failures
+pub fn error_code(err: &anyhow::Error) -> &'static str {
+    err.chain().find_map(|e| e.downcast_ref::<IpcError>()).map_or("BACKEND", IpcError::code)
+}
+
+/// Default IPC socket path for the current user
+pub fn default_socket_path() -> String {
+    format!("/run/user/{}/pipewire-volume-mixer.sock", Uid::current())
+}
 
 pub struct IpcServer {
     cache: Arc<RwLock<AudioCache>>,
+    controller: Arc<PipeWireController>,
     listener: UnixListener,
+    control_uid: Option<u32>, // Only this user may change state, anyone may if None
+    app_mappings: Option<Arc<RwLock<AppMappings>>>, // Where IMPORT saves, nowhere if None
 }
 
 impl IpcServer {
     pub fn new(cache: Arc<RwLock<AudioCache>>) -> Result<Self> {
-        let uid = Uid::current();
-        let socket_path = format!("/run/user/{uid}/pipewire-volume-mixer.sock");
+        let controller = Arc::new(PipeWireController::new(cache.clone()));
+        Self::bind(cache, controller, default_socket_path())
+    }
+
+    /// Listen on `socket_path`, applying commands through `controller`
+    pub fn bind<P: AsRef<Path>>(
+        cache: Arc<RwLock<AudioCache>>,
+        controller: Arc<PipeWireController>,
+        socket_path: P,
+    ) -> Result<Self> {
+        let socket_path = socket_path.as_ref();
 
         // Remove existing socket if it exists
-        let _ = std::fs::remove_file(&socket_path);
+        let _ = std::fs::remove_file(socket_path);
 
         // Create the socket
-        let listener = UnixListener::bind(&socket_path).context("Failed to bind Unix socket")?;
+        let listener = UnixListener::bind(socket_path).context("Failed to bind Unix socket")?;
+
+        info!("IPC server listening on {}", socket_path.display());
 
-        info!("IPC server listening on {}", socket_path);
+        Ok(Self { cache, controller, listener, control_uid: None, app_mappings: None })
+    }
 
-        Ok(Self { cache, listener })
+    /// Refuse commands that change state from clients running as any user but `uid`
+    ///
+    /// Read-only commands stay open to every client that can reach the socket.
+    #[allow(dead_code)] // Used by the daemon when restrict_ipc_control is set
+    pub fn with_control_uid(mut self, uid: Option<u32>) -> Self {
+        self.control_uid = uid;
+        self
+    }
+
+    /// Save imported setups with these app mappings, as the daemon does
+    #[allow(dead_code)] // Used by the daemon
+    pub fn with_app_mappings(mut self, app_mappings: Arc<RwLock<AppMappings>>) -> Self {
+        self.app_mappings = Some(app_mappings);
+        self
     }
 
     pub async fn run(self) -> Result<()> {
@@ -34,8 +153,18 @@ impl IpcServer {
             match self.listener.accept().await {
                 Ok((stream, _)) => {
                     let cache = self.cache.clone();
+                    let controller = self.controller.clone();
+                    let app_mappings = self.app_mappings.clone();
+                    let may_control = self.control_uid.map_or(true, |uid| {
+                        let peer_uid = peer_uid(&stream);
+                        if peer_uid != Some(uid) {
+                            info!("Client of uid {:?} may only run read-only commands", peer_uid);
+                        }
+                        peer_uid == Some(uid)
+                    });
                     tokio::spawn(async move {
-                        if let Err(e) = handle_client(stream, cache).await {
+                        let client = Client { may_control, cache, controller, app_mappings };
+                        if let Err(e) = handle_client(stream, client).await {
                             error!("Client handler error: {}", e);
                         }
                     });
@@ -48,15 +177,81 @@ impl IpcServer {
     }
 }
 
-async fn handle_client(stream: UnixStream, cache: Arc<RwLock<AudioCache>>) -> Result<()> {
+/// User id of the process on the other end of `stream`, from `SO_PEERCRED`
+fn peer_uid(stream: &UnixStream) -> Option<u32> {
+    match getsockopt(stream, PeerCredentials) {
+        Ok(credentials) => Some(credentials.uid()),
+        Err(e) => {
+            warn!("Could not read the client's credentials: {}", e);
+            None
+        }
+    }
+}
+
+/// Whether `command` only reads state
+pub fn is_read_only(command: &str) -> bool {
+    command.split_whitespace().next().is_some_and(|name| READ_ONLY_COMMANDS.contains(&name))
+}
+
+/// What a connection's commands run with
+struct Client {
+    may_control: bool, // Whether commands that change state are allowed
+    cache: Arc<RwLock<AudioCache>>,
+    controller: Arc<PipeWireController>,
+    app_mappings: Option<Arc<RwLock<AppMappings>>>,
+}
+
+/// Run a client's command, unless it changes state and the client may not do that
+async fn run_client_command(command: &str, client: &Client) -> Result<String> {
+    require_control(client.may_control || is_read_only(command))?;
+    execute_command(command, &client.cache, &client.controller, client.app_mappings.as_deref())
+        .await
+}
+
+/// Fail with `PERMISSION_DENIED` unless the client may run the command
+fn require_control(allowed: bool) -> Result<(), IpcError> {
+    if allowed {
+        return Ok(());
+    }
+    Err(IpcError::PermissionDenied(
+        "Only the daemon's own user may run commands that change state".to_string(),
+    ))
+}
+
+async fn handle_client(stream: UnixStream, client: Client) -> Result<()> {
     let (reader, mut writer) = stream.into_split();
     let mut reader = BufReader::new(reader);
     let mut line = String::new();
 
-    while reader.read_line(&mut line).await? > 0 {
-        let response = match process_command(line.trim(), &cache).await {
+    // Reading one byte past the limit tells an oversized line from one that fits
+    while (&mut reader).take(MAX_LINE_LEN as u64 + 1).read_line(&mut line).await? > 0 {
+        let limit = if line.starts_with("IMPORT ") { MAX_IMPORT_LEN } else { MAX_LINE_LEN };
+        // An IMPORT carries a whole export, so it is read on up to its own limit
+        if line.len() > MAX_LINE_LEN && line.len() <= limit && !line.ends_with('\n') {
+            let rest = (limit - line.len()) as u64 + 1;
+            (&mut reader).take(rest).read_line(&mut line).await?;
+        }
+        if !line.ends_with('\n') {
+            if line.len() > limit {
+                let err = IpcError::BadArgs(format!("Command exceeds {limit} bytes"));
+                writer.write_all(format!("ERROR {} {err}\n", err.code()).as_bytes()).await?;
+                warn!("Client sent a line over {} bytes, closing the connection", limit);
+                return Ok(());
+            }
+            // The client went away mid-line; a cut off command is not run
+            debug!("Client disconnected mid-line, dropping {} bytes", line.len());
+            break;
+        }
+
+        if line.trim() == BINARY_HANDSHAKE {
+            writer.write_all(b"OK binary\n").await?;
+            return handle_binary_client(reader, writer, client).await;
+        }
+
+        // A failed command is reported and the connection stays open for the next one
+        let response = match run_client_command(line.trim(), &client).await {
             Ok(msg) => format!("OK {msg}\n"),
-            Err(e) => format!("ERROR {e}\n"),
+            Err(e) => format!("ERROR {} {e:#}\n", error_code(&e)),
         };
 
         writer.write_all(response.as_bytes()).await?;
@@ -66,361 +261,660 @@ async fn handle_client(stream: UnixStream, cache: Arc<RwLock<AudioCache>>) -> Re
     Ok(())
 }
 
-async fn process_command(command: &str, cache: &Arc<RwLock<AudioCache>>) -> Result<String> {
+/// Serve a connection that switched to binary frames, until the client disconnects
+async fn handle_binary_client<R, W>(mut reader: R, mut writer: W, client: Client) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    while let Some(payload) = read_frame(&mut reader).await? {
+        let result = match Request::decode(&payload) {
+            Ok(request) => run_request(request, &client).await,
+            Err(e) => Err(e.into()),
+        };
+        let response = match result {
+            Ok(msg) => Response::Ok(msg),
+            Err(e) => {
+                Response::Error { code: error_code(&e).to_string(), message: format!("{e:#}") }
+            }
+        };
+
+        write_frame(&mut writer, &response.encode()).await?;
+    }
+
+    Ok(())
+}
+
+/// Run a decoded binary request with the same handlers as its text command
+async fn run_request(request: Request, client: &Client) -> Result<String> {
+    let read_only = match &request {
+        Request::Text(command) => is_read_only(command),
+        Request::GetVolume { .. } | Request::Ping => true,
+        _ => false,
+    };
+    require_control(client.may_control || read_only)?;
+
+    let (cache, controller) = (&client.cache, client.controller.as_ref());
+    match request {
+        Request::SetVolume { sink, volume } => set_volume(cache, controller, &sink, volume).await,
+        Request::SetVolumeDb { sink, db } => set_volume_db(cache, controller, &sink, db).await,
+        Request::Mute { sink, muted } => set_sink_mute(cache, controller, &sink, muted).await,
+        Request::Route { app, sink } => route_app(cache, controller, &app, &sink).await,
+        Request::GetVolume { sink } => get_volume(cache, &sink).await,
+        Request::Ping => Ok("PONG".to_string()),
+        Request::Text(command) => {
+            execute_command(&command, cache, controller, client.app_mappings.as_deref()).await
+        }
+    }
+}
+
+/// Execute a single protocol command and return the message for an `OK` reply
+#[allow(dead_code)] // Used by tests, the daemon passes its own controller
+pub async fn process_command(command: &str, cache: &Arc<RwLock<AudioCache>>) -> Result<String> {
+    process_command_with(command, cache, &PipeWireController::new(cache.clone())).await
+}
+
+/// Like [`process_command`], applying changes through the given controller
+pub async fn process_command_with(
+    command: &str,
+    cache: &Arc<RwLock<AudioCache>>,
+    controller: &PipeWireController,
+) -> Result<String> {
+    execute_command(command, cache, controller, None).await
+}
+
+/// Like [`process_command_with`], saving imports with `app_mappings` if given
+async fn execute_command(
+    command: &str,
+    cache: &Arc<RwLock<AudioCache>>,
+    controller: &PipeWireController,
+    app_mappings: Option<&RwLock<AppMappings>>,
+) -> Result<String> {
     let parts: Vec<&str> = command.split_whitespace().collect();
     if parts.is_empty() {
-        bail!("Empty command");
+        bail!(IpcError::UnknownCommand("Empty command".to_string()));
     }
 
     debug!("Processing command: {}", command);
 
     match parts[0] {
         "ROUTE" => {
+            if parts.len() < 3 {
+                bail!(IpcError::BadArgs("Usage: ROUTE <app_name> <sink_name>".to_string()));
+            }
+
+            // Stream-derived app names may contain spaces, as for PIN
+            let app_name = parts[1..parts.len() - 1].join(" ");
+            route_app(cache, controller, &app_name, parts[parts.len() - 1]).await
+        }
+
+        "PIN" => {
+            if parts.len() < 3 {
+                bail!(IpcError::BadArgs("Usage: PIN <app_name> <sink_name>".to_string()));
+            }
+
+            // Stream-derived app names may contain spaces
+            let app_name = parts[1..parts.len() - 1].join(" ");
+            let sink_name = parts[parts.len() - 1];
+            require_sink(cache, sink_name).await?;
+
+            let playing = {
+                let cache_read = cache.read().await;
+                cache_read.pin_app(&app_name, sink_name);
+                cache_read.apps.get(&app_name).is_some_and(|app| app.active)
+            };
+            // Otherwise it's routed when it next appears
+            if playing {
+                controller.route_app(&app_name, sink_name).await?;
+            }
+            Ok(format!("Pinned {app_name} to {sink_name}"))
+        }
+
+        "UNPIN" => {
+            if parts.len() < 2 {
+                bail!(IpcError::BadArgs("Usage: UNPIN <app_name>".to_string()));
+            }
+
+            let app_name = parts[1..].join(" ");
+            if !cache.read().await.unpin_app(&app_name) {
+                bail!(IpcError::UnknownApp(format!("App {app_name} is not pinned")));
+            }
+            // It stays where it is, only no longer goes back there by itself
+            Ok(format!("Unpinned {app_name}"))
+        }
+
+        "ROUTE_PID" => {
             if parts.len() != 3 {
-                bail!("Usage: ROUTE <app_name> <sink_name>");
+                bail!(IpcError::BadArgs("Usage: ROUTE_PID <pid> <sink_name>".to_string()));
             }
 
-            let app_name = parts[1];
+            let pid: u32 = parse_arg(parts[1], "pid")?;
             let sink_name = parts[2];
+            require_sink(cache, sink_name).await?;
 
-            // Update routing rule
-            cache.write().await.routing_rules.insert(app_name.to_string(), sink_name.to_string());
-
-            // Actually move the stream in PipeWire
-            let result = route_app_to_sink(app_name, sink_name).await;
-            match result {
-                Ok(_) => {
-                    // Update the app's current sink in cache properly
-                    let cache_read = cache.read().await;
-                    let app_clone =
-                        cache_read.apps.get(app_name).map(|app_ref| app_ref.value().clone());
-                    drop(cache_read);
-
-                    if let Some(mut app) = app_clone {
-                        app.current_sink = sink_name.to_string();
-                        // Use the proper update method to increment generation
-                        cache.write().await.update_app(app_name.to_string(), app);
-                    } else {
-                        // App doesn't exist yet, create it as inactive
-                        let app_info = crate::cache::AppInfo {
-                            display_name: app_name.to_string(),
-                            binary_name: app_name.to_lowercase(),
-                            stream_names: vec![app_name.to_string()], // Use app_name as initial stream name
-                            current_sink: sink_name.to_string(),
-                            active: false,
-                            sink_input_ids: vec![],
-                            pipewire_id: 0, // Default ID for new app
-                            inactive_since: Some(std::time::Instant::now()),
-                        };
-                        cache.write().await.update_app(app_name.to_string(), app_info);
-                    }
-
-                    Ok(format!("Routed {app_name} to {sink_name}"))
-                }
-                Err(e) => bail!("Failed to route {app_name} to {sink_name}: {e}"),
+            let moved = controller.route_pid(pid, sink_name).await?;
+            if moved == 0 {
+                bail!(IpcError::NoActiveStreams(format!(
+                    "Process {pid} has no active sink inputs"
+                )));
             }
+            Ok(format!("Routed {moved} streams of pid {pid} to {sink_name}"))
         }
 
-        "SET_VOLUME" => {
+        "ROUTE_INPUT" => {
             if parts.len() != 3 {
-                bail!("Usage: SET_VOLUME <sink_name> <volume>");
+                bail!(IpcError::BadArgs("Usage: ROUTE_INPUT <index> <sink_name>".to_string()));
             }
 
-            let sink_name = parts[1];
-            let volume: f32 = parts[2].parse().context("Invalid volume value")?;
-
-            if !(0.0..=1.0).contains(&volume) {
-                bail!("Volume must be between 0.0 and 1.0");
-            }
-
-            // Update cache and get sink ID
-            let cache_write = cache.write().await;
-            let (sink_id, was_muted) = match cache_write.sinks.get_mut(sink_name) {
-                Some(mut sink) => {
-                    let id = sink.id;
-                    let was_muted = sink.muted;
-                    sink.volume = volume;
-                    // If volume > 0, unmute
-                    if volume > 0.0 && sink.muted {
-                        sink.muted = false;
-                    }
-                    (id, was_muted)
-                }
-                None => bail!("Unknown sink: {}", sink_name),
+            let sink_input_id: u32 = parse_arg(parts[1], "sink input index")?;
+            let sink_name = parts[2];
+            require_sink(cache, sink_name).await?;
+
+            if !controller.route_sink_input(sink_input_id, sink_name).await? {
+                bail!(IpcError::NoActiveStreams(format!("No sink input {sink_input_id}")));
+            }
+            Ok(format!("Routed sink input {sink_input_id} to {sink_name}"))
+        }
+
+        "MUTE_APP" => {
+            if parts.len() < 3 {
+                bail!(IpcError::BadArgs("Usage: MUTE_APP <app_name> <true|false>".to_string()));
+            }
+
+            // Stream-derived app names may contain spaces
+            let app_name = parts[1..parts.len() - 1].join(" ");
+            let muted: bool = parse_arg(parts[parts.len() - 1], "mute value")?;
+            if !cache.read().await.apps.contains_key(&app_name) {
+                bail!(IpcError::UnknownApp(format!("Unknown app: {app_name}")));
+            }
+
+            let changed = controller.set_app_mute(&app_name, muted).await?;
+            if changed == 0 {
+                bail!(IpcError::NoActiveStreams(format!("App {app_name} has no active streams")));
+            }
+            Ok(format!("Set {app_name} mute to {muted}"))
+        }
+
+        "SET_APP_VOLUME" => {
+            if parts.len() < 3 {
+                bail!(IpcError::BadArgs("Usage: SET_APP_VOLUME <app_name> <volume>".to_string()));
+            }
+
+            // Stream-derived app names may contain spaces
+            let app_name = parts[1..parts.len() - 1].join(" ");
+            let volume: f32 = parse_arg(parts[parts.len() - 1], "volume value")?;
+            // Out of range values are clamped to 0.0 - 1.0
+            let Some(volume) = sanitize_volume(volume) else {
+                bail!(IpcError::BadArgs("Volume must be a finite number".to_string()));
             };
-            // Increment generation so UI updates
-            cache_write.increment_generation();
-            drop(cache_write);
-
-            // Actually set volume in PipeWire
-            // First set the sink volume
-            let volume_percent = (volume * 100.0) as u32;
-            let output = tokio::process::Command::new("wpctl")
-                .args(["set-volume", &sink_id.to_string(), &format!("{volume_percent}%")])
-                .output()
-                .await?;
-
-            if !output.status.success() {
-                bail!("Failed to set sink volume: {}", String::from_utf8_lossy(&output.stderr));
-            }
-
-            // If we unmuted due to volume change, also unmute the sink
-            if was_muted && volume > 0.0 {
-                let _ = tokio::process::Command::new("wpctl")
-                    .args(["set-mute", &sink_id.to_string(), "0"])
-                    .output()
-                    .await;
-            }
-
-            // Then find and set the loopback sink-input volume
-            let pactl_output = tokio::process::Command::new("pactl")
-                .args(["list", "sink-inputs"])
-                .output()
-                .await?;
-
-            if pactl_output.status.success() {
-                let stdout = String::from_utf8_lossy(&pactl_output.stdout);
-                let blocks: Vec<&str> = stdout.split("Sink Input #").collect();
-
-                for block in blocks {
-                    if block.contains(&format!("node.name = \"{sink_name}_to_Speaker\"")) {
-                        if let Some(id_match) = block.lines().next().and_then(|line| {
-                            line.split_whitespace().next().and_then(|s| s.parse::<u32>().ok())
-                        }) {
-                            // Set loopback volume
-                            let _ = tokio::process::Command::new("pactl")
-                                .args([
-                                    "set-sink-input-volume",
-                                    &id_match.to_string(),
-                                    &format!("{volume_percent}%"),
-                                ])
-                                .output()
-                                .await;
-
-                            // If we unmuted due to volume change, also unmute the loopback
-                            if was_muted && volume > 0.0 {
-                                let _ = tokio::process::Command::new("pactl")
-                                    .args(["set-sink-input-mute", &id_match.to_string(), "0"])
-                                    .output()
-                                    .await;
-                            }
-                            break;
-                        }
-                    }
-                }
+            if !cache.read().await.apps.contains_key(&app_name) {
+                bail!(IpcError::UnknownApp(format!("Unknown app: {app_name}")));
+            }
+
+            let changed = controller.set_app_volume(&app_name, volume).await?;
+            if changed == 0 {
+                bail!(IpcError::NoActiveStreams(format!("App {app_name} has no active streams")));
+            }
+            Ok(format!("Set {app_name} volume to {volume}"))
+        }
+
+        "SET_VOLUME" => {
+            if parts.len() != 3 {
+                bail!(IpcError::BadArgs("Usage: SET_VOLUME <sink_name> <volume>".to_string()));
+            }
+
+            let volume: f32 = parse_arg(parts[2], "volume value")?;
+            set_volume(cache, controller, parts[1], volume).await
+        }
+
+        "SET_VOLUME_DB" => {
+            if parts.len() != 3 {
+                bail!(IpcError::BadArgs("Usage: SET_VOLUME_DB <sink_name> <db>".to_string()));
+            }
+
+            let db: f32 = parse_arg(parts[2], "dB value")?;
+            set_volume_db(cache, controller, parts[1], db).await
+        }
+
+        "GET_VOLUME" => {
+            if parts.len() != 2 {
+                bail!(IpcError::BadArgs("Usage: GET_VOLUME <sink_name>".to_string()));
             }
 
-            Ok(format!("Set {sink_name} volume to {volume}"))
+            get_volume(cache, parts[1]).await
+        }
+
+        "GET_DEFAULT_SINK" => {
+            if parts.len() != 1 {
+                bail!(IpcError::BadArgs("Usage: GET_DEFAULT_SINK".to_string()));
+            }
+
+            if let Some(sink_name) = cache.read().await.default_sink() {
+                return Ok(sink_name);
+            }
+            // Not tracked yet, as right after startup
+            let sink_name = controller
+                .query_default_sink()
+                .await
+                .map_err(|e| IpcError::Backend(format!("Could not get the default sink: {e}")))?;
+            cache.read().await.set_default_sink(&sink_name);
+            Ok(sink_name)
         }
 
         "MUTE" => {
             if parts.len() != 3 {
-                bail!("Usage: MUTE <sink_name> <true|false>");
+                bail!(IpcError::BadArgs("Usage: MUTE <sink_name> <true|false>".to_string()));
+            }
+
+            let muted: bool = parse_arg(parts[2], "mute value")?;
+            set_sink_mute(cache, controller, parts[1], muted).await
+        }
+
+        "RESET_VOLUME" => {
+            if parts.len() != 2 {
+                bail!(IpcError::BadArgs("Usage: RESET_VOLUME <sink_name>".to_string()));
             }
 
             let sink_name = parts[1];
-            let muted: bool = parts[2].parse().context("Invalid mute value")?;
-
-            // Update cache and get sink ID
-            let cache_write = cache.write().await;
-            let sink_id = match cache_write.sinks.get_mut(sink_name) {
-                Some(mut sink) => {
-                    let id = sink.id;
-                    sink.muted = muted;
-                    id
-                }
-                None => bail!("Unknown sink: {}", sink_name),
-            };
-            // Increment generation so UI updates
-            cache_write.increment_generation();
-            drop(cache_write);
-
-            // Actually set mute in PipeWire
-            // First set the sink mute
-            let mute_arg = if muted { "1" } else { "0" };
-            let output = tokio::process::Command::new("wpctl")
-                .args(["set-mute", &sink_id.to_string(), mute_arg])
-                .output()
-                .await?;
-
-            if !output.status.success() {
-                bail!("Failed to set sink mute: {}", String::from_utf8_lossy(&output.stderr));
-            }
-
-            // Then find and mute/unmute the loopback sink-input
-            let pactl_output = tokio::process::Command::new("pactl")
-                .args(["list", "sink-inputs"])
-                .output()
-                .await?;
-
-            if pactl_output.status.success() {
-                let stdout = String::from_utf8_lossy(&pactl_output.stdout);
-                let blocks: Vec<&str> = stdout.split("Sink Input #").collect();
-
-                for block in blocks {
-                    if block.contains(&format!("node.name = \"{sink_name}_to_Speaker\"")) {
-                        if let Some(id_match) = block.lines().next().and_then(|line| {
-                            line.split_whitespace().next().and_then(|s| s.parse::<u32>().ok())
-                        }) {
-                            // Set loopback mute
-                            let _ = tokio::process::Command::new("pactl")
-                                .args(["set-sink-input-mute", &id_match.to_string(), mute_arg])
-                                .output()
-                                .await;
-                            break;
-                        }
-                    }
-                }
+            require_sink(cache, sink_name).await?;
+            let volume = controller.reset_sink_volume(sink_name).await?;
+            Ok(format!("Reset {sink_name} volume to {volume}"))
+        }
+
+        "CROSSFADE" => {
+            if parts.len() != 4 {
+                bail!(IpcError::BadArgs(
+                    "Usage: CROSSFADE <from_sink> <to_sink> <position>".to_string()
+                ));
+            }
+
+            let from_sink = parts[1];
+            let to_sink = parts[2];
+            let position: f32 = parse_arg(parts[3], "crossfade position")?;
+            if !(0.0..=1.0).contains(&position) || from_sink == to_sink {
+                bail!(IpcError::BadArgs(
+                    "Crossfade needs two different sinks and a position between 0.0 and 1.0"
+                        .to_string()
+                ));
+            }
+            require_sink(cache, from_sink).await?;
+            require_sink(cache, to_sink).await?;
+
+            controller.crossfade(from_sink, to_sink, position).await?;
+            Ok(format!("Crossfaded {from_sink} -> {to_sink} at {position}"))
+        }
+
+        "EVACUATE" => {
+            if parts.len() != 3 {
+                bail!(IpcError::BadArgs("Usage: EVACUATE <sink_name> <fallback_sink>".to_string()));
             }
 
-            Ok(format!("Set {sink_name} muted to {muted}"))
+            let sink_name = parts[1];
+            let fallback_sink = parts[2];
+            if sink_name == fallback_sink {
+                bail!(IpcError::BadArgs(
+                    "Fallback must differ from the evacuated sink".to_string()
+                ));
+            }
+            require_sink(cache, fallback_sink).await?;
+
+            let moved = controller.evacuate_sink(sink_name, fallback_sink).await?;
+            Ok(format!("Moved {moved} apps from {sink_name} to {fallback_sink}"))
+        }
+
+        "SOLO" => {
+            if parts.len() != 2 {
+                bail!(IpcError::BadArgs("Usage: SOLO <sink_name>".to_string()));
+            }
+
+            let sink_name = parts[1];
+            require_sink(cache, sink_name).await?;
+            controller.solo_sink(sink_name).await?;
+            Ok(format!("Soloed {sink_name}"))
+        }
+
+        "UNSOLO" => {
+            controller.unsolo().await?;
+            Ok("Solo ended".to_string())
+        }
+
+        "MUTE_ALL" => {
+            controller.mute_all().await?;
+            Ok("Muted all sinks".to_string())
+        }
+
+        "UNMUTE_ALL" => {
+            controller.unmute_all().await?;
+            Ok("Unmuted all sinks".to_string())
+        }
+
+        "SET_AUTO_ROUTING" => {
+            if parts.len() != 2 {
+                bail!(IpcError::BadArgs("Usage: SET_AUTO_ROUTING <true|false>".to_string()));
+            }
+
+            let enabled: bool = parse_arg(parts[1], "auto-routing value")?;
+            cache.read().await.set_auto_routing(enabled);
+            Ok(format!("Auto-routing {}", if enabled { "enabled" } else { "disabled" }))
+        }
+
+        "SET_SINK_LABEL" => {
+            if parts.len() < 3 {
+                bail!(IpcError::BadArgs("Usage: SET_SINK_LABEL <sink_name> <label>".to_string()));
+            }
+
+            let sink_name = parts[1];
+            require_sink(cache, sink_name).await?;
+            // Labels may contain spaces
+            let cache_read = cache.read().await;
+            cache_read.set_sink_label(sink_name, parts[2..].join(" "));
+            // Long labels come back shortened
+            Ok(format!("Labeled {sink_name} as {}", cache_read.sink_label(sink_name)))
+        }
+
+        "SET_FOLLOW_FOCUS" => {
+            if parts.len() != 2 {
+                bail!(IpcError::BadArgs("Usage: SET_FOLLOW_FOCUS <true|false>".to_string()));
+            }
+
+            let enabled: bool = parse_arg(parts[1], "follow focus value")?;
+            let cache_read = cache.read().await;
+            let Some(focus_sink) = cache_read.focus_sink() else {
+                bail!(IpcError::BadArgs("No focus_sink configured".to_string()));
+            };
+            cache_read.set_follow_focus(enabled);
+            if enabled {
+                Ok(format!("Following focus to {focus_sink}"))
+            } else {
+                Ok("Stopped following focus".to_string())
+            }
         }
 
         "RELOAD_CONFIG" => Ok("Config reload not implemented".to_string()),
 
+        "RESCAN" => {
+            let (sinks, streams) = controller.rescan().await?;
+            Ok(format!("Rescanned {sinks} sinks and {streams} streams"))
+        }
+
+        "PING" => Ok("PONG".to_string()),
+
+        "VERSION" => {
+            let cache_read = cache.read().await;
+            let pipewire = cache_read.server_version().unwrap_or("unknown");
+            Ok(format!(
+                "version={} protocol={PROTOCOL_VERSION} pipewire={pipewire}",
+                env!("CARGO_PKG_VERSION")
+            ))
+        }
+
         "HEALTH" => {
             // Health check command - returns status and basic info
             let cache_read = cache.read().await;
-            let sink_count = cache_read.sinks.len();
-            let app_count = cache_read.apps.len();
+            let stats = cache_read.stats();
             let generation = cache_read.get_generation();
+            let dropped_updates = cache_read.dropped_updates();
+            // Once updates are lost the cache no longer follows PipeWire
+            let status = if dropped_updates > 0 { "DEGRADED" } else { "OK" };
+            let p99: Vec<String> = cache_read
+                .latency_summaries()
+                .into_iter()
+                .map(|(operation, summary)| format!("{operation}:{}", summary.p99_us))
+                .collect();
+            // Seconds each sink has existed, so a recreated sink stands out
+            let mut uptimes: Vec<String> = cache_read
+                .sink_uptimes()
+                .into_iter()
+                .map(|(name, uptime)| format!("{name}:{}", uptime.as_secs()))
+                .collect();
             drop(cache_read);
+            uptimes.sort();
+
+            Ok(format!(
+                "sinks={} apps={} generation={generation} active_apps={} inactive_apps={} rules={} remembered_apps={} indexed_apps={} uptime_seconds={} p99_us={} dropped_updates={dropped_updates} status={status}",
+                stats.sinks,
+                stats.active_apps + stats.inactive_apps,
+                stats.active_apps,
+                stats.inactive_apps,
+                stats.routing_rules,
+                stats.remembered_apps,
+                stats.indexed_apps,
+                uptimes.join(","),
+                p99.join(",")
+            ))
+        }
 
-            Ok(format!("sinks={sink_count} apps={app_count} generation={generation} status=OK"))
+        "METRICS" => {
+            let summaries = cache.read().await.latency_summaries();
+            Ok(serde_json::to_string(&summaries)?)
         }
 
-        _ => {
-            bail!("Unknown command: {}", parts[0]);
+        "PAUSE" => {
+            cache.read().await.pause();
+            Ok("Paused".to_string())
         }
-    }
-}
 
-async fn route_app_to_sink(app_name: &str, sink_name: &str) -> Result<()> {
-    debug!("Attempting to route {} to {}", app_name, sink_name);
+        "RESUME" => {
+            cache.read().await.resume();
+            Ok("Resumed".to_string())
+        }
 
-    // First, find all sink input IDs for the app
-    let sink_inputs_output =
-        tokio::process::Command::new("pactl").args(["list", "sink-inputs"]).output().await?;
+        "DUMP_STATE" => {
+            let state = StateDump::from_cache(&*cache.read().await);
+            Ok(serde_json::to_string(&state)?)
+        }
 
-    if !sink_inputs_output.status.success() {
-        bail!("Failed to list sink inputs");
-    }
+        "DUMP_GRAPH" => {
+            let graph = GraphDump::from_cache(&*cache.read().await);
+            Ok(serde_json::to_string(&graph)?)
+        }
 
-    let stdout = String::from_utf8_lossy(&sink_inputs_output.stdout);
-    let mut sink_input_ids = Vec::new();
-
-    // Parse sink inputs to find all streams for our app
-    let blocks: Vec<&str> = stdout.split("Sink Input #").collect();
-
-    for block in blocks.iter().skip(1) {
-        // Skip first empty block
-        if let Some(id_str) = block.lines().next() {
-            if let Ok(id) = id_str.trim().parse::<u32>() {
-                let app_name_lower = app_name.to_lowercase();
-
-                // Check for application.process.binary match
-                for line in block.lines() {
-                    if let Some(binary_line) =
-                        line.trim().strip_prefix("application.process.binary = \"")
-                    {
-                        if let Some(binary_end) = binary_line.find('"') {
-                            let binary_path = &binary_line[..binary_end];
-                            let binary_name = binary_path
-                                .split('/')
-                                .next_back()
-                                .unwrap_or(binary_path)
-                                .to_lowercase();
-
-                            debug!(
-                                "Checking binary name '{}' against app name '{}'",
-                                binary_name, app_name_lower
-                            );
-
-                            if binary_name == app_name_lower {
-                                debug!("Found {} sink input: {} (binary match)", app_name, id);
-                                sink_input_ids.push(id);
-                                break;
-                            }
-                        }
-                    }
-                    // Also check application.name as fallback
-                    else if line.contains("application.name = \"") {
-                        if let Some(name_start) = line.find('"') {
-                            let name_line = &line[name_start + 1..];
-                            if let Some(name_end) = name_line.find('"') {
-                                let name = name_line[..name_end].to_lowercase();
-                                if name.contains(&app_name_lower) {
-                                    debug!("Found {} sink input: {} (name match)", app_name, id);
-                                    sink_input_ids.push(id);
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                }
+        "EVENTS" => {
+            let count = match parts.get(1) {
+                Some(count) => parse_arg(count, "event count")?,
+                None => usize::MAX,
+            };
+            let events = cache.read().await.recent_events(count);
+            Ok(serde_json::to_string(&events)?)
+        }
+
+        "EXPORT" => {
+            let export = cache.read().await.export_routing();
+            Ok(serde_json::to_string(&export)?)
+        }
+
+        "IMPORT" => {
+            let mode = match parts.get(1).copied() {
+                Some("merge") => ImportMode::Merge,
+                Some("replace") => ImportMode::Replace,
+                _ => bail!(IpcError::BadArgs("Usage: IMPORT <merge|replace> <json>".to_string())),
+            };
+            // The JSON may contain whitespace of its own, so take the rest of the line as is
+            let data = command.trim_start()[parts[0].len()..].trim_start()[parts[1].len()..].trim();
+            let export: RoutingExport = serde_json::from_str(data)
+                .map_err(|e| IpcError::BadArgs(format!("Invalid routing export: {e}")))?;
+            let counts = (export.rules.len(), export.default_volumes.len());
+            import_routing(cache, app_mappings, export, mode).await?;
+            Ok(format!("Imported {} rules and {} default volumes", counts.0, counts.1))
+        }
+
+        "LIST_KNOWN_APPS" => {
+            let apps = cache.read().await.known_apps();
+            Ok(serde_json::to_string(&apps)?)
+        }
+
+        "GET_APP" => {
+            if parts.len() < 2 {
+                bail!(IpcError::BadArgs("Usage: GET_APP <app_name>".to_string()));
             }
+
+            // Stream-derived app names may contain spaces
+            let app_name = parts[1..].join(" ");
+            let Some(record) = cache.read().await.app_record(&app_name) else {
+                bail!(IpcError::UnknownApp(format!("Unknown app: {app_name}")));
+            };
+            Ok(serde_json::to_string(&record)?)
         }
-    }
 
-    // If no active streams found, this is an inactive app - just update the routing rule
-    if sink_input_ids.is_empty() {
-        info!(
-            "No active streams found for {}. Routing rule will apply when app starts playing.",
-            app_name
-        );
-        return Ok(());
+        "TEST_RULE" => {
+            if parts.len() < 2 {
+                bail!(IpcError::BadArgs("Usage: TEST_RULE <app_name>".to_string()));
+            }
+
+            // The app needn't be running, rules are tested before it first appears
+            let app_name = parts[1..].join(" ");
+            let test = cache.read().await.test_rule(&app_name);
+            Ok(serde_json::to_string(&test)?)
+        }
+
+        _ => {
+            bail!(IpcError::UnknownCommand(format!("Unknown command: {}", parts[0])));
+        }
     }
+}
 
-    // Now find the sink ID for the target sink
-    let sinks_output =
-        tokio::process::Command::new("pactl").args(["list", "sinks", "short"]).output().await?;
+/// Parse a command argument, reporting failures as bad arguments
+fn parse_arg<T: FromStr>(value: &str, what: &str) -> Result<T, IpcError> {
+    value.parse().map_err(|_| IpcError::BadArgs(format!("Invalid {what}: {value}")))
+}
 
-    if !sinks_output.status.success() {
-        bail!("Failed to list sinks");
+/// Fail with `UNKNOWN_SINK` unless the cache knows about `sink_name`
+async fn require_sink(cache: &Arc<RwLock<AudioCache>>, sink_name: &str) -> Result<(), IpcError> {
+    if cache.read().await.sinks.contains_key(sink_name) {
+        Ok(())
+    } else {
+        Err(IpcError::UnknownSink(format!("Unknown sink: {sink_name}")))
     }
+}
 
-    let sinks_stdout = String::from_utf8_lossy(&sinks_output.stdout);
-    let mut target_sink_id = None;
+/// Apply an imported setup to the cache and save it with `app_mappings`, if given
+///
+/// Nothing is applied if the setup names a sink that doesn't exist.
+pub async fn import_routing(
+    cache: &Arc<RwLock<AudioCache>>,
+    app_mappings: Option<&RwLock<AppMappings>>,
+    export: RoutingExport,
+    mode: ImportMode,
+) -> Result<()> {
+    if let Err(unknown) = cache.read().await.import_routing(export.clone(), mode) {
+        bail!(IpcError::UnknownSink(format!("Unknown sinks: {}", unknown.join(", "))));
+    }
+    if let Some(app_mappings) = app_mappings {
+        app_mappings.write().await.import_and_save(&export, mode)?;
+    }
+    Ok(())
+}
 
-    for line in sinks_stdout.lines() {
-        let parts: Vec<&str> = line.split_whitespace().collect();
-        if parts.len() >= 2 && parts[1] == sink_name {
-            if let Ok(sink_id) = parts[0].parse::<u32>() {
-                target_sink_id = Some(sink_id);
-                break;
-            }
-        }
+/// Set a sink's linear volume, clamping it to 0.0 - 1.0
+async fn set_volume(
+    cache: &Arc<RwLock<AudioCache>>,
+    controller: &PipeWireController,
+    sink_name: &str,
+    volume: f32,
+) -> Result<String> {
+    let Some(volume) = sanitize_volume(volume) else {
+        bail!(IpcError::BadArgs("Volume must be a finite number".to_string()));
+    };
+    set_sink_volume(cache, controller, sink_name, volume).await
+}
+
+/// Set a sink's volume in dB, at most 0
+async fn set_volume_db(
+    cache: &Arc<RwLock<AudioCache>>,
+    controller: &PipeWireController,
+    sink_name: &str,
+    db: f32,
+) -> Result<String> {
+    if db.is_nan() || db > 0.0 {
+        bail!(IpcError::BadArgs("Volume must be at most 0 dB".to_string()));
     }
+    set_sink_volume(cache, controller, sink_name, db_to_linear(db)).await
+}
 
-    let sink_id =
-        target_sink_id.ok_or_else(|| anyhow::anyhow!("Could not find sink: {}", sink_name))?;
+/// A sink's volume, as GET_VOLUME reports it
+async fn get_volume(cache: &Arc<RwLock<AudioCache>>, sink_name: &str) -> Result<String> {
+    let cache_read = cache.read().await;
+    let Some(sink) = cache_read.sinks.get(sink_name) else {
+        bail!(IpcError::UnknownSink(format!("Unknown sink: {sink_name}")));
+    };
+
+    Ok(format!(
+        "volume={} volume_db={:.1} applied_percent={} muted={}",
+        sink.volume,
+        linear_to_db(sink.volume),
+        sink.applied_percent,
+        sink.muted
+    ))
+}
 
-    // Move all sink inputs for this app to the target sink
-    let mut success_count = 0;
-    let mut errors = Vec::new();
+/// Mute or unmute a sink and its loopback
+async fn set_sink_mute(
+    cache: &Arc<RwLock<AudioCache>>,
+    controller: &PipeWireController,
+    sink_name: &str,
+    muted: bool,
+) -> Result<String> {
+    // The controller updates the cache under the sink's lock, as for D-Bus
+    require_sink(cache, sink_name).await?;
+    controller.set_sink_mute(sink_name, muted).await?;
+    Ok(format!("Set {sink_name} muted to {muted}"))
+}
 
-    for input_id in sink_input_ids {
-        let move_output = tokio::process::Command::new("pactl")
-            .args(["move-sink-input", &input_id.to_string(), &sink_id.to_string()])
-            .output()
-            .await?;
+/// Route an app, or only remember the rule while it isn't playing
+async fn route_app(
+    cache: &Arc<RwLock<AudioCache>>,
+    controller: &PipeWireController,
+    app_name: &str,
+    sink_name: &str,
+) -> Result<String> {
+    let targets = parse_sink_targets(sink_name);
+    let Some(primary) = targets.first() else {
+        bail!(IpcError::UnknownSink(format!("Unknown sink: {sink_name}")));
+    };
+    for target in &targets {
+        require_sink(cache, target).await?;
+    }
 
-        if move_output.status.success() {
-            success_count += 1;
-            debug!("Moved sink input {} to {}", input_id, sink_name);
-        } else {
-            let error_msg = String::from_utf8_lossy(&move_output.stderr);
-            errors.push(format!("Failed to move input {input_id}: {error_msg}"));
+    // Update routing rule
+    cache.read().await.routing_rules.insert(app_name.to_string(), sink_name.to_string());
+
+    let active = cache.read().await.apps.get(app_name).is_some_and(|app| app.active);
+    if !active {
+        // Nothing to move, the rule applies when the app starts playing
+        info!("{} isn't playing, it will be routed to {} when it starts", app_name, sink_name);
+        let cache_read = cache.read().await;
+        if !cache_read.set_app_sinks(app_name, &targets) {
+            let app_info = crate::cache::AppInfo {
+                display_name: app_name.to_string(),
+                binary_name: app_name.to_lowercase(),
+                stream_names: vec![app_name.to_string()], // Use app_name as initial stream name
+                current_sink: primary.clone(),
+                active: false,
+                inactive_since: Some(std::time::Instant::now()),
+                ..Default::default()
+            };
+            cache_read.update_app(app_name.to_string(), app_info);
+            // Lists every target for an app duplicated to several sinks
+            cache_read.set_app_sinks(app_name, &targets);
         }
+        return Ok(format!("Routed {app_name} to {sink_name}"));
     }
 
-    if success_count == 0 {
-        bail!("Failed to move any sink inputs: {:?}", errors);
-    }
+    controller
+        .route_app(app_name, sink_name)
+        .await
+        .map_err(|e| e.context(format!("Failed to route {app_name} to {sink_name}")))?;
+    Ok(format!("Routed {app_name} to {sink_name}"))
+}
 
-    info!(
-        "Successfully routed {} ({} streams) to {} (sink #{})",
-        app_name, success_count, sink_name, sink_id
-    );
-    Ok(())
+/// Apply a linear volume to a sink and its loopback, the same way D-Bus does
+async fn set_sink_volume(
+    cache: &Arc<RwLock<AudioCache>>,
+    controller: &PipeWireController,
+    sink_name: &str,
+    volume: f32,
+) -> Result<String> {
+    require_sink(cache, sink_name).await?;
+    controller.set_sink_volume(sink_name, volume).await?;
+    Ok(format!("Set {sink_name} volume to {volume}"))
 }
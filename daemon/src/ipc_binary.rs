@@ -0,0 +1,249 @@
+use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::ipc::IpcError;
+
+/// Line that switches a connection to binary frames
+///
+/// After the daemon answers `OK binary`, each message is a little-endian `u32`
+/// payload length followed by the payload. Requests start with an opcode byte
+/// and responses with a status byte; strings are a `u16` length and UTF-8
+/// bytes. Requests go to the same handlers as their text command, and text
+/// requests are run as commands.
+pub const BINARY_HANDSHAKE: &str = "PROTOCOL binary";
+
+/// Largest payload either side accepts
+pub const MAX_FRAME_LEN: usize = 64 * 1024;
+
+const OP_TEXT: u8 = 0x00;
+const OP_SET_VOLUME: u8 = 0x01;
+const OP_SET_VOLUME_DB: u8 = 0x02;
+const OP_MUTE: u8 = 0x03;
+const OP_ROUTE: u8 = 0x04;
+const OP_GET_VOLUME: u8 = 0x05;
+const OP_PING: u8 = 0x06;
+
+const STATUS_OK: u8 = 0x00;
+const STATUS_ERROR: u8 = 0x01;
+
+/// A command sent as a binary frame
+#[derive(Debug, Clone, PartialEq)]
+pub enum Request {
+    /// Any text protocol command, for those without an opcode of their own
+    Text(String),
+    SetVolume {
+        sink: String,
+        volume: f32,
+    },
+    SetVolumeDb {
+        sink: String,
+        db: f32,
+    },
+    Mute {
+        sink: String,
+        muted: bool,
+    },
+    Route {
+        app: String,
+        sink: String,
+    },
+    GetVolume {
+        sink: String,
+    },
+    Ping,
+}
+
+impl Request {
+    /// The payload for this request, without the length prefix
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        match self {
+            Request::Text(command) => {
+                payload.push(OP_TEXT);
+                put_str(&mut payload, command);
+            }
+            Request::SetVolume { sink, volume } => {
+                payload.push(OP_SET_VOLUME);
+                put_str(&mut payload, sink);
+                payload.extend_from_slice(&volume.to_le_bytes());
+            }
+            Request::SetVolumeDb { sink, db } => {
+                payload.push(OP_SET_VOLUME_DB);
+                put_str(&mut payload, sink);
+                payload.extend_from_slice(&db.to_le_bytes());
+            }
+            Request::Mute { sink, muted } => {
+                payload.push(OP_MUTE);
+                put_str(&mut payload, sink);
+                payload.push(u8::from(*muted));
+            }
+            Request::Route { app, sink } => {
+                payload.push(OP_ROUTE);
+                put_str(&mut payload, app);
+                put_str(&mut payload, sink);
+            }
+            Request::GetVolume { sink } => {
+                payload.push(OP_GET_VOLUME);
+                put_str(&mut payload, sink);
+            }
+            Request::Ping => payload.push(OP_PING),
+        }
+        payload
+    }
+
+    /// Parse a request payload, rejecting unknown opcodes and trailing bytes
+    pub fn decode(payload: &[u8]) -> Result<Self, IpcError> {
+        let mut reader = Reader(payload);
+        let request = match reader.u8()? {
+            OP_TEXT => Request::Text(reader.string()?),
+            OP_SET_VOLUME => Request::SetVolume { sink: reader.name()?, volume: reader.f32()? },
+            OP_SET_VOLUME_DB => Request::SetVolumeDb { sink: reader.name()?, db: reader.f32()? },
+            OP_MUTE => Request::Mute { sink: reader.name()?, muted: reader.u8()? != 0 },
+            OP_ROUTE => Request::Route { app: reader.name()?, sink: reader.name()? },
+            OP_GET_VOLUME => Request::GetVolume { sink: reader.name()? },
+            OP_PING => Request::Ping,
+            op => return Err(IpcError::UnknownCommand(format!("Unknown opcode 0x{op:02x}"))),
+        };
+        reader.finish()?;
+        Ok(request)
+    }
+
+    /// The equivalent text protocol command
+    pub fn to_command(&self) -> String {
+        match self {
+            Request::Text(command) => command.clone(),
+            Request::SetVolume { sink, volume } => format!("SET_VOLUME {sink} {volume}"),
+            Request::SetVolumeDb { sink, db } => format!("SET_VOLUME_DB {sink} {db}"),
+            Request::Mute { sink, muted } => format!("MUTE {sink} {muted}"),
+            Request::Route { app, sink } => format!("ROUTE {app} {sink}"),
+            Request::GetVolume { sink } => format!("GET_VOLUME {sink}"),
+            Request::Ping => "PING".to_string(),
+        }
+    }
+}
+
+/// The daemon's answer to a binary request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    Ok(String),
+    Error { code: String, message: String },
+}
+
+impl Response {
+    /// The payload for this response, without the length prefix
+    pub fn encode(&self) -> Vec<u8> {
+        let mut payload = Vec::new();
+        match self {
+            Response::Ok(message) => {
+                payload.push(STATUS_OK);
+                put_str(&mut payload, message);
+            }
+            Response::Error { code, message } => {
+                payload.push(STATUS_ERROR);
+                put_str(&mut payload, code);
+                put_str(&mut payload, message);
+            }
+        }
+        payload
+    }
+
+    pub fn decode(payload: &[u8]) -> Result<Self, IpcError> {
+        let mut reader = Reader(payload);
+        let response = match reader.u8()? {
+            STATUS_OK => Response::Ok(reader.string()?),
+            STATUS_ERROR => Response::Error { code: reader.string()?, message: reader.string()? },
+            status => return Err(IpcError::BadArgs(format!("Unknown status 0x{status:02x}"))),
+        };
+        reader.finish()?;
+        Ok(response)
+    }
+}
+
+/// Read one frame's payload, or `None` once the peer has closed the connection
+pub async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0u8; 4];
+    match reader.read_exact(&mut len).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_le_bytes(len) as usize;
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Frame of {len} bytes exceeds the {MAX_FRAME_LEN} byte limit"),
+        ));
+    }
+    let mut payload = vec![0u8; len];
+    reader.read_exact(&mut payload).await?;
+    Ok(Some(payload))
+}
+
+/// Write `payload` with its length prefix
+pub async fn write_frame<W: AsyncWrite + Unpin>(writer: &mut W, payload: &[u8]) -> io::Result<()> {
+    if payload.len() > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Frame of {} bytes exceeds the {MAX_FRAME_LEN} byte limit", payload.len()),
+        ));
+    }
+    let mut frame = Vec::with_capacity(4 + payload.len());
+    frame.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame).await
+}
+
+/// Append a length-prefixed string, cutting it at the longest length a `u16` can hold
+fn put_str(payload: &mut Vec<u8>, value: &str) {
+    let mut end = value.len().min(u16::MAX as usize);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    payload.extend_from_slice(&(end as u16).to_le_bytes());
+    payload.extend_from_slice(&value.as_bytes()[..end]);
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], IpcError> {
+        if self.0.len() < count {
+            return Err(IpcError::BadArgs("Truncated frame".to_string()));
+        }
+        let (taken, rest) = self.0.split_at(count);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, IpcError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn f32(&mut self) -> Result<f32, IpcError> {
+        let bytes = self.take(4)?;
+        Ok(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn string(&mut self) -> Result<String, IpcError> {
+        let len = self.take(2)?;
+        let len = u16::from_le_bytes([len[0], len[1]]) as usize;
+        String::from_utf8(self.take(len)?.to_vec())
+            .map_err(|_| IpcError::BadArgs("String is not valid UTF-8".to_string()))
+    }
+
+    /// A sink or app name, which must fit in a single text protocol argument
+    fn name(&mut self) -> Result<String, IpcError> {
+        let name = self.string()?;
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(IpcError::BadArgs(format!("Invalid name: {name:?}")));
+        }
+        Ok(name)
+    }
+
+    fn finish(&self) -> Result<(), IpcError> {
+        if !self.0.is_empty() {
+            return Err(IpcError::BadArgs(format!("{} trailing bytes in frame", self.0.len())));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds of the histogram buckets in microseconds; slower samples go in one more
+const BUCKET_BOUNDS_US: [u64; 16] = [
+    10, 25, 50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000,
+    500_000, 1_000_000,
+];
+
+/// Samples after which every count is halved, so percentiles follow recent behaviour
+const DECAY_AFTER_SAMPLES: u64 = 1024;
+
+/// Work whose latency the daemon keeps track of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    CacheUpdate, // Applying one monitor update to the cache
+    Snapshot,    // Building and writing a shared memory snapshot
+    Route,       // Moving an app's streams to a sink
+}
+
+impl Operation {
+    pub const ALL: [Operation; 3] = [Operation::CacheUpdate, Operation::Snapshot, Operation::Route];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Operation::CacheUpdate => "cache_update",
+            Operation::Snapshot => "snapshot",
+            Operation::Route => "route",
+        }
+    }
+}
+
+/// Percentiles of the recent samples of one operation, in microseconds
+///
+/// Percentiles are the upper bound of the bucket they fall in, so they overstate the
+/// latency by at most one bucket. Samples beyond the last bucket report the maximum.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub p50_us: u64,
+    pub p90_us: u64,
+    pub p99_us: u64,
+    pub max_us: u64,
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    counts: [u64; BUCKET_BOUNDS_US.len() + 1],
+    total: u64,
+    max_us: u64,
+}
+
+/// Fixed-bucket histogram of how long an operation took
+#[derive(Debug, Default)]
+pub struct LatencyHistogram {
+    buckets: Mutex<Buckets>,
+}
+
+impl LatencyHistogram {
+    pub fn record(&self, elapsed: Duration) {
+        let micros = u64::try_from(elapsed.as_micros()).unwrap_or(u64::MAX);
+        let bucket = BUCKET_BOUNDS_US.partition_point(|bound| *bound < micros);
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.total >= DECAY_AFTER_SAMPLES {
+            buckets.counts.iter_mut().for_each(|count| *count /= 2);
+            buckets.total = buckets.counts.iter().sum();
+        }
+        buckets.counts[bucket] += 1;
+        buckets.total += 1;
+        buckets.max_us = buckets.max_us.max(micros);
+    }
+
+    pub fn summary(&self) -> LatencySummary {
+        let buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let percentile = |fraction: f64| {
+            let rank = ((buckets.total as f64 * fraction).ceil() as u64).max(1);
+            let mut seen = 0;
+            for (bucket, count) in buckets.counts.iter().enumerate() {
+                seen += count;
+                if seen >= rank {
+                    // Never report more than the slowest sample actually seen
+                    return BUCKET_BOUNDS_US
+                        .get(bucket)
+                        .map_or(buckets.max_us, |bound| (*bound).min(buckets.max_us));
+                }
+            }
+            0
+        };
+        LatencySummary {
+            count: buckets.total,
+            p50_us: percentile(0.5),
+            p90_us: percentile(0.9),
+            p99_us: percentile(0.99),
+            max_us: buckets.max_us,
+        }
+    }
+}
+
+/// A histogram per [`Operation`]
+#[derive(Debug, Default)]
+pub struct LatencyStats {
+    cache_update: LatencyHistogram,
+    snapshot: LatencyHistogram,
+    route: LatencyHistogram,
+}
+
+impl LatencyStats {
+    fn histogram(&self, operation: Operation) -> &LatencyHistogram {
+        match operation {
+            Operation::CacheUpdate => &self.cache_update,
+            Operation::Snapshot => &self.snapshot,
+            Operation::Route => &self.route,
+        }
+    }
+
+    pub fn record(&self, operation: Operation, elapsed: Duration) {
+        self.histogram(operation).record(elapsed);
+    }
+
+    /// Summaries by operation name, including operations with no samples yet
+    pub fn summaries(&self) -> BTreeMap<&'static str, LatencySummary> {
+        Operation::ALL
+            .into_iter()
+            .map(|operation| (operation.name(), self.histogram(operation).summary()))
+            .collect()
+    }
+}
@@ -1,7 +1,23 @@
 pub mod app_name_detector;
+pub mod backend;
 pub mod cache;
+pub mod command;
 pub mod config;
+pub mod daemon;
 pub mod dbus_service;
+pub mod events;
+pub mod focus;
+pub mod inspect;
 pub mod ipc;
+pub mod ipc_binary;
+pub mod latency;
+pub mod mock_backend;
 pub mod pipewire_controller;
 pub mod pipewire_monitor;
+pub mod resume;
+pub mod schedule;
+pub mod shared_memory;
+pub mod sink_inputs;
+pub mod volume;
+
+pub use daemon::{Daemon, DaemonBuilder};
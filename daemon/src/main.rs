@@ -1,27 +1,17 @@
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::sync::Arc;
-use tokio::sync::RwLock;
 use tracing::{debug, error, info};
 
-mod cache;
-mod config;
-mod dbus_service;
-mod ipc;
-mod pipewire_controller;
-mod pipewire_monitor;
-
-use cache::AudioCache;
-use config::{AppMappings, Config};
-use dbus_service::start_dbus_service;
-use ipc::IpcServer;
-use pipewire_controller::PipeWireController;
-use pipewire_monitor::PipeWireMonitor;
+use pipewire_volume_mixer_daemon::config::Config;
+use pipewire_volume_mixer_daemon::inspect;
+use pipewire_volume_mixer_daemon::ipc::default_socket_path;
+use pipewire_volume_mixer_daemon::Daemon;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Configuration file path
+    /// Configuration file path, read as JSON if it ends in .json and TOML otherwise
     #[arg(short, long, default_value = "/etc/pipewire-volume-mixer/config.toml")]
     config: String,
 
@@ -32,12 +22,41 @@ struct Args {
     /// Run in foreground (don't daemonize)
     #[arg(short, long)]
     foreground: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print the running daemon's sinks, apps and routing rules, then exit
+    #[command(alias = "once")]
+    Inspect {
+        /// Print JSON instead of tables
+        #[arg(long)]
+        json: bool,
+
+        /// IPC socket of the running daemon
+        #[arg(long)]
+        socket: Option<String>,
+    },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
 
+    if let Some(Command::Inspect { json, socket }) = args.command {
+        let socket = socket.unwrap_or_else(default_socket_path);
+        let state = inspect::query_daemon(&socket).await?;
+        if json {
+            println!("{}", inspect::format_json(&state)?);
+        } else {
+            print!("{}", inspect::format_table(&state));
+        }
+        return Ok(());
+    }
+
     // Initialize logging
     let filter = if args.debug { "debug" } else { "info" };
     tracing_subscriber::fmt().with_env_filter(filter).init();
@@ -48,92 +67,32 @@ async fn main() -> Result<()> {
     let config = Config::load(&args.config)?;
     debug!("Loaded configuration: {:?}", config);
 
-    // Load app mappings from disk
-    let app_mappings = match AppMappings::load() {
-        Ok(mappings) => {
-            info!("Loaded {} app mappings from disk", mappings.mappings.len());
-            Arc::new(RwLock::new(mappings))
-        }
-        Err(e) => {
-            error!("Failed to load app mappings: {}", e);
-            Arc::new(RwLock::new(AppMappings::default()))
-        }
-    };
-
-    // Initialize shared cache with loaded mappings
-    let cache = Arc::new(RwLock::new(AudioCache::new()));
-
-    // Populate cache with loaded mappings
-    {
-        #[allow(unused_mut)]
-        let mut cache_write = cache.write().await;
-        let mappings_read = app_mappings.read().await;
-        for (app_name, sink_name) in &mappings_read.mappings {
-            cache_write.remembered_apps.insert(app_name.clone(), sink_name.clone());
-            cache_write.routing_rules.insert(app_name.clone(), sink_name.clone());
-            debug!("Restored mapping: {} -> {}", app_name, sink_name);
-        }
-    }
-
-    // Initialize PipeWire controller
-    let controller = Arc::new(PipeWireController::new(cache.clone()));
-
-    // Start D-Bus service
-    let _dbus_connection =
-        start_dbus_service(cache.clone(), controller.clone(), app_mappings.clone()).await?;
-    info!("D-Bus service started on org.gnome.PipewireVolumeMixer");
+    let daemon = Arc::new(Daemon::builder(config).build());
 
-    // Initialize IPC server
-    let ipc_server = IpcServer::new(cache.clone())?;
-    let ipc_handle = tokio::spawn(async move {
-        if let Err(e) = ipc_server.run().await {
-            error!("IPC server error: {}", e);
-        }
+    // Stop the daemon on Ctrl+C or SIGTERM
+    let signal_daemon = daemon.clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        signal_daemon.shutdown();
     });
 
-    // Start cleanup task for inactive apps
-    let cache_cleanup = cache.clone();
-    let cleanup_handle = tokio::spawn(async move {
-        // Check less frequently - every 15 seconds is plenty
-        let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(15));
-        loop {
-            interval.tick().await;
-
-            // First do a quick check if there are any inactive apps at all
-            let (has_inactive, inactive_count) = {
-                let cache = cache_cleanup.read().await;
-                let inactive_apps: Vec<_> = cache
-                    .apps
-                    .iter()
-                    .filter(|entry| !entry.value().active)
-                    .map(|entry| entry.key().clone())
-                    .collect();
-                let count = inactive_apps.len();
-                (count > 0, count)
-            };
-
-            // Only run cleanup if there are inactive apps
-            if has_inactive {
-                debug!("Running cleanup for {} inactive apps", inactive_count);
-                let removed = cache_cleanup.read().await.cleanup_inactive_apps(300); // 5 minutes
-                if removed > 0 {
-                    info!("Cleaned up {} inactive apps after 5 minute TTL", removed);
-                } else {
-                    debug!("No apps exceeded TTL yet");
-                }
-            }
-        }
-    });
-
-    // Initialize PipeWire monitor
-    let pw_monitor = PipeWireMonitor::new(cache.clone(), config, controller.clone())?;
-
-    // Run PipeWire monitor in main thread
-    info!("Starting PipeWire monitoring");
-    pw_monitor.run().await?;
+    daemon.run().await
+}
 
-    // Wait for tasks to complete (they shouldn't unless there's an error)
-    tokio::try_join!(ipc_handle, cleanup_handle)?;
+/// Resolves on Ctrl+C or SIGTERM
+async fn shutdown_signal() {
+    let mut terminate =
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(signal) => signal,
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+                return;
+            }
+        };
 
-    Ok(())
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
 }
@@ -6,10 +6,39 @@ use std::time::Duration;
 use tokio::sync::RwLock;
 use tracing::{debug, info};
 
+#[path = "app_name_detector.rs"]
+#[allow(dead_code)] // Only stream naming for the controller's rescans is used here
+mod app_name_detector;
+#[path = "backend.rs"]
+#[allow(dead_code)] // Only reached through the controller's pid routing here
+mod backend;
 #[path = "cache.rs"]
 mod cache;
+#[path = "command.rs"]
+mod command;
+#[path = "config.rs"]
+#[allow(dead_code)] // Only referenced by the cache's routing resolution here
+mod config;
+#[path = "events.rs"]
+mod events;
+#[path = "inspect.rs"]
+#[allow(dead_code)] // Only the state dump is used by the IPC handler here
+mod inspect;
 #[path = "ipc.rs"]
 mod ipc;
+#[path = "ipc_binary.rs"]
+#[allow(dead_code)] // Clients encode requests, the daemon only decodes them
+mod ipc_binary;
+#[path = "latency.rs"]
+mod latency;
+#[path = "pipewire_controller.rs"]
+#[allow(dead_code)] // Only pid routing is reachable from the IPC handler here
+mod pipewire_controller;
+#[path = "sink_inputs.rs"]
+#[allow(dead_code)] // Node correlation is only used by the PipeWire monitor
+mod sink_inputs;
+#[path = "volume.rs"]
+mod volume;
 
 use cache::{AppInfo, AudioCache, SinkInfo};
 use ipc::IpcServer;
@@ -35,8 +64,9 @@ async fn main() -> Result<()> {
                 id: 100,
                 name: "Game".to_string(),
                 volume: 0.75,
-                muted: false,
                 pipewire_id: 100,
+                applied_percent: 75,
+                ..Default::default()
             },
         );
 
@@ -46,8 +76,9 @@ async fn main() -> Result<()> {
                 id: 101,
                 name: "Chat".to_string(),
                 volume: 0.5,
-                muted: false,
                 pipewire_id: 101,
+                applied_percent: 50,
+                ..Default::default()
             },
         );
 
@@ -57,8 +88,9 @@ async fn main() -> Result<()> {
                 id: 102,
                 name: "Media".to_string(),
                 volume: 1.0,
-                muted: false,
                 pipewire_id: 102,
+                applied_percent: 100,
+                ..Default::default()
             },
         );
 
@@ -73,7 +105,7 @@ async fn main() -> Result<()> {
                 active: true,
                 sink_input_ids: vec![200],
                 pipewire_id: 200,
-                inactive_since: None,
+                ..Default::default()
             },
         );
 
@@ -85,9 +117,9 @@ async fn main() -> Result<()> {
                 stream_names: vec!["Discord".to_string()],
                 current_sink: "Chat".to_string(),
                 active: false,
-                sink_input_ids: vec![],
                 pipewire_id: 201,
                 inactive_since: Some(std::time::Instant::now()),
+                ..Default::default()
             },
         );
     }
@@ -0,0 +1,244 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::broadcast;
+
+use crate::backend::{Node, PipeWireBackend, SinkEntry};
+use crate::sink_inputs::SinkInput;
+
+/// New streams a receiver can fall behind on before it lags
+const NEW_STREAM_CAPACITY: usize = 64;
+
+/// Id of the first sink or stream; sinks and streams share one id space like in PipeWire
+const FIRST_ID: u32 = 100;
+
+#[derive(Debug, Default)]
+struct MockState {
+    sinks: Vec<SinkEntry>,
+    inputs: Vec<SinkInput>,
+    volumes: HashMap<Node, u32>, // Last percent set per node
+    mutes: HashMap<Node, bool>,
+    modules: HashMap<u32, String>, // Module id -> sink it created
+    default_sink: Option<String>,  // The first sink when not set
+    next_id: u32,
+}
+
+impl MockState {
+    fn allocate_id(&mut self) -> u32 {
+        let id = self.next_id.max(FIRST_ID);
+        self.next_id = id + 1;
+        id
+    }
+
+    fn add_sink(&mut self, sink_name: &str) -> u32 {
+        if let Some(sink) = self.sinks.iter().find(|sink| sink.name == sink_name) {
+            return sink.id;
+        }
+        let id = self.allocate_id();
+        self.sinks.push(SinkEntry { id, name: sink_name.to_string() });
+        id
+    }
+
+    fn sink_id(&self, sink_name: &str) -> Result<u32> {
+        self.sinks
+            .iter()
+            .find(|sink| sink.name == sink_name)
+            .map(|sink| sink.id)
+            .ok_or_else(|| anyhow!("No sink named {}", sink_name))
+    }
+}
+
+/// In-memory audio server for tests of the whole daemon without PipeWire or pactl
+///
+/// Sinks and streams live in a shared model that moves, volume and mute changes act
+/// on, so a test can check where a stream ended up. Streams added with
+/// [`Self::add_stream`] are announced like the monitor would see them appear. Clones
+/// share the model, so keep one to inspect after handing the other to the daemon.
+#[derive(Debug, Clone)]
+pub struct MockBackend {
+    state: Arc<Mutex<MockState>>,
+    new_streams: broadcast::Sender<u32>,
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self { state: Arc::default(), new_streams: broadcast::channel(NEW_STREAM_CAPACITY).0 }
+    }
+
+    /// Start out with a sink named `sink_name`
+    pub fn with_sink(self, sink_name: &str) -> Self {
+        self.state().add_sink(sink_name);
+        self
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Start a stream on `sink_name` with the given properties and announce it
+    ///
+    /// Returns the stream's sink input id.
+    pub fn add_stream(&self, sink_name: &str, properties: &[(&str, &str)]) -> Result<u32> {
+        let id = {
+            let mut state = self.state();
+            let sink = state.sink_id(sink_name)?;
+            let id = state.allocate_id();
+            state.inputs.push(SinkInput {
+                id,
+                sink: Some(sink),
+                volume: Some(1.0),
+                corked: false,
+                properties: properties
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+            });
+            id
+        };
+        // Nobody listening is fine, the stream still exists
+        let _ = self.new_streams.send(id);
+        Ok(id)
+    }
+
+    /// End a stream, returning false if there was none with that id
+    pub fn remove_stream(&self, sink_input_id: u32) -> bool {
+        let mut state = self.state();
+        let before = state.inputs.len();
+        state.inputs.retain(|input| input.id != sink_input_id);
+        state.inputs.len() != before
+    }
+
+    /// Name of the sink a stream plays on
+    pub fn stream_sink(&self, sink_input_id: u32) -> Option<String> {
+        let state = self.state();
+        let sink = state.inputs.iter().find(|input| input.id == sink_input_id)?.sink?;
+        state.sinks.iter().find(|entry| entry.id == sink).map(|entry| entry.name.clone())
+    }
+
+    /// Id the model gave a sink
+    pub fn sink_id(&self, sink_name: &str) -> Option<u32> {
+        self.state().sink_id(sink_name).ok()
+    }
+
+    /// Percent last set on a node
+    pub fn volume(&self, node: Node) -> Option<u32> {
+        self.state().volumes.get(&node).copied()
+    }
+
+    /// Mute state last set on a node
+    pub fn muted(&self, node: Node) -> Option<bool> {
+        self.state().mutes.get(&node).copied()
+    }
+
+    /// Make `sink_name` the default sink, as if the user picked it in the settings
+    pub fn set_default_sink(&self, sink_name: &str) {
+        self.state().default_sink = Some(sink_name.to_string());
+    }
+}
+
+#[async_trait]
+impl PipeWireBackend for MockBackend {
+    async fn set_volume(&self, node: Node, percent: u32) -> Result<()> {
+        let mut state = self.state();
+        match node {
+            Node::Sink(id) if !state.sinks.iter().any(|sink| sink.id == id) => {
+                return Err(anyhow!("No sink with id {}", id));
+            }
+            Node::SinkInput(id) => {
+                let input = state
+                    .inputs
+                    .iter_mut()
+                    .find(|input| input.id == id)
+                    .ok_or_else(|| anyhow!("No sink input with id {}", id))?;
+                input.volume = Some(percent as f32 / 100.0);
+            }
+            Node::Sink(_) => {}
+        }
+        state.volumes.insert(node, percent);
+        Ok(())
+    }
+
+    async fn set_mute(&self, node: Node, muted: bool) -> Result<()> {
+        self.state().mutes.insert(node, muted);
+        Ok(())
+    }
+
+    async fn move_sink_input(&self, sink_input_id: u32, sink_name: &str) -> Result<()> {
+        let mut state = self.state();
+        let sink = state.sink_id(sink_name)?;
+        let input = state
+            .inputs
+            .iter_mut()
+            .find(|input| input.id == sink_input_id)
+            .ok_or_else(|| anyhow!("No sink input with id {}", sink_input_id))?;
+        input.sink = Some(sink);
+        Ok(())
+    }
+
+    async fn list_sink_inputs(&self) -> Result<Vec<SinkInput>> {
+        Ok(self.state().inputs.clone())
+    }
+
+    async fn combine_sinks(&self, sink_name: &str, targets: &[String]) -> Result<Option<u32>> {
+        let mut state = self.state();
+        for target in targets {
+            state.sink_id(target)?;
+        }
+        if state.sink_id(sink_name).is_ok() {
+            return Ok(None);
+        }
+        state.add_sink(sink_name);
+        let module_id = state.allocate_id();
+        state.modules.insert(module_id, sink_name.to_string());
+        Ok(Some(module_id))
+    }
+
+    async fn list_sinks(&self) -> Result<Vec<SinkEntry>> {
+        Ok(self.state().sinks.clone())
+    }
+
+    async fn create_virtual_sink(
+        &self,
+        sink_name: &str,
+        _description: &str,
+        _loopback_name: &str,
+    ) -> Result<Vec<u32>> {
+        let mut state = self.state();
+        state.add_sink(sink_name);
+        let module_id = state.allocate_id();
+        state.modules.insert(module_id, sink_name.to_string());
+        Ok(vec![module_id])
+    }
+
+    async fn unload_module(&self, module_id: u32) -> Result<()> {
+        let mut state = self.state();
+        let sink_name =
+            state.modules.remove(&module_id).ok_or_else(|| anyhow!("No module {}", module_id))?;
+        state.sinks.retain(|sink| sink.name != sink_name);
+        Ok(())
+    }
+
+    async fn server_version(&self) -> Result<String> {
+        Ok("mock".to_string())
+    }
+
+    async fn default_sink(&self) -> Result<String> {
+        let state = self.state();
+        state
+            .default_sink
+            .clone()
+            .or_else(|| state.sinks.first().map(|sink| sink.name.clone()))
+            .ok_or_else(|| anyhow!("No sinks"))
+    }
+
+    fn subscribe_new_streams(&self) -> Option<broadcast::Receiver<u32>> {
+        Some(self.new_streams.subscribe())
+    }
+}
@@ -1,98 +1,156 @@
 use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, Mutex, RwLock};
 use tracing::{debug, error, info, warn};
 
-use crate::cache::AudioCache;
+use crate::app_name_detector::{AppNameConfig, AppNameDetector};
+use crate::backend::{combined_sink_name, Node, PactlBackend, PipeWireBackend, SinkEntry};
+use crate::cache::{parse_sink_targets, AppInfo, AudioCache, NewStream, SinkInfo};
+use crate::command::CommandExecutor;
+use crate::config::{CacheConfig, Config, RoutingConfig, VirtualSink};
+use crate::events::EventKind;
+use crate::latency::Operation;
+use crate::sink_inputs::{find_sink_input, sink_inputs_for_pid, SinkInput};
+use crate::volume::{
+    crossfade_volumes, ramp_percents, sanitize_volume, volume_to_percent, RAMP_STEP_INTERVAL,
+};
+
+/// How long a listing of the sink inputs is reused by lookups that overlap it
+const SINK_INPUT_LIST_TTL: Duration = Duration::from_millis(100);
+
+/// Time a new stream's app gets to set it up before it's routed and given its saved
+/// volume and mute, so an app restoring its own volume doesn't undo ours
+pub const STREAM_SETTLE_DELAY: Duration = Duration::from_millis(500);
+
+/// The sink inputs as last listed by the backend
+struct SinkInputList {
+    inputs: Vec<SinkInput>,
+    listed_at: Instant,
+}
 
 /// Controller for PipeWire operations
 /// This module handles the actual PipeWire control operations
 pub struct PipeWireController {
     cache: Arc<RwLock<AudioCache>>,
+    backend: Box<dyn PipeWireBackend>,
+    sink_inputs: Mutex<Option<SinkInputList>>, // Shared by bursts of lookups, see list_sink_inputs
+    combined_sinks: Mutex<HashMap<String, u32>>, // Combine sink -> module this controller loaded
+    cache_config: CacheConfig, // How rescans name and filter apps, as the monitor does
+    detector: AppNameDetector,
 }
 
 impl PipeWireController {
     pub fn new(cache: Arc<RwLock<AudioCache>>) -> Self {
-        Self { cache }
+        Self::with_backend(cache, Box::new(PactlBackend::new()))
+    }
+
+    /// Create a controller that runs its pactl commands through a custom executor
+    ///
+    /// The lookups that name the apps of rescanned streams go through it too.
+    pub fn with_executor(
+        cache: Arc<RwLock<AudioCache>>,
+        executor: Arc<dyn CommandExecutor>,
+    ) -> Self {
+        let backend = PactlBackend::with_executor(executor.clone());
+        Self {
+            detector: AppNameDetector::new(executor, AppNameConfig::default()),
+            ..Self::with_backend(cache, Box::new(backend))
+        }
+    }
+
+    /// Create a controller on top of any backend
+    pub fn with_backend(cache: Arc<RwLock<AudioCache>>, backend: Box<dyn PipeWireBackend>) -> Self {
+        Self {
+            cache,
+            backend,
+            sink_inputs: Mutex::new(None),
+            combined_sinks: Mutex::new(HashMap::new()),
+            cache_config: Config::default().cache,
+            detector: AppNameDetector::new_system(),
+        }
+    }
+
+    /// Name and filter the apps of streams found in a rescan with these settings
+    pub fn with_cache_config(mut self, cache_config: CacheConfig) -> Self {
+        self.cache_config = cache_config;
+        self
     }
 
     /// Set volume for a virtual sink
     pub async fn set_sink_volume(&self, sink_name: &str, volume: f32) -> Result<()> {
+        let volume = sanitize_volume(volume)
+            .ok_or_else(|| anyhow::anyhow!("Volume must be a finite number, got {}", volume))?;
         debug!("Setting volume for sink {} to {}", sink_name, volume);
+        let sink_lock = self.cache.read().await.sink_lock(sink_name);
+        let _guard = sink_lock.lock().await;
 
         // Get the PipeWire ID for this sink
-        let pipewire_id = {
-            let cache = self.cache.read().await;
-            cache
-                .sinks
-                .get(sink_name)
-                .map(|s| s.pipewire_id)
-                .ok_or_else(|| anyhow::anyhow!("Sink {} not found", sink_name))?
-        };
+        let pipewire_id = self.sink_pipewire_id(sink_name).await?;
+
+        let volume_percent = volume_to_percent(volume);
 
-        let volume_percent = (volume * 100.0) as u32;
+        // The cache shows the target right away, even while the loopback ramps to it.
+        // The ramp starts from the cached volume, which a sink whose lookup hasn't
+        // landed yet has even without an applied percent
+        let cache = self.cache.write().await;
+        let (from, muted) = cache
+            .sinks
+            .get(sink_name)
+            .map(|sink| (volume_to_percent(sink.volume), sink.muted))
+            .unzip();
+        let ramp = cache.volume_ramp();
+        cache.record_applied_volume(sink_name, volume, volume_percent);
+        cache.record_event(EventKind::VolumeChanged { sink: sink_name.to_string(), volume });
+        drop(cache);
 
         // First set the sink volume (for completeness)
-        let output = tokio::process::Command::new("pactl")
-            .args(["set-sink-volume", &pipewire_id.to_string(), &format!("{volume_percent}%")])
-            .output()
-            .await?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            error!("Failed to set sink volume: {}", stderr);
+        if let Err(e) = self
+            .with_timeout(self.backend.set_volume(Node::Sink(pipewire_id), volume_percent))
+            .await
+        {
+            error!("Failed to set sink volume: {}", e);
             // Don't fail here, try to set loopback volume anyway
         }
 
-        // More importantly, find and set the loopback stream volume
+        // More importantly, find and set the loopback stream volumes
         // This is what actually controls the audio output
-        let pactl_output =
-            tokio::process::Command::new("pactl").args(["list", "sink-inputs"]).output().await?;
-
-        if pactl_output.status.success() {
-            let stdout = String::from_utf8_lossy(&pactl_output.stdout);
-            let blocks: Vec<&str> = stdout.split("Sink Input #").collect();
-
-            for block in blocks {
-                // Look for the loopback stream (e.g., "Game_to_Speaker" for "Game" sink)
-                if block.contains(&format!("node.name = \"{sink_name}_to_Speaker\"")) {
-                    if let Some(id_match) = block.lines().next().and_then(|line| {
-                        line.split_whitespace().next().and_then(|s| s.parse::<u32>().ok())
-                    }) {
-                        debug!("Found loopback stream {} for sink {}", id_match, sink_name);
-
-                        // Set loopback volume - this is what actually controls the audio
-                        let loopback_output = tokio::process::Command::new("pactl")
-                            .args([
-                                "set-sink-input-volume",
-                                &id_match.to_string(),
-                                &format!("{volume_percent}%"),
-                            ])
-                            .output()
-                            .await?;
-
-                        if !loopback_output.status.success() {
-                            let stderr = String::from_utf8_lossy(&loopback_output.stderr);
-                            error!("Failed to set loopback volume: {}", stderr);
-                        } else {
-                            debug!(
-                                "Successfully set loopback stream {} volume to {}%",
-                                id_match, volume_percent
-                            );
-                        }
-
-                        break;
+        let mut loopbacks = self.find_loopbacks(sink_name).await;
+        if !loopbacks.is_empty() {
+            debug!("Found loopback streams {:?} for sink {}", loopbacks, sink_name);
+
+            let steps = match from {
+                Some(from) if !ramp.is_zero() => ramp_percents(from as f32 / 100.0, volume, ramp),
+                _ => vec![volume_percent],
+            };
+            for (i, percent) in steps.into_iter().enumerate() {
+                if i > 0 {
+                    tokio::time::sleep(RAMP_STEP_INTERVAL).await;
+                }
+                // Set loopback volume - this is what actually controls the audio
+                let mut failed = Vec::new();
+                for &loopback_id in &loopbacks {
+                    if let Err(e) = self
+                        .with_timeout(
+                            self.backend.set_volume(Node::SinkInput(loopback_id), percent),
+                        )
+                        .await
+                    {
+                        error!("Failed to set loopback {} volume: {}", loopback_id, e);
+                        failed.push(loopback_id);
                     }
                 }
+                // A loopback that failed once isn't stepped any further
+                loopbacks.retain(|id| !failed.contains(id));
             }
+            debug!("Set loopback streams {:?} volume to {}%", loopbacks, volume_percent);
         }
 
-        // Update cache
-        {
-            let cache = self.cache.write().await;
-            if let Some(mut sink) = cache.sinks.get_mut(sink_name) {
-                sink.volume = volume;
-            };
+        // Turning a muted sink up means it should be heard again
+        if volume > 0.0 && muted == Some(true) {
+            self.apply_sink_mute(sink_name, pipewire_id, false).await;
         }
 
         Ok(())
@@ -101,91 +159,315 @@ impl PipeWireController {
     /// Set mute state for a virtual sink
     pub async fn set_sink_mute(&self, sink_name: &str, muted: bool) -> Result<()> {
         debug!("Setting mute for sink {} to {}", sink_name, muted);
+        let sink_lock = self.cache.read().await.sink_lock(sink_name);
+        let _guard = sink_lock.lock().await;
 
         // Get the PipeWire ID for this sink
-        let pipewire_id = {
-            let cache = self.cache.read().await;
-            cache
-                .sinks
-                .get(sink_name)
-                .map(|s| s.pipewire_id)
-                .ok_or_else(|| anyhow::anyhow!("Sink {} not found", sink_name))?
-        };
-
-        let mute_arg = if muted { "1" } else { "0" };
+        let pipewire_id = self.sink_pipewire_id(sink_name).await?;
+        self.apply_sink_mute(sink_name, pipewire_id, muted).await;
+        Ok(())
+    }
 
+    /// [`Self::set_sink_mute`] for a sink whose lock is held
+    async fn apply_sink_mute(&self, sink_name: &str, pipewire_id: u32, muted: bool) {
         // First set the sink mute (for completeness)
-        let output = tokio::process::Command::new("pactl")
-            .args(["set-sink-mute", &pipewire_id.to_string(), mute_arg])
-            .output()
-            .await?;
-
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            error!("Failed to set sink mute: {}", stderr);
+        if let Err(e) =
+            self.with_timeout(self.backend.set_mute(Node::Sink(pipewire_id), muted)).await
+        {
+            error!("Failed to set sink mute: {}", e);
             // Don't fail here, try to set loopback mute anyway
         }
 
-        // More importantly, find and mute/unmute the loopback stream
+        // More importantly, find and mute/unmute the loopback streams
         // This is what actually controls the audio output
-        let pactl_output =
-            tokio::process::Command::new("pactl").args(["list", "sink-inputs"]).output().await?;
-
-        if pactl_output.status.success() {
-            let stdout = String::from_utf8_lossy(&pactl_output.stdout);
-            let blocks: Vec<&str> = stdout.split("Sink Input #").collect();
-
-            for block in blocks {
-                // Look for the loopback stream (e.g., "Game_to_Speaker" for "Game" sink)
-                if block.contains(&format!("node.name = \"{sink_name}_to_Speaker\"")) {
-                    if let Some(id_match) = block.lines().next().and_then(|line| {
-                        line.split_whitespace().next().and_then(|s| s.parse::<u32>().ok())
-                    }) {
-                        debug!("Found loopback stream {} for sink {}", id_match, sink_name);
-
-                        // Set loopback mute - this is what actually controls the audio
-                        let loopback_output = tokio::process::Command::new("pactl")
-                            .args(["set-sink-input-mute", &id_match.to_string(), mute_arg])
-                            .output()
-                            .await?;
-
-                        if !loopback_output.status.success() {
-                            let stderr = String::from_utf8_lossy(&loopback_output.stderr);
-                            error!("Failed to set loopback mute: {}", stderr);
-                        } else {
-                            debug!(
-                                "Successfully set loopback stream {} mute to {}",
-                                id_match, muted
-                            );
-                        }
-
-                        break;
-                    }
-                }
+        for loopback_id in self.find_loopbacks(sink_name).await {
+            debug!("Found loopback stream {} for sink {}", loopback_id, sink_name);
+
+            // Set loopback mute - this is what actually controls the audio
+            if let Err(e) =
+                self.with_timeout(self.backend.set_mute(Node::SinkInput(loopback_id), muted)).await
+            {
+                error!("Failed to set loopback mute: {}", e);
+            } else {
+                debug!("Successfully set loopback stream {} mute to {}", loopback_id, muted);
             }
         }
 
         // Update cache
+        self.cache.write().await.record_applied_mute(sink_name, muted);
+    }
+
+    /// Mute or unmute every stream of one app, leaving the rest of its sink alone
+    ///
+    /// Returns how many streams were changed, 0 if the app has none playing.
+    pub async fn set_app_mute(&self, app_name: &str, muted: bool) -> Result<usize> {
+        debug!("Setting mute for app {} to {}", app_name, muted);
+        let stream_names = {
+            let cache = self.cache.read().await;
+            cache.apps.get(app_name).map(|app| app.stream_names.clone()).unwrap_or_default()
+        };
+        let inputs = self.list_sink_inputs().await?;
+        let sink_input_ids = app_sink_input_ids(&inputs, app_name, &stream_names);
+        if sink_input_ids.is_empty() {
+            debug!("App {} has no active sink inputs", app_name);
+            return Ok(0);
+        }
+
+        for sink_input_id in &sink_input_ids {
+            self.with_timeout(self.backend.set_mute(Node::SinkInput(*sink_input_id), muted))
+                .await?;
+        }
         {
-            let cache = self.cache.write().await;
-            if let Some(mut sink) = cache.sinks.get_mut(sink_name) {
-                sink.muted = muted;
-            };
+            let cache = self.cache.read().await;
+            cache.set_app_mute(app_name, muted);
+            cache.record_app_mute(app_name, muted);
+        }
+
+        info!(
+            "{} {} streams of {}",
+            if muted { "Muted" } else { "Unmuted" },
+            sink_input_ids.len(),
+            app_name
+        );
+        Ok(sink_input_ids.len())
+    }
+
+    /// Set the volume of every stream of one app, leaving the rest of its sink alone
+    ///
+    /// The volume is remembered and given to the app's streams when it comes back.
+    /// Returns how many streams were changed, 0 if the app has none playing.
+    pub async fn set_app_volume(&self, app_name: &str, volume: f32) -> Result<usize> {
+        let volume = sanitize_volume(volume)
+            .ok_or_else(|| anyhow::anyhow!("Volume must be a finite number, got {}", volume))?;
+        debug!("Setting volume for app {} to {}", app_name, volume);
+        let stream_names = {
+            let cache = self.cache.read().await;
+            cache.apps.get(app_name).map(|app| app.stream_names.clone()).unwrap_or_default()
+        };
+        let inputs = self.list_sink_inputs().await?;
+        let app_inputs = app_sink_inputs(&inputs, app_name, &stream_names);
+        if app_inputs.is_empty() {
+            debug!("App {} has no active sink inputs", app_name);
+            return Ok(0);
+        }
+
+        for input in &app_inputs {
+            self.set_stream_volume(app_name, input, volume).await?;
         }
+        self.cache.read().await.record_app_volume(app_name, volume);
 
+        info!("Set {} streams of {} to volume {}", app_inputs.len(), app_name, volume);
+        Ok(app_inputs.len())
+    }
+
+    /// Give a new stream of an app the volume and mute saved for the app
+    ///
+    /// The stream is named by its serial, as the cache keeps it, and looked up in
+    /// pactl's listing to find its index. Returns false if nothing was saved or the
+    /// stream is gone. Callers wait for the app to set its stream up first, as apps
+    /// that restore their own volume would undo this.
+    pub async fn restore_app_settings(&self, app_name: &str, serial: u32) -> Result<bool> {
+        let Some(settings) = self.cache.read().await.saved_app_settings(app_name) else {
+            return Ok(false);
+        };
+        let inputs = self.list_sink_inputs().await?;
+        let Some(input) = find_sink_input(&inputs, serial, "") else {
+            debug!("Stream {} of {} is gone, nothing to restore", serial, app_name);
+            return Ok(false);
+        };
+        if let Some(volume) = settings.volume {
+            self.set_stream_volume(app_name, input, volume).await?;
+        }
+        if let Some(muted) = settings.muted {
+            self.with_timeout(self.backend.set_mute(Node::SinkInput(input.id), muted)).await?;
+            self.cache.read().await.set_app_mute(app_name, muted);
+        }
+        info!("Restored saved volume and mute of {} on stream {}", app_name, input.id);
+        Ok(true)
+    }
+
+    /// Set one stream's volume and show it on its app
+    async fn set_stream_volume(
+        &self,
+        app_name: &str,
+        input: &SinkInput,
+        volume: f32,
+    ) -> Result<()> {
+        self.with_timeout(
+            self.backend.set_volume(Node::SinkInput(input.id), volume_to_percent(volume)),
+        )
+        .await?;
+        let cache = self.cache.read().await;
+        if cache.set_stream_volume(app_name, input.serial(), volume) {
+            cache.increment_generation();
+        }
         Ok(())
     }
 
+    /// Put a sink back to its configured default volume and unmute it
+    ///
+    /// Returns the volume that was applied.
+    pub async fn reset_sink_volume(&self, sink_name: &str) -> Result<f32> {
+        let volume = self.cache.read().await.default_volume(sink_name);
+        debug!("Resetting sink {} to volume {}", sink_name, volume);
+
+        self.set_sink_mute(sink_name, false).await?;
+        self.set_sink_volume(sink_name, volume).await?;
+        Ok(volume)
+    }
+
+    /// Mute an idle app's streams for `auto_mute_on_inactive`, or unmute them again
+    ///
+    /// The monitor marks the app with [`AudioCache::begin_auto_mute`] or
+    /// [`AudioCache::end_auto_mute`] first; by the time this runs the app may have
+    /// moved on, and then nothing is done. Only the streams the cache has for the app
+    /// are changed. Returns how many were.
+    pub async fn auto_mute_app(&self, app_name: &str, muted: bool) -> Result<usize> {
+        let serials = {
+            let cache = self.cache.read().await;
+            if cache.is_auto_muted(app_name) != muted {
+                debug!("Auto-mute of {} changed before it was applied", app_name);
+                return Ok(0);
+            }
+            cache.apps.get(app_name).map(|app| app.sink_input_ids.clone()).unwrap_or_default()
+        };
+        let inputs = self.list_sink_inputs().await?;
+        let mut changed = 0;
+        for input in inputs.iter().filter(|input| serials.contains(&input.serial())) {
+            debug!(
+                "Auto-{} {} (sink input {})",
+                if muted { "muting" } else { "unmuting" },
+                app_name,
+                input.id
+            );
+            // A listing from just before the stream went away may still name it
+            match self.with_timeout(self.backend.set_mute(Node::SinkInput(input.id), muted)).await {
+                Ok(()) => changed += 1,
+                Err(e) => debug!("Could not auto-mute sink input {}: {}", input.id, e),
+            }
+        }
+        self.cache.read().await.set_app_mute(app_name, muted);
+        Ok(changed)
+    }
+
+    /// Fade between two sinks, 0.0 leaving only `from_sink` audible and 1.0 only `to_sink`
+    pub async fn crossfade(&self, from_sink: &str, to_sink: &str, position: f32) -> Result<()> {
+        if !(0.0..=1.0).contains(&position) {
+            return Err(anyhow::anyhow!("Crossfade position must be between 0.0 and 1.0"));
+        }
+        if from_sink == to_sink {
+            return Err(anyhow::anyhow!("Cannot crossfade {} with itself", from_sink));
+        }
+
+        let (from_volume, to_volume) = crossfade_volumes(position);
+        debug!("Crossfading {} ({}) -> {} ({})", from_sink, from_volume, to_sink, to_volume);
+        self.set_sink_volume(from_sink, from_volume).await?;
+        self.set_sink_volume(to_sink, to_volume).await
+    }
+
+    /// Mute every virtual sink except `sink_name`, remembering their previous states
+    pub async fn solo_sink(&self, sink_name: &str) -> Result<()> {
+        let mutes = self
+            .cache
+            .read()
+            .await
+            .solo_sink(sink_name)
+            .ok_or_else(|| anyhow::anyhow!("Sink {} not found", sink_name))?;
+        info!("Soloing sink {}", sink_name);
+        self.apply_mutes(mutes).await;
+        Ok(())
+    }
+
+    /// Restore the mute states from before the sink was soloed
+    pub async fn unsolo(&self) -> Result<()> {
+        let mutes = self.cache.read().await.unsolo();
+        info!("Ending solo, restoring {} sinks", mutes.len());
+        self.apply_mutes(mutes).await;
+        Ok(())
+    }
+
+    /// Mute every virtual sink, remembering their previous states
+    pub async fn mute_all(&self) -> Result<()> {
+        let mutes = self.cache.read().await.mute_all();
+        info!("Muting all {} sinks", mutes.len());
+        self.apply_mutes(mutes).await;
+        Ok(())
+    }
+
+    /// Restore the mute states from before `mute_all` or a solo, else unmute every sink
+    pub async fn unmute_all(&self) -> Result<()> {
+        let mutes = self.cache.read().await.unmute_all();
+        info!("Unmuting all, restoring {} sinks", mutes.len());
+        self.apply_mutes(mutes).await;
+        Ok(())
+    }
+
+    async fn apply_mutes(&self, mutes: Vec<(String, bool)>) {
+        for (sink_name, muted) in mutes {
+            if let Err(e) = self.set_sink_mute(&sink_name, muted).await {
+                error!("Failed to set mute for {}: {}", sink_name, e);
+            }
+        }
+    }
+
+    async fn sink_pipewire_id(&self, sink_name: &str) -> Result<u32> {
+        let cache = self.cache.read().await;
+        cache
+            .sinks
+            .get(sink_name)
+            .map(|s| s.pipewire_id)
+            .ok_or_else(|| anyhow::anyhow!("Sink {} not found", sink_name))
+    }
+
+    /// Find the loopback streams (e.g., "Game_to_Speaker" for "Game" sink)
+    async fn find_loopbacks(&self, sink_name: &str) -> Vec<u32> {
+        let loopback_names = self.cache.read().await.loopback_node_names(sink_name);
+        let Ok(inputs) = self.list_sink_inputs().await else {
+            return Vec::new();
+        };
+        inputs
+            .iter()
+            .filter(|input| {
+                input
+                    .property("node.name")
+                    .is_some_and(|name| loopback_names.iter().any(|n| n == name))
+            })
+            .map(|input| input.id)
+            .collect()
+    }
+
     /// Route an application to a different sink
+    ///
+    /// The sink-input list is fetched once to find the app's streams and once more
+    /// after the move to verify where they ended up.
+    ///
+    /// A comma-separated `sink_name` such as `"Recording,Headphones"` duplicates the
+    /// app to every listed sink, see [`Self::route_app_to_sinks`].
     pub async fn route_app(&self, app_name: &str, sink_name: &str) -> Result<()> {
-        debug!("Routing app {} to sink {}", app_name, sink_name);
-
-        // First, refresh the sink input IDs by checking pactl
-        let fresh_sink_input_ids = self.get_fresh_sink_input_ids(app_name).await?;
+        let started = Instant::now();
+        let result = self.apply_route(app_name, sink_name).await;
+        let (app, sink) = (app_name.to_string(), sink_name.to_string());
+        let cache = self.cache.read().await;
+        cache.record_latency(Operation::Route, started.elapsed());
+        cache.record_event(match &result {
+            Ok(()) => EventKind::RouteApplied { app, sink },
+            Err(e) => EventKind::RouteFailed { app, sink, error: format!("{e:#}") },
+        });
+        result
+    }
 
-        if fresh_sink_input_ids.is_empty() {
-            return Err(anyhow::anyhow!("App {} has no active sink inputs", app_name));
+    /// [`Self::route_app`] without logging the outcome
+    async fn apply_route(&self, app_name: &str, sink_name: &str) -> Result<()> {
+        let targets = parse_sink_targets(sink_name);
+        if targets.len() > 1 {
+            return self.route_app_to_sinks(app_name, &targets).await;
         }
+        let sink_name = targets.first().map(String::as_str).unwrap_or(sink_name);
+        debug!("Routing app {} to sink {}", app_name, sink_name);
+        let previous = self.app_combined_sink(app_name).await;
+
+        // First, refresh the sink inputs by checking pactl
+        let streams = self.app_streams(app_name).await?;
 
         // Verify the sink exists in cache
         {
@@ -195,37 +477,31 @@ impl PipeWireController {
             }
         }
 
-        // Update cache with fresh IDs
-        let sink_input_ids = {
+        // Update cache with fresh IDs, which it keeps by serial
+        {
             let cache = self.cache.write().await;
             if let Some(mut app) = cache.apps.get_mut(app_name) {
-                app.sink_input_ids = fresh_sink_input_ids.clone();
+                app.sink_input_ids = streams.iter().map(SinkInput::serial).collect();
             }
-            fresh_sink_input_ids
-        };
+        }
+        let sink_input_ids: Vec<u32> = streams.iter().map(|input| input.id).collect();
 
         // Move all sink inputs for this app to the new sink
         // Use the sink NAME not the ID since pactl and pipewire IDs don't match
-        for sink_input_id in &sink_input_ids {
-            debug!("Moving sink input {} to sink {}", sink_input_id, sink_name);
-            let output = tokio::process::Command::new("pactl")
-                .args(["move-sink-input", &sink_input_id.to_string(), sink_name])
-                .output()
-                .await?;
-
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                error!("Failed to route sink input {}: {}", sink_input_id, stderr);
-                return Err(anyhow::anyhow!("pactl command failed: {}", stderr));
-            }
-        }
+        self.move_sink_inputs(&sink_input_ids, sink_name).await?;
 
         // Wait a moment for PipeWire to process the change
         tokio::time::sleep(std::time::Duration::from_millis(200)).await;
 
         // Now verify the actual sink connection and update cache
         // This is important because module-stream-restore might move it back
-        let actual_sink = self.get_app_actual_sink(app_name, &sink_input_ids).await;
+        let actual_sink = match self.list_sink_inputs().await {
+            Ok(inputs) => self.get_app_actual_sink(app_name, &inputs, &sink_input_ids).await,
+            Err(e) => {
+                warn!("Could not verify routing of {}: {}", app_name, e);
+                None
+            }
+        };
 
         // Log if it didn't stick
         if let Some(ref actual) = actual_sink {
@@ -239,203 +515,626 @@ impl PipeWireController {
 
         {
             let cache = self.cache.write().await;
-            if let Some(mut app) = cache.apps.get_mut(app_name) {
-                // Update with the actual sink we detected
-                if let Some(ref actual) = actual_sink {
-                    app.current_sink = actual.clone();
-                } else {
-                    // Fallback to what we requested
-                    app.current_sink = sink_name.to_string();
-                }
-            }
+            // Update with the actual sink we detected, falling back to what we requested
+            cache.set_app_sink(app_name, actual_sink.as_deref().unwrap_or(sink_name));
 
             // Also update remembered apps
             cache
                 .remembered_apps
                 .insert(app_name.to_string(), actual_sink.unwrap_or_else(|| sink_name.to_string()));
+            cache.record_recent_sink(app_name, sink_name);
         }
+        self.release_combined_sink(previous).await;
 
         info!("Routed {} to {}", app_name, sink_name);
         Ok(())
     }
 
-    /// Get fresh sink input IDs for an app from pactl
-    async fn get_fresh_sink_input_ids(&self, app_name: &str) -> Result<Vec<u32>> {
-        debug!("Refreshing sink input IDs for app {}", app_name);
+    /// Duplicate an app's audio to several sinks at once
+    ///
+    /// The streams are moved to a combine sink feeding every target, created on first
+    /// use. The cache keeps the first target as the app's `current_sink` and lists all
+    /// of them in `current_sinks`.
+    pub async fn route_app_to_sinks(&self, app_name: &str, targets: &[String]) -> Result<()> {
+        debug!("Routing app {} to sinks {:?}", app_name, targets);
+        let previous = self.app_combined_sink(app_name).await;
+
+        let streams = self.app_streams(app_name).await?;
+        let sink_input_ids: Vec<u32> = streams.iter().map(|input| input.id).collect();
+
+        {
+            let cache = self.cache.read().await;
+            if let Some(missing) = targets.iter().find(|target| !cache.sinks.contains_key(*target))
+            {
+                return Err(anyhow::anyhow!("Sink {} not found", missing));
+            }
+        }
+
+        let combined_sink = combined_sink_name(targets);
+        let slaves: Vec<String> = {
+            let cache = self.cache.read().await;
+            targets.iter().map(|target| cache.pactl_sink_name(target)).collect()
+        };
+        if let Some(module_id) =
+            self.with_timeout(self.backend.combine_sinks(&combined_sink, &slaves)).await?
+        {
+            self.combined_sinks.lock().await.insert(combined_sink.clone(), module_id);
+        }
+        self.move_sink_inputs(&sink_input_ids, &combined_sink).await?;
+
+        {
+            let cache = self.cache.write().await;
+            if let Some(mut app) = cache.apps.get_mut(app_name) {
+                app.sink_input_ids = streams.iter().map(SinkInput::serial).collect();
+            }
+            cache.set_app_sinks(app_name, targets);
+            cache.record_recent_sink(app_name, &targets.join(","));
+            cache.remembered_apps.insert(app_name.to_string(), targets.join(","));
+        }
+        self.release_combined_sink(previous).await;
+
+        info!("Routed {} to {}", app_name, targets.join(", "));
+        Ok(())
+    }
+
+    /// The combine sink an app plays to, if it's duplicated to several sinks
+    async fn app_combined_sink(&self, app_name: &str) -> Option<String> {
+        let cache = self.cache.read().await;
+        let app = cache.apps.get(app_name)?;
+        (app.current_sinks.len() > 1).then(|| combined_sink_name(&app.current_sinks))
+    }
+
+    /// Unload a combine sink this controller loaded once no app plays to it anymore
+    async fn release_combined_sink(&self, combined_sink: Option<String>) {
+        let Some(combined_sink) = combined_sink else {
+            return;
+        };
+        let in_use = self.cache.read().await.apps.iter().any(|app| {
+            app.current_sinks.len() > 1 && combined_sink_name(&app.current_sinks) == combined_sink
+        });
+        if in_use {
+            return;
+        }
+        let module_id = self.combined_sinks.lock().await.remove(&combined_sink);
+        if let Some(module_id) = module_id {
+            debug!("Removing unused combine sink {}", combined_sink);
+            self.unload_modules(&[module_id]).await;
+        }
+    }
 
+    /// Unload every combine sink this controller loaded, for when the daemon exits
+    pub async fn unload_combined_sinks(&self) {
+        let module_ids: Vec<u32> =
+            std::mem::take(&mut *self.combined_sinks.lock().await).into_values().collect();
+        if !module_ids.is_empty() {
+            info!("Removing {} combine sinks", module_ids.len());
+            self.unload_modules(&module_ids).await;
+        }
+    }
+
+    /// The app's live streams, failing if it has none
+    async fn app_streams(&self, app_name: &str) -> Result<Vec<SinkInput>> {
         // Get stream names from cache if available
         let stream_names = {
             let cache = self.cache.read().await;
             cache.apps.get(app_name).map(|app| app.stream_names.clone()).unwrap_or_default()
         };
 
-        let output =
-            tokio::process::Command::new("pactl").args(["list", "sink-inputs"]).output().await?;
-
-        if !output.status.success() {
-            return Err(anyhow::anyhow!("Failed to list sink inputs"));
-        }
-
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let mut sink_input_ids = Vec::new();
-        let app_name_lower = app_name.to_lowercase();
-
-        // Parse sink inputs to find all streams for our app
-        let mut current_id = None;
-        let mut in_properties = false;
-        let mut current_app_name = String::new();
-        let mut current_binary_name = String::new();
-
-        for line in stdout.lines() {
-            if let Some(id_str) = line.strip_prefix("Sink Input #") {
-                // Process previous sink input if it matches
-                if let Some(id) = current_id {
-                    // Check if this stream matches our app name, binary name, or any stored stream names
-                    let matches_stream_name = stream_names
-                        .iter()
-                        .any(|stream| stream.to_lowercase() == current_app_name.to_lowercase());
-
-                    // Special case: WEBRTC VoiceEngine with Discord binary should be grouped with Discord
-                    if (current_app_name.to_lowercase().contains("webrtc")
-                        && current_binary_name.to_lowercase() == app_name_lower)
-                        || current_app_name.to_lowercase() == app_name_lower
-                        || current_binary_name.to_lowercase() == app_name_lower
-                        || matches_stream_name
-                    {
-                        debug!(
-                            "Found {} sink input: {} (app: {}, binary: {})",
-                            app_name, id, current_app_name, current_binary_name
-                        );
-                        sink_input_ids.push(id);
+        let inputs = self.list_sink_inputs().await?;
+        let streams: Vec<SinkInput> =
+            app_sink_inputs(&inputs, app_name, &stream_names).into_iter().cloned().collect();
+        if streams.is_empty() {
+            return Err(anyhow::anyhow!("App {} has no active sink inputs", app_name));
+        }
+        Ok(streams)
+    }
+
+    /// Move every app on `sink_name` to `fallback_sink`, e.g. before the sink goes away
+    ///
+    /// Apps without live streams only have their cached sink updated. Returns the
+    /// number of apps that were moved.
+    pub async fn evacuate_sink(&self, sink_name: &str, fallback_sink: &str) -> Result<usize> {
+        if sink_name == fallback_sink {
+            return Err(anyhow::anyhow!("Cannot evacuate {} onto itself", sink_name));
+        }
+        if !self.cache.read().await.sinks.contains_key(fallback_sink) {
+            return Err(anyhow::anyhow!("Sink {} not found", fallback_sink));
+        }
+
+        let apps = self.cache.read().await.apps_for_sink(sink_name);
+        let mut moved = 0;
+        for app_name in &apps {
+            match self.route_app(app_name, fallback_sink).await {
+                Ok(()) => moved += 1,
+                Err(e) => {
+                    debug!("Could not move streams of {}: {}", app_name, e);
+                    if self.cache.read().await.set_app_sink(app_name, fallback_sink) {
+                        moved += 1;
                     }
                 }
+            }
+        }
 
-                // Reset for new sink input
-                current_id = id_str.parse::<u32>().ok();
-                in_properties = false;
-                current_app_name.clear();
-                current_binary_name.clear();
-            } else if line.trim() == "Properties:" {
-                in_properties = true;
-            } else if in_properties && current_id.is_some() {
-                // Collect application.name
-                if let Some(name_line) = line.trim().strip_prefix("application.name = \"") {
-                    if let Some(name_end) = name_line.find('"') {
-                        current_app_name = name_line[..name_end].to_string();
-                    }
+        info!("Evacuated {} of {} apps from {} to {}", moved, apps.len(), sink_name, fallback_sink);
+        Ok(moved)
+    }
+
+    /// Route only the streams owned by one process, leaving other instances alone
+    ///
+    /// Returns the number of sink inputs that were moved.
+    pub async fn route_pid(&self, pid: u32, sink_name: &str) -> Result<usize> {
+        debug!("Routing streams of pid {} to sink {}", pid, sink_name);
+
+        if !self.cache.read().await.sinks.contains_key(sink_name) {
+            return Err(anyhow::anyhow!("Sink {} not found", sink_name));
+        }
+
+        let inputs = self.list_sink_inputs().await?;
+        let sink_input_ids = sink_inputs_for_pid(&inputs, pid);
+        if sink_input_ids.is_empty() {
+            debug!("Process {} has no active sink inputs", pid);
+            return Ok(0);
+        }
+
+        self.move_sink_inputs(&sink_input_ids, sink_name).await?;
+
+        // Let the monitor pick up the new routing
+        self.cache.read().await.increment_generation();
+
+        info!("Routed {} streams of pid {} to {}", sink_input_ids.len(), pid, sink_name);
+        Ok(sink_input_ids.len())
+    }
+
+    /// Move a single sink input to a sink by its index, without resolving an app
+    ///
+    /// A fallback for streams whose names match no app. When a cached app owns the
+    /// input and it decides the app's sink (its first or only stream), the app is moved
+    /// in the cache too. Returns false if no such sink input exists.
+    pub async fn route_sink_input(&self, sink_input_id: u32, sink_name: &str) -> Result<bool> {
+        debug!("Routing sink input {} to sink {}", sink_input_id, sink_name);
+
+        if !self.cache.read().await.sinks.contains_key(sink_name) {
+            return Err(anyhow::anyhow!("Sink {} not found", sink_name));
+        }
+
+        let inputs = self.list_sink_inputs().await?;
+        let Some(serial) =
+            inputs.iter().find(|input| input.id == sink_input_id).map(SinkInput::serial)
+        else {
+            debug!("Sink input {} does not exist", sink_input_id);
+            return Ok(false);
+        };
+
+        self.move_sink_inputs(&[sink_input_id], sink_name).await?;
+
+        let cache = self.cache.read().await;
+        let owner = cache
+            .apps
+            .iter()
+            .find(|app| app.sink_input_ids.first() == Some(&serial))
+            .map(|app| app.key().clone());
+        if let Some(app) = owner {
+            cache.set_app_sink(&app, sink_name);
+            cache.record_event(EventKind::RouteApplied { app, sink: sink_name.to_string() });
+        }
+        cache.increment_generation();
+
+        info!("Routed sink input {} to {}", sink_input_id, sink_name);
+        Ok(true)
+    }
+
+    /// Create each of `sinks` the audio server doesn't have yet
+    ///
+    /// A sink that can't be created is logged and skipped. Returns the ids of the
+    /// modules loaded, to hand to [`Self::unload_modules`] on shutdown.
+    pub async fn create_missing_sinks(&self, sinks: &[VirtualSink]) -> Result<Vec<u32>> {
+        let existing = self.with_timeout(self.backend.list_sinks()).await?;
+        let mut module_ids = Vec::new();
+        for sink in sinks {
+            if existing.iter().any(|entry| entry.name == sink.name) {
+                debug!("Virtual sink {} already exists", sink.name);
+                continue;
+            }
+            let loopback_name = self.cache.read().await.loopback_name(&sink.name);
+            match self
+                .with_timeout(self.backend.create_virtual_sink(
+                    &sink.name,
+                    &sink.display_name,
+                    &loopback_name,
+                ))
+                .await
+            {
+                Ok(ids) => {
+                    info!("Created virtual sink {} (modules {:?})", sink.name, ids);
+                    module_ids.extend(ids);
+                }
+                Err(e) => error!("Failed to create virtual sink {}: {}", sink.name, e),
+            }
+        }
+        Ok(module_ids)
+    }
+
+    /// Unload modules, newest first, logging any that fail
+    pub async fn unload_modules(&self, module_ids: &[u32]) {
+        for module_id in module_ids.iter().rev() {
+            if let Err(e) = self.with_timeout(self.backend.unload_module(*module_id)).await {
+                warn!("Failed to unload module {}: {}", module_id, e);
+            }
+        }
+    }
+
+    /// Rebuild sink and app state from a fresh enumeration of PipeWire
+    ///
+    /// PipeWire can hand out new ids after a suspend, leaving the cache pointing at
+    /// nodes that are gone. Cached sinks take their current ids, or are dropped if
+    /// they no longer exist. Each app's streams are replaced by the ones playing now:
+    /// a stream goes to the app that already had it, else to the app the monitor would
+    /// name it after, which is created if needed. Apps left without streams go inactive.
+    ///
+    /// Returns how many sinks and streams were found.
+    pub async fn rescan(&self) -> Result<(usize, usize)> {
+        // A rescan is asked for when the cache may be out of date, so list afresh
+        self.forget_sink_inputs().await;
+        let sinks = self.with_timeout(self.backend.list_sinks()).await?;
+        let inputs = self.list_sink_inputs().await?;
+
+        let (sink_names, loopbacks) = {
+            let cache = self.cache.read().await;
+            (cache_sink_names(&cache, &sinks), loopback_names(&cache))
+        };
+        // Named as the monitor would before the cache is locked, which may read /proc
+        let streams: Vec<(&SinkInput, Option<NewStream>)> = inputs
+            .iter()
+            .filter(|input| !is_loopback(&loopbacks, input))
+            .map(|input| (input, self.identify_stream(&sink_names, input)))
+            .collect();
+
+        let cache = self.cache.write().await;
+        let cached_sinks: Vec<SinkInfo> =
+            cache.sinks.iter().map(|entry| entry.value().clone()).collect();
+        let mut sink_count = 0;
+        for mut sink in cached_sinks {
+            match sinks.iter().find(|entry| entry.name == sink.pactl_name()) {
+                Some(entry) => {
+                    sink.id = entry.id;
+                    sink.pipewire_id = entry.id;
+                    cache.update_sink(sink.name.clone(), sink);
+                    sink_count += 1;
+                }
+                None => {
+                    info!("Sink {} is gone after a rescan", sink.name);
+                    cache.remove_sink(&sink.name);
                 }
+            }
+        }
+
+        let mut apps: Vec<(String, AppInfo)> =
+            cache.apps.iter().map(|entry| (entry.key().clone(), entry.value().clone())).collect();
+        let mut found: Vec<Vec<&SinkInput>> = vec![Vec::new(); apps.len()];
+        let mut new_streams: Vec<(NewStream, &SinkInput)> = Vec::new();
+        let stream_count = streams.len();
+        for (input, stream) in streams {
+            let owner = apps
+                .iter()
+                .position(|(_, app)| app.sink_input_ids.contains(&input.serial()))
+                .or_else(|| {
+                    let app_key = cache.limit_name(stream.as_ref()?.app_key.clone());
+                    apps.iter().position(|(key, _)| *key == app_key)
+                });
+            match (owner, stream) {
+                (Some(index), _) => found[index].push(input),
+                (None, Some(stream)) => new_streams.push((stream, input)),
+                (None, None) => {} // Left out by the track lists
+            }
+        }
 
-                // Collect application.process.binary
-                if let Some(binary_line) =
-                    line.trim().strip_prefix("application.process.binary = \"")
-                {
-                    if let Some(binary_end) = binary_line.find('"') {
-                        let binary_path = &binary_line[..binary_end];
-                        current_binary_name = binary_path
-                            .split('/')
-                            .next_back()
-                            .unwrap_or(binary_path)
-                            .trim_end_matches("-bin")
-                            .trim_end_matches(".exe")
-                            .to_string();
+        for ((key, app), inputs) in apps.iter_mut().zip(found) {
+            let old_ids = std::mem::take(&mut app.sink_input_ids);
+            app.sink_input_ids = inputs.iter().map(|input| input.serial()).collect();
+            app.stream_labels.retain(|(id, _)| app.sink_input_ids.contains(id));
+            match inputs.first() {
+                Some(first) => {
+                    app.active = true;
+                    app.inactive_since = None;
+                    // Apps duplicated to several sinks play through a combine sink
+                    let sink_name = first.sink.and_then(|id| sink_names.get(&id));
+                    if let (Some(sink_name), true) = (sink_name, app.current_sinks.is_empty()) {
+                        app.current_sink = sink_name.to_string();
                     }
                 }
+                None if app.active => {
+                    app.active = false;
+                    app.inactive_since = Some(std::time::Instant::now());
+                }
+                None => {}
+            }
+            let gone: Vec<u32> =
+                old_ids.into_iter().filter(|id| !app.sink_input_ids.contains(id)).collect();
+            cache.update_app(key.clone(), app.clone());
+            for id in gone {
+                cache.forget_stream(key, id);
             }
+            for input in inputs {
+                if let Some(volume) = input.volume {
+                    cache.set_stream_volume(key, input.serial(), volume);
+                }
+                cache.set_stream_corked(key, input.serial(), input.corked);
+            }
+        }
+
+        // Streams of apps that aren't cached yet, which the first one creates
+        for (stream, input) in new_streams {
+            let (app_key, _) = cache.add_stream(stream);
+            info!("Found stream {} of {} in a rescan", input.id, app_key);
+        }
+
+        cache.increment_generation();
+        info!("Rescanned PipeWire: {} sinks, {} streams", sink_count, stream_count);
+        Ok((sink_count, stream_count))
+    }
+
+    /// A stream found without the PipeWire monitor, named as the monitor would name it
+    ///
+    /// Returns None for a stream the track lists leave out.
+    fn identify_stream(
+        &self,
+        sink_names: &HashMap<u32, String>,
+        input: &SinkInput,
+    ) -> Option<NewStream> {
+        let current_sink = input
+            .sink
+            .and_then(|id| sink_names.get(&id))
+            .map_or_else(|| "Unknown".to_string(), |name| name.to_string());
+        self.detector.new_stream(&self.cache_config, input, current_sink)
+    }
+
+    /// Take in a stream the backend reported without the PipeWire monitor
+    ///
+    /// Adds the stream to its app the way the monitor would, then routes that app and
+    /// restores its saved volume and mute as the monitor does for new streams, after
+    /// giving the app [`STREAM_SETTLE_DELAY`] to set the stream up. Returns the sink the
+    /// app was routed to, if any.
+    pub async fn adopt_stream(
+        &self,
+        sink_input_id: u32,
+        routing: &RoutingConfig,
+    ) -> Result<Option<String>> {
+        // The listing the announcement came from may be cached without this stream
+        self.forget_sink_inputs().await;
+        let inputs = self.list_sink_inputs().await?;
+        let Some(input) = inputs.iter().find(|input| input.id == sink_input_id) else {
+            debug!("Stream {} is gone before it could be taken in", sink_input_id);
+            return Ok(None);
+        };
+        let sinks = self.with_timeout(self.backend.list_sinks()).await?;
+
+        let (sink_names, loopbacks) = {
+            let cache = self.cache.read().await;
+            (cache_sink_names(&cache, &sinks), loopback_names(&cache))
+        };
+        if is_loopback(&loopbacks, input) {
+            return Ok(None);
+        }
+        let Some(stream) = self.identify_stream(&sink_names, input) else {
+            return Ok(None);
+        };
+
+        // The backend announces pactl's index, the cache knows the stream by serial
+        let serial = input.serial();
+        let (app_name, target) = {
+            let cache = self.cache.write().await;
+            let (app_name, _) = cache.add_stream(stream);
+            cache.increment_generation();
+            let target = cache.auto_route_target(&app_name, routing);
+            (app_name, target)
+        };
+        tokio::time::sleep(STREAM_SETTLE_DELAY).await;
+        if let Some(sink_name) = &target {
+            info!("Auto-routing {} -> {}", app_name, sink_name);
+            self.route_app(&app_name, sink_name).await?;
         }
+        self.restore_app_settings(&app_name, serial).await?;
+        Ok(target)
+    }
 
-        // Don't forget the last sink input
-        if let Some(id) = current_id {
-            let matches_stream_name = stream_names
+    /// Move each active app to the sink its routing rules pick, if it isn't there already
+    ///
+    /// Targets come from [`AudioCache::auto_route_target`], so only pinned apps move
+    /// while auto-routing is off. Failures are logged and the remaining apps still routed.
+    /// Returns how many apps were moved.
+    pub async fn reapply_routing(&self, routing: &RoutingConfig) -> usize {
+        let moves: Vec<(String, String)> = {
+            let cache = self.cache.read().await;
+            let apps: Vec<(String, Vec<String>)> = cache
+                .apps
                 .iter()
-                .any(|stream| stream.to_lowercase() == current_app_name.to_lowercase());
+                .filter(|entry| entry.value().active)
+                .map(|entry| {
+                    let app = entry.value();
+                    let sinks = if app.current_sinks.is_empty() {
+                        vec![app.current_sink.clone()]
+                    } else {
+                        app.current_sinks.clone()
+                    };
+                    (entry.key().clone(), sinks)
+                })
+                .collect();
+            apps.into_iter()
+                .filter_map(|(app_name, sinks)| {
+                    let target = cache.auto_route_target(&app_name, routing)?;
+                    (parse_sink_targets(&target) != sinks).then_some((app_name, target))
+                })
+                .collect()
+        };
 
-            if (current_app_name.to_lowercase().contains("webrtc")
-                && current_binary_name.to_lowercase() == app_name_lower)
-                || current_app_name.to_lowercase() == app_name_lower
-                || current_binary_name.to_lowercase() == app_name_lower
-                || matches_stream_name
-            {
-                debug!(
-                    "Found {} sink input: {} (app: {}, binary: {})",
-                    app_name, id, current_app_name, current_binary_name
-                );
-                sink_input_ids.push(id);
+        let mut moved = 0;
+        for (app_name, target) in moves {
+            match self.route_app(&app_name, &target).await {
+                Ok(()) => {
+                    info!("Routed {} back to {}", app_name, target);
+                    moved += 1;
+                }
+                Err(e) => warn!("Could not route {} back to {}: {}", app_name, target, e),
             }
         }
+        moved
+    }
 
-        debug!("Found {} active sink inputs for {}", sink_input_ids.len(), app_name);
-        Ok(sink_input_ids)
+    /// Await a backend call, giving up once the cache's command timeout has passed
+    async fn with_timeout<T>(&self, call: impl Future<Output = Result<T>>) -> Result<T> {
+        let timeout = self.cache.read().await.command_timeout();
+        tokio::time::timeout(timeout, call).await.unwrap_or_else(|_| {
+            Err(anyhow::anyhow!("Backend timeout after {}ms", timeout.as_millis()))
+        })
     }
 
-    /// Get the actual sink an app is connected to by checking PipeWire
-    async fn get_app_actual_sink(&self, app_name: &str, sink_input_ids: &[u32]) -> Option<String> {
-        debug!("Checking actual sink for app {} with sink inputs {:?}", app_name, sink_input_ids);
-        if sink_input_ids.is_empty() {
-            return None;
+    /// Ask the server for its PipeWire version
+    pub async fn query_server_version(&self) -> Result<String> {
+        self.with_timeout(self.backend.server_version()).await
+    }
+
+    /// Ask the server which sink is the default, see [`AudioCache::default_sink`]
+    pub async fn query_default_sink(&self) -> Result<String> {
+        self.with_timeout(self.backend.default_sink()).await
+    }
+
+    /// New streams the backend announces itself, see [`PipeWireBackend::subscribe_new_streams`]
+    pub fn subscribe_new_streams(&self) -> Option<broadcast::Receiver<u32>> {
+        self.backend.subscribe_new_streams()
+    }
+
+    /// Every stream currently playing
+    ///
+    /// A listing is reused for [`SINK_INPUT_LIST_TTL`], so a burst of routes and
+    /// queries asks the backend once. Lookups that arrive while one is in flight wait
+    /// for its result rather than starting their own.
+    pub async fn list_sink_inputs(&self) -> Result<Vec<SinkInput>> {
+        let mut cached = self.sink_inputs.lock().await;
+        if let Some(list) = cached.as_ref() {
+            if list.listed_at.elapsed() < SINK_INPUT_LIST_TTL {
+                return Ok(list.inputs.clone());
+            }
         }
+        let inputs = self.with_timeout(self.backend.list_sink_inputs()).await?;
+        *cached = Some(SinkInputList { inputs: inputs.clone(), listed_at: Instant::now() });
+        Ok(inputs)
+    }
 
-        // Use pactl to check the actual sink connection
-        let output = tokio::process::Command::new("pactl")
-            .args(["list", "sink-inputs"])
-            .output()
-            .await
-            .ok()?;
+    /// Make the next [`Self::list_sink_inputs`] ask the backend again
+    async fn forget_sink_inputs(&self) {
+        *self.sink_inputs.lock().await = None;
+    }
 
-        if !output.status.success() {
-            return None;
+    async fn move_sink_inputs(&self, sink_input_ids: &[u32], sink_name: &str) -> Result<()> {
+        // pactl knows a sink matched by description by its node name
+        let sink_name = self.cache.read().await.pactl_sink_name(sink_name);
+        for sink_input_id in sink_input_ids {
+            debug!("Moving sink input {} to sink {}", sink_input_id, sink_name);
+            if let Err(e) =
+                self.with_timeout(self.backend.move_sink_input(*sink_input_id, &sink_name)).await
+            {
+                error!("Failed to route sink input {}: {}", sink_input_id, e);
+                // The ones before it did move
+                self.forget_sink_inputs().await;
+                return Err(e);
+            }
         }
+        self.forget_sink_inputs().await;
+        Ok(())
+    }
 
-        let stdout = String::from_utf8_lossy(&output.stdout);
+    /// Get the actual sink an app is connected to from an already fetched sink-input list
+    async fn get_app_actual_sink(
+        &self,
+        app_name: &str,
+        inputs: &[SinkInput],
+        sink_input_ids: &[u32],
+    ) -> Option<String> {
+        debug!("Checking actual sink for app {} with sink inputs {:?}", app_name, sink_input_ids);
 
         // Look for the first sink input ID and get its sink
-        for sink_input_id in sink_input_ids {
-            let search_pattern = format!("Sink Input #{sink_input_id}");
-            if let Some(pos) = stdout.find(&search_pattern) {
-                // Look for the Sink: line in the next few lines
-                let lines = stdout[pos..].lines().take(20);
-                let mut found_sink_id = None;
-                for line in lines {
-                    if let Some(sink_line) = line.trim().strip_prefix("Sink: ") {
-                        // Get the sink ID (it's a number)
-                        if let Ok(sink_id) = sink_line.parse::<u32>() {
-                            found_sink_id = Some(sink_id);
-                            debug!("Found app {} connected to sink ID {}", app_name, sink_id);
-                            break;
-                        }
-                    }
-                }
+        let sink_id = sink_input_ids.iter().find_map(|sink_input_id| {
+            inputs.iter().find(|input| input.id == *sink_input_id).and_then(|input| input.sink)
+        })?;
+        debug!("Found app {} connected to sink ID {}", app_name, sink_id);
 
-                // Now get the sink name from pactl
-                if let Some(sink_id) = found_sink_id {
-                    let sink_output = tokio::process::Command::new("pactl")
-                        .args(["list", "sinks", "short"])
-                        .output()
-                        .await
-                        .ok()?;
-
-                    if sink_output.status.success() {
-                        let sink_stdout = String::from_utf8_lossy(&sink_output.stdout);
-                        for line in sink_stdout.lines() {
-                            let parts: Vec<&str> = line.split_whitespace().collect();
-                            if parts.len() >= 2 {
-                                if let Ok(id) = parts[0].parse::<u32>() {
-                                    if id == sink_id {
-                                        let sink_name = parts[1];
-                                        debug!(
-                                            "Sink ID {} maps to sink name {}",
-                                            sink_id, sink_name
-                                        );
-                                        return Some(sink_name.to_string());
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    warn!("Could not find sink name for sink ID {} in pactl", sink_id);
-                }
-            }
+        // Now get the sink name from the backend
+        let sinks = self.with_timeout(self.backend.list_sinks()).await.ok()?;
+        if let Some(sink) = sinks.into_iter().find(|sink| sink.id == sink_id) {
+            debug!("Sink ID {} maps to sink name {}", sink_id, sink.name);
+            // Compared with the configured name, which a sink matched by description lacks
+            return Some(self.cache.read().await.sink_for_node(&sink.name));
         }
+        warn!("Could not find sink name for sink ID {} in pactl", sink_id);
 
         None
     }
 }
+
+/// Node names of the loopbacks feeding the cached sinks to an output, which aren't apps
+fn loopback_names(cache: &AudioCache) -> HashSet<String> {
+    let sink_names: Vec<String> = cache.sinks.iter().map(|entry| entry.key().clone()).collect();
+    sink_names.iter().flat_map(|sink_name| cache.loopback_node_names(sink_name)).collect()
+}
+
+/// Names the cache knows each of `sinks` by, by id
+fn cache_sink_names(cache: &AudioCache, sinks: &[SinkEntry]) -> HashMap<u32, String> {
+    sinks.iter().map(|sink| (sink.id, cache.sink_for_node(&sink.name))).collect()
+}
+
+/// Whether a stream is one of [`loopback_names`]
+fn is_loopback(loopbacks: &HashSet<String>, input: &SinkInput) -> bool {
+    input.property("node.name").is_some_and(|name| loopbacks.contains(name))
+}
+
+/// Sink input IDs belonging to an app, see [`app_sink_inputs`]
+fn app_sink_input_ids(inputs: &[SinkInput], app_name: &str, stream_names: &[String]) -> Vec<u32> {
+    app_sink_inputs(inputs, app_name, stream_names).iter().map(|input| input.id).collect()
+}
+
+/// Sink inputs belonging to an app, matched by app name, binary name or known stream names
+fn app_sink_inputs<'a>(
+    inputs: &'a [SinkInput],
+    app_name: &str,
+    stream_names: &[String],
+) -> Vec<&'a SinkInput> {
+    let app_name_lower = app_name.to_lowercase();
+    let mut app_inputs = Vec::new();
+
+    for input in inputs {
+        let current_app_name = input.property("application.name").unwrap_or_default();
+        let current_binary_name = input
+            .property("application.process.binary")
+            .map(|binary_path| {
+                binary_path
+                    .split('/')
+                    .next_back()
+                    .unwrap_or(binary_path)
+                    .trim_end_matches("-bin")
+                    .trim_end_matches(".exe")
+            })
+            .unwrap_or_default();
+
+        // Check if this stream matches our app name, binary name, or any stored stream names
+        let matches_stream_name = stream_names
+            .iter()
+            .any(|stream| stream.to_lowercase() == current_app_name.to_lowercase());
+
+        // Special case: WEBRTC VoiceEngine with Discord binary should be grouped with Discord
+        if (current_app_name.to_lowercase().contains("webrtc")
+            && current_binary_name.to_lowercase() == app_name_lower)
+            || current_app_name.to_lowercase() == app_name_lower
+            || current_binary_name.to_lowercase() == app_name_lower
+            || matches_stream_name
+        {
+            debug!(
+                "Found {} sink input: {} (app: {}, binary: {})",
+                app_name, input.id, current_app_name, current_binary_name
+            );
+            app_inputs.push(input);
+        }
+    }
+
+    debug!("Found {} active sink inputs for {}", app_inputs.len(), app_name);
+    app_inputs
+}
@@ -1,40 +1,158 @@
 use anyhow::{Context as AnyhowContext, Result};
+use dashmap::DashMap;
 use pipewire::context::Context;
 use pipewire::main_loop::MainLoop;
+use pipewire::metadata::{Metadata, MetadataListener};
+use pipewire::node::{Node, NodeChangeMask, NodeListener, NodeState};
+use pipewire::registry::{GlobalObject, Registry};
+use pipewire::spa::utils::dict::DictRef;
 use pipewire::types::ObjectType;
 use std::cell::RefCell;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
 use std::rc::Rc;
-use std::sync::{mpsc, Arc};
-use tokio::sync::RwLock;
-use tracing::{debug, error, info};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, RwLock};
+use tracing::{debug, error, info, warn};
 
-use crate::cache::{AppInfo, AudioCache, SinkInfo};
-use crate::config::Config;
-use crate::pipewire_controller::PipeWireController;
+use crate::app_name_detector::{
+    choose_app_name, stream_binary_name, stream_label, AppNameConfig, AppNameDetector,
+};
+use crate::backend::is_combined_sink;
+use crate::cache::{AudioCache, GraphNode, NewStream, SinkInfo};
+use crate::command::{CommandExecutor, SystemCommandExecutor};
+use crate::config::{Config, RoutingConfig};
+use crate::events::EventKind;
+use crate::latency::Operation;
+use crate::pipewire_controller::{PipeWireController, STREAM_SETTLE_DELAY};
+use crate::schedule::should_log_failure;
+use crate::sink_inputs::{find_sink_input, parse_sink_inputs, parse_sinks, Sink, SinkInput};
+use crate::volume::volume_to_percent;
 
 pub struct PipeWireMonitor {
     cache: Arc<RwLock<AudioCache>>,
     config: Config,
     controller: Arc<PipeWireController>,
+    executor: Arc<dyn CommandExecutor>, // Runs pactl, wpctl and the app name lookups
 }
 
 enum CacheUpdate {
     UpdateSink(String, SinkInfo),
     MarkAppInactive(u32), // sink_input_id
-    AddSinkInputToApp(String, String, String, String, u32, String), // app_key, display_name, binary_name, stream_name, sink_input_id, current_sink
-    CheckRoutingRule(String, u32),                                  // app_name, sink_input_id
+    AddSinkInputToApp(NewStream),
+    SetStreamCorked(u32, bool),      // sink_input_id, corked
+    CheckRoutingRule(String, u32),   // app_name, sink_input_id
+    AddPhysicalSink(String, String), // sink_name, display_name
+    RemovePhysicalSink(String),      // sink_name
+    RemoveSink(String),              // sink_name
+    InitialScanComplete,             // Every object present at startup has been sent
+    SetDefaultSink(String),          // node.name of the sink new streams play to
 }
 
+/// Sends updates to the cache worker, counting the ones that can't be delivered
+///
+/// A send only fails once the worker has stopped, so every update after that is lost.
+/// The count is shared with the cache, where HEALTH reports it.
+#[derive(Clone)]
+struct CacheSender {
+    tx: mpsc::UnboundedSender<CacheUpdate>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl CacheSender {
+    fn new(tx: mpsc::UnboundedSender<CacheUpdate>, dropped: Arc<AtomicU64>) -> Self {
+        Self { tx, dropped }
+    }
+
+    fn send(&self, update: CacheUpdate) {
+        if self.tx.send(update).is_err() {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            if u32::try_from(dropped).is_ok_and(should_log_failure) {
+                warn!("Cache update worker is gone, {} updates dropped so far", dropped);
+            }
+        }
+    }
+}
+
+/// Reads the volume of newly found virtual sinks, one at a time on a worker thread
+///
+/// Finding a sink only queues its lookup, so a burst of sinks, as when their modules
+/// are reloaded, doesn't start a thread for each one.
+struct VolumeLookups {
+    tx: std::sync::mpsc::Sender<SinkInfo>, // Sinks as found, their volume still unknown
+}
+
+impl VolumeLookups {
+    /// Start the worker, which sends each volume `lookup` finds on to the cache
+    fn spawn<F>(cache_tx: CacheSender, lookup: F) -> Self
+    where
+        F: Fn(u32) -> Option<(f32, bool)> + Send + 'static,
+    {
+        let (tx, rx) = std::sync::mpsc::channel::<SinkInfo>();
+        // Runs until the queue's sender is dropped with the monitor state
+        std::thread::spawn(move || {
+            for sink in rx {
+                let Some((volume, muted)) = lookup(sink.pipewire_id) else {
+                    continue;
+                };
+                let sink_info =
+                    SinkInfo { volume, muted, applied_percent: volume_to_percent(volume), ..sink };
+                cache_tx.send(CacheUpdate::UpdateSink(sink_info.name.clone(), sink_info));
+            }
+        });
+        Self { tx }
+    }
+
+    fn queue(&self, sink: SinkInfo) {
+        // The worker only stops once this sender is gone
+        let _ = self.tx.send(sink);
+    }
+}
+
+/// A sink's volume and mute state from `wpctl get-volume`
+fn wpctl_volume(executor: &dyn CommandExecutor, sink_id: u32) -> Option<(f32, bool)> {
+    let output = executor.execute("wpctl", &["get-volume", &sink_id.to_string()]).ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    // Output like "Volume: 0.75 [MUTED]" or "Volume: 0.75"
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let volume_str = stdout.split(':').nth(1)?;
+    let volume = volume_str.split_whitespace().next().unwrap_or("1.0").parse().ok()?;
+    Some((volume, volume_str.contains("[MUTED]")))
+}
+
+/// How often the tracked streams are checked against the streams pactl still lists
+const RECONCILE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Streams tracked at most; the oldest is dropped to make room under pathological churn
+const MAX_TRACKED_NODES: usize = 1024;
+
 struct MonitorState {
-    cache_tx: mpsc::Sender<CacheUpdate>,
+    cache_tx: CacheSender,
     config: Config,
+    executor: Arc<dyn CommandExecutor>,
     nodes: HashMap<u32, NodeInfo>,
+    graph_nodes: Arc<DashMap<u32, GraphNode>>, // `nodes` as the cache shows them in DUMP_GRAPH
+    physical_sinks: HashMap<u32, String>,      // PipeWire id -> sink name
+    virtual_sinks: HashMap<u32, String>,       // PipeWire id -> sink name
+    stream_watches: HashMap<u32, (Node, NodeListener)>, // Bound app streams, for property changes
+    default_metadata: Option<(Metadata, MetadataListener)>, // Bound "default" metadata, for the default sink
+    volume_lookups: VolumeLookups,
 }
 
 struct NodeInfo {
     app_name: Option<String>,
     serial_id: u32, // object.serial used as sink_input_id
+    added: Instant, // When the stream was first tracked
+}
+
+/// Serials of the streams pactl listed, for [`reconcile_nodes`]
+struct LiveStreams {
+    serials: HashSet<u32>,
+    listed_at: Instant, // Streams tracked after this may be missing from the list
 }
 
 impl PipeWireMonitor {
@@ -43,15 +161,42 @@ impl PipeWireMonitor {
         config: Config,
         controller: Arc<PipeWireController>,
     ) -> Result<Self> {
-        Ok(Self { cache, config, controller })
+        Ok(Self { cache, config, controller, executor: Arc::new(SystemCommandExecutor) })
+    }
+
+    /// Run every command the monitor needs through `executor`, such as the daemon's
+    /// tracing one
+    pub fn with_executor(mut self, executor: Arc<dyn CommandExecutor>) -> Self {
+        self.executor = executor;
+        self
     }
 
-    pub async fn run(self) -> Result<()> {
+    /// Monitor PipeWire until it fails or `shutdown` completes
+    pub async fn run(self, shutdown: impl Future<Output = ()>) -> Result<()> {
+        // Updates from the PipeWire thread are applied on this runtime
+        let (cache_tx, cache_rx) = mpsc::unbounded_channel();
+        let (cache_tx, graph_nodes) = {
+            let cache = self.cache.read().await;
+            (CacheSender::new(cache_tx, cache.dropped_update_counter()), cache.graph_nodes())
+        };
+        let worker = tokio::spawn(run_cache_worker(
+            self.cache,
+            self.controller,
+            self.config.routing.clone(),
+            cache_rx,
+        ));
+
         // PipeWire requires running in its own thread with MainLoop
-        let (tx, rx) = tokio::sync::oneshot::channel();
+        let (tx, mut rx) = tokio::sync::oneshot::channel();
+        let (quit_tx, quit_rx) = pipewire::channel::channel();
+        let poll_interval = Duration::from_millis(self.config.performance.pactl_poll_interval_ms);
+        let poll_config = self.config.clone();
+        let fallback_tx = cache_tx.clone();
+        let config = self.config;
+        let executor = self.executor.clone();
 
         std::thread::spawn(move || {
-            if let Err(e) = run_pipewire_loop(self.cache, self.config, self.controller) {
+            if let Err(e) = run_pipewire_loop(config, cache_tx, graph_nodes, executor, quit_rx) {
                 error!("PipeWire loop error: {}", e);
                 let _ = tx.send(Err(e));
             } else {
@@ -59,133 +204,91 @@ impl PipeWireMonitor {
             }
         });
 
-        rx.await.with_context(|| "PipeWire thread panicked")?
+        tokio::pin!(shutdown);
+        let (result, stopping) = tokio::select! {
+            result = &mut rx => (result, false),
+            () = &mut shutdown => {
+                info!("Stopping PipeWire monitor");
+                if quit_tx.send(()).is_err() {
+                    // The loop has already stopped on its own
+                    debug!("PipeWire loop was not running");
+                }
+                (rx.await, true)
+            }
+        };
+
+        // The loop only fails before it runs, so nothing from PipeWire reached the cache
+        let result = match result {
+            Ok(Err(e)) if !stopping && !poll_interval.is_zero() => {
+                warn!(
+                    "PipeWire is unavailable, polling pactl every {:?} instead: {:#}",
+                    poll_interval, e
+                );
+                poll_pactl(&poll_config, &fallback_tx, &self.executor, poll_interval, shutdown)
+                    .await;
+                Ok(Ok(()))
+            }
+            result => result,
+        };
+        drop(fallback_tx);
+
+        // The worker finishes once the PipeWire thread has dropped every sender
+        if let Err(e) = worker.await {
+            error!("Cache update worker failed: {}", e);
+        }
+        result.with_context(|| "PipeWire thread panicked")?
     }
 }
 
 fn run_pipewire_loop(
-    cache: Arc<RwLock<AudioCache>>,
     config: Config,
-    controller: Arc<PipeWireController>,
+    cache_tx: CacheSender,
+    graph_nodes: Arc<DashMap<u32, GraphNode>>,
+    executor: Arc<dyn CommandExecutor>,
+    quit_rx: pipewire::channel::Receiver<()>,
 ) -> Result<()> {
     pipewire::init();
 
     let mainloop = MainLoop::new(None)?;
     let context = Context::new(&mainloop)?;
     let core = context.connect(None)?;
-    let registry = core.get_registry()?;
-
-    // Create channel for cache updates
-    let (cache_tx, cache_rx) = mpsc::channel();
-
-    // Spawn a task to handle cache updates
-    let cache_clone = cache.clone();
-    let controller_clone = controller.clone();
-    let default_sink = config.routing.default_sink.clone();
-    std::thread::spawn(move || {
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        rt.block_on(async {
-            while let Ok(update) = cache_rx.recv() {
-                let cache = cache_clone.write().await;
-                match update {
-                    CacheUpdate::UpdateSink(name, info) => cache.update_sink(name, info),
-                    CacheUpdate::MarkAppInactive(sink_input_id) => {
-                        // Find the app that has this sink_input_id
-                        for mut entry in cache.apps.iter_mut() {
-                            let (app_name, app) = entry.pair_mut();
-                            if app.sink_input_ids.contains(&sink_input_id) {
-                                app.sink_input_ids.retain(|&x| x != sink_input_id);
-                                // If no more active streams, mark as inactive with timestamp
-                                if app.sink_input_ids.is_empty() {
-                                    app.active = false;
-                                    app.inactive_since = Some(std::time::Instant::now());
-                                    info!("App {} is now inactive, will be removed in 5 minutes if not used", app_name);
-                                }
-                                break;
-                            }
-                        }
-                    }
-                    CacheUpdate::AddSinkInputToApp(app_key, display_name, binary_name, stream_name, sink_input_id, current_sink) => {
-                        if let Some(mut app) = cache.apps.get_mut(&app_key) {
-                            if !app.sink_input_ids.contains(&sink_input_id) {
-                                app.sink_input_ids.push(sink_input_id);
-                            }
-                            // Add stream name if not already present
-                            if !app.stream_names.contains(&stream_name) {
-                                app.stream_names.push(stream_name);
-                            }
-                            // Mark as active and clear inactive timestamp
-                            app.active = true;
-                            app.inactive_since = None;
-                            // Update display name if we have a better one
-                            if !display_name.is_empty() && display_name != app_key {
-                                app.display_name = display_name;
-                            }
-                            // Update sink if it's different (in case of multiple streams)
-                            if app.current_sink != current_sink && app.current_sink != "Unknown" {
-                                debug!("App {} has streams in multiple sinks", app_key);
-                            }
-                        } else {
-                            // App doesn't exist yet, create it with minimal info
-                            let app_info = AppInfo {
-                                display_name,
-                                binary_name,
-                                stream_names: vec![stream_name],
-                                current_sink,
-                                active: true,
-                                sink_input_ids: vec![sink_input_id],
-                                pipewire_id: sink_input_id,  // Use sink_input_id as pipewire_id
-                                inactive_since: None,
-                            };
-                            cache.update_app(app_key, app_info);
-                        }
-                        cache.increment_generation();
-                    }
-                    CacheUpdate::CheckRoutingRule(app_name, _sink_input_id) => {
-                        // Check if we have a routing rule for this app, or use default sink
-                        let target_sink_name = if let Some(target_sink) = cache.routing_rules.get(&app_name) {
-                            let sink_name = target_sink.clone();
-                            info!("Applying routing rule: {} -> {}", app_name, sink_name);
-                            sink_name
-                        } else {
-                            // No routing rule exists, use default sink for new apps
-                            info!("No routing rule for {}, auto-routing to default sink: {}", app_name, default_sink);
-                            
-                            // Save this as a new routing rule for next time
-                            cache.routing_rules.insert(app_name.clone(), default_sink.clone());
-                            default_sink.clone()
-                        };
-
-                        // Use the controller to properly route the app (same as manual routing)
-                        // This ensures loopback streams are set up correctly
-                        let controller = controller_clone.clone();
-                        let app_name_clone = app_name.clone();
-                        tokio::spawn(async move {
-                            // Give the app a moment to fully initialize
-                            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
-                            
-                            if let Err(e) = controller.route_app(&app_name_clone, &target_sink_name).await {
-                                error!("Failed to apply routing for {}: {}", app_name_clone, e);
-                            } else {
-                                info!("Successfully routed {} to {}", app_name_clone, target_sink_name);
-                            }
-                        });
-                    }
-                }
-            }
-        });
-    });
+    let registry = Rc::new(core.get_registry()?);
 
-    let state = Rc::new(RefCell::new(MonitorState { cache_tx, config, nodes: HashMap::new() }));
+    let volume_lookups = VolumeLookups::spawn(cache_tx.clone(), {
+        let executor = executor.clone();
+        move |sink_id| wpctl_volume(executor.as_ref(), sink_id)
+    });
+    let state = Rc::new(RefCell::new(MonitorState {
+        cache_tx,
+        config,
+        executor: executor.clone(),
+        nodes: HashMap::new(),
+        graph_nodes,
+        physical_sinks: HashMap::new(),
+        virtual_sinks: HashMap::new(),
+        stream_watches: HashMap::new(),
+        default_metadata: None,
+        volume_lookups,
+    }));
 
     // Listen for global objects
-    let _listener = registry
+    let listener = registry
         .add_listener_local()
         .global({
             let state = state.clone();
+            let registry = registry.clone();
             move |global| {
                 if let Some(props) = global.props.as_ref() {
                     handle_global(&state, global.id, props, global.type_.clone());
+                    // Only app streams are kept in `nodes`
+                    if state.borrow().nodes.contains_key(&global.id) {
+                        watch_stream(&state, &registry, global);
+                    }
+                    if global.type_ == ObjectType::Metadata
+                        && props.get("metadata.name") == Some("default")
+                    {
+                        watch_default_sink(&state, &registry, global);
+                    }
                 }
             }
         })
@@ -195,12 +298,211 @@ fn run_pipewire_loop(
         })
         .register();
 
+    // The server answers a sync after everything queued before it, including the
+    // registry's initial burst of globals
+    let initial_scan = core.sync(0)?;
+    let core_listener = core
+        .add_listener_local()
+        .done({
+            let state = state.clone();
+            move |id, seq| {
+                if id == pipewire::core::PW_ID_CORE && seq == initial_scan {
+                    state.borrow().cache_tx.send(CacheUpdate::InitialScanComplete);
+                }
+            }
+        })
+        .register();
+
+    // Drop streams whose removal was missed
+    let (live_tx, live_rx) = pipewire::channel::channel();
+    let _reconcile = live_rx.attach(mainloop.loop_(), {
+        let state = state.clone();
+        move |live: LiveStreams| {
+            let dropped = reconcile_nodes(&mut state.borrow_mut(), &live);
+            if dropped > 0 {
+                info!("Dropped {} streams PipeWire no longer has", dropped);
+            }
+        }
+    });
+    let reconciling = Arc::new(AtomicBool::new(true));
+    std::thread::spawn({
+        let reconciling = reconciling.clone();
+        move || list_live_streams_periodically(executor.as_ref(), live_tx, reconciling)
+    });
+
+    // Quit the loop when the daemon shuts down
+    let _quit = quit_rx.attach(mainloop.loop_(), {
+        let mainloop = mainloop.clone();
+        move |()| mainloop.quit()
+    });
+
     info!("PipeWire monitor started");
     mainloop.run();
 
+    reconciling.store(false, Ordering::Relaxed);
+    // Dropping the listeners and state drops the last long-lived sender, ending the worker
+    drop(core_listener);
+    drop(listener);
+    drop(state);
+
     Ok(())
 }
 
+/// Apply monitor updates to the cache until every sender of `cache_rx` is dropped
+async fn run_cache_worker(
+    cache: Arc<RwLock<AudioCache>>,
+    controller: Arc<PipeWireController>,
+    routing: RoutingConfig,
+    mut cache_rx: mpsc::UnboundedReceiver<CacheUpdate>,
+) {
+    let auto_mute_apps = routing.auto_mute_on_inactive.clone();
+    while let Some(update) = cache_rx.recv().await {
+        let cache = cache.write().await;
+        let started = Instant::now();
+        match update {
+            CacheUpdate::UpdateSink(name, info) => cache.update_sink(name, info),
+            CacheUpdate::SetDefaultSink(sink_name) => {
+                if cache.set_default_sink(&sink_name) {
+                    info!("Default sink is now {}", sink_name);
+                }
+            }
+            CacheUpdate::MarkAppInactive(sink_input_id) => {
+                // Find the app that has this sink_input_id
+                let mut inactive_app = None;
+                let mut owner = None;
+                for mut entry in cache.apps.iter_mut() {
+                    let (app_name, app) = entry.pair_mut();
+                    if app.sink_input_ids.contains(&sink_input_id) {
+                        owner = Some((app_name.clone(), app.playing));
+                        app.sink_input_ids.retain(|&x| x != sink_input_id);
+                        app.stream_labels.retain(|(id, _)| *id != sink_input_id);
+                        // If no more active streams, mark as inactive with timestamp
+                        if app.sink_input_ids.is_empty() {
+                            app.active = false;
+                            app.inactive_since = Some(std::time::Instant::now());
+                            info!(
+                                "App {} is now inactive, will be removed in 5 minutes if not used",
+                                app_name
+                            );
+                            inactive_app = Some(app_name.clone());
+                        }
+                        break;
+                    }
+                }
+                // Not while iterating the apps, which the re-average reads
+                if let Some((app_name, was_playing)) = owner {
+                    cache.forget_stream(&app_name, sink_input_id);
+                    // The streams it has left may all be paused
+                    if inactive_app.is_none() {
+                        auto_mute_if_idle(
+                            &controller,
+                            &cache,
+                            &auto_mute_apps,
+                            &app_name,
+                            was_playing,
+                        );
+                    }
+                    cache.record_event(EventKind::StreamRemoved { app: app_name, sink_input_id });
+                }
+                // With no streams left there is nothing muted, the next ones start unmuted
+                if let Some(app_name) = inactive_app {
+                    if cache.end_auto_mute(&app_name) {
+                        cache.set_app_mute(&app_name, false);
+                    }
+                }
+            }
+            CacheUpdate::AddSinkInputToApp(stream) => {
+                let (app_key, was_playing) = cache.add_stream(stream);
+                auto_mute_if_idle(&controller, &cache, &auto_mute_apps, &app_key, was_playing);
+                cache.increment_generation();
+            }
+            CacheUpdate::SetStreamCorked(sink_input_id, corked) => {
+                let owner = cache
+                    .apps
+                    .iter()
+                    .find(|app| app.sink_input_ids.contains(&sink_input_id))
+                    .map(|app| app.key().clone());
+                // Not while iterating the apps, which the update reads
+                if let Some(app_name) = owner {
+                    debug!(
+                        "Stream {} of {} is {}",
+                        sink_input_id,
+                        app_name,
+                        if corked { "corked" } else { "playing" }
+                    );
+                    let was_playing = cache.apps.get(&app_name).is_some_and(|app| app.playing);
+                    cache.set_stream_corked(&app_name, sink_input_id, corked);
+                    auto_mute_if_idle(&controller, &cache, &auto_mute_apps, &app_name, was_playing);
+                    cache.increment_generation();
+                }
+            }
+            CacheUpdate::AddPhysicalSink(sink_name, display_name) => {
+                if cache.add_physical_sink(&sink_name, &display_name) {
+                    info!("Output device connected: {} ({})", display_name, sink_name);
+                }
+            }
+            CacheUpdate::RemovePhysicalSink(sink_name) => {
+                if cache.remove_physical_sink(&sink_name) {
+                    info!("Output device disconnected: {}", sink_name);
+                }
+            }
+            CacheUpdate::RemoveSink(sink_name) => {
+                cache.remove_sink(&sink_name);
+            }
+            CacheUpdate::InitialScanComplete => {
+                if cache.mark_ready() {
+                    info!(
+                        "Initial PipeWire scan complete: {} sinks, {} apps",
+                        cache.sinks.len(),
+                        cache.apps.len()
+                    );
+                }
+            }
+            CacheUpdate::CheckRoutingRule(app_name, sink_input_id) => {
+                // Use the most specific rule: app name, binary, media role, then default sink
+                let target = cache.auto_route_target(&app_name, &routing);
+                let restore = cache.saved_app_settings(&app_name).is_some();
+                match &target {
+                    Some(target_sink_name) => {
+                        info!("Auto-routing {} -> {}", app_name, target_sink_name)
+                    }
+                    None if restore => {}
+                    None => {
+                        debug!("No routing target for {}, leaving it where it is", app_name);
+                        cache.record_latency(Operation::CacheUpdate, started.elapsed());
+                        continue;
+                    }
+                }
+
+                // Use the controller to properly route the app (same as manual routing)
+                // This ensures loopback streams are set up correctly
+                let controller = controller.clone();
+                tokio::spawn(async move {
+                    // Give the app a moment to fully initialize, its own volume included
+                    tokio::time::sleep(STREAM_SETTLE_DELAY).await;
+
+                    if let Some(target_sink_name) = target {
+                        if let Err(e) = controller.route_app(&app_name, &target_sink_name).await {
+                            error!("Failed to apply routing for {}: {}", app_name, e);
+                        } else {
+                            info!("Successfully routed {} to {}", app_name, target_sink_name);
+                        }
+                    }
+                    if restore {
+                        if let Err(e) =
+                            controller.restore_app_settings(&app_name, sink_input_id).await
+                        {
+                            warn!("Failed to restore volume and mute of {}: {}", app_name, e);
+                        }
+                    }
+                });
+            }
+        }
+        cache.record_latency(Operation::CacheUpdate, started.elapsed());
+    }
+    debug!("Cache update worker stopped");
+}
+
 fn handle_global(
     state: &Rc<RefCell<MonitorState>>,
     id: u32,
@@ -224,75 +526,54 @@ fn handle_global(
     // Check if this is an audio sink
     if media_class == "Audio/Sink" {
         // Check if it's one of our virtual sinks
-        if state.config.virtual_sinks.iter().any(|s| s.name == node_name) {
+        let virtual_sink = state
+            .config
+            .find_virtual_sink(node_name, props.get("node.description"))
+            .map(|sink| sink.name.clone());
+        if let Some(sink_name) = virtual_sink {
             // Store the sink with ID, we'll get the actual volume separately
             let sink_info = SinkInfo {
                 id,
-                name: node_name.to_string(),
+                name: sink_name.clone(),
                 volume: 1.0,
-                muted: false,
+                applied_percent: volume_to_percent(1.0),
                 pipewire_id: id,
+                node_name: node_name.to_string(),
+                ..Default::default()
             };
 
             // Update cache asynchronously
-            let _ = state.cache_tx.send(CacheUpdate::UpdateSink(node_name.to_string(), sink_info));
+            state.virtual_sinks.insert(id, sink_name.clone());
+            state.cache_tx.send(CacheUpdate::UpdateSink(sink_name.clone(), sink_info.clone()));
 
-            info!("Found virtual sink: {} (id: {})", node_name, id);
+            info!("Found virtual sink: {} (node {}, id: {})", sink_name, node_name, id);
 
             // Get actual volume asynchronously
-            let sink_id = id;
-            let sink_name = node_name.to_string();
-            let cache_tx = state.cache_tx.clone();
-
-            std::thread::spawn(move || {
-                // Get actual volume using wpctl
-                if let Ok(output) = std::process::Command::new("wpctl")
-                    .args(["get-volume", &sink_id.to_string()])
-                    .output()
-                {
-                    if output.status.success() {
-                        let stdout = String::from_utf8_lossy(&output.stdout);
-                        // Parse output like "Volume: 0.75 [MUTED]" or "Volume: 0.75"
-                        if let Some(volume_str) = stdout.split(':').nth(1) {
-                            let parts: Vec<&str> = volume_str.split_whitespace().collect();
-                            if let Ok(volume) = parts.first().unwrap_or(&"1.0").parse::<f32>() {
-                                let muted = volume_str.contains("[MUTED]");
-                                let sink_info = SinkInfo {
-                                    id: sink_id,
-                                    name: sink_name.clone(),
-                                    volume,
-                                    muted,
-                                    pipewire_id: sink_id,
-                                };
-                                let _ =
-                                    cache_tx.send(CacheUpdate::UpdateSink(sink_name, sink_info));
-                            }
-                        }
-                    }
-                }
-            });
+            state.volume_lookups.queue(sink_info);
+        } else if !node_name.is_empty() && !is_combined_sink(node_name) {
+            // A hardware device, such as headphones or a USB DAC
+            let display_name = props
+                .get("node.description")
+                .or_else(|| props.get("node.nick"))
+                .unwrap_or(node_name);
+            state.physical_sinks.insert(id, node_name.to_string());
+            state.cache_tx.send(CacheUpdate::AddPhysicalSink(
+                node_name.to_string(),
+                display_name.to_string(),
+            ));
         }
     }
 
     // Check if this is an audio output stream (ignore input streams)
     if media_class == "Stream/Output/Audio" {
-        // Skip loopback streams (check multiple properties)
-        if node_name.contains("_to_") || node_name.ends_with("_Loopback") {
+        if is_loopback(node_name, props.get("media.name"), &state.config.loopback_target_suffixes) {
             return;
         }
 
-        // Also check media.name for loopback patterns
-        if let Some(media_name) = props.get("media.name") {
-            if media_name.contains("Loopback") {
-                return;
-            }
-        }
-
-        let app_name = props
-            .get("application.name")
-            .or_else(|| props.get("node.description"))
-            .unwrap_or_default()
-            .to_string();
+        let app_name =
+            state.config.cache.app_identity(|key| get_lossy(props, key)).unwrap_or_default();
+        let stream_label =
+            get_lossy(props, "media.name").and_then(|name| stream_label(&name, &app_name));
 
         // Binary name extraction will happen in the async thread with pactl
 
@@ -301,17 +582,20 @@ fn handle_global(
             props.get("object.serial").and_then(|s| s.parse::<u32>().ok()).unwrap_or(id);
 
         // We'll determine the final name later after checking pactl
-        let node_info = NodeInfo { app_name: Some(app_name.clone()), serial_id };
+        let node_info =
+            NodeInfo { app_name: Some(app_name.clone()), serial_id, added: Instant::now() };
 
-        state.nodes.insert(id, node_info);
+        track_node(&mut state, id, node_info);
 
         // Auto-routing will be handled after we know the binary name
 
         // Get sink connection info asynchronously
         let app_id = serial_id;
         let app_name_for_log = app_name.clone();
+        let node_name_owned = node_name.to_string();
         let cache_tx = state.cache_tx.clone();
-        let default_sink = state.config.routing.default_sink.clone();
+        let config = state.config.clone();
+        let executor = state.executor.clone();
 
         std::thread::spawn(move || {
             debug!("Looking up sink for app {} with ID {}", app_name_for_log, app_id);
@@ -319,43 +603,23 @@ fn handle_global(
             // Try to get the binary name and PID from pactl
             let mut extracted_binary_name = None;
             let mut process_pid = None;
-            if let Ok(pactl_output) =
-                std::process::Command::new("pactl").args(["list", "sink-inputs"]).output()
-            {
-                if pactl_output.status.success() {
-                    let stdout = String::from_utf8_lossy(&pactl_output.stdout);
-                    let search_pattern = format!("Sink Input #{app_id}");
-                    if let Some(pos) = stdout.find(&search_pattern) {
-                        // Look for application.process.binary and PID in the next several lines
-                        let lines = stdout[pos..].lines().take(30);
-                        for line in lines {
-                            if let Some(binary_line) =
-                                line.trim().strip_prefix("application.process.binary = \"")
-                            {
-                                if let Some(binary_end) = binary_line.find('"') {
-                                    let binary_path = &binary_line[..binary_end];
-                                    let extracted = binary_path
-                                        .split('/')
-                                        .next_back()
-                                        .unwrap_or(binary_path)
-                                        .trim_end_matches("-bin")
-                                        .trim_end_matches(".exe");
-                                    if !extracted.is_empty() {
-                                        extracted_binary_name = Some(extracted.to_string());
-                                        debug!("Found binary name from pactl: {}", extracted);
-                                    }
-                                }
-                            } else if let Some(pid_line) =
-                                line.trim().strip_prefix("application.process.id = \"")
-                            {
-                                if let Some(pid_end) = pid_line.find('"') {
-                                    if let Ok(pid) = pid_line[..pid_end].parse::<u32>() {
-                                        process_pid = Some(pid);
-                                        debug!("Found PID from pactl: {}", pid);
-                                    }
-                                }
-                            }
-                        }
+            let mut media_role = None;
+            let mut application_id = None;
+            let mut stream_volume = None;
+            let mut corked = false;
+            if let Some(inputs) = list_sink_inputs(executor.as_ref()) {
+                if let Some(input) = find_sink_input(&inputs, app_id, &node_name_owned) {
+                    extracted_binary_name = stream_binary_name(input);
+                    if let Some(extracted) = &extracted_binary_name {
+                        debug!("Found binary name from pactl: {}", extracted);
+                    }
+                    process_pid = input.process_id();
+                    media_role = input.media_role().map(str::to_string);
+                    application_id = input.property("application.id").map(str::to_string);
+                    stream_volume = input.volume;
+                    corked = input.corked;
+                    if let Some(pid) = process_pid {
+                        debug!("Found PID from pactl: {}", pid);
                     }
                 }
             }
@@ -371,10 +635,7 @@ fn handle_global(
 
                 while attempts < 3 && window_title.is_none() {
                     // Try to get window title for current PID - check all windows and pick the best one
-                    if let Ok(xdotool_output) = std::process::Command::new("sh")
-                        .arg("-c")
-                        .arg(format!("xdotool search --pid {current_pid} 2>/dev/null | while read wid; do xdotool getwindowname $wid 2>/dev/null; done"))
-                        .output()
+                    if let Ok(xdotool_output) = executor.execute_shell(&format!("xdotool search --pid {current_pid} 2>/dev/null | while read wid; do xdotool getwindowname $wid 2>/dev/null; done"))
                     {
                         // Get all window titles for this PID
                         let titles: Vec<String> = String::from_utf8_lossy(&xdotool_output.stdout)
@@ -405,9 +666,8 @@ fn handle_global(
                     }
 
                     // Try to get parent PID and process name
-                    if let Ok(ps_output) = std::process::Command::new("ps")
-                        .args(["-o", "ppid=,comm=", "-p", &current_pid.to_string()])
-                        .output()
+                    if let Ok(ps_output) = executor
+                        .execute("ps", &["-o", "ppid=,comm=", "-p", &current_pid.to_string()])
                     {
                         let ps_str = String::from_utf8_lossy(&ps_output.stdout).trim().to_string();
                         let parts: Vec<&str> = ps_str.splitn(2, ' ').collect();
@@ -452,225 +712,1355 @@ fn handle_global(
                 }
             }
 
-            // Get sink info using pactl
-            if let Ok(output) =
-                std::process::Command::new("pactl").args(["list", "sink-inputs"]).output()
+            // Sandboxed apps sit under wrappers like bwrap, so group them by their app ID
+            if let Some(name) = AppNameDetector::new(executor.clone(), AppNameConfig::default())
+                .sandboxed_app_name(application_id.as_deref(), process_pid)
             {
-                if output.status.success() {
-                    let stdout = String::from_utf8_lossy(&output.stdout);
-
-                    // Find our sink input by ID
-                    let search_pattern = format!("Sink Input #{app_id}");
-                    if let Some(pos) = stdout.find(&search_pattern) {
-                        // Look for the Sink: line in the next several lines
-                        let lines = stdout[pos..].lines().take(10);
-                        for line in lines {
-                            if let Some(sink_id_str) = line.trim().strip_prefix("Sink:") {
-                                if let Ok(sink_id) = sink_id_str.trim().parse::<u32>() {
-                                    // Get sink name
-                                    if let Ok(sink_output) = std::process::Command::new("pactl")
-                                        .args(["list", "sinks"])
-                                        .output()
-                                    {
-                                        let sink_stdout =
-                                            String::from_utf8_lossy(&sink_output.stdout);
-                                        let sink_search = format!("Sink #{sink_id}");
-                                        if let Some(sink_pos) = sink_stdout.find(&sink_search) {
-                                            // Find the Name: line
-                                            for line in sink_stdout[sink_pos..].lines().take(10) {
-                                                if let Some(name) =
-                                                    line.trim().strip_prefix("Name:")
-                                                {
-                                                    let sink_name = name.trim().to_string();
-                                                    info!(
-                                                        "Found app {} connected to sink {}",
-                                                        app_name_for_log, sink_name
-                                                    );
-                                                    // Determine the best display name with priority:
-                                                    // 1. Window title from X11/Wayland (most accurate)
-                                                    // 2. Ultimate parent process name (for grouping related streams)
-                                                    // 3. Binary name if we have it and app name is generic
-                                                    // 4. application.name if it's not generic
-                                                    // 5. Binary name as fallback
-                                                    // 6. application.name as last resort
-                                                    let final_display_name = if let Some(title) =
-                                                        window_title.as_ref()
-                                                    {
-                                                        // Use window title if we got it
-                                                        title.clone()
-                                                    } else if let Some(parent) =
-                                                        ultimate_parent_name.as_ref()
-                                                    {
-                                                        // If we have an ultimate parent process (like Discord), use that for grouping
-                                                        // Capitalize first letter for display
-                                                        let mut chars = parent.chars();
-                                                        match chars.next() {
-                                                            None => parent.clone(),
-                                                            Some(first) => {
-                                                                first
-                                                                    .to_uppercase()
-                                                                    .collect::<String>()
-                                                                    + chars.as_str()
-                                                            }
-                                                        }
-                                                    } else if app_name_for_log.contains("WEBRTC")
-                                                        || app_name_for_log.contains("WebRTC")
-                                                    {
-                                                        // For WEBRTC apps without a parent, prefer binary name
-                                                        if let Some(binary_name) =
-                                                            extracted_binary_name.as_ref()
-                                                        {
-                                                            // Capitalize first letter of binary name for display
-                                                            let mut chars = binary_name.chars();
-                                                            match chars.next() {
-                                                                None => app_name_for_log.clone(),
-                                                                Some(first) => {
-                                                                    first
-                                                                        .to_uppercase()
-                                                                        .collect::<String>()
-                                                                        + chars.as_str()
-                                                                }
-                                                            }
-                                                        } else {
-                                                            app_name_for_log.clone()
-                                                        }
-                                                    } else if !app_name_for_log.is_empty()
-                                                        && !app_name_for_log.contains("wine")
-                                                        && !app_name_for_log.contains("preloader")
-                                                    {
-                                                        // Use application.name if it's meaningful
-                                                        app_name_for_log.clone()
-                                                    } else if let Some(binary_name) =
-                                                        extracted_binary_name.as_ref()
-                                                    {
-                                                        // Capitalize first letter of binary name for display
-                                                        let mut chars = binary_name.chars();
-                                                        match chars.next() {
-                                                            None => String::new(),
-                                                            Some(first) => {
-                                                                first
-                                                                    .to_uppercase()
-                                                                    .collect::<String>()
-                                                                    + chars.as_str()
-                                                            }
-                                                        }
-                                                    } else {
-                                                        // Last resort - use application.name as-is
-                                                        app_name_for_log.clone()
-                                                    };
-
-                                                    // Use the display name as the key to group related streams
-                                                    // This groups Discord Chromium and WEBRTC under "Discord"
-                                                    let final_key = final_display_name.clone();
-
-                                                    // Always use AddSinkInputToApp - it will create the app if needed
-                                                    let _ = cache_tx.send(
-                                                        CacheUpdate::AddSinkInputToApp(
-                                                            final_key.clone(),
-                                                            final_display_name.clone(),
-                                                            extracted_binary_name
-                                                                .as_ref()
-                                                                .unwrap_or(&app_name_for_log)
-                                                                .clone(),
-                                                            app_name_for_log.clone(), // The actual stream name
-                                                            app_id,
-                                                            sink_name,
-                                                        ),
-                                                    );
-
-                                                    // Check if we need to apply a routing rule
-                                                    let _ = cache_tx.send(
-                                                        CacheUpdate::CheckRoutingRule(
-                                                            final_key, app_id,
-                                                        ),
-                                                    );
-                                                    return;
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+                debug!("Found sandboxed app {} for {}", name, app_name_for_log);
+                ultimate_parent_name = Some(name);
             }
 
-            // Fallback if we couldn't get sink info
-            // Use the same priority as above, but also consider ultimate parent
-            let final_display_name = if let Some(title) = window_title.as_ref() {
-                // Use window title if we got it
-                title.clone()
-            } else if let Some(parent) = ultimate_parent_name.as_ref() {
-                // If we have an ultimate parent process (like Discord), use that for grouping
-                // This groups Chromium and WEBRTC VoiceEngine under Discord
-                // Capitalize first letter for display
-                let mut chars = parent.chars();
-                match chars.next() {
-                    None => parent.clone(),
-                    Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
-                }
-            } else if app_name_for_log.contains("WEBRTC") || app_name_for_log.contains("WebRTC") {
-                // For WEBRTC apps without a parent, prefer binary name
-                if let Some(binary_name) = extracted_binary_name.as_ref() {
-                    // Capitalize first letter of binary name for display
-                    let mut chars = binary_name.chars();
-                    match chars.next() {
-                        None => app_name_for_log.clone(),
-                        Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
-                    }
-                } else {
-                    app_name_for_log.clone()
-                }
-            } else if !app_name_for_log.is_empty()
-                && !app_name_for_log.contains("wine")
-                && !app_name_for_log.contains("preloader")
-            {
-                // Use application.name if it's meaningful
-                app_name_for_log.clone()
-            } else if let Some(binary_name) = extracted_binary_name.as_ref() {
-                // Capitalize first letter of binary name for display
-                let mut chars = binary_name.chars();
-                match chars.next() {
-                    None => String::new(),
-                    Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
+            // Get sink info using pactl
+            let connected_sink = list_sink_inputs(executor.as_ref()).and_then(|inputs| {
+                find_sink_input(&inputs, app_id, &node_name_owned).and_then(|input| input.sink)
+            });
+            let sink = connected_sink.and_then(|sink_id| {
+                list_sinks(executor.as_ref())?.into_iter().find(|sink| sink.id == sink_id)
+            });
+            let current_sink = match sink {
+                Some(sink) => {
+                    let sink_name = cache_sink_name(&config, &sink);
+                    info!("Found app {} connected to sink {}", app_name_for_log, sink_name);
+                    sink_name
                 }
-            } else {
-                // Last resort - use application.name as-is
-                app_name_for_log.clone()
+                // Use the default sink from config instead of "Unknown"
+                None => config.routing.default_sink.clone(),
             };
 
-            // Use the display name as the key to group related streams together
-            // For example, WEBRTC VoiceEngine with binary=Discord will be grouped under "Discord"
-            let final_key = final_display_name.clone();
+            let (final_key, final_display_name) = choose_app_name(
+                window_title.as_deref(),
+                ultimate_parent_name.as_deref(),
+                &app_name_for_log,
+                extracted_binary_name.as_deref(),
+                config.cache.capitalize_binary_names,
+            );
+
+            let binary_name = extracted_binary_name.as_ref().unwrap_or(&app_name_for_log);
+            if !config.cache.should_track(&[&final_key, binary_name, &app_name_for_log]) {
+                debug!("Not tracking {} ({})", final_key, binary_name);
+                return;
+            }
 
             // Always use AddSinkInputToApp - it will create the app if needed
-            // Use the default sink from config instead of "Unknown"
-            let _ = cache_tx.send(CacheUpdate::AddSinkInputToApp(
-                final_key.clone(),
-                final_display_name.clone(),
-                extracted_binary_name.as_ref().unwrap_or(&app_name_for_log).clone(),
-                app_name_for_log.clone(), // The actual stream name
-                app_id,
-                default_sink,
-            ));
+            cache_tx.send(CacheUpdate::AddSinkInputToApp(NewStream {
+                app_key: final_key.clone(),
+                display_name: final_display_name.clone(),
+                binary_name: binary_name.clone(),
+                stream_name: app_name_for_log.clone(),
+                sink_input_id: app_id,
+                current_sink,
+                media_role,
+                stream_label,
+                stream_volume,
+                corked,
+                window_title,
+            }));
 
             // Check if we need to apply a routing rule
-            let _ = cache_tx.send(CacheUpdate::CheckRoutingRule(final_key, app_id));
+            cache_tx.send(CacheUpdate::CheckRoutingRule(final_key, app_id));
+        });
+    }
+}
+
+/// Whether a stream is one of the loopbacks that feed a virtual sink to a device
+///
+/// Those are named with one of the configured `loopback_target_suffixes`.
+fn is_loopback(node_name: &str, media_name: Option<&str>, suffixes: &[String]) -> bool {
+    suffixes.iter().any(|suffix| node_name.ends_with(suffix.as_str()))
+        || media_name.is_some_and(|name| name.contains("Loopback"))
+}
+
+/// Apply `auto_mute_on_inactive` to an app that stopped or started playing
+///
+/// An app that went idle, every stream it has paused, is muted unless it was muted
+/// already; one that plays again is unmuted if it was auto-muted.
+fn auto_mute_if_idle(
+    controller: &Arc<PipeWireController>,
+    cache: &AudioCache,
+    auto_mute_apps: &[String],
+    app_name: &str,
+    was_playing: bool,
+) {
+    let Some(playing) = cache.apps.get(app_name).map(|app| app.playing) else {
+        return;
+    };
+    if playing == was_playing
+        || !auto_mute_apps.iter().any(|app| app.eq_ignore_ascii_case(app_name))
+    {
+        return;
+    }
+    let muted = !playing;
+    let changed =
+        if muted { cache.begin_auto_mute(app_name) } else { cache.end_auto_mute(app_name) };
+    if !changed {
+        return;
+    }
+    let controller = controller.clone();
+    let app_name = app_name.to_string();
+    tokio::spawn(async move {
+        if let Err(e) = controller.auto_mute_app(&app_name, muted).await {
+            debug!("Auto-mute for {} failed: {}", app_name, e);
+        }
+    });
+}
+
+/// Look up a property, replacing invalid UTF-8 instead of treating it as missing
+fn get_lossy(props: &pipewire::spa::utils::dict::DictRef, key: &str) -> Option<String> {
+    props
+        .iter_cstr()
+        .find(|(k, _)| k.to_bytes() == key.as_bytes())
+        .map(|(_, v)| String::from_utf8_lossy(v.to_bytes()).into_owned())
+}
+
+/// Run `pactl list sink-inputs` and parse the result
+fn list_sink_inputs(executor: &dyn CommandExecutor) -> Option<Vec<SinkInput>> {
+    let output = executor.execute("pactl", &["list", "sink-inputs"]).ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(parse_sink_inputs(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Run `pactl list sinks` and parse the result
+fn list_sinks(executor: &dyn CommandExecutor) -> Option<Vec<Sink>> {
+    let output = executor.execute("pactl", &["list", "sinks"]).ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(parse_sinks(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Run `pactl get-default-sink`
+fn get_default_sink(executor: &dyn CommandExecutor) -> Option<String> {
+    let output = executor.execute("pactl", &["get-default-sink"]).ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let sink_name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    // "@DEFAULT_SINK@" when there is no output device
+    (!sink_name.is_empty() && !sink_name.starts_with('@')).then_some(sink_name)
+}
+
+/// Feed the cache from pactl every `interval` until `shutdown`, for when PipeWire
+/// itself can't be used
+async fn poll_pactl(
+    config: &Config,
+    cache_tx: &CacheSender,
+    executor: &Arc<dyn CommandExecutor>,
+    interval: Duration,
+    shutdown: impl Future<Output = ()>,
+) {
+    let mut poll = PactlPoll {
+        detector: AppNameDetector::new(executor.clone(), AppNameConfig::default()),
+        ..PactlPoll::default()
+    };
+    let mut ticks = tokio::time::interval(interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    tokio::pin!(shutdown);
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {}
+            () = &mut shutdown => {
+                info!("Stopping pactl polling");
+                return;
+            }
+        }
+        let executor = executor.clone();
+        let listed = tokio::task::spawn_blocking(move || {
+            let executor = executor.as_ref();
+            Some((list_sinks(executor)?, list_sink_inputs(executor)?, get_default_sink(executor)))
         });
+        let Ok(Some((sinks, inputs, default_sink))) = listed.await else {
+            debug!("Skipping pactl poll, pactl is unavailable");
+            continue;
+        };
+        for update in poll.updates(config, &sinks, &inputs) {
+            cache_tx.send(update);
+        }
+        // An unchanged default leaves the cache as it is
+        if let Some(sink_name) = default_sink {
+            cache_tx.send(CacheUpdate::SetDefaultSink(sink_name));
+        }
+    }
+}
+
+/// What the last pactl poll saw, so each poll only sends what changed
+#[derive(Default)]
+struct PactlPoll {
+    detector: AppNameDetector, // Names the apps of new streams
+    virtual_sinks: HashSet<String>,
+    physical_sinks: HashSet<String>,
+    streams: HashMap<u32, bool>, // Serial -> corked
+    scanned: bool,
+}
+
+impl PactlPoll {
+    /// Updates that bring the cache in line with one listing of sinks and streams
+    fn updates(
+        &mut self,
+        config: &Config,
+        sinks: &[Sink],
+        inputs: &[SinkInput],
+    ) -> Vec<CacheUpdate> {
+        let mut updates = Vec::new();
+
+        let mut virtual_sinks = HashSet::new();
+        let mut physical_sinks = HashSet::new();
+        for sink in sinks.iter().filter(|sink| !sink.name.is_empty()) {
+            if let Some(virtual_sink) =
+                config.find_virtual_sink(&sink.name, sink.description.as_deref())
+            {
+                // Unchanged sinks leave the cache as it is, so these can repeat each poll
+                let volume = sink.volume.unwrap_or(1.0);
+                let sink_info = SinkInfo {
+                    id: sink.id,
+                    name: virtual_sink.name.clone(),
+                    volume,
+                    muted: sink.muted,
+                    pipewire_id: sink.id,
+                    applied_percent: volume_to_percent(volume),
+                    node_name: sink.name.clone(),
+                };
+                updates.push(CacheUpdate::UpdateSink(virtual_sink.name.clone(), sink_info));
+                virtual_sinks.insert(virtual_sink.name.clone());
+            } else if !is_combined_sink(&sink.name) {
+                if !self.physical_sinks.contains(&sink.name) {
+                    let display_name = sink.description.as_ref().unwrap_or(&sink.name);
+                    updates.push(CacheUpdate::AddPhysicalSink(
+                        sink.name.clone(),
+                        display_name.clone(),
+                    ));
+                }
+                physical_sinks.insert(sink.name.clone());
+            }
+        }
+        for sink_name in self.virtual_sinks.difference(&virtual_sinks) {
+            updates.push(CacheUpdate::RemoveSink(sink_name.clone()));
+        }
+        for sink_name in self.physical_sinks.difference(&physical_sinks) {
+            updates.push(CacheUpdate::RemovePhysicalSink(sink_name.clone()));
+        }
+
+        let mut streams = HashMap::new();
+        for input in inputs {
+            if is_loopback(
+                input.property("node.name").unwrap_or_default(),
+                input.property("media.name"),
+                &config.loopback_target_suffixes,
+            ) {
+                continue;
+            }
+            let serial = input.serial();
+            streams.insert(serial, input.corked);
+            match self.streams.get(&serial) {
+                Some(&corked) if corked != input.corked => {
+                    updates.push(CacheUpdate::SetStreamCorked(serial, input.corked));
+                }
+                Some(_) => {}
+                None => updates.extend(stream_appeared(&self.detector, config, sinks, input)),
+            }
+        }
+        for serial in self.streams.keys().filter(|serial| !streams.contains_key(serial)) {
+            updates.push(CacheUpdate::MarkAppInactive(*serial));
+        }
+
+        self.virtual_sinks = virtual_sinks;
+        self.physical_sinks = physical_sinks;
+        self.streams = streams;
+        if !self.scanned {
+            self.scanned = true;
+            updates.push(CacheUpdate::InitialScanComplete);
+        }
+        updates
+    }
+}
+
+/// The name the cache knows `sink` by, the configured one for a virtual sink
+fn cache_sink_name(config: &Config, sink: &Sink) -> String {
+    config
+        .find_virtual_sink(&sink.name, sink.description.as_deref())
+        .map_or_else(|| sink.name.clone(), |virtual_sink| virtual_sink.name.clone())
+}
+
+/// Updates for a stream a pactl poll found, named as the PipeWire monitor would
+/// without a window title to go by
+fn stream_appeared(
+    detector: &AppNameDetector,
+    config: &Config,
+    sinks: &[Sink],
+    input: &SinkInput,
+) -> Vec<CacheUpdate> {
+    let current_sink = sinks
+        .iter()
+        .find(|sink| Some(sink.id) == input.sink)
+        .map_or_else(|| config.routing.default_sink.clone(), |sink| cache_sink_name(config, sink));
+    let Some(stream) = detector.new_stream(&config.cache, input, current_sink) else {
+        return Vec::new();
+    };
+    let check_rule = CacheUpdate::CheckRoutingRule(stream.app_key.clone(), stream.sink_input_id);
+    vec![CacheUpdate::AddSinkInputToApp(stream), check_rule]
+}
+
+/// Follow property changes of an app's stream, as browsers rename theirs once set up
+fn watch_stream(
+    state: &Rc<RefCell<MonitorState>>,
+    registry: &Registry,
+    global: &GlobalObject<&DictRef>,
+) {
+    let node: Node = match registry.bind(global) {
+        Ok(node) => node,
+        Err(e) => {
+            debug!("Not watching stream {} for changes: {}", global.id, e);
+            return;
+        }
+    };
+    let id = global.id;
+    let listener = node
+        .add_listener_local()
+        .info({
+            // Weak, as the state owns this listener
+            let state = Rc::downgrade(state);
+            move |info| {
+                let Some(state) = state.upgrade() else {
+                    return;
+                };
+                if info.change_mask().contains(NodeChangeMask::STATE) {
+                    handle_stream_state(&state, id, info.state());
+                }
+                if !info.change_mask().contains(NodeChangeMask::PROPS) {
+                    return;
+                }
+                if let Some(props) = info.props() {
+                    handle_stream_props(&state, id, props);
+                }
+            }
+        })
+        .register();
+    state.borrow_mut().stream_watches.insert(id, (node, listener));
+}
+
+/// Follow the default sink through the "default" metadata, which holds it as
+/// `default.audio.sink`
+fn watch_default_sink(
+    state: &Rc<RefCell<MonitorState>>,
+    registry: &Registry,
+    global: &GlobalObject<&DictRef>,
+) {
+    let metadata: Metadata = match registry.bind(global) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            warn!("Not following the default sink: {}", e);
+            return;
+        }
+    };
+    let cache_tx = state.borrow().cache_tx.clone();
+    let listener = metadata
+        .add_listener_local()
+        .property(move |_subject, key, _type, value| {
+            if key == Some("default.audio.sink") {
+                if let Some(sink_name) = value.and_then(default_sink_name) {
+                    cache_tx.send(CacheUpdate::SetDefaultSink(sink_name));
+                }
+            }
+            0
+        })
+        .register();
+    state.borrow_mut().default_metadata = Some((metadata, listener));
+}
+
+/// The sink named by a `default.audio.sink` value like `{"name":"alsa_output.usb"}`
+fn default_sink_name(value: &str) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_str(value).ok()?;
+    let name = value.get("name")?.as_str()?;
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Look a stream up again if its properties now name a different app
+///
+/// The cache worker then moves the stream's id over to the app of the new name.
+fn handle_stream_props(state: &Rc<RefCell<MonitorState>>, id: u32, props: &DictRef) {
+    let renamed = {
+        let state = state.borrow();
+        let app_name = state.config.cache.app_identity(|key| get_lossy(props, key));
+        match (state.nodes.get(&id), app_name) {
+            (Some(node), Some(app_name)) if !app_name.is_empty() => {
+                (node.app_name.as_ref() != Some(&app_name)).then_some(app_name)
+            }
+            _ => None,
+        }
+    };
+    if let Some(app_name) = renamed {
+        info!("Audio stream {} is now named {}", id, app_name);
+        handle_global(state, id, props, ObjectType::Node);
+    }
+}
+
+/// Follow a stream being corked and uncorked, which pipewire-pulse maps to the node
+/// going idle and back to running
+fn handle_stream_state(state: &Rc<RefCell<MonitorState>>, id: u32, node_state: NodeState) {
+    let corked = match node_state {
+        NodeState::Running => false,
+        NodeState::Idle | NodeState::Suspended => true,
+        NodeState::Creating | NodeState::Error(_) => return,
+    };
+    let state = state.borrow();
+    if let Some(node) = state.nodes.get(&id) {
+        state.cache_tx.send(CacheUpdate::SetStreamCorked(node.serial_id, corked));
     }
 }
 
 fn handle_global_remove(state: &Rc<RefCell<MonitorState>>, id: u32) {
     let mut state = state.borrow_mut();
 
+    if let Some(sink_name) = state.physical_sinks.remove(&id) {
+        state.cache_tx.send(CacheUpdate::RemovePhysicalSink(sink_name));
+        return;
+    }
+
+    if let Some(sink_name) = state.virtual_sinks.remove(&id) {
+        info!("Virtual sink removed: {} (id: {})", sink_name, id);
+        state.cache_tx.send(CacheUpdate::RemoveSink(sink_name));
+        return;
+    }
+
+    forget_node(&mut state, id);
+}
+
+/// Start tracking an app stream, making room under [`MAX_TRACKED_NODES`] if needed
+fn track_node(state: &mut MonitorState, id: u32, node_info: NodeInfo) {
+    if !state.nodes.contains_key(&id) && state.nodes.len() >= MAX_TRACKED_NODES {
+        let oldest = state.nodes.iter().min_by_key(|(_, node)| node.added).map(|(id, _)| *id);
+        if let Some(oldest) = oldest {
+            warn!("Tracking {} streams, dropping the oldest ({})", MAX_TRACKED_NODES, oldest);
+            forget_node(state, oldest);
+        }
+    }
+    state.graph_nodes.insert(
+        id,
+        GraphNode { id, serial: node_info.serial_id, app_name: node_info.app_name.clone() },
+    );
+    state.nodes.insert(id, node_info);
+}
+
+/// Stop tracking a stream and mark it gone in the cache
+fn forget_node(state: &mut MonitorState, id: u32) {
+    state.stream_watches.remove(&id);
+    state.graph_nodes.remove(&id);
     if let Some(node_info) = state.nodes.remove(&id) {
         if let Some(app_name) = node_info.app_name {
             let app_name_for_log = app_name.clone();
             // Mark app as inactive in cache using the serial_id
-            let _ = state.cache_tx.send(CacheUpdate::MarkAppInactive(node_info.serial_id));
+            state.cache_tx.send(CacheUpdate::MarkAppInactive(node_info.serial_id));
 
             info!("Audio stream removed: {} (id: {})", app_name_for_log, id);
         }
     }
 }
+
+/// Forget tracked streams pactl no longer lists, in case their global_remove was missed
+///
+/// Streams tracked after the list was taken are kept. Returns how many were dropped.
+fn reconcile_nodes(state: &mut MonitorState, live: &LiveStreams) -> usize {
+    let stale: Vec<u32> = state
+        .nodes
+        .iter()
+        .filter(|(_, node)| node.added < live.listed_at && !live.serials.contains(&node.serial_id))
+        .map(|(id, _)| *id)
+        .collect();
+    for id in &stale {
+        forget_node(state, *id);
+    }
+    stale.len()
+}
+
+/// List the live streams every [`RECONCILE_INTERVAL`] until `running` is cleared
+fn list_live_streams_periodically(
+    executor: &dyn CommandExecutor,
+    live_tx: pipewire::channel::Sender<LiveStreams>,
+    running: Arc<AtomicBool>,
+) {
+    loop {
+        std::thread::sleep(RECONCILE_INTERVAL);
+        if !running.load(Ordering::Relaxed) {
+            return;
+        }
+        let listed_at = Instant::now();
+        let Some(inputs) = list_sink_inputs(executor) else {
+            debug!("Skipping stream reconciliation, pactl is unavailable");
+            continue;
+        };
+        let serials = inputs.iter().map(SinkInput::serial).collect();
+        if live_tx.send(LiveStreams { serials, listed_at }).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{Node, PipeWireBackend, SinkEntry};
+    use crate::cache::AppInfo;
+    use crate::config::SinkMatch;
+    use crate::inspect::GraphDump;
+    use async_trait::async_trait;
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    #[test]
+    fn test_updates_sent_after_the_worker_stops_are_counted() {
+        let cache = AudioCache::new();
+        let (tx, rx) = mpsc::unbounded_channel();
+        let cache_tx = CacheSender::new(tx, cache.dropped_update_counter());
+
+        cache_tx.send(CacheUpdate::InitialScanComplete);
+        assert_eq!(cache.dropped_updates(), 0);
+
+        drop(rx);
+        cache_tx.send(CacheUpdate::RemoveSink("Game".to_string()));
+        cache_tx.clone().send(CacheUpdate::InitialScanComplete);
+        assert_eq!(cache.dropped_updates(), 2);
+    }
+
+    fn monitor_state() -> (MonitorState, mpsc::UnboundedReceiver<CacheUpdate>) {
+        let (cache_tx, cache_rx) = mpsc::unbounded_channel();
+        let cache_tx = CacheSender::new(cache_tx, Arc::default());
+        let state = MonitorState {
+            volume_lookups: VolumeLookups::spawn(cache_tx.clone(), |_| None),
+            cache_tx,
+            config: Config::default(),
+            executor: Arc::new(SystemCommandExecutor),
+            nodes: HashMap::new(),
+            graph_nodes: Arc::default(),
+            physical_sinks: HashMap::new(),
+            virtual_sinks: HashMap::new(),
+            stream_watches: HashMap::new(),
+            default_metadata: None,
+        };
+        (state, cache_rx)
+    }
+
+    fn node(serial_id: u32, added: Instant) -> NodeInfo {
+        NodeInfo { app_name: Some(format!("App{serial_id}")), serial_id, added }
+    }
+
+    /// Serials of the streams a batch of updates marks gone
+    fn marked_inactive(cache_rx: &mut mpsc::UnboundedReceiver<CacheUpdate>) -> Vec<u32> {
+        let mut serials = Vec::new();
+        while let Ok(update) = cache_rx.try_recv() {
+            if let CacheUpdate::MarkAppInactive(serial) = update {
+                serials.push(serial);
+            }
+        }
+        serials.sort();
+        serials
+    }
+
+    #[test]
+    fn test_reconcile_drops_nodes_pactl_no_longer_lists() {
+        let (mut state, mut cache_rx) = monitor_state();
+        let before = Instant::now();
+        state.nodes.insert(40, node(140, before));
+        state.nodes.insert(41, node(141, before));
+        let listed_at = Instant::now();
+        // Appeared after pactl was asked, so it can't be in the list yet
+        state.nodes.insert(42, node(142, listed_at + Duration::from_millis(1)));
+
+        let live = LiveStreams { serials: HashSet::from([141]), listed_at };
+        assert_eq!(reconcile_nodes(&mut state, &live), 1);
+        let mut tracked: Vec<u32> = state.nodes.keys().copied().collect();
+        tracked.sort();
+        assert_eq!(tracked, vec![41, 42]);
+        assert_eq!(marked_inactive(&mut cache_rx), vec![140]);
+
+        assert_eq!(reconcile_nodes(&mut state, &live), 0);
+    }
+
+    #[test]
+    fn test_tracked_nodes_are_capped_by_dropping_the_oldest() {
+        let (mut state, mut cache_rx) = monitor_state();
+        let start = Instant::now();
+        for id in 0..MAX_TRACKED_NODES as u32 {
+            track_node(&mut state, id, node(id, start + Duration::from_micros(id as u64)));
+        }
+        // Tracking a known stream again takes no extra room
+        track_node(&mut state, 5, node(5, start + Duration::from_secs(1)));
+        assert!(marked_inactive(&mut cache_rx).is_empty());
+
+        track_node(&mut state, 5000, node(5000, start + Duration::from_secs(2)));
+        assert_eq!(state.nodes.len(), MAX_TRACKED_NODES);
+        assert!(!state.nodes.contains_key(&0) && state.nodes.contains_key(&5000));
+        assert_eq!(marked_inactive(&mut cache_rx), vec![0]);
+    }
+
+    /// Backend with one Firefox stream on the Game sink
+    struct StreamBackend {
+        input: Mutex<SinkInput>,
+        mutes: Arc<Mutex<HashMap<Node, bool>>>,
+    }
+
+    #[async_trait]
+    impl PipeWireBackend for StreamBackend {
+        async fn set_volume(&self, _node: Node, _percent: u32) -> Result<()> {
+            Ok(())
+        }
+
+        async fn set_mute(&self, node: Node, muted: bool) -> Result<()> {
+            self.mutes.lock().unwrap().insert(node, muted);
+            Ok(())
+        }
+
+        async fn move_sink_input(&self, _sink_input_id: u32, sink_name: &str) -> Result<()> {
+            let sink = self.list_sinks().await?.into_iter().find(|sink| sink.name == sink_name);
+            self.input.lock().unwrap().sink = sink.map(|sink| sink.id);
+            Ok(())
+        }
+
+        async fn list_sink_inputs(&self) -> Result<Vec<SinkInput>> {
+            Ok(vec![self.input.lock().unwrap().clone()])
+        }
+
+        async fn combine_sinks(
+            &self,
+            _sink_name: &str,
+            _targets: &[String],
+        ) -> Result<Option<u32>> {
+            Ok(None)
+        }
+
+        async fn list_sinks(&self) -> Result<Vec<SinkEntry>> {
+            Ok(vec![
+                SinkEntry { id: 56, name: "Game".to_string() },
+                SinkEntry { id: 57, name: "Media".to_string() },
+            ])
+        }
+
+        async fn create_virtual_sink(
+            &self,
+            _sink_name: &str,
+            _description: &str,
+            _loopback_name: &str,
+        ) -> Result<Vec<u32>> {
+            Ok(vec![])
+        }
+
+        async fn unload_module(&self, _module_id: u32) -> Result<()> {
+            Ok(())
+        }
+
+        async fn server_version(&self) -> Result<String> {
+            Ok("1.0.0".to_string())
+        }
+
+        async fn default_sink(&self) -> Result<String> {
+            Ok("Game".to_string())
+        }
+    }
+
+    fn firefox_backend() -> StreamBackend {
+        StreamBackend {
+            input: Mutex::new(SinkInput {
+                id: 71,
+                sink: Some(56),
+                volume: None,
+                corked: false,
+                properties: HashMap::from([(
+                    "application.name".to_string(),
+                    "Firefox".to_string(),
+                )]),
+            }),
+            mutes: Arc::default(),
+        }
+    }
+
+    fn sink(name: &str, id: u32) -> SinkInfo {
+        SinkInfo {
+            id,
+            name: name.to_string(),
+            volume: 1.0,
+            muted: false,
+            pipewire_id: id,
+            applied_percent: 100,
+            ..Default::default()
+        }
+    }
+
+    /// Updates the monitor sends when Firefox starts playing on the Game sink
+    fn firefox_appears() -> Vec<CacheUpdate> {
+        firefox_appears_as(71)
+    }
+
+    /// [`firefox_appears`] for a stream whose node has the serial `serial`
+    fn firefox_appears_as(serial: u32) -> Vec<CacheUpdate> {
+        vec![
+            CacheUpdate::UpdateSink("Game".to_string(), sink("Game", 56)),
+            CacheUpdate::UpdateSink("Media".to_string(), sink("Media", 57)),
+            CacheUpdate::AddSinkInputToApp(firefox_stream(serial)),
+        ]
+    }
+
+    /// A Firefox stream on `Game`, for tests to vary field by field
+    fn firefox_stream(sink_input_id: u32) -> NewStream {
+        NewStream {
+            app_key: "Firefox".to_string(),
+            display_name: "Firefox".to_string(),
+            binary_name: "firefox".to_string(),
+            stream_name: "Firefox".to_string(),
+            sink_input_id,
+            current_sink: "Game".to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Run the worker over `updates` until they are all applied
+    async fn apply_updates(
+        cache: &Arc<RwLock<AudioCache>>,
+        controller: Arc<PipeWireController>,
+        updates: Vec<CacheUpdate>,
+    ) {
+        apply_updates_with(cache, controller, Config::default().routing, updates).await;
+    }
+
+    /// [`apply_updates`] with the given routing settings
+    async fn apply_updates_with(
+        cache: &Arc<RwLock<AudioCache>>,
+        controller: Arc<PipeWireController>,
+        routing: RoutingConfig,
+        updates: Vec<CacheUpdate>,
+    ) {
+        let (cache_tx, cache_rx) = mpsc::unbounded_channel();
+        let worker = tokio::spawn(run_cache_worker(cache.clone(), controller, routing, cache_rx));
+        for update in updates {
+            cache_tx.send(update).unwrap();
+        }
+
+        // Dropping the last sender stops the worker
+        drop(cache_tx);
+        tokio::time::timeout(Duration::from_secs(1), worker).await.unwrap().unwrap();
+    }
+
+    async fn wait_for_sink(cache: &Arc<RwLock<AudioCache>>, app_name: &str, sink_name: &str) {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        loop {
+            let current_sink = cache.read().await.apps.get(app_name).unwrap().current_sink.clone();
+            if current_sink == sink_name {
+                return;
+            }
+            assert!(tokio::time::Instant::now() < deadline, "{app_name} was never routed");
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cache_worker_routes_on_the_calling_runtime() {
+        let cache = Arc::new(RwLock::new(AudioCache::new()));
+        cache.read().await.routing_rules.insert("Firefox".to_string(), "Media".to_string());
+        let controller =
+            Arc::new(PipeWireController::with_backend(cache.clone(), Box::new(firefox_backend())));
+
+        let mut updates = firefox_appears();
+        updates.push(CacheUpdate::CheckRoutingRule("Firefox".to_string(), 71));
+        apply_updates(&cache, controller, updates).await;
+
+        // The routing task it spawned still runs on this runtime
+        wait_for_sink(&cache, "Firefox", "Media").await;
+    }
+
+    #[tokio::test]
+    async fn test_auto_route_matches_manual_route() {
+        // Auto-routing by rule
+        let auto_cache = Arc::new(RwLock::new(AudioCache::new()));
+        auto_cache.read().await.routing_rules.insert("Firefox".to_string(), "Media".to_string());
+        let controller = Arc::new(PipeWireController::with_backend(
+            auto_cache.clone(),
+            Box::new(firefox_backend()),
+        ));
+        let mut updates = firefox_appears();
+        updates.push(CacheUpdate::CheckRoutingRule("Firefox".to_string(), 71));
+        apply_updates(&auto_cache, controller, updates).await;
+        wait_for_sink(&auto_cache, "Firefox", "Media").await;
+
+        // The same app routed by hand, as the D-Bus RouteApplication method does
+        let manual_cache = Arc::new(RwLock::new(AudioCache::new()));
+        let controller = Arc::new(PipeWireController::with_backend(
+            manual_cache.clone(),
+            Box::new(firefox_backend()),
+        ));
+        apply_updates(&manual_cache, controller.clone(), firefox_appears()).await;
+        controller.route_app("Firefox", "Media").await.unwrap();
+        manual_cache.read().await.routing_rules.insert("Firefox".to_string(), "Media".to_string());
+
+        let state = |cache: &AudioCache| {
+            let app = cache.apps.get("Firefox").unwrap().clone();
+            let rules: BTreeMap<String, String> =
+                cache.routing_rules.iter().map(|r| (r.key().clone(), r.value().clone())).collect();
+            let remembered: BTreeMap<String, String> = cache
+                .remembered_apps
+                .iter()
+                .map(|r| (r.key().clone(), r.value().clone()))
+                .collect();
+            (
+                app.current_sink,
+                app.sink_input_ids,
+                rules,
+                remembered,
+                cache.apps_for_sink("Media"),
+                cache.apps_for_sink("Game"),
+            )
+        };
+        assert_eq!(state(&*auto_cache.read().await), state(&*manual_cache.read().await));
+    }
+
+    #[tokio::test]
+    async fn test_routed_app_keeps_stream_serials() {
+        // pactl lists the stream as 71, PipeWire knows its node by serial 171
+        let backend = firefox_backend();
+        backend
+            .input
+            .lock()
+            .unwrap()
+            .properties
+            .insert("object.serial".to_string(), "171".to_string());
+        let cache = Arc::new(RwLock::new(AudioCache::new()));
+        let controller =
+            Arc::new(PipeWireController::with_backend(cache.clone(), Box::new(backend)));
+        apply_updates(&cache, controller.clone(), firefox_appears_as(171)).await;
+
+        controller.route_app("Firefox", "Media").await.unwrap();
+        {
+            let cache = cache.read().await;
+            let app = cache.apps.get("Firefox").unwrap();
+            assert_eq!(
+                (app.current_sink.as_str(), app.sink_input_ids.clone()),
+                ("Media", vec![171])
+            );
+        }
+
+        // The monitor reports the stream gone by its serial
+        apply_updates(&cache, controller, vec![CacheUpdate::MarkAppInactive(171)]).await;
+        let app = cache.read().await.apps.get("Firefox").unwrap().clone();
+        assert!(!app.active);
+        assert!(app.sink_input_ids.is_empty());
+    }
+
+    /// Wait for the spawned auto-mute to leave `app_name` muted or unmuted
+    async fn wait_for_app_mute(cache: &Arc<RwLock<AudioCache>>, app_name: &str, muted: bool) {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(5);
+        while cache.read().await.apps.get(app_name).unwrap().muted != muted {
+            assert!(tokio::time::Instant::now() < deadline, "{app_name} never had muted={muted}");
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cache_worker_auto_mutes_idle_apps() {
+        let backend = firefox_backend();
+        let mutes = backend.mutes.clone();
+        let cache = Arc::new(RwLock::new(AudioCache::new()));
+        let controller =
+            Arc::new(PipeWireController::with_backend(cache.clone(), Box::new(backend)));
+        let mut routing = Config::default().routing;
+        routing.auto_mute_on_inactive = vec!["firefox".to_string()];
+        let apply =
+            |updates| apply_updates_with(&cache, controller.clone(), routing.clone(), updates);
+
+        apply(firefox_appears()).await;
+        assert!(mutes.lock().unwrap().is_empty());
+
+        // Pausing its only stream leaves the app idle
+        apply(vec![CacheUpdate::SetStreamCorked(71, true)]).await;
+        wait_for_app_mute(&cache, "Firefox", true).await;
+        assert_eq!(mutes.lock().unwrap().get(&Node::SinkInput(71)), Some(&true));
+
+        apply(vec![CacheUpdate::SetStreamCorked(71, false)]).await;
+        wait_for_app_mute(&cache, "Firefox", false).await;
+        assert_eq!(mutes.lock().unwrap().get(&Node::SinkInput(71)), Some(&false));
+
+        // An app that goes away while auto-muted comes back unmuted, with nothing to undo
+        apply(vec![CacheUpdate::SetStreamCorked(71, true)]).await;
+        wait_for_app_mute(&cache, "Firefox", true).await;
+        apply(vec![CacheUpdate::MarkAppInactive(71)]).await;
+        assert!(!cache.read().await.apps.get("Firefox").unwrap().muted);
+        mutes.lock().unwrap().clear();
+        apply(firefox_appears()).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(mutes.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_cache_worker_auto_mute_leaves_other_mutes_alone() {
+        let backend = firefox_backend();
+        let mutes = backend.mutes.clone();
+        let cache = Arc::new(RwLock::new(AudioCache::new()));
+        let controller =
+            Arc::new(PipeWireController::with_backend(cache.clone(), Box::new(backend)));
+        let mut routing = Config::default().routing;
+        routing.auto_mute_on_inactive = vec!["Discord".to_string()];
+        let apply =
+            |updates| apply_updates_with(&cache, controller.clone(), routing.clone(), updates);
+
+        // Firefox isn't configured, so pausing it changes nothing
+        apply(firefox_appears()).await;
+        apply(vec![CacheUpdate::SetStreamCorked(71, true)]).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(mutes.lock().unwrap().is_empty());
+
+        // An app muted by hand stays muted when it plays again
+        routing.auto_mute_on_inactive = vec!["Firefox".to_string()];
+        let apply =
+            |updates| apply_updates_with(&cache, controller.clone(), routing.clone(), updates);
+        controller.set_app_mute("Firefox", true).await.unwrap();
+        apply(vec![CacheUpdate::SetStreamCorked(71, false)]).await;
+        apply(vec![CacheUpdate::SetStreamCorked(71, true)]).await;
+        apply(vec![CacheUpdate::SetStreamCorked(71, false)]).await;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(mutes.lock().unwrap().get(&Node::SinkInput(71)), Some(&true));
+        assert!(cache.read().await.apps.get("Firefox").unwrap().muted);
+    }
+
+    #[tokio::test]
+    async fn test_cache_worker_keeps_window_title_for_rules() {
+        let cache = Arc::new(RwLock::new(AudioCache::new()));
+        let controller =
+            Arc::new(PipeWireController::with_backend(cache.clone(), Box::new(firefox_backend())));
+        cache
+            .read()
+            .await
+            .routing_rules
+            .insert("Elite - Dangerous".to_string(), "Game".to_string());
+
+        // Audio starts before the game's window exists, so the key is the Wine loader
+        let stream = |sink_input_id, window_title: Option<&str>| {
+            CacheUpdate::AddSinkInputToApp(NewStream {
+                app_key: "Wine64-preloader".to_string(),
+                display_name: "Wine64-preloader".to_string(),
+                binary_name: "wine64-preloader".to_string(),
+                stream_name: "wine64-preloader".to_string(),
+                current_sink: "Media".to_string(),
+                window_title: window_title.map(str::to_string),
+                ..firefox_stream(sink_input_id)
+            })
+        };
+        let updates =
+            vec![stream(81, None), stream(82, Some("Elite - Dangerous")), stream(83, None)];
+        apply_updates(&cache, controller, updates).await;
+
+        let cache = cache.read().await;
+        let app = cache.apps.get("Wine64-preloader").unwrap().clone();
+        // A stream without a window doesn't clear the title another one found
+        assert_eq!(app.window_title.as_deref(), Some("Elite - Dangerous"));
+        let routing = Config::default().routing;
+        assert_eq!(cache.auto_route_target("Wine64-preloader", &routing).as_deref(), Some("Game"));
+    }
+
+    #[tokio::test]
+    async fn test_cache_worker_labels_streams_by_media_name() {
+        let cache = Arc::new(RwLock::new(AudioCache::new()));
+        let controller =
+            Arc::new(PipeWireController::with_backend(cache.clone(), Box::new(firefox_backend())));
+
+        let tab = |sink_input_id, label: &str| {
+            CacheUpdate::AddSinkInputToApp(NewStream {
+                stream_label: Some(label.to_string()),
+                ..firefox_stream(sink_input_id)
+            })
+        };
+        let updates = vec![tab(71, "Podcast"), tab(72, "Music video"), tab(71, "Next episode")];
+        apply_updates(&cache, controller.clone(), updates).await;
+        {
+            let cache = cache.read().await;
+            let app = cache.apps.get("Firefox").unwrap();
+            // Both tabs stay grouped under one app key
+            assert_eq!(cache.apps.len(), 1);
+            assert_eq!(app.sink_input_ids, vec![71, 72]);
+            assert_eq!(
+                app.stream_labels,
+                vec![(72, "Music video".to_string()), (71, "Next episode".to_string())]
+            );
+        }
+
+        apply_updates(&cache, controller, vec![CacheUpdate::MarkAppInactive(71)]).await;
+        let cache = cache.read().await;
+        let app = cache.apps.get("Firefox").unwrap();
+        assert_eq!(app.stream_labels, vec![(72, "Music video".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn test_cache_worker_seeds_app_volume_from_streams() {
+        let cache = Arc::new(RwLock::new(AudioCache::new()));
+        let controller =
+            Arc::new(PipeWireController::with_backend(cache.clone(), Box::new(firefox_backend())));
+
+        let stream = |sink_input_id, stream_volume| {
+            CacheUpdate::AddSinkInputToApp(NewStream {
+                stream_volume,
+                ..firefox_stream(sink_input_id)
+            })
+        };
+        apply_updates(&cache, controller.clone(), vec![stream(71, Some(0.4))]).await;
+        assert_eq!(cache.read().await.apps.get("Firefox").unwrap().volume, Some(0.4));
+
+        // Several streams average out; one without a volume doesn't count
+        let updates = vec![stream(72, Some(0.8)), stream(73, None)];
+        apply_updates(&cache, controller.clone(), updates).await;
+        let volume = cache.read().await.apps.get("Firefox").unwrap().volume.unwrap();
+        assert!((volume - 0.6).abs() < 1e-6);
+
+        apply_updates(&cache, controller.clone(), vec![CacheUpdate::MarkAppInactive(71)]).await;
+        assert_eq!(cache.read().await.apps.get("Firefox").unwrap().volume, Some(0.8));
+        apply_updates(&cache, controller, vec![CacheUpdate::MarkAppInactive(72)]).await;
+        assert_eq!(cache.read().await.apps.get("Firefox").unwrap().volume, Some(0.8));
+    }
+
+    #[tokio::test]
+    async fn test_cache_worker_tracks_corked_streams() {
+        let cache = Arc::new(RwLock::new(AudioCache::new()));
+        let controller =
+            Arc::new(PipeWireController::with_backend(cache.clone(), Box::new(firefox_backend())));
+
+        let stream = |sink_input_id, corked| {
+            CacheUpdate::AddSinkInputToApp(NewStream { corked, ..firefox_stream(sink_input_id) })
+        };
+        let playing = |cache: &AudioCache| cache.apps.get("Firefox").unwrap().playing;
+
+        // A paused tab is an active stream that plays nothing
+        apply_updates(&cache, controller.clone(), vec![stream(71, true)]).await;
+        let cache_read = cache.read().await;
+        assert!(cache_read.apps.get("Firefox").unwrap().active);
+        assert!(!playing(&cache_read));
+        drop(cache_read);
+
+        // Any running stream makes the app play
+        apply_updates(&cache, controller.clone(), vec![stream(72, false)]).await;
+        assert!(playing(&*cache.read().await));
+        let updates = vec![CacheUpdate::SetStreamCorked(72, true)];
+        apply_updates(&cache, controller.clone(), updates).await;
+        assert!(!playing(&*cache.read().await));
+        let updates = vec![CacheUpdate::SetStreamCorked(71, false)];
+        apply_updates(&cache, controller.clone(), updates).await;
+        assert!(playing(&*cache.read().await));
+
+        let updates = vec![CacheUpdate::MarkAppInactive(71), CacheUpdate::MarkAppInactive(72)];
+        apply_updates(&cache, controller, updates).await;
+        assert!(!playing(&*cache.read().await));
+    }
+
+    #[tokio::test]
+    async fn test_cache_worker_follows_renamed_streams() {
+        let cache = Arc::new(RwLock::new(AudioCache::new()));
+        let controller =
+            Arc::new(PipeWireController::with_backend(cache.clone(), Box::new(firefox_backend())));
+
+        let stream = |app_key: &str, sink_input_id| {
+            CacheUpdate::AddSinkInputToApp(NewStream {
+                app_key: app_key.to_string(),
+                display_name: app_key.to_string(),
+                binary_name: "chromium".to_string(),
+                stream_name: app_key.to_string(),
+                stream_volume: Some(0.5),
+                ..firefox_stream(sink_input_id)
+            })
+        };
+        // Chromium names its stream only after it has been created
+        let updates = vec![stream("Chromium", 71), stream("YouTube Music", 71)];
+        apply_updates(&cache, controller.clone(), updates).await;
+        {
+            let cache = cache.read().await;
+            assert_eq!(cache.apps.len(), 1);
+            let app = cache.apps.get("YouTube Music").unwrap();
+            assert_eq!(app.sink_input_ids, vec![71]);
+            assert_eq!(app.volume, Some(0.5));
+            assert_eq!(cache.apps_for_sink("Game"), vec!["YouTube Music".to_string()]);
+        }
+
+        // Renaming one of several streams leaves the others where they were
+        let updates = vec![stream("YouTube Music", 72), stream("Podcast", 72)];
+        apply_updates(&cache, controller.clone(), updates).await;
+        {
+            let cache = cache.read().await;
+            assert_eq!(cache.apps.get("YouTube Music").unwrap().sink_input_ids, vec![71]);
+            assert_eq!(cache.apps.get("Podcast").unwrap().sink_input_ids, vec![72]);
+        }
+
+        // The stream going away afterwards marks the app of its new name inactive
+        apply_updates(&cache, controller, vec![CacheUpdate::MarkAppInactive(72)]).await;
+        let cache = cache.read().await;
+        assert!(!cache.apps.get("Podcast").unwrap().active);
+        assert!(cache.apps.get("YouTube Music").unwrap().active);
+    }
+
+    fn listed_sink(id: u32, name: &str, volume: f32) -> Sink {
+        Sink { id, name: name.to_string(), description: None, volume: Some(volume), muted: false }
+    }
+
+    fn listed_input(id: u32, sink: u32, corked: bool, properties: &[(&str, &str)]) -> SinkInput {
+        SinkInput {
+            id,
+            sink: Some(sink),
+            volume: Some(0.5),
+            corked,
+            properties: properties.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pactl_poll_feeds_the_cache() {
+        let cache = Arc::new(RwLock::new(AudioCache::new()));
+        let controller =
+            Arc::new(PipeWireController::with_backend(cache.clone(), Box::new(firefox_backend())));
+        let config = Config::default();
+        let mut poll = PactlPoll::default();
+
+        let mut speaker = listed_sink(60, "alsa_output.usb-headset", 1.0);
+        speaker.description = Some("USB Headset".to_string());
+        let combined = listed_sink(61, "combined_Game_Media", 1.0);
+        let sinks =
+            vec![listed_sink(56, "Game", 0.75), listed_sink(57, "Media", 1.0), speaker, combined];
+        let firefox = |corked| {
+            listed_input(
+                7,
+                56,
+                corked,
+                &[
+                    ("application.name", "Firefox"),
+                    ("application.process.binary", "/usr/lib/firefox/firefox-bin"),
+                    ("media.name", "Lo-fi beats - YouTube"),
+                    ("object.serial", "171"),
+                ],
+            )
+        };
+        let loopback = listed_input(8, 60, false, &[("node.name", "Game_to_Speaker")]);
+
+        let updates = poll.updates(&config, &sinks, &[firefox(false), loopback.clone()]);
+        apply_updates(&cache, controller.clone(), updates).await;
+        {
+            let cache = cache.read().await;
+            assert!(cache.is_ready());
+            let game = cache.sinks.get("Game").unwrap().clone();
+            assert_eq!((game.pipewire_id, game.volume, game.applied_percent), (56, 0.75, 75));
+            assert_eq!(
+                cache.physical_sinks.get("alsa_output.usb-headset").unwrap().as_str(),
+                "USB Headset"
+            );
+            // Combine sinks of apps duplicated to several sinks aren't devices
+            assert_eq!(cache.physical_sinks.len(), 1);
+            // Loopbacks aren't apps
+            assert_eq!(cache.apps.len(), 1);
+            let app = cache.apps.get("Firefox").unwrap().clone();
+            assert_eq!((app.binary_name.as_str(), app.current_sink.as_str()), ("firefox", "Game"));
+            assert_eq!(app.sink_input_ids, vec![171]);
+            assert_eq!(app.volume, Some(0.5));
+            assert!(app.active && app.playing);
+        }
+
+        // A poll that finds nothing new changes nothing
+        assert!(poll
+            .updates(&config, &sinks, &[firefox(false), loopback.clone()])
+            .iter()
+            .all(|update| matches!(update, CacheUpdate::UpdateSink(..))));
+
+        let updates = poll.updates(&config, &sinks, &[firefox(true), loopback]);
+        apply_updates(&cache, controller.clone(), updates).await;
+        assert!(!cache.read().await.apps.get("Firefox").unwrap().playing);
+
+        // The stream and the headset going away are noticed on the next poll
+        let updates = poll.updates(&config, &sinks[..2], &[]);
+        apply_updates(&cache, controller, updates).await;
+        let cache = cache.read().await;
+        assert!(!cache.apps.get("Firefox").unwrap().active);
+        assert!(cache.physical_sinks.is_empty());
+        assert!(cache.sinks.contains_key("Game"));
+    }
+
+    #[test]
+    fn test_loopbacks_are_recognized_by_the_configured_suffixes() {
+        let suffixes = vec!["_to_Headphones".to_string()];
+        assert!(is_loopback("Game_to_Headphones", None, &suffixes));
+        assert!(!is_loopback("Game_to_Speaker", None, &suffixes));
+        // An app whose node name merely contains "_to_" is still an app
+        assert!(!is_loopback("back_to_the_future", None, &suffixes));
+        assert!(is_loopback("loopback-1234-13", Some("Loopback of Game"), &suffixes));
+    }
+
+    #[test]
+    fn test_pactl_poll_finds_virtual_sinks_by_description() {
+        let config = Config { match_sinks_by: SinkMatch::Description, ..Config::default() };
+        let mut poll = PactlPoll::default();
+
+        let mut game = listed_sink(56, "output.null-sink.3", 0.5);
+        game.description = Some("Game".to_string());
+        let firefox = listed_input(71, 56, false, &[("application.name", "Firefox")]);
+        let updates = poll.updates(&config, &[game], &[firefox]);
+        let sink = updates.iter().find_map(|update| match update {
+            CacheUpdate::UpdateSink(name, info) => {
+                Some((name.clone(), info.pipewire_id, info.pactl_name().to_string()))
+            }
+            _ => None,
+        });
+        // Known by its configured name, not the generated one pactl still needs
+        assert_eq!(sink, Some(("Game".to_string(), 56, "output.null-sink.3".to_string())));
+        let current_sink = updates.iter().find_map(|update| match update {
+            CacheUpdate::AddSinkInputToApp(stream) => Some(stream.current_sink.clone()),
+            _ => None,
+        });
+        assert_eq!(current_sink.as_deref(), Some("Game"));
+
+        // Gone again, it's removed under the same name
+        let updates = poll.updates(&config, &[], &[]);
+        assert!(updates
+            .iter()
+            .any(|update| matches!(update, CacheUpdate::RemoveSink(name) if name == "Game")));
+    }
+
+    #[tokio::test]
+    async fn test_default_sink_follows_the_default_metadata() {
+        assert_eq!(
+            default_sink_name(r#"{"name":"alsa_output.usb"}"#).as_deref(),
+            Some("alsa_output.usb")
+        );
+        assert_eq!(default_sink_name(r#"{"name":""}"#), None);
+        assert_eq!(default_sink_name("alsa_output.usb"), None);
+
+        let cache = Arc::new(RwLock::new(AudioCache::new()));
+        let controller =
+            Arc::new(PipeWireController::with_backend(cache.clone(), Box::new(firefox_backend())));
+        let set = |name: &str| CacheUpdate::SetDefaultSink(name.to_string());
+        apply_updates(&cache, controller.clone(), vec![set("alsa_output.usb")]).await;
+        let generation = cache.read().await.get_generation();
+        assert_eq!(cache.read().await.default_sink().as_deref(), Some("alsa_output.usb"));
+
+        // Only a change is news to clients
+        apply_updates(&cache, controller.clone(), vec![set("alsa_output.usb")]).await;
+        assert_eq!(cache.read().await.get_generation(), generation);
+        apply_updates(&cache, controller, vec![set("bluez_output.headset")]).await;
+        assert_eq!(cache.read().await.default_sink().as_deref(), Some("bluez_output.headset"));
+        assert!(cache.read().await.get_generation() > generation);
+    }
+
+    /// Answers the monitor's commands with canned output and records each one
+    #[derive(Default)]
+    struct CannedExecutor {
+        calls: Mutex<Vec<String>>,
+    }
+
+    impl CommandExecutor for CannedExecutor {
+        fn execute(&self, program: &str, args: &[&str]) -> std::io::Result<std::process::Output> {
+            let call = format!("{program} {}", args.join(" "));
+            let stdout = match call.as_str() {
+                "pactl get-default-sink" => "alsa_output.usb-headset\n",
+                "wpctl get-volume 56" => "Volume: 0.75 [MUTED]\n",
+                _ => "",
+            };
+            self.calls.lock().unwrap().push(call);
+            Ok(std::process::Output {
+                status: std::os::unix::process::ExitStatusExt::from_raw(0),
+                stdout: stdout.as_bytes().to_vec(),
+                stderr: Vec::new(),
+            })
+        }
+
+        fn execute_shell(&self, cmd: &str) -> std::io::Result<std::process::Output> {
+            self.execute("sh", &["-c", cmd])
+        }
+    }
+
+    #[test]
+    fn test_commands_run_through_the_executor() {
+        let executor = CannedExecutor::default();
+        assert_eq!(get_default_sink(&executor).as_deref(), Some("alsa_output.usb-headset"));
+        assert_eq!(wpctl_volume(&executor, 56), Some((0.75, true)));
+        assert_eq!(list_sink_inputs(&executor), Some(Vec::new()));
+        assert_eq!(
+            *executor.calls.lock().unwrap(),
+            ["pactl get-default-sink", "wpctl get-volume 56", "pactl list sink-inputs"]
+        );
+    }
+
+    #[test]
+    fn test_graph_dump_shows_tracked_nodes_and_their_apps() {
+        let cache = AudioCache::new();
+        let (mut state, _cache_rx) = monitor_state();
+        state.graph_nodes = cache.graph_nodes();
+        let start = Instant::now();
+        track_node(&mut state, 40, node(140, start));
+        track_node(&mut state, 41, NodeInfo { app_name: None, serial_id: 141, added: start });
+        track_node(&mut state, 42, node(142, start));
+        forget_node(&mut state, 42);
+        cache.update_app(
+            "Firefox".to_string(),
+            AppInfo {
+                display_name: "Firefox".to_string(),
+                binary_name: "firefox".to_string(),
+                stream_names: vec!["App140".to_string()],
+                current_sink: "Game".to_string(),
+                active: true,
+                playing: true,
+                sink_input_ids: vec![140],
+                pipewire_id: 40,
+                ..Default::default()
+            },
+        );
+        cache.add_physical_sink("alsa_output.usb-headset", "USB Headset");
+
+        let dump = serde_json::to_value(GraphDump::from_cache(&cache)).unwrap();
+        assert_eq!(
+            dump["nodes"],
+            serde_json::json!([
+                {"id": 40, "serial": 140, "app_name": "App140", "cache_app": "Firefox"},
+                {"id": 41, "serial": 141, "app_name": null, "cache_app": null},
+            ])
+        );
+        assert_eq!(dump["apps"]["Firefox"]["current_sink"], "Game");
+        assert_eq!(dump["physical_sinks"]["alsa_output.usb-headset"], "USB Headset");
+        assert_eq!(dump["generation"], cache.get_generation());
+    }
+
+    #[test]
+    fn test_sink_volume_lookups_share_one_worker() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let threads = Arc::new(Mutex::new(HashSet::new()));
+        let lookups = VolumeLookups::spawn(CacheSender::new(tx, Arc::default()), {
+            let threads = threads.clone();
+            move |sink_id| {
+                threads.lock().unwrap().insert(std::thread::current().id());
+                (sink_id != 58).then_some((0.5, sink_id == 57))
+            }
+        });
+        let caller = std::thread::current().id();
+
+        for (sink_id, name) in [(56, "Game"), (57, "Chat"), (58, "Media"), (56, "Game")] {
+            lookups.queue(sink(name, sink_id));
+        }
+        drop(lookups);
+
+        let mut found = Vec::new();
+        while let Some(update) = rx.blocking_recv() {
+            if let CacheUpdate::UpdateSink(name, info) = update {
+                found.push((name, info.volume, info.muted));
+            }
+        }
+        // In order, skipping the sink whose volume couldn't be read
+        assert_eq!(
+            found,
+            vec![
+                ("Game".to_string(), 0.5, false),
+                ("Chat".to_string(), 0.5, true),
+                ("Game".to_string(), 0.5, false),
+            ]
+        );
+        let threads = threads.lock().unwrap();
+        assert_eq!(threads.len(), 1);
+        assert!(!threads.contains(&caller));
+    }
+}
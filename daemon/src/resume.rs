@@ -0,0 +1,86 @@
+use anyhow::Result;
+use futures_util::stream::BoxStream;
+use futures_util::{Stream, StreamExt};
+use std::sync::Arc;
+use tracing::{debug, error, info, warn};
+use zbus::{Connection, Proxy};
+
+use crate::config::RoutingConfig;
+use crate::pipewire_controller::PipeWireController;
+
+/// Rescans PipeWire and reapplies routing rules each time the system wakes up
+///
+/// PipeWire can give nodes new ids across a suspend and move streams back to the
+/// default output, which is exactly when the cache goes stale.
+pub struct ResumeWatcher {
+    controller: Arc<PipeWireController>,
+    routing: RoutingConfig,
+}
+
+impl ResumeWatcher {
+    pub fn new(controller: Arc<PipeWireController>, routing: RoutingConfig) -> Self {
+        Self { controller, routing }
+    }
+
+    /// Watch logind's PrepareForSleep signal until the task is aborted
+    pub async fn run(self) {
+        let signals = match prepare_for_sleep_signals().await {
+            Ok(signals) => signals,
+            Err(e) => {
+                warn!("Not watching for resume, could not subscribe to logind: {}", e);
+                return;
+            }
+        };
+        info!("Rescanning PipeWire whenever the system resumes");
+        self.watch(signals).await;
+    }
+
+    /// Handle PrepareForSleep arguments from `signals`: `true` before the system goes to
+    /// sleep, `false` once it has woken up
+    pub async fn watch<S: Stream<Item = bool> + Unpin>(&self, mut signals: S) {
+        while let Some(going_to_sleep) = signals.next().await {
+            if going_to_sleep {
+                debug!("System is going to sleep");
+                continue;
+            }
+            if let Err(e) = self.resumed().await {
+                error!("Failed to rescan PipeWire after resume: {}", e);
+            }
+        }
+    }
+
+    /// Rebuild the cache and route apps back to where their rules put them
+    async fn resumed(&self) -> Result<()> {
+        let (sinks, streams) = self.controller.rescan().await?;
+        let moved = self.controller.reapply_routing(&self.routing).await;
+        info!(
+            "System resumed: found {} sinks and {} streams, moved {} apps",
+            sinks, streams, moved
+        );
+        Ok(())
+    }
+}
+
+/// The argument of each PrepareForSleep signal logind sends on the system bus
+async fn prepare_for_sleep_signals() -> Result<BoxStream<'static, bool>> {
+    let connection = Connection::system().await?;
+    let proxy = Proxy::new(
+        &connection,
+        "org.freedesktop.login1",
+        "/org/freedesktop/login1",
+        "org.freedesktop.login1.Manager",
+    )
+    .await?;
+    let signals = proxy.receive_signal("PrepareForSleep").await?;
+    Ok(signals
+        .filter_map(|message| async move {
+            match message.body::<bool>() {
+                Ok(going_to_sleep) => Some(going_to_sleep),
+                Err(e) => {
+                    debug!("Ignoring malformed PrepareForSleep signal: {}", e);
+                    None
+                }
+            }
+        })
+        .boxed())
+}
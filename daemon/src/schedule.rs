@@ -0,0 +1,95 @@
+use std::time::Duration;
+
+/// Polling interval that backs off while nothing changes and snaps back on activity
+///
+/// Every tick reports whether anything changed since the previous one. A change
+/// resets the interval to `min`. Once `idle_ticks` quiet ticks have passed in a row,
+/// each further quiet tick doubles the interval, up to `max`.
+#[derive(Debug, Clone)]
+pub struct AdaptiveInterval {
+    min: Duration,
+    max: Duration,
+    idle_ticks: u32,
+    current: Duration,
+    quiet_ticks: u32,
+}
+
+impl AdaptiveInterval {
+    /// A `max` below `min` is raised to `min`, giving a fixed interval
+    pub fn new(min: Duration, max: Duration, idle_ticks: u32) -> Self {
+        Self { min, max: max.max(min), idle_ticks, current: min, quiet_ticks: 0 }
+    }
+
+    /// How long to wait before the next tick
+    pub fn current(&self) -> Duration {
+        self.current
+    }
+
+    /// Record the outcome of a tick, returning the interval until the next one
+    pub fn tick(&mut self, changed: bool) -> Duration {
+        if changed {
+            self.reset();
+        } else {
+            self.quiet_ticks = self.quiet_ticks.saturating_add(1);
+            if self.quiet_ticks > self.idle_ticks {
+                self.current = (self.current * 2).min(self.max);
+            }
+        }
+        self.current
+    }
+
+    /// Go back to the shortest interval, e.g. after being resumed
+    pub fn reset(&mut self) {
+        self.current = self.min;
+        self.quiet_ticks = 0;
+    }
+}
+
+/// Delay between retries of something that keeps failing
+///
+/// The first failure waits `base`, each further one in a row twice as long as the
+/// one before, up to `max`. A success resets it.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    base: Duration,
+    max: Duration,
+    failures: u32,
+}
+
+impl Backoff {
+    /// A `max` below `base` is raised to `base`, giving a fixed delay
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self { base, max: max.max(base), failures: 0 }
+    }
+
+    /// Delay after `failures` failures in a row, none before the first
+    pub fn delay(&self, failures: u32) -> Duration {
+        if failures == 0 {
+            return Duration::ZERO;
+        }
+        let factor = 1u32.checked_shl(failures - 1).unwrap_or(u32::MAX);
+        self.base.saturating_mul(factor).min(self.max)
+    }
+
+    /// Record a failure, returning how long to wait before trying again
+    pub fn fail(&mut self) -> Duration {
+        self.failures = self.failures.saturating_add(1);
+        self.delay(self.failures)
+    }
+
+    /// Failures in a row so far
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Record a success
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+}
+
+/// Whether the `failures`-th failure in a row is worth logging: the first, then
+/// every time the count doubles
+pub fn should_log_failure(failures: u32) -> bool {
+    failures.is_power_of_two()
+}
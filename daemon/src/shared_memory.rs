@@ -0,0 +1,546 @@
+use anyhow::{bail, Context, Result};
+use memmap2::{Mmap, MmapMut};
+use nix::unistd::Uid;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::os::fd::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tracing::{debug, error, info, warn};
+
+use crate::cache::{AudioCache, CacheSnapshot};
+use crate::latency::Operation;
+use crate::schedule::{should_log_failure, AdaptiveInterval, Backoff};
+
+/// Total size of the shared memory region
+pub const SHM_SIZE: usize = 64 * 1024;
+
+/// Size of the fixed header at the start of the region
+pub const HEADER_SIZE: usize = 32;
+
+/// Format version written into the header
+/// Version 2 added the payload length and CRC32 fields, version 3 double-buffered slots
+pub const FORMAT_VERSION: u32 = 3;
+
+/// Size of each of the two snapshot slots following the header
+pub const SLOT_SIZE: usize = (SHM_SIZE - HEADER_SIZE) / 2;
+
+/// Size of the per-slot header preceding each payload
+pub const SLOT_HEADER_SIZE: usize = 32;
+
+// Global header layout (all little-endian):
+//   0..4   version
+//   4..8   reserved
+//   8..16  sequence number, the active slot is `sequence % 2`
+//  16..32  reserved
+//
+// Slot header layout, relative to the start of the slot:
+//   0..8   generation
+//   8..16  timestamp (ms since epoch)
+//  16..20  payload length
+//  20..24  CRC32 of the payload
+//  24..32  reserved
+const OFFSET_VERSION: usize = 0;
+const OFFSET_SEQUENCE: usize = 8;
+const SLOT_OFFSET_GENERATION: usize = 0;
+const SLOT_OFFSET_TIMESTAMP: usize = 8;
+const SLOT_OFFSET_PAYLOAD_LEN: usize = 16;
+const SLOT_OFFSET_CHECKSUM: usize = 20;
+
+/// Write interval while the cache is changing
+const FAST_INTERVAL: Duration = Duration::from_millis(50);
+/// Longest write interval once the cache has been idle for a while
+const SLOW_INTERVAL: Duration = Duration::from_millis(500);
+/// Number of unchanged ticks before the interval starts backing off
+const IDLE_TICKS_BEFORE_SLOW: u32 = 20;
+/// Wait after the first attempt to recreate the file before trying again
+const RECREATE_BACKOFF_MIN: Duration = Duration::from_secs(1);
+/// Longest wait between attempts to recreate the file
+const RECREATE_BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+/// Number of attempts a reader makes before reporting a torn buffer
+const READ_RETRIES: usize = 5;
+
+/// Byte offset of a slot within the shared memory region
+pub fn slot_offset(slot: usize) -> usize {
+    HEADER_SIZE + slot * SLOT_SIZE
+}
+
+/// Default shared memory path for the current user
+pub fn default_shm_path() -> PathBuf {
+    PathBuf::from(format!("/dev/shm/pipewire-volume-mixer-{}", Uid::current()))
+}
+
+/// CRC32 (IEEE 802.3) lookup table, matching zlib's crc32
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Compute the CRC32 checksum of a byte slice
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc = CRC32_TABLE[((crc ^ byte as u32) & 0xFF) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+/// Why the shared memory region could not be set up
+///
+/// Typically /dev/shm is missing, read-only or full, as in some containers.
+#[derive(Debug)]
+pub enum ShmError {
+    Open { path: PathBuf, source: std::io::Error },
+    Allocate { path: PathBuf, source: std::io::Error },
+    Map { path: PathBuf, source: std::io::Error },
+}
+
+impl ShmError {
+    pub fn path(&self) -> &Path {
+        match self {
+            ShmError::Open { path, .. }
+            | ShmError::Allocate { path, .. }
+            | ShmError::Map { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for ShmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShmError::Open { path, source } => {
+                write!(f, "Failed to open shared memory file {path:?}: {source}")
+            }
+            ShmError::Allocate { path, source } => {
+                write!(f, "Failed to allocate shared memory file {path:?}: {source}")
+            }
+            ShmError::Map { path, source } => {
+                write!(f, "Failed to map shared memory file {path:?}: {source}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShmError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ShmError::Open { source, .. }
+            | ShmError::Allocate { source, .. }
+            | ShmError::Map { source, .. } => Some(source),
+        }
+    }
+}
+
+/// Writes cache snapshots into a memory-mapped file for zero-copy reads
+pub struct SharedMemoryWriter {
+    cache: Arc<RwLock<AudioCache>>,
+    path: PathBuf,
+    mmap: MmapMut,
+    sequence: u64,
+    schedule: AdaptiveInterval,
+}
+
+impl SharedMemoryWriter {
+    pub fn new(cache: Arc<RwLock<AudioCache>>) -> Result<Self, ShmError> {
+        Self::with_path(cache, default_shm_path())
+    }
+
+    pub fn with_path<P: AsRef<Path>>(
+        cache: Arc<RwLock<AudioCache>>,
+        path: P,
+    ) -> Result<Self, ShmError> {
+        let path = path.as_ref().to_path_buf();
+        let mmap = create_shared_memory(&path)?;
+        info!("Shared memory created at {:?}", path);
+        let schedule = AdaptiveInterval::new(FAST_INTERVAL, SLOW_INTERVAL, IDLE_TICKS_BEFORE_SLOW);
+        Ok(Self { cache, path, mmap, sequence: 0, schedule })
+    }
+
+    /// Poll the cache every `min` while it changes, backing off up to `max` when idle
+    pub fn with_intervals(mut self, min: Duration, max: Duration) -> Self {
+        self.schedule = AdaptiveInterval::new(min, max, IDLE_TICKS_BEFORE_SLOW);
+        self
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Serialize a snapshot into the shared memory region
+    ///
+    /// The snapshot is written into the inactive slot, checksum last, and only
+    /// then is the sequence number bumped to publish it. Readers therefore always
+    /// find a complete snapshot in the active slot.
+    pub fn write_snapshot(&mut self, snapshot: &CacheSnapshot) -> Result<()> {
+        let payload = encode_payload(snapshot)?;
+        if SLOT_HEADER_SIZE + payload.len() > SLOT_SIZE {
+            bail!("Snapshot too large for shared memory: {} bytes", payload.len());
+        }
+
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+
+        let next_sequence = self.sequence + 1;
+        let slot = slot_offset((next_sequence % 2) as usize);
+        let payload_start = slot + SLOT_HEADER_SIZE;
+
+        self.mmap[payload_start..payload_start + payload.len()].copy_from_slice(&payload);
+        self.mmap[slot + SLOT_OFFSET_GENERATION..slot + SLOT_OFFSET_GENERATION + 8]
+            .copy_from_slice(&snapshot.generation.to_le_bytes());
+        self.mmap[slot + SLOT_OFFSET_TIMESTAMP..slot + SLOT_OFFSET_TIMESTAMP + 8]
+            .copy_from_slice(&timestamp.to_le_bytes());
+        self.mmap[slot + SLOT_OFFSET_PAYLOAD_LEN..slot + SLOT_OFFSET_PAYLOAD_LEN + 4]
+            .copy_from_slice(&(payload.len() as u32).to_le_bytes());
+        self.mmap[slot + SLOT_OFFSET_CHECKSUM..slot + SLOT_OFFSET_CHECKSUM + 4]
+            .copy_from_slice(&crc32(&payload).to_le_bytes());
+
+        // Publish the slot only after its contents are complete
+        sequence_atomic(&self.mmap).store(next_sequence.to_le(), Ordering::Release);
+        self.sequence = next_sequence;
+
+        self.mmap.flush_async()?;
+        Ok(())
+    }
+
+    /// Re-create the backing file, e.g. after it was removed from /dev/shm
+    pub fn recreate_shared_memory(&mut self) -> Result<()> {
+        warn!("Recreating shared memory at {:?}", self.path);
+        self.mmap = create_shared_memory(&self.path)?;
+        self.sequence = 0;
+        Ok(())
+    }
+
+    /// Write a snapshot whenever the cache generation changes
+    ///
+    /// While the cache is paused no snapshots are written. Resuming wakes the loop
+    /// and forces a snapshot straight away. Once the generation stops changing one
+    /// more snapshot is written, so changes made after their generation bump aren't
+    /// lost.
+    pub async fn run(mut self) -> Result<()> {
+        let mut last_generation = None;
+        let mut settled = true;
+        let mut consecutive_failures = 0u32;
+        // Recreating doesn't help with every failure, so it's retried ever less often
+        let mut recreate_backoff = Backoff::new(RECREATE_BACKOFF_MIN, RECREATE_BACKOFF_MAX);
+        let mut next_recreate: Option<Instant> = None;
+        let resumed = self.cache.read().await.resume_signal();
+
+        loop {
+            if self.cache.read().await.is_paused() {
+                debug!("Shared memory writes paused");
+                while self.cache.read().await.is_paused() {
+                    resumed.notified().await;
+                }
+                debug!("Resumed, forcing a shared memory snapshot");
+                last_generation = None;
+                self.schedule.reset();
+            } else {
+                tokio::time::sleep(self.schedule.current()).await;
+            }
+
+            let started = Instant::now();
+            let (snapshot, changed) = {
+                let cache = self.cache.read().await;
+                if cache.is_paused() {
+                    continue;
+                }
+                let changed = last_generation != Some(cache.get_generation());
+                if !changed && settled {
+                    self.schedule.tick(false);
+                    continue;
+                }
+                // Writers bump the generation before they're done, so the last
+                // snapshot of a burst may have missed what came after its bump
+                (cache.get_snapshot(), changed)
+            };
+            self.schedule.tick(changed);
+
+            match self.write_snapshot(&snapshot) {
+                Ok(()) => {
+                    debug!("Wrote snapshot generation {} to shared memory", snapshot.generation);
+                    self.cache.read().await.record_latency(Operation::Snapshot, started.elapsed());
+                    last_generation = Some(snapshot.generation);
+                    settled = !changed;
+                    consecutive_failures = 0;
+                    recreate_backoff.reset();
+                    next_recreate = None;
+                }
+                Err(e) => {
+                    consecutive_failures += 1;
+                    if should_log_failure(consecutive_failures) {
+                        error!(
+                            "Failed to write shared memory snapshot ({} in a row): {}",
+                            consecutive_failures, e
+                        );
+                    }
+                    let due = next_recreate.map_or(true, |at| Instant::now() >= at);
+                    if consecutive_failures > 3 && due {
+                        next_recreate = Some(Instant::now() + recreate_backoff.fail());
+                        if let Err(e) = self.recreate_shared_memory() {
+                            if should_log_failure(recreate_backoff.failures()) {
+                                error!("Failed to recreate shared memory: {}", e);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn create_shared_memory(path: &Path) -> Result<MmapMut, ShmError> {
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .map_err(|source| ShmError::Open { path: path.to_path_buf(), source })?;
+
+    // Reserve the pages up front: on a full tmpfs a plain resize succeeds, and the
+    // first write through the mapping then kills the daemon with SIGBUS
+    nix::fcntl::posix_fallocate(file.as_raw_fd(), 0, SHM_SIZE as i64)
+        .map_err(|errno| ShmError::Allocate { path: path.to_path_buf(), source: errno.into() })?;
+
+    // SAFETY: the file is owned by this daemon and never truncated while mapped
+    let mut mmap = unsafe { MmapMut::map_mut(&file) }
+        .map_err(|source| ShmError::Map { path: path.to_path_buf(), source })?;
+
+    // Start from a clean header; no slot is valid until the first write
+    mmap[..HEADER_SIZE].fill(0);
+    mmap[OFFSET_VERSION..OFFSET_VERSION + 4].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+    Ok(mmap)
+}
+
+/// The sequence number as an atomic view into the mapping
+fn sequence_atomic(data: &[u8]) -> &AtomicU64 {
+    let ptr = data[OFFSET_SEQUENCE..OFFSET_SEQUENCE + 8].as_ptr();
+    // SAFETY: mappings are page aligned, so the offset is suitably aligned for a u64,
+    // and the slice keeps the mapping alive for the lifetime of the reference
+    unsafe { &*(ptr as *const AtomicU64) }
+}
+
+fn push_string(buf: &mut Vec<u8>, s: &str) {
+    // The cache already limits names; never split a character if one slips through
+    let mut len = s.len().min(u8::MAX as usize);
+    while !s.is_char_boundary(len) {
+        len -= 1;
+    }
+    buf.push(len as u8);
+    buf.extend_from_slice(&s.as_bytes()[..len]);
+}
+
+fn encode_payload(snapshot: &CacheSnapshot) -> Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(4096);
+
+    let mut sinks: Vec<_> = snapshot.sinks.iter().collect();
+    sinks.sort_by(|a, b| a.0.cmp(b.0));
+    buf.extend_from_slice(&(sinks.len() as u32).to_le_bytes());
+    for (name, sink) in sinks {
+        push_string(&mut buf, name);
+        buf.extend_from_slice(&sink.id.to_le_bytes());
+        buf.extend_from_slice(&sink.volume.to_le_bytes());
+        buf.push(sink.muted as u8);
+    }
+
+    let mut apps: Vec<_> = snapshot.apps.iter().collect();
+    apps.sort_by(|a, b| a.0.cmp(b.0));
+    buf.extend_from_slice(&(apps.len() as u32).to_le_bytes());
+    for (name, app) in apps {
+        push_string(&mut buf, name);
+        push_string(&mut buf, &app.current_sink);
+        buf.push(app.active as u8);
+    }
+
+    Ok(buf)
+}
+
+/// A sink as stored in shared memory
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedSink {
+    pub name: String,
+    pub id: u32,
+    pub volume: f32,
+    pub muted: bool,
+}
+
+/// An app as stored in shared memory
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedApp {
+    pub name: String,
+    pub current_sink: String,
+    pub active: bool,
+}
+
+/// A decoded, checksum-validated shared memory snapshot
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedSnapshot {
+    pub generation: u64,
+    pub timestamp_ms: u64,
+    pub sinks: Vec<SharedSink>,
+    pub apps: Vec<SharedApp>,
+}
+
+/// Reads and validates snapshots written by [`SharedMemoryWriter`]
+pub struct SharedMemoryReader {
+    path: PathBuf,
+}
+
+impl SharedMemoryReader {
+    pub fn new() -> Self {
+        Self::with_path(default_shm_path())
+    }
+
+    pub fn with_path<P: AsRef<Path>>(path: P) -> Self {
+        Self { path: path.as_ref().to_path_buf() }
+    }
+
+    /// Read the active slot once
+    ///
+    /// Fails if the slot is invalid or the writer published a new snapshot while
+    /// the slot was being copied.
+    pub fn try_read(&self) -> Result<SharedSnapshot> {
+        let file = std::fs::File::open(&self.path)
+            .with_context(|| format!("Failed to open shared memory {:?}", self.path))?;
+        // SAFETY: the mapping is read-only and every byte is copied out before use
+        let mmap = unsafe { Mmap::map(&file) }.context("Failed to map shared memory")?;
+        if mmap.len() < SHM_SIZE {
+            bail!("Shared memory region too small");
+        }
+
+        let version = read_u32(&mmap, OFFSET_VERSION)?;
+        if version != FORMAT_VERSION {
+            bail!("Unsupported shared memory version {version}");
+        }
+
+        let sequence = u64::from_le(sequence_atomic(&mmap).load(Ordering::Acquire));
+        if sequence == 0 {
+            bail!("No snapshot has been written yet");
+        }
+        let slot = slot_offset((sequence % 2) as usize);
+        let data = mmap[slot..slot + SLOT_SIZE].to_vec();
+
+        fence(Ordering::Acquire);
+        if u64::from_le(sequence_atomic(&mmap).load(Ordering::Acquire)) != sequence {
+            bail!("Snapshot was replaced during read");
+        }
+
+        decode_slot(&data)
+    }
+
+    /// Read a snapshot, retrying while the buffer fails validation
+    pub fn read(&self) -> Result<SharedSnapshot> {
+        let mut last_error = None;
+        for _ in 0..READ_RETRIES {
+            match self.try_read() {
+                Ok(snapshot) => return Ok(snapshot),
+                Err(e) => {
+                    debug!("Shared memory read was invalid, retrying: {}", e);
+                    last_error = Some(e);
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("Shared memory read failed")))
+    }
+}
+
+impl Default for SharedMemoryReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    let bytes = data.get(offset..offset + 4).context("Unexpected end of buffer")?;
+    Ok(u32::from_le_bytes(bytes.try_into()?))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Result<u64> {
+    let bytes = data.get(offset..offset + 8).context("Unexpected end of buffer")?;
+    Ok(u64::from_le_bytes(bytes.try_into()?))
+}
+
+fn read_string(data: &[u8], offset: &mut usize) -> Result<String> {
+    let len = *data.get(*offset).context("Unexpected end of buffer")? as usize;
+    let bytes = data.get(*offset + 1..*offset + 1 + len).context("Unexpected end of buffer")?;
+    *offset += 1 + len;
+    Ok(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// Decode and validate the active slot of a raw shared memory buffer
+pub fn decode(data: &[u8]) -> Result<SharedSnapshot> {
+    if data.len() < SHM_SIZE {
+        bail!("Buffer too small to contain both slots");
+    }
+
+    let version = read_u32(data, OFFSET_VERSION)?;
+    if version != FORMAT_VERSION {
+        bail!("Unsupported shared memory version {version}");
+    }
+
+    let sequence = read_u64(data, OFFSET_SEQUENCE)?;
+    if sequence == 0 {
+        bail!("No snapshot has been written yet");
+    }
+    let slot = slot_offset((sequence % 2) as usize);
+    decode_slot(&data[slot..slot + SLOT_SIZE])
+}
+
+/// Decode and validate a single slot
+pub fn decode_slot(slot: &[u8]) -> Result<SharedSnapshot> {
+    let generation = read_u64(slot, SLOT_OFFSET_GENERATION)?;
+    let timestamp_ms = read_u64(slot, SLOT_OFFSET_TIMESTAMP)?;
+    let payload_len = read_u32(slot, SLOT_OFFSET_PAYLOAD_LEN)? as usize;
+    let checksum = read_u32(slot, SLOT_OFFSET_CHECKSUM)?;
+
+    let payload = slot
+        .get(SLOT_HEADER_SIZE..SLOT_HEADER_SIZE + payload_len)
+        .context("Payload length out of range")?;
+    if crc32(payload) != checksum {
+        bail!("Checksum mismatch");
+    }
+
+    let mut offset = 0;
+    let sink_count = read_u32(payload, offset)?;
+    offset += 4;
+    let mut sinks = Vec::with_capacity(sink_count as usize);
+    for _ in 0..sink_count {
+        let name = read_string(payload, &mut offset)?;
+        let id = read_u32(payload, offset)?;
+        let volume = f32::from_bits(read_u32(payload, offset + 4)?);
+        let muted = *payload.get(offset + 8).context("Unexpected end of buffer")? == 1;
+        offset += 9;
+        sinks.push(SharedSink { name, id, volume, muted });
+    }
+
+    let app_count = read_u32(payload, offset)?;
+    offset += 4;
+    let mut apps = Vec::with_capacity(app_count as usize);
+    for _ in 0..app_count {
+        let name = read_string(payload, &mut offset)?;
+        let current_sink = read_string(payload, &mut offset)?;
+        let active = *payload.get(offset).context("Unexpected end of buffer")? == 1;
+        offset += 1;
+        apps.push(SharedApp { name, current_sink, active });
+    }
+
+    Ok(SharedSnapshot { generation, timestamp_ms, sinks, apps })
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+/// A stream as listed by `pactl list sink-inputs`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SinkInput {
+    pub id: u32,
+    pub sink: Option<u32>,
+    pub volume: Option<f32>, // Average over the stream's channels, 1.0 being 100%
+    pub corked: bool,        // Paused by its app, the stream exists but plays nothing
+    pub properties: HashMap<String, String>,
+}
+
+impl SinkInput {
+    pub fn property(&self, key: &str) -> Option<&str> {
+        self.properties.get(key).map(String::as_str)
+    }
+
+    /// PipeWire `object.serial` of the node behind the stream
+    pub fn object_serial(&self) -> Option<u32> {
+        self.property("object.serial")?.parse().ok()
+    }
+
+    /// The stream's id in the cache, its serial or, on servers that don't report
+    /// one, pactl's index
+    pub fn serial(&self) -> u32 {
+        self.object_serial().unwrap_or(self.id)
+    }
+
+    /// `media.role` hint the app gave for the stream, such as "Music" or "Game"
+    pub fn media_role(&self) -> Option<&str> {
+        self.property("media.role")
+    }
+
+    /// PID of the process that owns the stream
+    pub fn process_id(&self) -> Option<u32> {
+        self.property("application.process.id")?.parse().ok()
+    }
+}
+
+/// Parse the output of `pactl list sink-inputs`
+pub fn parse_sink_inputs(output: &str) -> Vec<SinkInput> {
+    let mut inputs = Vec::new();
+    let mut current: Option<SinkInput> = None;
+    let mut in_properties = false;
+
+    for line in output.lines() {
+        if let Some(id_str) = line.strip_prefix("Sink Input #") {
+            inputs.extend(current.take());
+            in_properties = false;
+            current = id_str.trim().parse().ok().map(|id| SinkInput { id, ..Default::default() });
+            continue;
+        }
+
+        let Some(input) = current.as_mut() else {
+            continue;
+        };
+        let trimmed = line.trim();
+
+        if trimmed == "Properties:" {
+            in_properties = true;
+        } else if in_properties {
+            match trimmed.split_once(" = ") {
+                Some((key, value)) => {
+                    input.properties.insert(key.to_string(), value.trim_matches('"').to_string());
+                }
+                // Properties are the last indented section of a block
+                None => in_properties = false,
+            }
+        } else if let Some(sink) = trimmed.strip_prefix("Sink: ") {
+            input.sink = sink.parse().ok();
+        } else if let Some(volume) = trimmed.strip_prefix("Volume: ") {
+            input.volume = parse_channel_volumes(volume);
+        } else if let Some(corked) = trimmed.strip_prefix("Corked: ") {
+            input.corked = corked == "yes";
+        }
+    }
+
+    inputs.extend(current);
+    inputs
+}
+
+/// A sink as listed by `pactl list sinks`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sink {
+    pub id: u32,
+    pub name: String,
+    pub description: Option<String>,
+    pub volume: Option<f32>, // Average over the sink's channels, 1.0 being 100%
+    pub muted: bool,
+}
+
+/// Parse the output of `pactl list sinks`
+pub fn parse_sinks(output: &str) -> Vec<Sink> {
+    let mut sinks = Vec::new();
+    let mut current: Option<Sink> = None;
+
+    for line in output.lines() {
+        if let Some(id_str) = line.strip_prefix("Sink #") {
+            sinks.extend(current.take());
+            current = id_str.trim().parse().ok().map(|id| Sink { id, ..Default::default() });
+            continue;
+        }
+
+        let Some(sink) = current.as_mut() else {
+            continue;
+        };
+        // Only the sink's own fields, not its properties or ports, sit one tab deep
+        let Some(field) = line.strip_prefix('\t').filter(|field| !field.starts_with('\t')) else {
+            continue;
+        };
+
+        if let Some(name) = field.strip_prefix("Name: ") {
+            sink.name = name.trim().to_string();
+        } else if let Some(description) = field.strip_prefix("Description: ") {
+            sink.description = Some(description.trim().to_string());
+        } else if let Some(volume) = field.strip_prefix("Volume: ") {
+            sink.volume = parse_channel_volumes(volume);
+        } else if let Some(muted) = field.strip_prefix("Mute: ") {
+            sink.muted = muted.trim() == "yes";
+        }
+    }
+
+    sinks.extend(current);
+    sinks
+}
+
+/// Raw volume pactl reports for 100%
+const PA_VOLUME_NORM: f32 = 65536.0;
+
+/// Average of a pactl volume line like `front-left: 32768 /  50% / -18.06 dB, front-right: …`
+fn parse_channel_volumes(line: &str) -> Option<f32> {
+    let raw: Vec<f32> = line
+        .split(',')
+        .filter_map(|channel| channel.split_once(':')?.1.split('/').next()?.trim().parse().ok())
+        .collect();
+    (!raw.is_empty()).then(|| raw.iter().sum::<f32>() / raw.len() as f32 / PA_VOLUME_NORM)
+}
+
+/// IDs of the sink inputs owned by a single process
+pub fn sink_inputs_for_pid(inputs: &[SinkInput], pid: u32) -> Vec<u32> {
+    inputs.iter().filter(|input| input.process_id() == Some(pid)).map(|input| input.id).collect()
+}
+
+/// Find the sink input backing a PipeWire node
+///
+/// pactl's sink input index is not guaranteed to equal the node's `object.serial`,
+/// so match on the `object.serial` property pactl reports, then on a unique
+/// `node.name`. The index is only trusted for entries that report no serial at all.
+pub fn find_sink_input<'a>(
+    inputs: &'a [SinkInput],
+    serial: u32,
+    node_name: &str,
+) -> Option<&'a SinkInput> {
+    if let Some(input) = inputs.iter().find(|input| input.object_serial() == Some(serial)) {
+        return Some(input);
+    }
+
+    if !node_name.is_empty() {
+        let mut by_name =
+            inputs.iter().filter(|input| input.property("node.name") == Some(node_name));
+        if let (Some(input), None) = (by_name.next(), by_name.next()) {
+            return Some(input);
+        }
+    }
+
+    inputs.iter().find(|input| input.id == serial && input.object_serial().is_none())
+}
@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+/// Quietest level represented in dB; anything at or below it is silence
+pub const MIN_DB: f32 = -60.0;
+
+/// Percentage handed to pactl/wpctl for a linear volume
+///
+/// The tools apply their own curve to this value, so it is recorded per sink to make
+/// the difference between the slider and what was applied visible.
+pub fn volume_to_percent(volume: f32) -> u32 {
+    (volume * 100.0) as u32
+}
+
+/// A volume that is safe to hand to PipeWire, clamped to 0.0 - 1.0
+///
+/// Returns None for NaN and infinities, which have no sensible level.
+pub fn sanitize_volume(volume: f32) -> Option<f32> {
+    volume.is_finite().then(|| volume.clamp(0.0, 1.0))
+}
+
+/// Time between the steps of a volume ramp
+pub const RAMP_STEP_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Percentages to step through when ramping from `from` to `to` over `duration`
+///
+/// There is one step per [`RAMP_STEP_INTERVAL`], at least one, and the last is always
+/// `to`. Repeated percentages are dropped, so a ramp between equal levels is one step.
+pub fn ramp_percents(from: f32, to: f32, duration: Duration) -> Vec<u32> {
+    let steps = (duration.as_millis() / RAMP_STEP_INTERVAL.as_millis()).max(1) as u32;
+    let mut percents: Vec<u32> = (1..steps)
+        .map(|step| volume_to_percent(from + (to - from) * step as f32 / steps as f32))
+        .chain(std::iter::once(volume_to_percent(to)))
+        .collect();
+    percents.dedup();
+    percents
+}
+
+/// Convert a linear volume (0.0 - 1.0) to decibels, clamped to [`MIN_DB`]
+pub fn linear_to_db(linear: f32) -> f32 {
+    if linear <= 0.0 {
+        return MIN_DB;
+    }
+    (20.0 * linear.log10()).max(MIN_DB)
+}
+
+/// Convert decibels to a linear volume, treating [`MIN_DB`] and below as silence
+pub fn db_to_linear(db: f32) -> f32 {
+    if db <= MIN_DB {
+        return 0.0;
+    }
+    10f32.powf(db / 20.0)
+}
+
+/// Volumes for the `from` and `to` sinks at a crossfade position
+///
+/// Position 0.0 is all `from`, 1.0 is all `to`; values outside that range are clamped.
+pub fn crossfade_volumes(position: f32) -> (f32, f32) {
+    let position = position.clamp(0.0, 1.0);
+    (1.0 - position, position)
+}
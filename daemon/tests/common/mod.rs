@@ -0,0 +1,168 @@
+//! Fake `pactl`/`wpctl` executables for end-to-end tests
+//!
+//! [`FakeTools`] writes a shell script per tool into a temp dir and puts that dir
+//! first on `PATH`, so the daemon's real command path runs them instead of the
+//! system tools. Each script logs its arguments and prints the canned output
+//! scripted for them; unscripted calls succeed without output.
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+use tempfile::TempDir;
+
+/// `PATH` is process-wide, so only one set of fake tools can be installed at a time
+static PATH_LOCK: Mutex<()> = Mutex::new(());
+
+/// What a fake tool does for one scripted invocation
+struct Response {
+    stdout: String,
+    stderr: String,
+    exit_code: i32,
+}
+
+/// Scripts the invocations of the fake tools before installing them
+#[derive(Default)]
+pub struct FakeToolsBuilder {
+    tools: Vec<String>,
+    scripted: Vec<(String, Vec<String>, Vec<Response>)>, // tool, args, responses in order
+}
+
+impl FakeToolsBuilder {
+    /// Answer `tool args…` with `stdout`
+    ///
+    /// Scripting the same invocation again queues another answer; once the queue is
+    /// used up the last answer repeats.
+    pub fn respond(self, tool: &str, args: &[&str], stdout: &str) -> Self {
+        self.push(
+            tool,
+            args,
+            Response { stdout: stdout.into(), stderr: String::new(), exit_code: 0 },
+        )
+    }
+
+    /// Make `tool args…` exit with status 1, printing `stderr`
+    pub fn fail(self, tool: &str, args: &[&str], stderr: &str) -> Self {
+        self.push(
+            tool,
+            args,
+            Response { stdout: String::new(), stderr: stderr.into(), exit_code: 1 },
+        )
+    }
+
+    /// Install a fake `tool` that only has unscripted calls
+    pub fn tool(mut self, tool: &str) -> Self {
+        if !self.tools.iter().any(|known| known == tool) {
+            self.tools.push(tool.to_string());
+        }
+        self
+    }
+
+    fn push(mut self, tool: &str, args: &[&str], response: Response) -> Self {
+        self = self.tool(tool);
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        match self.scripted.iter_mut().find(|(t, a, _)| t == tool && *a == args) {
+            Some((_, _, responses)) => responses.push(response),
+            None => self.scripted.push((tool.to_string(), args, vec![response])),
+        }
+        self
+    }
+
+    /// Write the scripts and put them first on `PATH` until the result is dropped
+    pub fn install(self) -> FakeTools {
+        let lock = PATH_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let dir = tempfile::tempdir().unwrap();
+        let log = dir.path().join("calls.log");
+        fs::write(&log, "").unwrap();
+
+        for tool in &self.tools {
+            let mut script = format!(
+                "#!/bin/sh\n{{ printf '%s' {tool}; for arg in \"$@\"; do printf '\\t%s' \"$arg\"; done; echo; }} >> {log}\ncase \"$*\" in\n",
+                tool = quote(tool),
+                log = quote(&log.display().to_string()),
+            );
+            for (index, (_, args, responses)) in
+                self.scripted.iter().enumerate().filter(|(_, (t, _, _))| t == tool)
+            {
+                for (n, response) in responses.iter().enumerate() {
+                    let file = |extension| dir.path().join(format!("{index}.{n}.{extension}"));
+                    fs::write(file("out"), &response.stdout).unwrap();
+                    fs::write(file("err"), &response.stderr).unwrap();
+                    fs::write(file("code"), response.exit_code.to_string()).unwrap();
+                }
+                let counter =
+                    quote(&dir.path().join(format!("{index}.count")).display().to_string());
+                let prefix = quote(&dir.path().join(index.to_string()).display().to_string());
+                script.push_str(&format!(
+                    "  {pattern})\n    n=$(cat {counter} 2>/dev/null || echo 0)\n    echo $((n + 1)) > {counter}\n    [ \"$n\" -lt {last} ] || n={last}\n    cat {prefix}.$n.out; cat {prefix}.$n.err >&2; exit $(cat {prefix}.$n.code) ;;\n",
+                    pattern = quote(&args.join(" ")),
+                    last = responses.len() - 1,
+                ));
+            }
+            script.push_str("esac\nexit 0\n");
+
+            let path = dir.path().join(tool);
+            fs::write(&path, script).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let original_path = std::env::var_os("PATH");
+        let mut paths = vec![dir.path().to_path_buf()];
+        paths.extend(original_path.iter().flat_map(std::env::split_paths));
+        std::env::set_var("PATH", std::env::join_paths(paths).unwrap());
+
+        FakeTools { _dir: dir, log, original_path, _lock: lock }
+    }
+}
+
+/// Fake tools on `PATH`; the previous `PATH` comes back when this is dropped
+pub struct FakeTools {
+    _dir: TempDir, // Keeps the scripts around
+    log: PathBuf,
+    original_path: Option<std::ffi::OsString>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl FakeTools {
+    pub fn builder() -> FakeToolsBuilder {
+        FakeToolsBuilder::default()
+    }
+
+    /// Every invocation so far, oldest first, as `tool arg…` joined by spaces
+    pub fn calls(&self) -> Vec<String> {
+        fs::read_to_string(&self.log).unwrap().lines().map(|line| line.replace('\t', " ")).collect()
+    }
+
+    /// Panic unless `tool args…` was run at least once
+    pub fn assert_called(&self, tool: &str, args: &[&str]) {
+        let expected =
+            std::iter::once(tool).chain(args.iter().copied()).collect::<Vec<_>>().join(" ");
+        let calls = self.calls();
+        assert!(calls.contains(&expected), "{expected:?} was never run, calls: {calls:#?}");
+    }
+
+    /// Panic if any call of `tool` starts with `args`
+    pub fn assert_not_called(&self, tool: &str, args: &[&str]) {
+        let prefix =
+            std::iter::once(tool).chain(args.iter().copied()).collect::<Vec<_>>().join(" ");
+        let calls = self.calls();
+        assert!(
+            !calls.iter().any(|call| call == &prefix || call.starts_with(&format!("{prefix} "))),
+            "{prefix:?} was run, calls: {calls:#?}"
+        );
+    }
+}
+
+impl Drop for FakeTools {
+    fn drop(&mut self) {
+        match &self.original_path {
+            Some(path) => std::env::set_var("PATH", path),
+            None => std::env::remove_var("PATH"),
+        }
+    }
+}
+
+/// Quote `value` for a POSIX shell
+fn quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
@@ -1,4 +1,9 @@
-use pipewire_volume_mixer_daemon::cache::{AppInfo, AudioCache, SinkInfo};
+use pipewire_volume_mixer_daemon::cache::{
+    parse_sink_targets, truncate_name, AppInfo, AudioCache, CacheStats, SinkInfo,
+    FUZZY_MIN_RULE_LENGTH, RECENT_SINKS_LEN,
+};
+use pipewire_volume_mixer_daemon::config::{Config, StaleRulePolicy};
+use std::time::Duration;
 
 #[test]
 fn test_cache_creation() {
@@ -14,8 +19,8 @@ fn test_sink_operations() {
         id: 42,
         name: "Test Sink".to_string(),
         volume: 0.75,
-        muted: false,
         pipewire_id: 42,
+        ..Default::default()
     };
 
     cache.update_sink("Test Sink".to_string(), sink.clone());
@@ -25,6 +30,20 @@ fn test_sink_operations() {
     assert_eq!(sinks.get("Test Sink").unwrap().volume, 0.75);
 }
 
+#[test]
+fn test_record_applied_volume_only_for_cached_sinks() {
+    let cache = AudioCache::new();
+    cache.update_sink(
+        "Chat".to_string(),
+        SinkInfo { name: "Chat".to_string(), ..Default::default() },
+    );
+
+    assert!(cache.record_applied_volume("Chat", 0.5, 50));
+    assert!(!cache.record_applied_volume("Missing", 0.5, 50));
+    let chat = cache.sinks.get("Chat").unwrap().clone();
+    assert_eq!((chat.volume, chat.applied_percent), (0.5, 50));
+}
+
 #[test]
 fn test_app_operations() {
     let cache = AudioCache::new();
@@ -37,7 +56,7 @@ fn test_app_operations() {
         active: true,
         sink_input_ids: vec![123, 456],
         pipewire_id: 100,
-        inactive_since: None,
+        ..Default::default()
     };
 
     cache.update_app("Firefox".to_string(), app.clone());
@@ -54,9 +73,888 @@ fn test_generation_increment() {
 
     cache.update_sink(
         "Test".to_string(),
-        SinkInfo { id: 1, name: "Test".to_string(), volume: 1.0, muted: false, pipewire_id: 1 },
+        SinkInfo {
+            id: 1,
+            name: "Test".to_string(),
+            volume: 1.0,
+            pipewire_id: 1,
+            ..Default::default()
+        },
     );
 
     let gen2 = cache.get_generation();
     assert!(gen2 > gen1);
 }
+
+#[test]
+fn test_consistent_read_starts_over_when_a_change_lands_mid_read() {
+    let cache = AudioCache::new();
+    let sink = |applied_percent| SinkInfo {
+        id: 1,
+        name: "Game".to_string(),
+        volume: 1.0,
+        pipewire_id: 1,
+        applied_percent,
+        ..Default::default()
+    };
+    cache.update_sink("Game".to_string(), sink(10));
+
+    // The first read is overtaken by a change, as a writer on another thread would
+    let reads = std::cell::Cell::new(0);
+    let (generation, applied) = cache.read_consistent(|cache| {
+        reads.set(reads.get() + 1);
+        let applied = cache.sinks.get("Game").unwrap().applied_percent;
+        if reads.get() == 1 {
+            cache.update_sink("Game".to_string(), sink(20));
+        }
+        applied
+    });
+    assert_eq!(reads.get(), 2);
+    assert_eq!((generation, applied), (cache.get_generation(), 20));
+}
+
+#[test]
+fn test_identical_sink_update_keeps_generation() {
+    let cache = AudioCache::new();
+    let mut events = cache.subscribe_sink_events();
+    let sink = SinkInfo {
+        id: 1,
+        name: "Test".to_string(),
+        volume: 0.5,
+        pipewire_id: 1,
+        applied_percent: 50,
+        ..Default::default()
+    };
+    cache.update_sink("Test".to_string(), sink.clone());
+    let generation = cache.get_generation();
+    assert!(events.try_recv().is_ok());
+
+    cache.update_sink("Test".to_string(), sink.clone());
+    assert_eq!(cache.get_generation(), generation);
+    assert!(events.try_recv().is_err());
+
+    // Any difference is still a change
+    cache.update_sink("Test".to_string(), SinkInfo { applied_percent: 51, ..sink });
+    assert_eq!(cache.get_generation(), generation + 1);
+}
+
+#[test]
+fn test_sink_membership_follows_app() {
+    let cache = AudioCache::new();
+
+    let app = AppInfo {
+        display_name: "Firefox".to_string(),
+        binary_name: "firefox".to_string(),
+        stream_names: vec!["firefox".to_string()],
+        current_sink: "Media".to_string(),
+        active: false,
+        pipewire_id: 100,
+        inactive_since: Some(std::time::Instant::now() - std::time::Duration::from_secs(10)),
+        ..Default::default()
+    };
+    cache.update_app("Firefox".to_string(), app);
+    assert_eq!(cache.apps_for_sink("Media"), vec!["Firefox".to_string()]);
+
+    assert!(cache.set_app_sink("Firefox", "Game"));
+    assert!(cache.apps_for_sink("Media").is_empty());
+    assert_eq!(cache.apps_for_sink("Game"), vec!["Firefox".to_string()]);
+    assert!(!cache.set_app_sink("Missing", "Game"));
+
+    // Cleaned up apps drop out of the index
+    assert_eq!(cache.cleanup_inactive_apps(5), 1);
+    assert!(cache.apps_for_sink("Game").is_empty());
+}
+
+#[test]
+fn test_stats_count_each_collection() {
+    let cache = AudioCache::new();
+    assert_eq!(cache.stats(), CacheStats::default());
+
+    for (id, name) in [(34, "Game"), (39, "Media")] {
+        cache.update_sink(
+            name.to_string(),
+            SinkInfo {
+                id,
+                name: name.to_string(),
+                volume: 1.0,
+                pipewire_id: id,
+                applied_percent: 100,
+                ..Default::default()
+            },
+        );
+    }
+    for (name, sink, active) in
+        [("Firefox", "Media", true), ("Steam", "Game", true), ("mpv", "Media", false)]
+    {
+        cache.update_app(
+            name.to_string(),
+            AppInfo {
+                display_name: name.to_string(),
+                binary_name: name.to_lowercase(),
+                stream_names: vec![name.to_string()],
+                current_sink: sink.to_string(),
+                active,
+                pipewire_id: 100,
+                ..Default::default()
+            },
+        );
+    }
+    cache.routing_rules.insert("firefox".to_string(), "Media".to_string());
+    // Active apps are remembered as they're added, Discord only from an earlier run
+    cache.remembered_apps.insert("Discord".to_string(), "Chat".to_string());
+
+    assert_eq!(
+        cache.stats(),
+        CacheStats {
+            sinks: 2,
+            active_apps: 2,
+            inactive_apps: 1,
+            routing_rules: 1,
+            remembered_apps: 3,
+            indexed_apps: 3,
+        }
+    );
+}
+
+#[test]
+fn test_oversized_app_name_is_truncated() {
+    let cache = AudioCache::new().with_max_name_length(64);
+    let long_name = "x".repeat(300);
+
+    let app = AppInfo {
+        display_name: long_name.clone(),
+        binary_name: "x".to_string(),
+        stream_names: vec![long_name.clone()],
+        current_sink: "Media".to_string(),
+        active: true,
+        sink_input_ids: vec![1],
+        pipewire_id: 1,
+        ..Default::default()
+    };
+    cache.update_app(long_name.clone(), app);
+
+    let truncated = truncate_name(&long_name, 64);
+    assert_eq!(truncated.len(), 64);
+    assert!(truncated.ends_with('…'));
+
+    let stored = cache.apps.get(&truncated).unwrap();
+    assert_eq!(stored.display_name, truncated);
+    assert_eq!(stored.stream_names, vec![truncated.clone()]);
+    assert!(cache.apps.get(&long_name).is_none());
+}
+
+#[test]
+fn test_truncation_respects_char_boundaries() {
+    // Multi-byte characters must never be split
+    let name = "音".repeat(100);
+    let truncated = truncate_name(&name, 64);
+    assert!(truncated.len() <= 64);
+    assert!(truncated.ends_with('…'));
+    assert!(truncated.trim_end_matches('…').chars().all(|c| c == '音'));
+
+    assert_eq!(truncate_name("Firefox", 64), "Firefox");
+}
+
+#[test]
+fn test_lossy_decoded_name_is_stored() {
+    let cache = AudioCache::new();
+    let name = String::from_utf8_lossy(b"Game \xFF\xFE Audio").into_owned();
+    assert_eq!(name, "Game \u{FFFD}\u{FFFD} Audio");
+
+    let app = AppInfo {
+        display_name: name.clone(),
+        binary_name: "game".to_string(),
+        stream_names: vec![name.clone()],
+        current_sink: "Game".to_string(),
+        active: true,
+        sink_input_ids: vec![1],
+        pipewire_id: 1,
+        ..Default::default()
+    };
+    cache.update_app(name.clone(), app);
+    assert_eq!(cache.apps.get(&name).unwrap().display_name, name);
+}
+
+#[test]
+fn test_solo_then_unsolo_restores_prior_mutes() {
+    let cache = AudioCache::new();
+    for (id, (name, muted)) in
+        [("Chat", true), ("Game", false), ("Media", false)].iter().enumerate()
+    {
+        cache.update_sink(
+            name.to_string(),
+            SinkInfo {
+                id: id as u32,
+                name: name.to_string(),
+                volume: 1.0,
+                muted: *muted,
+                pipewire_id: id as u32,
+                applied_percent: 100,
+                ..Default::default()
+            },
+        );
+    }
+    let mutes = |cache: &AudioCache| {
+        ["Chat", "Game", "Media"].map(|name| cache.sinks.get(name).unwrap().muted)
+    };
+
+    assert!(cache.solo_sink("Missing").is_none());
+    assert!(cache.unsolo().is_empty());
+
+    let changes = cache.solo_sink("Game").unwrap();
+    assert_eq!(
+        changes,
+        vec![("Chat".to_string(), true), ("Game".to_string(), false), ("Media".to_string(), true)]
+    );
+    assert_eq!(mutes(&cache), [true, false, true]);
+
+    // Soloing again is idempotent
+    cache.solo_sink("Game").unwrap();
+    assert_eq!(mutes(&cache), [true, false, true]);
+
+    // Switching the solo to another sink keeps the original states
+    cache.solo_sink("Chat").unwrap();
+    assert_eq!(mutes(&cache), [false, true, true]);
+
+    cache.unsolo();
+    assert_eq!(mutes(&cache), [true, false, false]);
+    assert!(cache.unsolo().is_empty());
+}
+
+#[test]
+fn test_mute_all_then_unmute_all_restores_prior_mutes() {
+    let cache = AudioCache::new();
+    for (id, (name, muted)) in
+        [("Chat", true), ("Game", false), ("Media", false)].iter().enumerate()
+    {
+        cache.update_sink(
+            name.to_string(),
+            SinkInfo {
+                id: id as u32,
+                name: name.to_string(),
+                volume: 1.0,
+                muted: *muted,
+                pipewire_id: id as u32,
+                applied_percent: 100,
+                ..Default::default()
+            },
+        );
+    }
+    let mutes = |cache: &AudioCache| {
+        ["Chat", "Game", "Media"].map(|name| cache.sinks.get(name).unwrap().muted)
+    };
+
+    let changes = cache.mute_all();
+    assert_eq!(
+        changes,
+        vec![("Chat".to_string(), true), ("Game".to_string(), true), ("Media".to_string(), true)]
+    );
+    // Muting again keeps the original states
+    cache.mute_all();
+    cache.unmute_all();
+    assert_eq!(mutes(&cache), [true, false, false]);
+
+    // Panic-muting during a solo, then unmuting, goes back to before the solo
+    cache.solo_sink("Media").unwrap();
+    cache.mute_all();
+    assert_eq!(mutes(&cache), [true, true, true]);
+    cache.unmute_all();
+    assert_eq!(mutes(&cache), [true, false, false]);
+    assert!(cache.unsolo().is_empty());
+
+    // So does unmuting straight out of a solo
+    cache.solo_sink("Media").unwrap();
+    cache.unmute_all();
+    assert_eq!(mutes(&cache), [true, false, false]);
+
+    // With nothing stashed every sink is unmuted
+    cache.unmute_all();
+    assert_eq!(mutes(&cache), [false, false, false]);
+}
+
+#[test]
+fn test_new_stream_not_routed_while_auto_routing_disabled() {
+    let cache = AudioCache::new();
+    let mut routing = Config::default().routing;
+    routing.route_unmatched_to_default = true;
+    cache.routing_rules.insert("discord".to_string(), "Chat".to_string());
+
+    cache.set_auto_routing(false);
+    assert_eq!(cache.auto_route_target("discord", &routing), None);
+    assert_eq!(cache.auto_route_target("firefox", &routing), None);
+    // No rule is invented for the app that was left alone
+    assert!(!cache.routing_rules.contains_key("firefox"));
+
+    cache.set_auto_routing(true);
+    assert_eq!(cache.auto_route_target("discord", &routing).as_deref(), Some("Chat"));
+    assert_eq!(cache.auto_route_target("firefox", &routing).as_deref(), Some("Game"));
+    assert_eq!(cache.routing_rules.get("firefox").unwrap().as_str(), "Game");
+    // Only the rule it learned is handed out to be saved, and only once
+    assert_eq!(cache.take_learned_rules(), vec![("firefox".to_string(), "Game".to_string())]);
+    assert!(cache.take_learned_rules().is_empty());
+}
+
+fn app_with_role(display_name: &str, binary_name: &str, media_role: Option<&str>) -> AppInfo {
+    AppInfo {
+        display_name: display_name.to_string(),
+        binary_name: binary_name.to_string(),
+        stream_names: vec![display_name.to_string()],
+        current_sink: "Game".to_string(),
+        current_sinks: vec![],
+        stream_labels: vec![],
+        window_title: None,
+        active: true,
+        playing: false,
+        muted: false,
+        sink_input_ids: vec![1],
+        pipewire_id: 1,
+        media_role: media_role.map(str::to_string),
+        volume: None,
+        inactive_since: None,
+    }
+}
+
+#[test]
+fn test_resolve_target_precedence() {
+    let cache = AudioCache::new();
+    let mut routing = Config::default().routing;
+    routing.route_unmatched_to_default = true;
+    routing.role_rules.insert("Music".to_string(), "Media".to_string());
+    cache.update_app("Spotify".to_string(), app_with_role("Spotify", "spotify", Some("Music")));
+
+    // Default sink when nothing else matches
+    assert_eq!(cache.resolve_target("Unknown", &routing).as_deref(), Some("Game"));
+
+    // Media role beats the default sink
+    assert_eq!(cache.resolve_target("Spotify", &routing).as_deref(), Some("Media"));
+
+    // A binary-name rule is more specific than the role
+    cache.routing_rules.insert("spotify".to_string(), "Chat".to_string());
+    assert_eq!(cache.resolve_target("Spotify", &routing).as_deref(), Some("Chat"));
+
+    // An exact display-name rule wins over everything
+    cache.routing_rules.insert("Spotify".to_string(), "Game".to_string());
+    assert_eq!(cache.resolve_target("Spotify", &routing).as_deref(), Some("Game"));
+
+    // Nothing to fall back on without a default sink
+    routing.default_sink.clear();
+    assert_eq!(cache.resolve_target("Unknown", &routing), None);
+}
+
+#[test]
+fn test_unmatched_app_goes_to_default_sink_only_when_enabled() {
+    let cache = AudioCache::new();
+    let mut routing = Config::default().routing;
+    cache.update_app("Firefox".to_string(), app_with_role("Firefox", "firefox", None));
+
+    // Off by default: the app stays where PipeWire put it and gains no rule
+    assert_eq!(cache.auto_route_target("Firefox", &routing), None);
+    assert!(cache.routing_rules.is_empty());
+
+    routing.route_unmatched_to_default = true;
+    assert_eq!(cache.auto_route_target("Firefox", &routing).as_deref(), Some("Game"));
+    assert_eq!(cache.routing_rules.get("Firefox").unwrap().as_str(), "Game");
+}
+
+#[test]
+fn test_auto_route_remembers_role_target_only_without_app_rule() {
+    let cache = AudioCache::new();
+    let mut routing = Config::default().routing;
+    routing.role_rules.insert("Communication".to_string(), "Chat".to_string());
+    cache.update_app(
+        "Discord".to_string(),
+        app_with_role("Discord", "discord", Some("Communication")),
+    );
+    cache.update_app(
+        "Firefox".to_string(),
+        app_with_role("Firefox", "firefox", Some("Communication")),
+    );
+    cache.routing_rules.insert("firefox".to_string(), "Media".to_string());
+
+    assert_eq!(cache.auto_route_target("Discord", &routing).as_deref(), Some("Chat"));
+    assert_eq!(cache.routing_rules.get("Discord").unwrap().as_str(), "Chat");
+
+    // The binary rule applies as-is, without adding a display-name rule
+    assert_eq!(cache.auto_route_target("Firefox", &routing).as_deref(), Some("Media"));
+    assert!(!cache.routing_rules.contains_key("Firefox"));
+}
+
+#[test]
+fn test_fuzzy_rule_matches_name_prefix_but_exact_wins() {
+    let cache = AudioCache::new();
+    let mut routing = Config::default().routing;
+    routing.route_unmatched_to_default = true;
+    cache.update_app(
+        "Firefox Developer Edition".to_string(),
+        app_with_role("Firefox Developer Edition", "firefox-bin", None),
+    );
+    cache.update_app("Firefox ESR".to_string(), app_with_role("Firefox ESR", "firefox-esr", None));
+    cache.routing_rules.insert("firefox".to_string(), "Media".to_string());
+
+    // Off by default
+    assert_eq!(cache.resolve_target("Firefox ESR", &routing).as_deref(), Some("Game"));
+
+    routing.fuzzy_matching = true;
+    assert_eq!(cache.resolve_target("Firefox ESR", &routing).as_deref(), Some("Media"));
+    assert_eq!(
+        cache.resolve_target("Firefox Developer Edition", &routing).as_deref(),
+        Some("Media")
+    );
+
+    // An exact binary-name rule beats the fuzzy one, as does a longer prefix
+    cache.routing_rules.insert("firefox-esr".to_string(), "Chat".to_string());
+    cache.routing_rules.insert("Firefox Developer".to_string(), "Game".to_string());
+    assert_eq!(cache.resolve_target("Firefox ESR", &routing).as_deref(), Some("Chat"));
+    assert_eq!(
+        cache.resolve_target("Firefox Developer Edition", &routing).as_deref(),
+        Some("Game")
+    );
+
+    // A fuzzy match counts as the app's own rule, so nothing new is remembered
+    assert_eq!(cache.auto_route_target("Firefox ESR", &routing).as_deref(), Some("Chat"));
+    assert!(!cache.routing_rules.contains_key("Firefox ESR"));
+}
+
+#[test]
+fn test_fuzzy_rule_requires_minimum_key_length() {
+    let cache = AudioCache::new();
+    let mut routing = Config::default().routing;
+    routing.fuzzy_matching = true;
+    routing.route_unmatched_to_default = true;
+    cache.update_app("Steam".to_string(), app_with_role("Steam", "steam", None));
+    cache.update_app("Stellarium".to_string(), app_with_role("Stellarium", "stellarium", None));
+
+    // A key one character short of the minimum is ignored for prefix matching
+    assert_eq!("ste".len(), FUZZY_MIN_RULE_LENGTH - 1);
+    cache.routing_rules.insert("ste".to_string(), "Chat".to_string());
+    assert_eq!(cache.resolve_target("Steam", &routing).as_deref(), Some("Game"));
+    assert_eq!(cache.resolve_target("Stellarium", &routing).as_deref(), Some("Game"));
+
+    cache.routing_rules.insert("stea".to_string(), "Media".to_string());
+    assert_eq!(cache.resolve_target("Steam", &routing).as_deref(), Some("Media"));
+    assert_eq!(cache.resolve_target("Stellarium", &routing).as_deref(), Some("Game"));
+}
+
+#[test]
+fn test_sink_uptime_grows_and_resets_when_recreated() {
+    let cache = AudioCache::new();
+    let sink = |pipewire_id: u32| SinkInfo {
+        id: pipewire_id,
+        name: "Game".to_string(),
+        volume: 1.0,
+        pipewire_id,
+        applied_percent: 100,
+        ..Default::default()
+    };
+    cache.update_sink("Game".to_string(), sink(34));
+
+    let fresh = cache.sink_uptimes()["Game"];
+    assert!(fresh < Duration::from_secs(1));
+
+    std::thread::sleep(Duration::from_millis(50));
+    // Updating the same sink keeps its discovery time
+    cache.update_sink("Game".to_string(), sink(34));
+    let later = cache.sink_uptimes()["Game"];
+    assert!(later >= fresh + Duration::from_millis(50));
+
+    // A new PipeWire id means the sink was recreated
+    cache.update_sink("Game".to_string(), sink(35));
+    assert!(cache.sink_uptimes()["Game"] < later);
+}
+
+#[test]
+fn test_parse_sink_targets() {
+    assert_eq!(parse_sink_targets("Game"), vec!["Game"]);
+    assert_eq!(parse_sink_targets("Recording, Headphones"), vec!["Recording", "Headphones"]);
+    assert_eq!(parse_sink_targets(" Media,,Game ,Media"), vec!["Media", "Game"]);
+    assert!(parse_sink_targets(" , ").is_empty());
+}
+
+#[test]
+fn test_set_app_sinks_keeps_primary_and_all_targets() {
+    let cache = AudioCache::new();
+    cache.update_app("Firefox".to_string(), app_with_role("Firefox", "firefox", None));
+
+    let targets = vec!["Recording".to_string(), "Headphones".to_string()];
+    assert!(cache.set_app_sinks("Firefox", &targets));
+    {
+        let firefox = cache.apps.get("Firefox").unwrap();
+        assert_eq!(firefox.current_sink, "Recording");
+        assert_eq!(firefox.current_sinks, targets);
+    }
+    assert_eq!(cache.apps_for_sink("Recording"), vec!["Firefox".to_string()]);
+    assert!(cache.apps_for_sink("Game").is_empty());
+
+    // Going back to a single sink clears the target list
+    assert!(cache.set_app_sink("Firefox", "Game"));
+    let firefox = cache.apps.get("Firefox").unwrap();
+    assert_eq!(firefox.current_sink, "Game");
+    assert!(firefox.current_sinks.is_empty());
+    drop(firefox);
+
+    assert!(!cache.set_app_sinks("Firefox", &[]));
+    assert!(!cache.set_app_sinks("Spotify", &targets));
+}
+
+#[test]
+fn test_app_info_without_current_sinks_deserializes() {
+    let app: AppInfo = serde_json::from_str(
+        r#"{"display_name":"Firefox","binary_name":"firefox","stream_names":[],
+            "current_sink":"Game","active":true,"sink_input_ids":[],"pipewire_id":0}"#,
+    )
+    .unwrap();
+    assert_eq!(app.current_sink, "Game");
+    assert!(app.current_sinks.is_empty());
+    assert!(!app.muted);
+}
+
+#[test]
+fn test_app_mute_is_cached_per_app() {
+    let cache = AudioCache::new();
+    let app = |name: &str| AppInfo {
+        display_name: name.to_string(),
+        binary_name: name.to_lowercase(),
+        stream_names: vec![name.to_string()],
+        current_sink: "Media".to_string(),
+        active: true,
+        sink_input_ids: vec![71],
+        pipewire_id: 71,
+        ..Default::default()
+    };
+    cache.update_app("Firefox".to_string(), app("Firefox"));
+    cache.update_app("Spotify".to_string(), app("Spotify"));
+    let generation = cache.get_generation();
+
+    assert!(cache.set_app_mute("Firefox", true));
+    assert!(cache.apps.get("Firefox").unwrap().muted);
+    assert!(!cache.apps.get("Spotify").unwrap().muted);
+    assert_eq!(cache.get_generation(), generation + 1);
+
+    // Setting the same state again isn't a change
+    assert!(cache.set_app_mute("Firefox", true));
+    assert_eq!(cache.get_generation(), generation + 1);
+    assert!(cache.app_record("Firefox").unwrap().muted);
+
+    assert!(!cache.set_app_mute("Chromium", true));
+}
+
+#[test]
+fn test_per_stream_labels_toggle_display_name() {
+    for per_stream_labels in [false, true] {
+        let cache = AudioCache::new().with_per_stream_labels(per_stream_labels);
+        cache.update_app("Firefox".to_string(), app_with_role("Firefox", "firefox", None));
+        assert!(cache.set_stream_label("Firefox", 71, "Lo-fi beats".to_string()));
+        assert!(!cache.set_stream_label("Spotify", 72, "Song".to_string()));
+
+        let expected = if per_stream_labels { "Lo-fi beats" } else { "Firefox" };
+        assert_eq!(cache.app_record("Firefox").unwrap().display_name, expected);
+        assert_eq!(cache.get_snapshot().apps["Firefox"].display_name, expected);
+        // The app keeps its key either way, so rules and routing are unaffected
+        assert_eq!(cache.app_record("Firefox").unwrap().name, "Firefox");
+        assert_eq!(cache.apps.get("Firefox").unwrap().display_name, "Firefox");
+    }
+}
+
+#[test]
+fn test_app_label_falls_back_without_stream_labels() {
+    let mut app = app_with_role("Firefox", "firefox", None);
+    assert_eq!(app.label(true), "Firefox");
+    app.stream_labels = vec![(71, "Podcast".to_string()), (72, "Music video".to_string())];
+    assert_eq!(app.label(true), "Music video");
+    assert_eq!(app.label(false), "Firefox");
+}
+
+#[test]
+fn test_identical_app_update_keeps_generation() {
+    let cache = AudioCache::new();
+    let firefox = app_with_role("Firefox", "firefox", None);
+    cache.update_app("Firefox".to_string(), firefox.clone());
+    let generation = cache.get_generation();
+
+    // Only the time it went inactive differs, which clients never see
+    let resent = AppInfo { inactive_since: Some(std::time::Instant::now()), ..firefox.clone() };
+    cache.update_app("Firefox".to_string(), resent);
+    assert_eq!(cache.get_generation(), generation);
+
+    let moved = AppInfo { current_sink: "Media".to_string(), ..firefox };
+    cache.update_app("Firefox".to_string(), moved);
+    assert_eq!(cache.get_generation(), generation + 1);
+    assert_eq!(cache.apps_for_sink("Media"), vec!["Firefox".to_string()]);
+    assert!(cache.apps_for_sink("Game").is_empty());
+}
+
+#[test]
+fn test_recent_sinks_are_newest_first_without_repeats() {
+    let cache = AudioCache::new();
+    assert!(cache.recent_sinks("Firefox").is_empty());
+
+    for sink in ["Game", "Media", "Chat"] {
+        cache.record_recent_sink("Firefox", sink);
+    }
+    assert_eq!(cache.recent_sinks("Firefox"), vec!["Chat", "Media", "Game"]);
+
+    // Going back to a sink moves it to the front instead of listing it twice
+    cache.record_recent_sink("Firefox", "Game");
+    assert_eq!(cache.recent_sinks("Firefox"), vec!["Game", "Chat", "Media"]);
+    assert!(cache.recent_sinks("Spotify").is_empty());
+}
+
+#[test]
+fn test_recent_sinks_keep_only_the_newest() {
+    let cache = AudioCache::new();
+    let sinks: Vec<String> = (0..RECENT_SINKS_LEN + 2).map(|i| format!("Sink{i}")).collect();
+    for sink in &sinks {
+        cache.record_recent_sink("Firefox", sink);
+    }
+
+    let recent = cache.recent_sinks("Firefox");
+    assert_eq!(recent.len(), RECENT_SINKS_LEN);
+    assert_eq!(recent[0], sinks[sinks.len() - 1]);
+
+    // Restored lists are cut to the same length
+    let restored = AudioCache::new().with_recent_sinks([("Firefox".to_string(), sinks.clone())]);
+    assert_eq!(restored.recent_sinks("Firefox"), sinks[..RECENT_SINKS_LEN].to_vec());
+}
+
+#[test]
+fn test_saved_sink_labels_stay_saved_until_reset() {
+    let cache = AudioCache::new()
+        .with_persisted_sink_labels(true)
+        .with_sink_labels([("Game".to_string(), "Game Audio".to_string())])
+        .with_saved_sink_labels([("Game".to_string(), "Raid Night".to_string())]);
+    assert_eq!(cache.sink_label("Game"), "Raid Night");
+    assert_eq!(cache.labels_to_save().get("Game").map(String::as_str), Some("Raid Night"));
+
+    assert!(cache.set_sink_label("Chat", "Voice".to_string()));
+    assert_eq!(cache.labels_to_save().len(), 2);
+    // Back to the configured label there's nothing left to save for Game
+    assert!(cache.set_sink_label("Game", "Game Audio".to_string()));
+    assert_eq!(cache.sink_label("Game"), "Game Audio");
+    assert_eq!(cache.labels_to_save().keys().collect::<Vec<_>>(), vec!["Chat"]);
+}
+
+#[test]
+fn test_window_title_rule_routes_wine_app() {
+    let cache = AudioCache::new();
+    let routing = Config::default().routing;
+    let mut wine = app_with_role("Wine64-preloader", "wine64-preloader", None);
+    wine.window_title = Some("Elite - Dangerous (CLIENT)".to_string());
+    cache.update_app("Wine64-preloader".to_string(), wine.clone());
+    assert_eq!(cache.resolve_target("Wine64-preloader", &routing), None);
+
+    cache.routing_rules.insert("Elite - Dangerous (CLIENT)".to_string(), "Game".to_string());
+    assert_eq!(cache.resolve_target("Wine64-preloader", &routing).as_deref(), Some("Game"));
+
+    // Rules on the app's own names are more specific than its window title
+    cache.routing_rules.insert("wine64-preloader".to_string(), "Media".to_string());
+    assert_eq!(cache.resolve_target("Wine64-preloader", &routing).as_deref(), Some("Media"));
+
+    // Other Wine apps without that window aren't caught by the title rule
+    cache.routing_rules.remove("wine64-preloader");
+    wine.window_title = Some("Notepad".to_string());
+    cache.update_app("Wine64-preloader".to_string(), wine);
+    assert_eq!(cache.resolve_target("Wine64-preloader", &routing), None);
+}
+
+#[test]
+fn test_reassign_stream_renames_or_merges_the_old_app() {
+    let cache = AudioCache::new();
+    let mut chromium = app_with_role("Chromium", "chromium", None);
+    chromium.sink_input_ids = vec![71];
+    chromium.current_sink = "Media".to_string();
+    cache.update_app("Chromium".to_string(), chromium);
+    cache.record_recent_sink("Chromium", "Media");
+
+    // The only stream takes the whole app along, sink and recent sinks included
+    assert_eq!(cache.reassign_stream(71, "Spotify").as_deref(), Some("Chromium"));
+    assert!(cache.apps.get("Chromium").is_none());
+    let spotify = cache.apps.get("Spotify").unwrap().clone();
+    assert_eq!((spotify.sink_input_ids, spotify.current_sink), (vec![71], "Media".to_string()));
+    assert_eq!(cache.apps_for_sink("Media"), vec!["Spotify".to_string()]);
+    assert_eq!(cache.recent_sinks("Spotify"), vec!["Media".to_string()]);
+    // Nothing to do once the stream is with the right app
+    assert_eq!(cache.reassign_stream(71, "Spotify"), None);
+    assert_eq!(cache.reassign_stream(99, "Spotify"), None);
+
+    // An app that already exists under the new name absorbs the old one
+    let mut discord = app_with_role("Discord", "discord", None);
+    discord.sink_input_ids = vec![80];
+    cache.update_app("Discord".to_string(), discord);
+    let mut webrtc = app_with_role("WEBRTC VoiceEngine", "discord", None);
+    webrtc.sink_input_ids = vec![81];
+    cache.update_app("WEBRTC VoiceEngine".to_string(), webrtc);
+    let generation = cache.get_generation();
+
+    assert_eq!(cache.reassign_stream(81, "Discord").as_deref(), Some("WEBRTC VoiceEngine"));
+    assert!(cache.apps.get("WEBRTC VoiceEngine").is_none());
+    let discord = cache.apps.get("Discord").unwrap().clone();
+    assert_eq!(discord.sink_input_ids, vec![80, 81]);
+    assert_eq!(discord.stream_names, vec!["Discord".to_string(), "WEBRTC VoiceEngine".to_string()]);
+    assert!(cache.get_generation() > generation);
+}
+
+fn cache_for_stale_rules() -> AudioCache {
+    let cache = AudioCache::new();
+    for (id, name) in [(1, "Game"), (2, "Chat")] {
+        let sink = SinkInfo {
+            id,
+            name: name.to_string(),
+            volume: 1.0,
+            pipewire_id: id,
+            applied_percent: 100,
+            ..Default::default()
+        };
+        cache.update_sink(name.to_string(), sink);
+    }
+    cache.add_physical_sink("alsa_output.speakers", "Speakers");
+    cache.routing_rules.insert("firefox".to_string(), "Media".to_string());
+    cache.routing_rules.insert("obs".to_string(), "Chat, Recording".to_string());
+    cache.routing_rules.insert("discord".to_string(), "Chat".to_string());
+    cache.routing_rules.insert("mpv".to_string(), "alsa_output.speakers".to_string());
+    cache.remembered_apps.insert("firefox".to_string(), "Media".to_string());
+    cache
+}
+
+/// Virtual sinks the stale rules name that are no longer configured
+fn removed_sinks() -> Vec<String> {
+    vec!["Media".to_string(), "Recording".to_string()]
+}
+
+#[test]
+fn test_dangling_rules_name_missing_sinks() {
+    let cache = cache_for_stale_rules();
+
+    assert_eq!(
+        cache.dangling_rules(),
+        vec![
+            ("firefox".to_string(), "Media".to_string()),
+            ("obs".to_string(), "Chat, Recording".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn test_reconcile_keeps_stale_rules_by_default() {
+    let cache = cache_for_stale_rules();
+    let generation = cache.get_generation();
+
+    let stale = cache.reconcile_routing_rules(StaleRulePolicy::default(), "Game", &removed_sinks());
+
+    assert_eq!(stale.len(), 2);
+    assert_eq!(cache.routing_rules.get("firefox").unwrap().as_str(), "Media");
+    assert_eq!(cache.get_generation(), generation);
+}
+
+#[test]
+fn test_reconcile_prunes_stale_rules() {
+    let cache = cache_for_stale_rules();
+
+    cache.reconcile_routing_rules(StaleRulePolicy::Prune, "Game", &removed_sinks());
+
+    assert!(cache.routing_rules.get("firefox").is_none());
+    assert!(cache.routing_rules.get("obs").is_none());
+    assert!(cache.remembered_apps.get("firefox").is_none());
+    assert_eq!(cache.routing_rules.len(), 2);
+    assert!(cache.dangling_rules().is_empty());
+}
+
+#[test]
+fn test_reconcile_keeps_rules_for_sinks_that_are_only_missing() {
+    let cache = cache_for_stale_rules();
+    cache.routing_rules.insert("vlc".to_string(), "alsa_output.usb_headset".to_string());
+
+    // The headset is unplugged, not taken out of the config
+    let stale = cache.reconcile_routing_rules(StaleRulePolicy::Prune, "Game", &removed_sinks());
+
+    assert_eq!(stale.len(), 2);
+    assert_eq!(cache.routing_rules.get("vlc").unwrap().as_str(), "alsa_output.usb_headset");
+    assert_eq!(
+        cache.dangling_rules(),
+        vec![("vlc".to_string(), "alsa_output.usb_headset".to_string())]
+    );
+}
+
+#[test]
+fn test_reconcile_redirects_stale_rules_to_default_sink() {
+    let cache = cache_for_stale_rules();
+    let generation = cache.get_generation();
+
+    cache.reconcile_routing_rules(StaleRulePolicy::Redirect, "Game", &removed_sinks());
+
+    assert_eq!(cache.routing_rules.get("firefox").unwrap().as_str(), "Game");
+    assert_eq!(cache.routing_rules.get("obs").unwrap().as_str(), "Game");
+    assert_eq!(cache.remembered_apps.get("firefox").unwrap().as_str(), "Game");
+    assert_eq!(cache.routing_rules.get("discord").unwrap().as_str(), "Chat");
+    assert!(cache.get_generation() > generation);
+}
+
+#[test]
+fn test_reconcile_keeps_stale_rules_without_a_default_sink() {
+    let cache = cache_for_stale_rules();
+
+    cache.reconcile_routing_rules(StaleRulePolicy::Redirect, "Missing", &removed_sinks());
+
+    assert_eq!(cache.routing_rules.get("firefox").unwrap().as_str(), "Media");
+    assert_eq!(cache.dangling_rules().len(), 2);
+}
+
+fn inactive_app(binary_name: &str, inactive_for: Duration) -> AppInfo {
+    AppInfo {
+        display_name: binary_name.to_string(),
+        binary_name: binary_name.to_string(),
+        stream_names: vec![binary_name.to_string()],
+        current_sink: "Game".to_string(),
+        current_sinks: vec![],
+        stream_labels: vec![],
+        window_title: None,
+        active: false,
+        playing: false,
+        muted: false,
+        sink_input_ids: vec![],
+        pipewire_id: 0,
+        media_role: None,
+        volume: None,
+        inactive_since: Some(std::time::Instant::now() - inactive_for),
+    }
+}
+
+#[test]
+fn test_pinned_app_auto_routes_after_cleanup() {
+    let cache = AudioCache::new();
+    let routing = Config::default().routing;
+    cache.set_auto_routing(false);
+    cache.pin_app("Spotify", "Media");
+    cache.remembered_apps.insert("Spotify".to_string(), "Media".to_string());
+    cache.update_app("Spotify".to_string(), inactive_app("spotify", Duration::from_secs(600)));
+
+    assert_eq!(cache.cleanup_inactive_apps(300), 1);
+    assert!(cache.apps.get("Spotify").is_none());
+    assert!(cache.remembered_apps.get("Spotify").is_none());
+
+    // Back again, routed by its pin even though auto-routing is off
+    let mut app = inactive_app("spotify", Duration::ZERO);
+    app.active = true;
+    cache.update_app("Spotify".to_string(), app);
+    assert_eq!(cache.auto_route_target("Spotify", &routing).as_deref(), Some("Media"));
+    // The pin isn't turned into a routing rule, so cleanup still drops the app later
+    assert!(cache.routing_rules.get("Spotify").is_none());
+
+    assert!(cache.unpin_app("Spotify"));
+    assert!(!cache.unpin_app("Spotify"));
+    assert_eq!(cache.auto_route_target("Spotify", &routing), None);
+}
+
+#[test]
+fn test_pin_matches_binary_name_and_beats_rules() {
+    let cache = AudioCache::new().with_pins([("firefox".to_string(), "Chat".to_string())]);
+    let routing = Config::default().routing;
+    cache.routing_rules.insert("Firefox".to_string(), "Media".to_string());
+    cache.update_app("Firefox".to_string(), inactive_app("firefox", Duration::ZERO));
+
+    assert_eq!(cache.auto_route_target("Firefox", &routing).as_deref(), Some("Chat"));
+    assert_eq!(cache.resolve_target("Firefox", &routing).as_deref(), Some("Chat"));
+    assert_eq!(
+        cache.pins().into_iter().collect::<Vec<_>>(),
+        vec![("firefox".to_string(), "Chat".to_string())]
+    );
+}
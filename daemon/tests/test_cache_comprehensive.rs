@@ -22,8 +22,8 @@ fn test_concurrent_cache_access() {
                     id: (i * 100 + j) as u32,
                     name: format!("Sink_{i}"),
                     volume: 0.5,
-                    muted: false,
                     pipewire_id: (i * 100 + j) as u32,
+                    ..Default::default()
                 };
                 cache_clone.update_sink(format!("Sink_{i}_{j}"), sink);
             }
@@ -43,8 +43,13 @@ fn test_concurrent_cache_access() {
 fn test_cache_performance_single_update() {
     let cache = AudioCache::new();
 
-    let sink =
-        SinkInfo { id: 1, name: "Test".to_string(), volume: 0.5, muted: false, pipewire_id: 1 };
+    let sink = SinkInfo {
+        id: 1,
+        name: "Test".to_string(),
+        volume: 0.5,
+        pipewire_id: 1,
+        ..Default::default()
+    };
 
     let start = Instant::now();
     cache.update_sink("Test".to_string(), sink);
@@ -68,8 +73,8 @@ fn test_cache_performance_bulk_updates() {
             id: i as u32,
             name: format!("Sink_{i}"),
             volume: 0.5,
-            muted: false,
             pipewire_id: i as u32,
+            ..Default::default()
         };
         cache.update_sink(format!("Sink_{i}"), sink);
     }
@@ -94,8 +99,8 @@ fn test_snapshot_performance() {
                 id: i,
                 name: format!("Sink_{i}"),
                 volume: 0.5,
-                muted: false,
                 pipewire_id: i,
+                ..Default::default()
             },
         );
     }
@@ -111,7 +116,7 @@ fn test_snapshot_performance() {
                 active: true,
                 sink_input_ids: vec![i * 2, i * 2 + 1],
                 pipewire_id: i,
-                inactive_since: None,
+                ..Default::default()
             },
         );
     }
@@ -142,9 +147,9 @@ fn test_memory_cleanup() {
                 stream_names: vec![format!("inactive_{i}")],
                 current_sink: "Game".to_string(),
                 active: false,
-                sink_input_ids: vec![],
                 pipewire_id: i + 100,
                 inactive_since: Some(Instant::now() - Duration::from_secs(400)), // Old inactive
+                ..Default::default()
             },
         );
     }
@@ -161,7 +166,7 @@ fn test_memory_cleanup() {
                 active: true,
                 sink_input_ids: vec![i],
                 pipewire_id: i + 200,
-                inactive_since: None,
+                ..Default::default()
             },
         );
     }
@@ -194,8 +199,7 @@ fn test_routing_rules_persistence() {
             current_sink: "Media".to_string(),
             active: true,
             sink_input_ids: vec![1],
-            pipewire_id: 0,
-            inactive_since: None,
+            ..Default::default()
         },
     );
 
@@ -236,8 +240,7 @@ fn test_cache_with_special_characters() {
                 current_sink: "Game".to_string(),
                 active: true,
                 sink_input_ids: vec![1],
-                pipewire_id: 0,
-                inactive_since: None,
+                ..Default::default()
             },
         );
 
@@ -263,8 +266,8 @@ async fn test_async_cache_operations() {
                         id: (i * 100 + j) as u32,
                         name: format!("AsyncSink_{i}"),
                         volume: 0.5,
-                        muted: false,
                         pipewire_id: (i * 100 + j) as u32,
+                        ..Default::default()
                     },
                 );
                 drop(cache_write);
@@ -309,8 +312,7 @@ fn test_wine_app_detection() {
                 current_sink: "Game".to_string(),
                 active: true,
                 sink_input_ids: vec![1],
-                pipewire_id: 0,
-                inactive_since: None,
+                ..Default::default()
             },
         );
 
@@ -331,8 +333,8 @@ fn test_cache_memory_usage() {
                 id: i,
                 name: format!("Very_Long_Sink_Name_To_Test_Memory_Usage_{i}"),
                 volume: 0.5,
-                muted: false,
                 pipewire_id: i,
+                ..Default::default()
             },
         );
 
@@ -347,6 +349,7 @@ fn test_cache_memory_usage() {
                 sink_input_ids: vec![i * 2, i * 2 + 1],
                 pipewire_id: i,
                 inactive_since: if i % 2 == 1 { Some(Instant::now()) } else { None },
+                ..Default::default()
             },
         );
     }
@@ -364,3 +367,63 @@ fn test_cache_memory_usage() {
         duration.as_millis()
     );
 }
+
+#[test]
+fn test_cleanup_concurrent_with_updates() {
+    let cache = Arc::new(AudioCache::new());
+    let expired_app = |i: u32| AppInfo {
+        display_name: format!("App_{i}"),
+        binary_name: format!("app_{i}"),
+        stream_names: vec![format!("app_{i}")],
+        current_sink: "Game".to_string(),
+        active: false,
+        pipewire_id: i,
+        inactive_since: Some(Instant::now() - Duration::from_secs(400)),
+        ..Default::default()
+    };
+
+    // Updaters keep touching apps, remembered apps and routing rules while cleanup runs
+    let mut handles = vec![];
+    for t in 0..4u32 {
+        let cache = cache.clone();
+        handles.push(std::thread::spawn(move || {
+            for i in 0..2000u32 {
+                let id = t * 10_000 + i;
+                cache.update_app(format!("App_{id}"), expired_app(id));
+                cache.remembered_apps.insert(format!("App_{id}"), "Game".to_string());
+                if i % 10 == 0 {
+                    cache.routing_rules.insert(format!("App_{id}"), "Chat".to_string());
+                }
+                if let Some(mut app) = cache.apps.get_mut(&format!("App_{}", id.saturating_sub(1)))
+                {
+                    app.active = true;
+                }
+            }
+        }));
+    }
+
+    let cleaner = {
+        let cache = cache.clone();
+        std::thread::spawn(move || {
+            let mut removed = 0;
+            for _ in 0..200 {
+                removed += cache.cleanup_inactive_apps(300);
+            }
+            removed
+        })
+    };
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    let removed = cleaner.join().unwrap();
+    let removed = removed + cache.cleanup_inactive_apps(300);
+
+    // Only active apps and apps with routing rules survive, and no removal was lost
+    for entry in cache.apps.iter() {
+        assert!(entry.value().active || cache.routing_rules.contains_key(entry.key()));
+    }
+    assert!(removed > 0);
+    assert_eq!(cache.apps.len() + removed, 8000);
+    assert_eq!(cache.cleanup_inactive_apps(300), 0);
+}
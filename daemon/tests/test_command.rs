@@ -0,0 +1,42 @@
+use pipewire_volume_mixer_daemon::command::{CommandExecutor, TracingExecutor};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ExitStatus, Output};
+use std::sync::Arc;
+
+/// Fails `pactl move-sink-input`, succeeds at everything else
+struct MoveFailsExecutor;
+
+impl CommandExecutor for MoveFailsExecutor {
+    fn execute(&self, _program: &str, args: &[&str]) -> std::io::Result<Output> {
+        let code = if args.first() == Some(&"move-sink-input") { 1 } else { 0 };
+        Ok(Output { status: ExitStatus::from_raw(code << 8), stdout: vec![], stderr: vec![] })
+    }
+
+    fn execute_shell(&self, _cmd: &str) -> std::io::Result<Output> {
+        Err(std::io::Error::new(std::io::ErrorKind::NotFound, "no shell"))
+    }
+}
+
+#[test]
+fn test_traced_commands_are_recorded_with_their_exit_status() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("commands.log");
+    let tracer = TracingExecutor::new(Arc::new(MoveFailsExecutor)).with_file(&path).unwrap();
+
+    // The traced command's result is passed through untouched
+    let output = tracer.execute("pactl", &["move-sink-input", "71", "Media"]).unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    tracer.execute("pactl", &["set-sink-volume", "Game Sink", "50%"]).unwrap();
+    assert!(tracer.execute_shell("xdotool search --pid 42").is_err());
+
+    let trace = std::fs::read_to_string(&path).unwrap();
+    let entries: Vec<&str> = trace.lines().map(|line| line.split_once(' ').unwrap().1).collect();
+    assert_eq!(
+        entries,
+        vec![
+            "pactl move-sink-input 71 Media: exit status: 1",
+            "pactl set-sink-volume 'Game Sink' 50%: exit status: 0",
+            "sh -c 'xdotool search --pid 42': failed to start: no shell",
+        ]
+    );
+}
@@ -0,0 +1,376 @@
+use pipewire_volume_mixer_daemon::config::{
+    expand_path, expand_path_with, AppMappings, AppSettings, Config, SinkMatch, StaleRulePolicy,
+};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+#[test]
+fn test_role_sink_matches_media_role() {
+    let mut config = Config::default();
+    config.routing.role_rules.insert("Music".to_string(), "Media".to_string());
+    config.routing.role_rules.insert("Communication".to_string(), "Chat".to_string());
+    let routing = &config.routing;
+
+    assert_eq!(routing.role_sink("Music"), Some("Media"));
+    // PipeWire role names aren't consistently capitalized
+    assert_eq!(routing.role_sink("communication"), Some("Chat"));
+    assert_eq!(routing.role_sink("Notification"), None);
+}
+
+#[test]
+fn test_role_rules_default_to_empty() {
+    let config: Config = toml::from_str(
+        r#"
+        virtual_sinks = []
+
+        [cache]
+        update_interval_ms = 100
+        max_remembered_apps = 50
+
+        [routing]
+        enable_auto_routing = true
+        default_sink = "Game"
+        rules = {}
+
+        [performance]
+        event_debounce_ms = 50
+        max_events_per_second = 100
+        "#,
+    )
+    .unwrap();
+    assert!(config.routing.role_rules.is_empty());
+    assert_eq!(config.routing.stale_rules, StaleRulePolicy::Keep);
+    // Older configs keep capitalizing binary-derived names
+    assert!(config.cache.capitalize_binary_names);
+    assert_eq!(
+        config.cache.app_identity_properties,
+        Config::default().cache.app_identity_properties
+    );
+}
+
+#[test]
+fn test_denylisted_app_is_not_tracked() {
+    let mut config = Config::default();
+    config.cache.track_denylist = vec!["canberra-gtk-play".to_string()];
+    let cache = &config.cache;
+
+    assert!(!cache.should_track(&["Event Sound", "canberra-gtk-play"]));
+    assert!(!cache.should_track(&["Canberra-GTK-Play"]));
+    // With no allowlist, everything else is tracked
+    assert!(cache.should_track(&["Firefox", "firefox"]));
+}
+
+#[test]
+fn test_allowlist_ignores_other_apps() {
+    let mut config = Config::default();
+    config.cache.track_allowlist = vec!["firefox".to_string(), "Discord".to_string()];
+    config.cache.track_denylist = vec!["discord".to_string()];
+    let cache = &config.cache;
+
+    assert!(cache.should_track(&["Firefox", "firefox"]));
+    assert!(!cache.should_track(&["Spotify", "spotify"]));
+    // The denylist takes precedence over the allowlist
+    assert!(!cache.should_track(&["Discord", "Discord"]));
+}
+
+#[test]
+fn test_app_identity_follows_configured_property_order() {
+    let props = HashMap::from([
+        ("application.name", ""),
+        ("application.process.binary", "obscure-player"),
+        ("media.name", "Episode 12"),
+        ("node.description", "Obscure Player Output"),
+    ]);
+    let property = |key: &str| props.get(key).map(|value| value.to_string());
+    let mut config = Config::default();
+
+    // A blank application.name falls through to the next property
+    assert_eq!(config.cache.app_identity(property).as_deref(), Some("Obscure Player Output"));
+
+    config.cache.app_identity_properties =
+        vec!["media.name".to_string(), "application.process.binary".to_string()];
+    assert_eq!(config.cache.app_identity(property).as_deref(), Some("Episode 12"));
+
+    config.cache.app_identity_properties =
+        vec!["application.id".to_string(), "application.process.binary".to_string()];
+    assert_eq!(config.cache.app_identity(property).as_deref(), Some("obscure-player"));
+
+    config.cache.app_identity_properties = vec!["application.id".to_string()];
+    assert_eq!(config.cache.app_identity(property), None);
+}
+
+/// Looks variables up in `vars` instead of the process environment
+fn fake_env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+    let vars: HashMap<String, String> =
+        vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+    move |name| vars.get(name).cloned()
+}
+
+#[test]
+fn test_expand_path_uses_environment() {
+    let env = fake_env(&[("HOME", "/home/alice"), ("XDG_CONFIG_HOME", "/home/alice/.xdg")]);
+    let expand = |path| expand_path_with(path, &env);
+
+    assert_eq!(expand("~"), PathBuf::from("/home/alice"));
+    assert_eq!(expand("~/mixer.sock"), PathBuf::from("/home/alice/mixer.sock"));
+    assert_eq!(expand("$HOME/mixer.sock"), PathBuf::from("/home/alice/mixer.sock"));
+    assert_eq!(expand("${HOME}.d/x"), PathBuf::from("/home/alice.d/x"));
+    assert_eq!(expand("$XDG_CONFIG_HOME/mixer"), PathBuf::from("/home/alice/.xdg/mixer"));
+    // Only a leading tilde means home, and unknown variables are kept as written
+    assert_eq!(expand("/srv/~x"), PathBuf::from("/srv/~x"));
+    assert_eq!(expand("$PVM_UNSET_VAR/x"), PathBuf::from("$PVM_UNSET_VAR/x"));
+}
+
+#[test]
+fn test_expand_path_falls_back_to_xdg_defaults() {
+    let env = fake_env(&[("HOME", "/home/alice"), ("XDG_CONFIG_HOME", "")]);
+    // An empty XDG_CONFIG_HOME counts as unset, so the default under $HOME applies
+    assert_eq!(
+        expand_path_with("$XDG_CONFIG_HOME/mixer", &env),
+        PathBuf::from("/home/alice/.config/mixer")
+    );
+
+    // Without $HOME the home directory is guessed from $USER
+    let env = fake_env(&[("USER", "bob")]);
+    assert_eq!(expand_path_with("~/mixer", &env), PathBuf::from("/home/bob/mixer"));
+    assert_eq!(expand_path_with("$XDG_CONFIG_HOME", &env), PathBuf::from("/home/bob/.config"));
+}
+
+#[test]
+fn test_configured_paths_are_expanded() {
+    let config: Config = toml::from_str(
+        r#"
+        virtual_sinks = []
+
+        [cache]
+        update_interval_ms = 100
+        max_remembered_apps = 50
+
+        [routing]
+        enable_auto_routing = true
+        default_sink = "Game"
+        rules = {}
+
+        [performance]
+        event_debounce_ms = 50
+        max_events_per_second = 100
+
+        [paths]
+        socket = "$XDG_RUNTIME_DIR/mixer.sock"
+        mappings_dir = "~/mixer"
+        "#,
+    )
+    .unwrap();
+    assert_eq!(config.paths.socket_path(), Some(expand_path("$XDG_RUNTIME_DIR/mixer.sock")));
+    assert_eq!(config.paths.shm_path(), None);
+    assert_eq!(
+        config.paths.mappings_file(),
+        Some(expand_path("~/mixer").join("app-mappings.toml"))
+    );
+    assert_eq!(
+        AppMappings::config_dir().unwrap(),
+        expand_path("$XDG_CONFIG_HOME").join("pipewire-volume-mixer")
+    );
+}
+
+#[test]
+fn test_app_mappings_save_to_the_file_they_came_from() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("nested").join("app-mappings.toml");
+
+    let mut mappings = AppMappings::load_from(&file).unwrap();
+    assert!(mappings.mappings.is_empty());
+    mappings.update_and_save("Firefox".to_string(), "Media".to_string()).unwrap();
+
+    let reloaded = AppMappings::load_from(&file).unwrap();
+    assert_eq!(reloaded.get("Firefox").map(String::as_str), Some("Media"));
+}
+
+#[test]
+fn test_app_mappings_keep_recent_sinks() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("app-mappings.toml");
+
+    let mut mappings = AppMappings::load_from(&file).unwrap();
+    mappings
+        .recent_sinks
+        .insert("Firefox".to_string(), vec!["Media".to_string(), "Game".to_string()]);
+    mappings.update_and_save("Firefox".to_string(), "Media".to_string()).unwrap();
+
+    let reloaded = AppMappings::load_from(&file).unwrap();
+    assert_eq!(reloaded.recent_sinks["Firefox"], vec!["Media", "Game"]);
+    // Files saved before recent sinks were tracked still load
+    std::fs::write(&file, "version = 1\n[mappings]\nFirefox = \"Media\"\n").unwrap();
+    assert!(AppMappings::load_from(&file).unwrap().recent_sinks.is_empty());
+}
+
+#[test]
+fn test_json_and_toml_configs_load_the_same() {
+    let dir = tempfile::tempdir().unwrap();
+    let toml_file = dir.path().join("config.toml");
+    let json_file = dir.path().join("config.json");
+    std::fs::write(
+        &toml_file,
+        r#"
+auto_create_sinks = true
+loopback_target_suffixes = ["_to_Speaker", "_to_Headphones"]
+
+[[virtual_sinks]]
+name = "Game"
+display_name = "Game"
+icon = "input-gaming-symbolic"
+default_volume = 0.8
+
+[[virtual_sinks]]
+name = "Chat"
+display_name = "Chat"
+icon = "user-available-symbolic"
+
+[routing]
+enable_auto_routing = true
+default_sink = "Game"
+stale_rules = "prune"
+
+[routing.rules]
+discord = "Chat"
+
+[cache]
+update_interval_ms = 100
+max_remembered_apps = 50
+track_denylist = ["speech-dispatcher"]
+
+[performance]
+event_debounce_ms = 50
+max_events_per_second = 100
+snapshot_interval_min_ms = 20
+"#,
+    )
+    .unwrap();
+    std::fs::write(
+        &json_file,
+        r#"{
+  "auto_create_sinks": true,
+  "loopback_target_suffixes": ["_to_Speaker", "_to_Headphones"],
+  "virtual_sinks": [
+    {
+      "name": "Game",
+      "display_name": "Game",
+      "icon": "input-gaming-symbolic",
+      "default_volume": 0.8
+    },
+    {"name": "Chat", "display_name": "Chat", "icon": "user-available-symbolic"}
+  ],
+  "routing": {
+    "enable_auto_routing": true,
+    "default_sink": "Game",
+    "stale_rules": "prune",
+    "rules": {"discord": "Chat"}
+  },
+  "cache": {
+    "update_interval_ms": 100,
+    "max_remembered_apps": 50,
+    "track_denylist": ["speech-dispatcher"]
+  },
+  "performance": {
+    "event_debounce_ms": 50,
+    "max_events_per_second": 100,
+    "snapshot_interval_min_ms": 20
+  }
+}"#,
+    )
+    .unwrap();
+
+    let from_toml = Config::load(&toml_file).unwrap();
+    let from_json = Config::load(&json_file).unwrap();
+    assert_eq!(from_json.routing.stale_rules, StaleRulePolicy::Prune);
+    assert_eq!(from_json.virtual_sinks[0].default_volume, Some(0.8));
+    assert_eq!(
+        serde_json::to_value(&from_toml).unwrap(),
+        serde_json::to_value(&from_json).unwrap()
+    );
+
+    // The extension decides, so TOML in a .json file doesn't load
+    std::fs::copy(&toml_file, &json_file).unwrap();
+    assert!(Config::load(&json_file).is_err());
+}
+
+#[test]
+fn test_app_mappings_keep_the_json_format() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("app-mappings.json");
+
+    let mut mappings = AppMappings::load_from(&file).unwrap();
+    mappings.update_and_save("Firefox".to_string(), "Media".to_string()).unwrap();
+
+    let saved: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap();
+    assert_eq!(saved["mappings"]["Firefox"], "Media");
+    let reloaded = AppMappings::load_from(&file).unwrap();
+    assert_eq!(reloaded.get("Firefox").map(String::as_str), Some("Media"));
+}
+
+#[test]
+fn test_virtual_sinks_match_by_the_configured_property() {
+    let mut config = Config::default();
+    assert_eq!(config.match_sinks_by, SinkMatch::NodeName);
+    let found = |config: &Config, node_name: &str, description: Option<&str>| {
+        config.find_virtual_sink(node_name, description).map(|sink| sink.name.clone())
+    };
+    // A null sink named in the config, and one whose node.name was generated
+    let named = ("Game", Some("Game Audio"));
+    let generated = ("output.null-sink.3", Some("Game"));
+    let undescribed = ("output.null-sink.4", None);
+
+    assert_eq!(found(&config, named.0, named.1).as_deref(), Some("Game"));
+    assert_eq!(found(&config, generated.0, generated.1), None);
+
+    config.match_sinks_by = SinkMatch::Description;
+    assert_eq!(found(&config, named.0, named.1), None);
+    assert_eq!(found(&config, generated.0, generated.1).as_deref(), Some("Game"));
+    assert_eq!(found(&config, undescribed.0, undescribed.1), None);
+
+    config.match_sinks_by = SinkMatch::Either;
+    assert_eq!(found(&config, named.0, named.1).as_deref(), Some("Game"));
+    assert_eq!(found(&config, generated.0, generated.1).as_deref(), Some("Game"));
+    assert_eq!(found(&config, undescribed.0, undescribed.1), None);
+    // node.name wins over another sink's description
+    assert_eq!(found(&config, "Media", Some("Chat")).as_deref(), Some("Media"));
+
+    let parsed: SinkMatch = toml::Value::String("either".to_string()).try_into().unwrap();
+    assert_eq!(parsed, SinkMatch::Either);
+}
+
+#[test]
+fn test_app_mappings_keep_app_volumes_and_mutes() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("app-mappings.toml");
+
+    let mut mappings = AppMappings::load_from(&file).unwrap();
+    mappings
+        .app_settings
+        .insert("Spotify".to_string(), AppSettings { volume: Some(0.4), muted: Some(false) });
+    // Only ever muted, so there's no volume to restore
+    mappings
+        .app_settings
+        .insert("Discord".to_string(), AppSettings { volume: None, muted: Some(true) });
+    mappings.save().unwrap();
+
+    let reloaded = AppMappings::load_from(&file).unwrap();
+    assert_eq!(reloaded.app_settings, mappings.app_settings);
+    // Files saved before app settings were kept still load
+    std::fs::write(&file, "version = 1\n[mappings]\nFirefox = \"Media\"\n").unwrap();
+    assert!(AppMappings::load_from(&file).unwrap().app_settings.is_empty());
+}
+
+#[test]
+fn test_sink_order_defaults_to_declaration_order() {
+    let mut config = Config::default();
+    let names: Vec<String> = config.virtual_sinks.iter().map(|sink| sink.name.clone()).collect();
+    let expected: Vec<(String, u32)> = names.iter().cloned().zip(0..).collect();
+    assert_eq!(config.sink_order(), expected);
+
+    // A configured order replaces the sink's place in the list
+    config.virtual_sinks[0].order = Some(7);
+    assert_eq!(config.sink_order()[0], (names[0].clone(), 7));
+    assert_eq!(config.sink_order()[1], (names[1].clone(), 1));
+}
@@ -0,0 +1,1604 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use pipewire_volume_mixer_daemon::backend::{
+    loopback_module_args, loopback_node_names, null_sink_module_args, parse_default_sink,
+    parse_server_version, parse_sinks_short, Node, PipeWireBackend, SinkEntry,
+};
+use pipewire_volume_mixer_daemon::cache::{AppInfo, AudioCache, SinkInfo};
+use pipewire_volume_mixer_daemon::command::CommandExecutor;
+use pipewire_volume_mixer_daemon::config::{CacheConfig, Config};
+use pipewire_volume_mixer_daemon::events::EventKind;
+use pipewire_volume_mixer_daemon::mock_backend::MockBackend;
+use pipewire_volume_mixer_daemon::pipewire_controller::PipeWireController;
+use pipewire_volume_mixer_daemon::resume::ResumeWatcher;
+use pipewire_volume_mixer_daemon::sink_inputs::SinkInput;
+use std::collections::HashMap;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ExitStatus, Output};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+const FIREFOX_STREAMS: &str = r#"Sink Input #71
+	Driver: PipeWire
+	Sink: 57
+	Properties:
+		application.name = "Firefox"
+		application.process.binary = "firefox"
+
+Sink Input #72
+	Driver: PipeWire
+	Sink: 57
+	Properties:
+		application.name = "Firefox"
+		application.process.binary = "firefox"
+
+Sink Input #73
+	Driver: PipeWire
+	Sink: 57
+	Properties:
+		application.name = "Firefox"
+		application.process.binary = "firefox"
+
+Sink Input #80
+	Driver: PipeWire
+	Sink: 56
+	Properties:
+		application.name = "Spotify"
+		application.process.binary = "spotify"
+"#;
+
+/// Answers pactl calls with canned output and records every invocation
+#[derive(Default)]
+struct RecordingExecutor {
+    calls: Mutex<Vec<String>>,
+}
+
+impl RecordingExecutor {
+    fn count(&self, command: &str) -> usize {
+        self.calls.lock().unwrap().iter().filter(|call| call.as_str() == command).count()
+    }
+
+    fn last_with_prefix(&self, prefix: &str) -> Option<String> {
+        self.calls.lock().unwrap().iter().rev().find(|call| call.starts_with(prefix)).cloned()
+    }
+}
+
+impl CommandExecutor for RecordingExecutor {
+    fn execute(&self, program: &str, args: &[&str]) -> std::io::Result<Output> {
+        let call = format!("{program} {}", args.join(" "));
+        self.calls.lock().unwrap().push(call.clone());
+
+        let stdout = match call.as_str() {
+            "pactl list sink-inputs" => FIREFOX_STREAMS.to_string(),
+            "pactl list sinks short" => "56\tGame\tPipeWire\n57\tMedia\tPipeWire\n".to_string(),
+            _ if call.starts_with("pactl load-module") => "536870913\n".to_string(),
+            _ => String::new(),
+        };
+        Ok(Output { status: ExitStatus::from_raw(0), stdout: stdout.into_bytes(), stderr: vec![] })
+    }
+
+    fn execute_shell(&self, _cmd: &str) -> std::io::Result<Output> {
+        Ok(Output { status: ExitStatus::from_raw(1), stdout: vec![], stderr: vec![] })
+    }
+}
+
+#[tokio::test]
+async fn test_route_app_lists_sink_inputs_twice() {
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    {
+        let cache_write = cache.write().await;
+        cache_write.update_sink(
+            "Media".to_string(),
+            SinkInfo {
+                id: 57,
+                name: "Media".to_string(),
+                volume: 1.0,
+                pipewire_id: 57,
+                applied_percent: 100,
+                ..Default::default()
+            },
+        );
+        cache_write.update_app(
+            "Firefox".to_string(),
+            AppInfo {
+                display_name: "Firefox".to_string(),
+                binary_name: "firefox".to_string(),
+                stream_names: vec!["Firefox".to_string()],
+                current_sink: "Game".to_string(),
+                active: true,
+                sink_input_ids: vec![71],
+                pipewire_id: 71,
+                ..Default::default()
+            },
+        );
+    }
+
+    let executor = Arc::new(RecordingExecutor::default());
+    let controller = PipeWireController::with_executor(cache.clone(), executor.clone());
+    controller.route_app("Firefox", "Media").await.unwrap();
+
+    // One lookup before the move and one to verify it, regardless of stream count
+    assert_eq!(executor.count("pactl list sink-inputs"), 2);
+    for id in [71, 72, 73] {
+        assert_eq!(executor.count(&format!("pactl move-sink-input {id} Media")), 1);
+    }
+    assert_eq!(executor.count("pactl move-sink-input 80 Media"), 0);
+
+    let cache_read = cache.read().await;
+    let firefox = cache_read.apps.get("Firefox").unwrap();
+    assert_eq!(firefox.current_sink, "Media");
+    assert_eq!(firefox.sink_input_ids, vec![71, 72, 73]);
+}
+
+#[tokio::test]
+async fn test_route_app_to_several_sinks_uses_combine_sink() {
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    {
+        let cache_write = cache.write().await;
+        for (name, id) in [("Game", 56), ("Media", 57)] {
+            cache_write.update_sink(
+                name.to_string(),
+                SinkInfo {
+                    id,
+                    name: name.to_string(),
+                    volume: 1.0,
+                    pipewire_id: id,
+                    applied_percent: 100,
+                    ..Default::default()
+                },
+            );
+        }
+        cache_write.update_app(
+            "Firefox".to_string(),
+            AppInfo {
+                display_name: "Firefox".to_string(),
+                binary_name: "firefox".to_string(),
+                stream_names: vec!["Firefox".to_string()],
+                current_sink: "Game".to_string(),
+                active: true,
+                sink_input_ids: vec![71],
+                pipewire_id: 71,
+                ..Default::default()
+            },
+        );
+    }
+
+    let executor = Arc::new(RecordingExecutor::default());
+    let controller = PipeWireController::with_executor(cache.clone(), executor.clone());
+    controller.route_app("Firefox", "Media, Game").await.unwrap();
+
+    assert_eq!(
+        executor.count(
+            "pactl load-module module-combine-sink sink_name=combined_Media_Game slaves=Media,Game"
+        ),
+        1
+    );
+    for id in [71, 72, 73] {
+        assert_eq!(executor.count(&format!("pactl move-sink-input {id} combined_Media_Game")), 1);
+    }
+
+    let cache_read = cache.read().await;
+    let firefox = cache_read.apps.get("Firefox").unwrap();
+    assert_eq!(firefox.current_sink, "Media");
+    assert_eq!(firefox.current_sinks, vec!["Media".to_string(), "Game".to_string()]);
+    drop(firefox);
+    assert_eq!(cache_read.apps_for_sink("Media"), vec!["Firefox".to_string()]);
+    assert_eq!(cache_read.remembered_apps.get("Firefox").unwrap().as_str(), "Media,Game");
+}
+
+#[tokio::test]
+async fn test_route_app_to_several_sinks_requires_every_sink() {
+    let (controller, _backend, cache) = fake_controller();
+    cache.read().await.update_sink(
+        "Media".to_string(),
+        SinkInfo {
+            id: 57,
+            name: "Media".to_string(),
+            volume: 1.0,
+            pipewire_id: 57,
+            applied_percent: 100,
+            ..Default::default()
+        },
+    );
+
+    let err = controller.route_app("Firefox", "Media,Recording").await.unwrap_err();
+    assert_eq!(err.to_string(), "Sink Recording not found");
+}
+
+#[tokio::test]
+async fn test_solo_and_unsolo_apply_mutes() {
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    {
+        let cache_write = cache.write().await;
+        for ((name, muted), id) in
+            [("Chat", false), ("Game", true), ("Media", false)].into_iter().zip(56..)
+        {
+            cache_write.update_sink(
+                name.to_string(),
+                SinkInfo {
+                    id,
+                    name: name.to_string(),
+                    volume: 1.0,
+                    muted,
+                    pipewire_id: id,
+                    applied_percent: 100,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    let executor = Arc::new(RecordingExecutor::default());
+    let controller = PipeWireController::with_executor(cache.clone(), executor.clone());
+
+    controller.solo_sink("Game").await.unwrap();
+    assert_eq!(executor.count("pactl set-sink-mute 56 1"), 1);
+    assert_eq!(executor.count("pactl set-sink-mute 57 0"), 1);
+    assert_eq!(executor.count("pactl set-sink-mute 58 1"), 1);
+    assert!(controller.solo_sink("Missing").await.is_err());
+
+    controller.unsolo().await.unwrap();
+    assert_eq!(executor.count("pactl set-sink-mute 56 0"), 1);
+    assert_eq!(executor.count("pactl set-sink-mute 57 1"), 1);
+    assert_eq!(executor.count("pactl set-sink-mute 58 0"), 1);
+
+    let cache_read = cache.read().await;
+    assert!(!cache_read.sinks.get("Chat").unwrap().muted);
+    assert!(cache_read.sinks.get("Game").unwrap().muted);
+    assert!(!cache_read.sinks.get("Media").unwrap().muted);
+}
+
+#[tokio::test]
+async fn test_mute_all_and_unmute_all_apply_mutes() {
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    {
+        let cache_write = cache.write().await;
+        for ((name, muted), id) in
+            [("Chat", false), ("Game", true), ("Media", false)].into_iter().zip(56..)
+        {
+            cache_write.update_sink(
+                name.to_string(),
+                SinkInfo {
+                    id,
+                    name: name.to_string(),
+                    volume: 1.0,
+                    muted,
+                    pipewire_id: id,
+                    applied_percent: 100,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    let executor = Arc::new(RecordingExecutor::default());
+    let controller = PipeWireController::with_executor(cache.clone(), executor.clone());
+
+    controller.mute_all().await.unwrap();
+    assert_eq!(executor.count("pactl set-sink-mute 56 1"), 1);
+    assert_eq!(executor.count("pactl set-sink-mute 57 1"), 1);
+    assert_eq!(executor.count("pactl set-sink-mute 58 1"), 1);
+
+    controller.unmute_all().await.unwrap();
+    assert_eq!(executor.count("pactl set-sink-mute 56 0"), 1);
+    assert_eq!(executor.count("pactl set-sink-mute 57 1"), 2);
+    assert_eq!(executor.count("pactl set-sink-mute 58 0"), 1);
+
+    let cache_read = cache.read().await;
+    assert!(!cache_read.sinks.get("Chat").unwrap().muted);
+    assert!(cache_read.sinks.get("Game").unwrap().muted);
+    assert!(!cache_read.sinks.get("Media").unwrap().muted);
+}
+
+#[tokio::test]
+async fn test_crossfade_sets_both_sinks() {
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    {
+        let cache_write = cache.write().await;
+        for (name, id) in [("Game", 56), ("Media", 57)] {
+            cache_write.update_sink(
+                name.to_string(),
+                SinkInfo {
+                    id,
+                    name: name.to_string(),
+                    volume: 1.0,
+                    pipewire_id: id,
+                    applied_percent: 100,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    let executor = Arc::new(RecordingExecutor::default());
+    let controller = PipeWireController::with_executor(cache.clone(), executor.clone());
+
+    for (position, game, media) in [(0.0, 1.0, 0.0), (0.5, 0.5, 0.5), (1.0, 0.0, 1.0)] {
+        controller.crossfade("Game", "Media", position).await.unwrap();
+        let cache_read = cache.read().await;
+        assert_eq!(cache_read.sinks.get("Game").unwrap().volume, game);
+        assert_eq!(cache_read.sinks.get("Media").unwrap().volume, media);
+    }
+    assert_eq!(executor.count("pactl set-sink-volume 56 50%"), 1);
+    assert_eq!(executor.count("pactl set-sink-volume 57 50%"), 1);
+
+    assert!(controller.crossfade("Game", "Media", 1.5).await.is_err());
+    assert!(controller.crossfade("Game", "Game", 0.5).await.is_err());
+}
+
+#[tokio::test]
+async fn test_auto_mute_changes_only_the_apps_cached_streams() {
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    cache.read().await.update_app(
+        "Firefox".to_string(),
+        AppInfo {
+            display_name: "Firefox".to_string(),
+            current_sink: "Media".to_string(),
+            active: true,
+            sink_input_ids: vec![71, 72],
+            pipewire_id: 71,
+            ..Default::default()
+        },
+    );
+    let executor = Arc::new(RecordingExecutor::default());
+    let controller = PipeWireController::with_executor(cache.clone(), executor.clone());
+
+    // Nothing is muted unless the monitor marked the app first
+    assert_eq!(controller.auto_mute_app("Firefox", true).await.unwrap(), 0);
+    assert!(cache.read().await.begin_auto_mute("Firefox"));
+    assert_eq!(controller.auto_mute_app("Firefox", true).await.unwrap(), 2);
+    assert_eq!(executor.count("pactl set-sink-input-mute 71 1"), 1);
+    assert_eq!(executor.count("pactl set-sink-input-mute 72 1"), 1);
+    // A stream of the same name the cache doesn't give the app is left alone
+    assert_eq!(executor.count("pactl set-sink-input-mute 73 1"), 0);
+    assert!(cache.read().await.apps.get("Firefox").unwrap().muted);
+
+    // Already muted, so there is no second auto-mute to undo
+    assert!(!cache.read().await.begin_auto_mute("Firefox"));
+    assert!(cache.read().await.end_auto_mute("Firefox"));
+    assert_eq!(controller.auto_mute_app("Firefox", false).await.unwrap(), 2);
+    assert_eq!(executor.count("pactl set-sink-input-mute 71 0"), 1);
+    assert!(!cache.read().await.apps.get("Firefox").unwrap().muted);
+}
+
+#[tokio::test]
+async fn test_evacuate_sink_moves_all_apps() {
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    {
+        let cache_write = cache.write().await;
+        for (name, id) in [("Game", 56), ("Media", 57)] {
+            cache_write.update_sink(
+                name.to_string(),
+                SinkInfo {
+                    id,
+                    name: name.to_string(),
+                    volume: 1.0,
+                    pipewire_id: id,
+                    applied_percent: 100,
+                    ..Default::default()
+                },
+            );
+        }
+        // One app with live streams and one that is currently silent
+        for (name, active, ids) in [("Firefox", true, vec![71]), ("Discord", false, vec![])] {
+            cache_write.update_app(
+                name.to_string(),
+                AppInfo {
+                    display_name: name.to_string(),
+                    binary_name: name.to_lowercase(),
+                    stream_names: vec![name.to_string()],
+                    current_sink: "Game".to_string(),
+                    active,
+                    sink_input_ids: ids,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    let executor = Arc::new(RecordingExecutor::default());
+    let controller = PipeWireController::with_executor(cache.clone(), executor.clone());
+    assert_eq!(controller.evacuate_sink("Game", "Media").await.unwrap(), 2);
+
+    assert_eq!(executor.count("pactl move-sink-input 71 Media"), 1);
+    let cache_read = cache.read().await;
+    assert!(cache_read.apps_for_sink("Game").is_empty());
+    assert_eq!(cache_read.apps_for_sink("Media"), vec!["Discord", "Firefox"]);
+    drop(cache_read);
+
+    assert!(controller.evacuate_sink("Game", "Game").await.is_err());
+    assert!(controller.evacuate_sink("Game", "Missing").await.is_err());
+}
+
+#[test]
+fn test_parse_server_version() {
+    let pipewire_pulse = "Server String: /run/user/1000/pulse/native
+Library Protocol Version: 35
+Server Protocol Version: 35
+Is Local: yes
+Server Name: PulseAudio (on PipeWire 1.0.5)
+Server Version: 15.0.0
+Default Sink: Game
+";
+    assert_eq!(parse_server_version(pipewire_pulse).as_deref(), Some("1.0.5"));
+
+    let pulseaudio = "Server Name: pulseaudio\nServer Version: 16.1\n";
+    assert_eq!(parse_server_version(pulseaudio).as_deref(), Some("16.1"));
+
+    assert_eq!(parse_server_version("Connection failure: Connection refused\n"), None);
+}
+
+#[test]
+fn test_parse_default_sink() {
+    let pactl_info = "Server String: /run/user/1000/pulse/native
+Library Protocol Version: 35
+Server Protocol Version: 35
+Is Local: yes
+Client Index: 120
+Tile Size: 65472
+User Name: user
+Host Name: desktop
+Server Name: PulseAudio (on PipeWire 1.0.5)
+Server Version: 15.0.0
+Default Sample Specification: float32le 2ch 48000Hz
+Default Channel Map: front-left,front-right
+Default Sink: alsa_output.pci-0000_00_1f.3.analog-stereo
+Default Source: alsa_input.pci-0000_00_1f.3.analog-stereo
+Cookie: 4e1f:ab40
+";
+    assert_eq!(
+        parse_default_sink(pactl_info).as_deref(),
+        Some("alsa_output.pci-0000_00_1f.3.analog-stereo")
+    );
+
+    // Without any output device there's no sink to name
+    assert_eq!(parse_default_sink("Default Sink: @DEFAULT_SINK@\n"), None);
+    assert_eq!(parse_default_sink("Default Sink: \n"), None);
+    assert_eq!(parse_default_sink("Connection failure: Connection refused\n"), None);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_volume_sets_leave_last_applied_in_cache() {
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    cache.read().await.update_sink(
+        "Game".to_string(),
+        SinkInfo {
+            id: 56,
+            name: "Game".to_string(),
+            volume: 1.0,
+            pipewire_id: 56,
+            applied_percent: 100,
+            ..Default::default()
+        },
+    );
+
+    let executor = Arc::new(RecordingExecutor::default());
+    let controller = Arc::new(PipeWireController::with_executor(cache.clone(), executor.clone()));
+    let handles: Vec<_> = (1..=20)
+        .map(|i| {
+            let controller = controller.clone();
+            tokio::spawn(async move { controller.set_sink_volume("Game", i as f32 / 20.0).await })
+        })
+        .collect();
+    for handle in handles {
+        handle.await.unwrap().unwrap();
+    }
+
+    let last = executor.last_with_prefix("pactl set-sink-volume 56 ").unwrap();
+    let cache_read = cache.read().await;
+    let game = cache_read.sinks.get("Game").unwrap();
+    assert_eq!(last, format!("pactl set-sink-volume 56 {}%", game.applied_percent));
+    assert_eq!(game.volume, game.applied_percent as f32 / 100.0);
+}
+
+#[test]
+fn test_parse_sinks_short() {
+    let output = "56\tGame\tPipeWire\ts32le 2ch 48000Hz\tRUNNING\n57\tMedia\tPipeWire\n\nbogus\n";
+    assert_eq!(
+        parse_sinks_short(output),
+        vec![
+            SinkEntry { id: 56, name: "Game".to_string() },
+            SinkEntry { id: 57, name: "Media".to_string() }
+        ]
+    );
+}
+
+#[test]
+fn test_virtual_sink_module_args() {
+    assert_eq!(
+        null_sink_module_args("Game", "Game Audio"),
+        vec![
+            "module-null-sink",
+            "sink_name=Game",
+            "sink_properties=device.description=\"Game Audio\""
+        ]
+    );
+    assert_eq!(
+        null_sink_module_args("Chat", r#"Say "hi" \o/"#)[2],
+        r#"sink_properties=device.description="Say \"hi\" \\o/""#
+    );
+    assert_eq!(
+        loopback_module_args("Game", "Game_to_Headphones"),
+        vec![
+            "module-loopback",
+            "source=Game.monitor",
+            "source_dont_move=true",
+            "sink_input_properties=node.name=Game_to_Headphones",
+        ]
+    );
+}
+
+/// In-memory audio server: streams move between sinks and volumes are remembered
+///
+/// Clones share their state, so a test can keep one to inspect after boxing another.
+#[derive(Default, Clone)]
+struct FakeBackend {
+    sinks: Vec<SinkEntry>,
+    inputs: Arc<Mutex<Vec<SinkInput>>>,
+    volumes: Arc<Mutex<HashMap<Node, u32>>>,
+    volume_calls: Arc<Mutex<Vec<(Node, u32)>>>, // Every volume set, in order
+    mutes: Arc<Mutex<HashMap<Node, bool>>>,
+    modules: Arc<Mutex<Vec<(u32, String)>>>, // Loaded module ids and the sink each is for
+    list_calls: Arc<Mutex<usize>>,           // Times the sink inputs were listed
+}
+
+impl FakeBackend {
+    fn new() -> Self {
+        let stream = |id: u32, sink: u32, props: &[(&str, &str)]| SinkInput {
+            id,
+            sink: Some(sink),
+            volume: None,
+            corked: false,
+            properties: props.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        };
+        Self {
+            sinks: vec![
+                SinkEntry { id: 56, name: "Game".to_string() },
+                SinkEntry { id: 57, name: "Media".to_string() },
+                SinkEntry { id: 1, name: "Speaker".to_string() },
+            ],
+            inputs: Arc::new(Mutex::new(vec![
+                stream(71, 56, &[("application.name", "Firefox")]),
+                stream(90, 1, &[("node.name", "Game_to_Speaker")]),
+            ])),
+            ..Default::default()
+        }
+    }
+}
+
+#[async_trait]
+impl PipeWireBackend for FakeBackend {
+    async fn set_volume(&self, node: Node, percent: u32) -> Result<()> {
+        self.volumes.lock().unwrap().insert(node, percent);
+        self.volume_calls.lock().unwrap().push((node, percent));
+        Ok(())
+    }
+
+    async fn set_mute(&self, node: Node, muted: bool) -> Result<()> {
+        self.mutes.lock().unwrap().insert(node, muted);
+        Ok(())
+    }
+
+    async fn move_sink_input(&self, sink_input_id: u32, sink_name: &str) -> Result<()> {
+        let sink = self
+            .sinks
+            .iter()
+            .find(|sink| sink.name == sink_name)
+            .ok_or_else(|| anyhow!("no sink"))?;
+        let mut inputs = self.inputs.lock().unwrap();
+        let input = inputs
+            .iter_mut()
+            .find(|input| input.id == sink_input_id)
+            .ok_or_else(|| anyhow!("no input"))?;
+        input.sink = Some(sink.id);
+        Ok(())
+    }
+
+    async fn list_sink_inputs(&self) -> Result<Vec<SinkInput>> {
+        *self.list_calls.lock().unwrap() += 1;
+        Ok(self.inputs.lock().unwrap().clone())
+    }
+
+    async fn combine_sinks(&self, _sink_name: &str, _targets: &[String]) -> Result<Option<u32>> {
+        Err(anyhow!("no combine sinks"))
+    }
+
+    async fn list_sinks(&self) -> Result<Vec<SinkEntry>> {
+        Ok(self.sinks.clone())
+    }
+
+    async fn create_virtual_sink(
+        &self,
+        sink_name: &str,
+        _description: &str,
+        loopback_name: &str,
+    ) -> Result<Vec<u32>> {
+        let mut modules = self.modules.lock().unwrap();
+        let ids = vec![modules.len() as u32 + 100, modules.len() as u32 + 101];
+        modules.push((ids[0], sink_name.to_string()));
+        modules.push((ids[1], loopback_name.to_string()));
+        Ok(ids)
+    }
+
+    async fn unload_module(&self, module_id: u32) -> Result<()> {
+        let mut modules = self.modules.lock().unwrap();
+        let index = modules
+            .iter()
+            .position(|(id, _)| *id == module_id)
+            .ok_or_else(|| anyhow!("no module"))?;
+        modules.remove(index);
+        Ok(())
+    }
+
+    async fn server_version(&self) -> Result<String> {
+        Ok("1.2.3".to_string())
+    }
+
+    async fn default_sink(&self) -> Result<String> {
+        Ok("Game".to_string())
+    }
+}
+
+#[test]
+fn test_loopback_node_names_per_suffix() {
+    let suffixes = vec!["_to_Speaker".to_string(), "_to_Headphones".to_string()];
+    assert_eq!(
+        loopback_node_names("Game", &suffixes),
+        vec!["Game_to_Speaker", "Game_to_Headphones"]
+    );
+    assert!(loopback_node_names("Game", &[]).is_empty());
+
+    // Unconfigured caches look for the loopbacks the daemon creates itself
+    assert_eq!(AudioCache::new().loopback_node_names("Chat"), vec!["Chat_to_Speaker"]);
+    let cache = AudioCache::new().with_loopback_suffixes(suffixes);
+    assert_eq!(cache.loopback_node_names("Chat"), vec!["Chat_to_Speaker", "Chat_to_Headphones"]);
+}
+
+fn fake_controller() -> (PipeWireController, FakeBackend, Arc<RwLock<AudioCache>>) {
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    let backend = FakeBackend::new();
+    let controller = PipeWireController::with_backend(cache.clone(), Box::new(backend.clone()));
+    (controller, backend, cache)
+}
+
+#[tokio::test]
+async fn test_restore_app_settings_finds_the_stream_by_serial() {
+    let (controller, backend, cache) = fake_controller();
+    {
+        let mut inputs = backend.inputs.lock().unwrap();
+        // pactl lists Firefox as 71 while its node has serial 171, the index of another stream
+        inputs[0].properties.insert("object.serial".to_string(), "171".to_string());
+        inputs.push(SinkInput {
+            id: 171,
+            sink: Some(56),
+            properties: HashMap::from([
+                ("application.name".to_string(), "Discord".to_string()),
+                ("object.serial".to_string(), "280".to_string()),
+            ]),
+            ..Default::default()
+        });
+    }
+    cache.read().await.record_app_volume("Firefox", 0.4);
+    cache.read().await.record_app_mute("Firefox", true);
+
+    assert!(controller.restore_app_settings("Firefox", 171).await.unwrap());
+    assert_eq!(backend.volumes.lock().unwrap().get(&Node::SinkInput(71)), Some(&40));
+    assert_eq!(backend.mutes.lock().unwrap().get(&Node::SinkInput(71)), Some(&true));
+    assert!(!backend.volumes.lock().unwrap().contains_key(&Node::SinkInput(171)));
+    assert!(!backend.mutes.lock().unwrap().contains_key(&Node::SinkInput(171)));
+
+    // A stream that went away before it settled gets nothing
+    assert!(!controller.restore_app_settings("Firefox", 172).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_create_missing_sinks_skips_existing_and_unloads() {
+    let (controller, backend, _cache) = fake_controller();
+    // Game and Media already exist, Chat doesn't
+    let virtual_sinks = Config::default().virtual_sinks;
+    assert!(virtual_sinks.iter().any(|sink| sink.name == "Chat"));
+
+    let module_ids = controller.create_missing_sinks(&virtual_sinks).await.unwrap();
+    assert_eq!(module_ids, vec![100, 101]);
+    assert_eq!(
+        *backend.modules.lock().unwrap(),
+        vec![(100, "Chat".to_string()), (101, "Chat_to_Speaker".to_string())]
+    );
+
+    controller.unload_modules(&module_ids).await;
+    assert!(backend.modules.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_created_loopback_takes_the_first_configured_suffix() {
+    let cache =
+        Arc::new(RwLock::new(AudioCache::new().with_loopback_suffixes(vec![
+            "_to_Headphones".to_string(),
+            "_to_Speaker".to_string(),
+        ])));
+    let backend = FakeBackend::new();
+    let controller = PipeWireController::with_backend(cache, Box::new(backend.clone()));
+
+    controller.create_missing_sinks(&Config::default().virtual_sinks).await.unwrap();
+    assert_eq!(backend.modules.lock().unwrap()[1].1, "Chat_to_Headphones");
+}
+
+#[tokio::test]
+async fn test_volume_and_mute_reach_every_configured_loopback() {
+    let cache =
+        Arc::new(RwLock::new(AudioCache::new().with_loopback_suffixes(vec![
+            "_to_Speaker".to_string(),
+            "_to_Headphones".to_string(),
+        ])));
+    let backend = FakeBackend::new();
+    for (id, node_name) in [(91, "Game_to_Headphones"), (92, "Game_to_Monitor")] {
+        backend.inputs.lock().unwrap().push(SinkInput {
+            id,
+            sink: Some(1),
+            volume: None,
+            corked: false,
+            properties: HashMap::from([("node.name".to_string(), node_name.to_string())]),
+        });
+    }
+    let controller = PipeWireController::with_backend(cache.clone(), Box::new(backend.clone()));
+    cache.read().await.update_sink(
+        "Game".to_string(),
+        SinkInfo {
+            id: 56,
+            name: "Game".to_string(),
+            volume: 1.0,
+            pipewire_id: 56,
+            applied_percent: 100,
+            ..Default::default()
+        },
+    );
+
+    controller.set_sink_volume("Game", 0.4).await.unwrap();
+    controller.set_sink_mute("Game", true).await.unwrap();
+
+    let volumes = backend.volumes.lock().unwrap().clone();
+    let mutes = backend.mutes.lock().unwrap().clone();
+    for loopback in [Node::SinkInput(90), Node::SinkInput(91)] {
+        assert_eq!(volumes.get(&loopback), Some(&40));
+        assert_eq!(mutes.get(&loopback), Some(&true));
+    }
+    // Its suffix isn't configured, so it isn't one of Game's loopbacks
+    assert_eq!(volumes.get(&Node::SinkInput(92)), None);
+    assert_eq!(mutes.get(&Node::SinkInput(92)), None);
+}
+
+#[tokio::test]
+async fn test_fake_backend_volume_and_mute_reach_sink_and_loopback() {
+    let (controller, backend, cache) = fake_controller();
+    cache.read().await.update_sink(
+        "Game".to_string(),
+        SinkInfo {
+            id: 56,
+            name: "Game".to_string(),
+            volume: 1.0,
+            pipewire_id: 56,
+            applied_percent: 100,
+            ..Default::default()
+        },
+    );
+
+    controller.set_sink_volume("Game", 0.4).await.unwrap();
+    controller.set_sink_mute("Game", true).await.unwrap();
+
+    let volumes = backend.volumes.lock().unwrap().clone();
+    assert_eq!(volumes.get(&Node::Sink(56)), Some(&40));
+    assert_eq!(volumes.get(&Node::SinkInput(90)), Some(&40));
+    let mutes = backend.mutes.lock().unwrap().clone();
+    assert_eq!(mutes.get(&Node::Sink(56)), Some(&true));
+    assert_eq!(mutes.get(&Node::SinkInput(90)), Some(&true));
+
+    let cache_read = cache.read().await;
+    let game = cache_read.sinks.get("Game").unwrap();
+    assert_eq!(game.applied_percent, 40);
+    assert!(game.muted);
+    drop(game);
+    drop(cache_read);
+
+    assert_eq!(controller.query_server_version().await.unwrap(), "1.2.3");
+}
+
+#[tokio::test]
+async fn test_fake_backend_route_app_follows_actual_sink() {
+    let (controller, backend, cache) = fake_controller();
+    {
+        let cache_write = cache.write().await;
+        cache_write.update_sink(
+            "Media".to_string(),
+            SinkInfo {
+                id: 57,
+                name: "Media".to_string(),
+                volume: 1.0,
+                pipewire_id: 57,
+                applied_percent: 100,
+                ..Default::default()
+            },
+        );
+        cache_write.update_app(
+            "Firefox".to_string(),
+            AppInfo {
+                display_name: "Firefox".to_string(),
+                binary_name: "firefox".to_string(),
+                stream_names: vec!["Firefox".to_string()],
+                current_sink: "Game".to_string(),
+                active: true,
+                sink_input_ids: vec![71],
+                pipewire_id: 71,
+                ..Default::default()
+            },
+        );
+    }
+
+    controller.route_app("Firefox", "Media").await.unwrap();
+
+    let inputs = backend.inputs.lock().unwrap().clone();
+    assert_eq!(inputs.iter().find(|input| input.id == 71).unwrap().sink, Some(57));
+    assert_eq!(cache.read().await.apps.get("Firefox").unwrap().current_sink, "Media");
+
+    // Apps without streams can't be moved
+    assert!(controller.route_app("Spotify", "Media").await.is_err());
+}
+
+#[tokio::test]
+async fn test_route_app_to_a_sink_matched_by_description() {
+    let mut backend = FakeBackend::new();
+    backend.sinks.push(SinkEntry { id: 58, name: "output.null-sink.3".to_string() });
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    let controller = PipeWireController::with_backend(cache.clone(), Box::new(backend.clone()));
+    {
+        let cache_write = cache.write().await;
+        // Configured as Music, which is only the sink's description
+        cache_write.update_sink(
+            "Music".to_string(),
+            SinkInfo {
+                id: 58,
+                name: "Music".to_string(),
+                volume: 1.0,
+                pipewire_id: 58,
+                node_name: "output.null-sink.3".to_string(),
+                ..Default::default()
+            },
+        );
+        cache_write.update_app(
+            "Firefox".to_string(),
+            AppInfo {
+                display_name: "Firefox".to_string(),
+                binary_name: "firefox".to_string(),
+                current_sink: "Game".to_string(),
+                active: true,
+                sink_input_ids: vec![71],
+                ..Default::default()
+            },
+        );
+    }
+
+    controller.route_app("Firefox", "Music").await.unwrap();
+
+    let inputs = backend.inputs.lock().unwrap().clone();
+    assert_eq!(inputs.iter().find(|input| input.id == 71).unwrap().sink, Some(58));
+    // The sink it ended up on is known by its configured name again
+    assert_eq!(cache.read().await.apps.get("Firefox").unwrap().current_sink, "Music");
+}
+
+#[tokio::test]
+async fn test_combine_sink_is_unloaded_once_nothing_plays_to_it() {
+    let backend = MockBackend::new().with_sink("Game").with_sink("Media");
+    let stream = backend.add_stream("Game", &[("application.name", "Firefox")]).unwrap();
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    let controller = PipeWireController::with_backend(cache.clone(), Box::new(backend.clone()));
+    {
+        let cache_write = cache.write().await;
+        for name in ["Game", "Media"] {
+            let id = backend.sink_id(name).unwrap();
+            cache_write.update_sink(
+                name.to_string(),
+                SinkInfo {
+                    id,
+                    name: name.to_string(),
+                    volume: 1.0,
+                    pipewire_id: id,
+                    ..Default::default()
+                },
+            );
+        }
+        cache_write.update_app(
+            "Firefox".to_string(),
+            AppInfo {
+                display_name: "Firefox".to_string(),
+                current_sink: "Game".to_string(),
+                active: true,
+                sink_input_ids: vec![stream],
+                ..Default::default()
+            },
+        );
+    }
+
+    controller.route_app("Firefox", "Media,Game").await.unwrap();
+    assert_eq!(backend.stream_sink(stream).as_deref(), Some("combined_Media_Game"));
+
+    // Routed back to one sink, the combine sink has nothing left to play
+    controller.route_app("Firefox", "Game").await.unwrap();
+    assert_eq!(backend.stream_sink(stream).as_deref(), Some("Game"));
+    assert_eq!(backend.sink_id("combined_Media_Game"), None);
+
+    // The ones still in use go when the daemon exits
+    controller.route_app("Firefox", "Media,Game").await.unwrap();
+    assert!(backend.sink_id("combined_Media_Game").is_some());
+    controller.unload_combined_sinks().await;
+    assert_eq!(backend.sink_id("combined_Media_Game"), None);
+}
+
+#[tokio::test]
+async fn test_route_sink_input_moves_one_stream_and_its_app() {
+    let (controller, backend, cache) = fake_controller();
+    {
+        let cache_write = cache.write().await;
+        cache_write.update_sink(
+            "Media".to_string(),
+            SinkInfo {
+                id: 57,
+                name: "Media".to_string(),
+                volume: 1.0,
+                pipewire_id: 57,
+                applied_percent: 100,
+                ..Default::default()
+            },
+        );
+        cache_write.update_app(
+            "Firefox".to_string(),
+            AppInfo {
+                display_name: "Firefox".to_string(),
+                binary_name: "firefox".to_string(),
+                stream_names: vec!["Firefox".to_string()],
+                current_sink: "Game".to_string(),
+                active: true,
+                sink_input_ids: vec![71],
+                pipewire_id: 71,
+                ..Default::default()
+            },
+        );
+    }
+
+    assert!(controller.route_sink_input(71, "Media").await.unwrap());
+    let inputs = backend.inputs.lock().unwrap().clone();
+    assert_eq!(inputs.iter().find(|input| input.id == 71).unwrap().sink, Some(57));
+    let cache_read = cache.read().await;
+    assert_eq!(cache_read.apps.get("Firefox").unwrap().current_sink, "Media");
+    assert_eq!(cache_read.apps_for_sink("Media"), vec!["Firefox".to_string()]);
+    assert!(cache_read.apps_for_sink("Game").is_empty());
+    drop(cache_read);
+
+    // A stream no app owns is still moved
+    assert!(controller.route_sink_input(90, "Media").await.unwrap());
+    let inputs = backend.inputs.lock().unwrap().clone();
+    assert_eq!(inputs.iter().find(|input| input.id == 90).unwrap().sink, Some(57));
+    assert_eq!(cache.read().await.apps.len(), 1);
+
+    // Unknown inputs and sinks move nothing
+    assert!(!controller.route_sink_input(4242, "Media").await.unwrap());
+    assert!(controller.route_sink_input(71, "Missing").await.is_err());
+}
+
+#[tokio::test]
+async fn test_set_app_mute_mutes_only_the_apps_streams() {
+    let (controller, backend, cache) = fake_controller();
+    cache.read().await.update_app(
+        "Firefox".to_string(),
+        AppInfo {
+            display_name: "Firefox".to_string(),
+            binary_name: "firefox".to_string(),
+            stream_names: vec!["Firefox".to_string()],
+            current_sink: "Game".to_string(),
+            active: true,
+            sink_input_ids: vec![71],
+            pipewire_id: 71,
+            ..Default::default()
+        },
+    );
+    // A second Firefox stream, and another app on the same sink
+    let stream = |id: u32, name: &str| SinkInput {
+        id,
+        sink: Some(56),
+        volume: None,
+        corked: false,
+        properties: HashMap::from([("application.name".to_string(), name.to_string())]),
+    };
+    backend.inputs.lock().unwrap().extend([stream(72, "Firefox"), stream(73, "Steam")]);
+
+    assert_eq!(controller.set_app_mute("Firefox", true).await.unwrap(), 2);
+    let mutes = backend.mutes.lock().unwrap().clone();
+    assert_eq!(mutes.get(&Node::SinkInput(71)), Some(&true));
+    assert_eq!(mutes.get(&Node::SinkInput(72)), Some(&true));
+    assert_eq!(mutes.get(&Node::SinkInput(73)), None);
+    assert_eq!(mutes.get(&Node::Sink(56)), None);
+    assert!(cache.read().await.apps.get("Firefox").unwrap().muted);
+
+    assert_eq!(controller.set_app_mute("Firefox", false).await.unwrap(), 2);
+    assert_eq!(backend.mutes.lock().unwrap().get(&Node::SinkInput(72)), Some(&false));
+    assert!(!cache.read().await.apps.get("Firefox").unwrap().muted);
+
+    // Nothing to mute for an app without streams
+    assert_eq!(controller.set_app_mute("Spotify", true).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_invalid_volumes_never_reach_pipewire() {
+    let (controller, backend, cache) = fake_controller();
+    cache.read().await.update_sink(
+        "Game".to_string(),
+        SinkInfo {
+            id: 56,
+            name: "Game".to_string(),
+            volume: 0.5,
+            pipewire_id: 56,
+            applied_percent: 50,
+            ..Default::default()
+        },
+    );
+
+    for volume in [f32::NAN, f32::INFINITY, f32::NEG_INFINITY] {
+        assert!(controller.set_sink_volume("Game", volume).await.is_err());
+    }
+    assert!(backend.volumes.lock().unwrap().is_empty());
+    assert_eq!(cache.read().await.sinks.get("Game").unwrap().volume, 0.5);
+
+    controller.set_sink_volume("Game", -1.0).await.unwrap();
+    assert_eq!(backend.volumes.lock().unwrap().get(&Node::Sink(56)), Some(&0));
+    assert_eq!(cache.read().await.sinks.get("Game").unwrap().volume, 0.0);
+
+    controller.set_sink_volume("Game", 5.0).await.unwrap();
+    assert_eq!(backend.volumes.lock().unwrap().get(&Node::Sink(56)), Some(&100));
+    assert_eq!(backend.volumes.lock().unwrap().get(&Node::SinkInput(90)), Some(&100));
+    assert_eq!(cache.read().await.sinks.get("Game").unwrap().volume, 1.0);
+}
+
+fn loopback_volumes(backend: &FakeBackend) -> Vec<u32> {
+    let calls = backend.volume_calls.lock().unwrap();
+    calls.iter().filter(|(node, _)| *node == Node::SinkInput(90)).map(|(_, p)| *p).collect()
+}
+
+#[tokio::test]
+async fn test_volume_ramp_steps_the_loopback_to_the_target() {
+    let (_, backend, _) = fake_controller();
+    let cache =
+        Arc::new(RwLock::new(AudioCache::new().with_volume_ramp(Duration::from_millis(200))));
+    let controller =
+        Arc::new(PipeWireController::with_backend(cache.clone(), Box::new(backend.clone())));
+    cache.read().await.update_sink(
+        "Game".to_string(),
+        SinkInfo {
+            id: 56,
+            name: "Game".to_string(),
+            volume: 0.2,
+            pipewire_id: 56,
+            applied_percent: 20,
+            ..Default::default()
+        },
+    );
+
+    let ramping = controller.clone();
+    let handle = tokio::spawn(async move { ramping.set_sink_volume("Game", 0.9).await });
+    // The cache has the target while the loopback is still on its way there
+    while loopback_volumes(&backend).is_empty() {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    assert_eq!(cache.read().await.sinks.get("Game").unwrap().volume, 0.9);
+    assert!(*loopback_volumes(&backend).last().unwrap() < 90);
+    handle.await.unwrap().unwrap();
+
+    let steps = loopback_volumes(&backend);
+    assert_eq!(steps.len(), 20);
+    assert!(steps.windows(2).all(|pair| pair[0] < pair[1]), "{steps:?}");
+    assert!(steps[0] > 20);
+    assert_eq!(steps.last(), Some(&90));
+    // The sink itself goes straight to the target
+    let sink_calls: Vec<_> = backend
+        .volume_calls
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(node, _)| *node == Node::Sink(56))
+        .cloned()
+        .collect();
+    assert_eq!(sink_calls, vec![(Node::Sink(56), 90)]);
+}
+
+#[tokio::test]
+async fn test_volume_ramp_starts_from_the_cached_volume() {
+    let (_, backend, _) = fake_controller();
+    let cache =
+        Arc::new(RwLock::new(AudioCache::new().with_volume_ramp(Duration::from_millis(200))));
+    let controller = PipeWireController::with_backend(cache.clone(), Box::new(backend.clone()));
+    // Nothing applied yet, as for a sink whose volume lookup hasn't landed
+    cache.read().await.update_sink(
+        "Game".to_string(),
+        SinkInfo {
+            id: 56,
+            name: "Game".to_string(),
+            volume: 1.0,
+            pipewire_id: 56,
+            ..Default::default()
+        },
+    );
+
+    controller.set_sink_volume("Game", 0.9).await.unwrap();
+    let steps = loopback_volumes(&backend);
+    assert!(steps.iter().all(|&percent| (90..100).contains(&percent)), "{steps:?}");
+    assert_eq!(steps.last(), Some(&90));
+}
+
+#[tokio::test]
+async fn test_volume_changes_jump_without_a_ramp() {
+    let (controller, backend, cache) = fake_controller();
+    cache.read().await.update_sink(
+        "Game".to_string(),
+        SinkInfo {
+            id: 56,
+            name: "Game".to_string(),
+            volume: 0.2,
+            pipewire_id: 56,
+            applied_percent: 20,
+            ..Default::default()
+        },
+    );
+
+    controller.set_sink_volume("Game", 0.9).await.unwrap();
+    assert_eq!(loopback_volumes(&backend), vec![90]);
+}
+
+#[tokio::test]
+async fn test_routing_through_sinks_records_recent_sinks() {
+    let (controller, _backend, cache) = fake_controller();
+    {
+        let cache_write = cache.write().await;
+        for (name, id) in [("Game", 56), ("Media", 57), ("Speaker", 1)] {
+            cache_write.update_sink(
+                name.to_string(),
+                SinkInfo {
+                    id,
+                    name: name.to_string(),
+                    volume: 1.0,
+                    pipewire_id: id,
+                    applied_percent: 100,
+                    ..Default::default()
+                },
+            );
+        }
+        cache_write.update_app(
+            "Firefox".to_string(),
+            AppInfo {
+                display_name: "Firefox".to_string(),
+                binary_name: "firefox".to_string(),
+                stream_names: vec!["Firefox".to_string()],
+                current_sink: "Game".to_string(),
+                active: true,
+                sink_input_ids: vec![71],
+                pipewire_id: 71,
+                ..Default::default()
+            },
+        );
+    }
+
+    for sink in ["Media", "Speaker", "Game"] {
+        controller.route_app("Firefox", sink).await.unwrap();
+    }
+    assert_eq!(cache.read().await.recent_sinks("Firefox"), vec!["Game", "Speaker", "Media"]);
+
+    // A failed route is not a sink the app was on
+    controller.route_app("Firefox", "Recording").await.unwrap_err();
+    assert_eq!(cache.read().await.recent_sinks("Firefox"), vec!["Game", "Speaker", "Media"]);
+}
+
+#[tokio::test]
+async fn test_routing_and_volume_changes_are_logged() {
+    let (controller, _backend, cache) = fake_controller();
+    {
+        let cache_write = cache.write().await;
+        for (name, id) in [("Game", 56), ("Media", 57)] {
+            cache_write.update_sink(
+                name.to_string(),
+                SinkInfo {
+                    id,
+                    name: name.to_string(),
+                    volume: 1.0,
+                    pipewire_id: id,
+                    applied_percent: 100,
+                    ..Default::default()
+                },
+            );
+        }
+        cache_write.update_app(
+            "Firefox".to_string(),
+            AppInfo {
+                display_name: "Firefox".to_string(),
+                binary_name: "firefox".to_string(),
+                stream_names: vec!["Firefox".to_string()],
+                current_sink: "Game".to_string(),
+                active: true,
+                sink_input_ids: vec![71],
+                pipewire_id: 71,
+                ..Default::default()
+            },
+        );
+    }
+
+    controller.route_app("Firefox", "Media").await.unwrap();
+    controller.route_app("Firefox", "Recording").await.unwrap_err();
+    controller.set_sink_volume("Game", 0.4).await.unwrap();
+
+    let events: Vec<EventKind> =
+        cache.read().await.recent_events(usize::MAX).into_iter().map(|event| event.kind).collect();
+    assert_eq!(
+        events,
+        vec![
+            EventKind::SinkAdded { sink: "Game".to_string() },
+            EventKind::SinkAdded { sink: "Media".to_string() },
+            EventKind::RouteApplied { app: "Firefox".to_string(), sink: "Media".to_string() },
+            EventKind::RouteFailed {
+                app: "Firefox".to_string(),
+                sink: "Recording".to_string(),
+                error: "Sink Recording not found".to_string(),
+            },
+            EventKind::VolumeChanged { sink: "Game".to_string(), volume: 0.4 },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_reset_sink_volume_applies_default_and_unmutes() {
+    let defaults = HashMap::from([("Game".to_string(), 0.8)]);
+    let cache = Arc::new(RwLock::new(AudioCache::new().with_default_volumes(defaults)));
+    let backend = FakeBackend::new();
+    let controller = PipeWireController::with_backend(cache.clone(), Box::new(backend.clone()));
+    for (name, id) in [("Game", 56), ("Media", 57)] {
+        cache.read().await.update_sink(
+            name.to_string(),
+            SinkInfo {
+                id,
+                name: name.to_string(),
+                volume: 0.2,
+                muted: true,
+                pipewire_id: id,
+                applied_percent: 20,
+                ..Default::default()
+            },
+        );
+    }
+
+    assert_eq!(controller.reset_sink_volume("Game").await.unwrap(), 0.8);
+    let volumes = backend.volumes.lock().unwrap().clone();
+    assert_eq!(volumes.get(&Node::Sink(56)), Some(&80));
+    assert_eq!(volumes.get(&Node::SinkInput(90)), Some(&80));
+    let mutes = backend.mutes.lock().unwrap().clone();
+    assert_eq!(mutes.get(&Node::Sink(56)), Some(&false));
+    assert_eq!(mutes.get(&Node::SinkInput(90)), Some(&false));
+    {
+        let cache_read = cache.read().await;
+        let game = cache_read.sinks.get("Game").unwrap();
+        assert_eq!((game.volume, game.applied_percent, game.muted), (0.8, 80, false));
+    }
+
+    // Sinks without a configured default go back to full volume
+    assert_eq!(controller.reset_sink_volume("Media").await.unwrap(), 1.0);
+    let cache_read = cache.read().await;
+    let media = cache_read.sinks.get("Media").unwrap();
+    assert_eq!((media.volume, media.applied_percent, media.muted), (1.0, 100, false));
+}
+
+/// Backend that never answers within any reasonable deadline
+struct HungBackend;
+
+impl HungBackend {
+    async fn hang<T>(&self) -> Result<T> {
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        Err(anyhow!("should have timed out"))
+    }
+}
+
+#[async_trait]
+impl PipeWireBackend for HungBackend {
+    async fn set_volume(&self, _node: Node, _percent: u32) -> Result<()> {
+        self.hang().await
+    }
+
+    async fn set_mute(&self, _node: Node, _muted: bool) -> Result<()> {
+        self.hang().await
+    }
+
+    async fn move_sink_input(&self, _sink_input_id: u32, _sink_name: &str) -> Result<()> {
+        self.hang().await
+    }
+
+    async fn list_sink_inputs(&self) -> Result<Vec<SinkInput>> {
+        self.hang().await
+    }
+
+    async fn combine_sinks(&self, _sink_name: &str, _targets: &[String]) -> Result<Option<u32>> {
+        self.hang().await
+    }
+
+    async fn list_sinks(&self) -> Result<Vec<SinkEntry>> {
+        self.hang().await
+    }
+
+    async fn create_virtual_sink(
+        &self,
+        _sink_name: &str,
+        _description: &str,
+        _loopback_name: &str,
+    ) -> Result<Vec<u32>> {
+        self.hang().await
+    }
+
+    async fn unload_module(&self, _module_id: u32) -> Result<()> {
+        self.hang().await
+    }
+
+    async fn server_version(&self) -> Result<String> {
+        self.hang().await
+    }
+
+    async fn default_sink(&self) -> Result<String> {
+        self.hang().await
+    }
+}
+
+#[tokio::test]
+async fn test_hung_backend_times_out() {
+    let cache = AudioCache::new().with_command_timeout(Duration::from_millis(50));
+    cache.update_sink(
+        "Game".to_string(),
+        SinkInfo {
+            id: 56,
+            name: "Game".to_string(),
+            volume: 1.0,
+            pipewire_id: 56,
+            applied_percent: 100,
+            ..Default::default()
+        },
+    );
+    let controller =
+        PipeWireController::with_backend(Arc::new(RwLock::new(cache)), Box::new(HungBackend));
+
+    let started = std::time::Instant::now();
+    let err = controller.route_pid(4242, "Game").await.unwrap_err();
+    assert!(err.to_string().contains("Backend timeout"), "unexpected error: {err}");
+    let err = controller.query_server_version().await.unwrap_err();
+    assert!(err.to_string().contains("Backend timeout"), "unexpected error: {err}");
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_rescan_rebuilds_stale_ids_without_duplicates() {
+    let (controller, backend, cache) = fake_controller();
+    let sink = |name: &str, id: u32| SinkInfo {
+        id,
+        name: name.to_string(),
+        volume: 1.0,
+        pipewire_id: id,
+        applied_percent: 100,
+        ..Default::default()
+    };
+    let app = |name: &str, id: u32| AppInfo {
+        display_name: name.to_string(),
+        binary_name: name.to_lowercase(),
+        stream_names: vec![name.to_string()],
+        current_sink: "Media".to_string(),
+        active: true,
+        sink_input_ids: vec![id],
+        pipewire_id: id,
+        ..Default::default()
+    };
+    {
+        // Ids from before a suspend, and a sink that has since disappeared
+        let cache_write = cache.write().await;
+        cache_write.update_sink("Game".to_string(), sink("Game", 10));
+        cache_write.update_sink("Chat".to_string(), sink("Chat", 11));
+        cache_write.update_app("Firefox".to_string(), app("Firefox", 5));
+        cache_write.update_app("Old".to_string(), app("Old", 6));
+    }
+    backend.inputs.lock().unwrap().push(SinkInput {
+        id: 80,
+        sink: Some(57),
+        volume: Some(0.5),
+        corked: false,
+        properties: [("application.name", "Spotify"), ("application.process.binary", "spotify")]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    });
+    let generation = cache.read().await.get_generation();
+
+    assert_eq!(controller.rescan().await.unwrap(), (1, 2));
+
+    let cache_read = cache.read().await;
+    assert_eq!(cache_read.sinks.get("Game").unwrap().pipewire_id, 56);
+    assert!(cache_read.sinks.get("Chat").is_none());
+    let firefox = cache_read.apps.get("Firefox").unwrap().clone();
+    assert_eq!(firefox.sink_input_ids, vec![71]);
+    assert_eq!(firefox.current_sink, "Game");
+    assert!(firefox.active);
+    let old = cache_read.apps.get("Old").unwrap().clone();
+    assert!(!old.active && old.sink_input_ids.is_empty());
+    let spotify = cache_read.apps.get("Spotify").unwrap().clone();
+    assert_eq!((spotify.sink_input_ids, spotify.current_sink), (vec![80], "Media".to_string()));
+    assert_eq!(spotify.binary_name, "spotify");
+    // The Game loopback is not an app
+    assert_eq!(cache_read.apps.len(), 3);
+    assert!(cache_read.get_generation() > generation);
+    drop(cache_read);
+
+    controller.rescan().await.unwrap();
+    let cache_read = cache.read().await;
+    assert_eq!(cache_read.apps.len(), 3);
+    assert_eq!(cache_read.apps.get("Spotify").unwrap().sink_input_ids, vec![80]);
+}
+
+/// A Discord voice stream, which the monitor names after its binary
+fn discord_stream(id: u32) -> SinkInput {
+    SinkInput {
+        id,
+        sink: Some(57),
+        properties: HashMap::from([
+            ("application.name".to_string(), "WEBRTC VoiceEngine".to_string()),
+            ("application.process.binary".to_string(), "/usr/bin/discord".to_string()),
+        ]),
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_rescan_names_apps_as_the_monitor_does() {
+    let (controller, backend, cache) = fake_controller();
+    let controller = controller.with_cache_config(CacheConfig {
+        track_denylist: vec!["spotify".to_string()],
+        ..Config::default().cache
+    });
+    cache.read().await.update_app(
+        "Discord".to_string(),
+        AppInfo {
+            display_name: "Discord".to_string(),
+            binary_name: "discord".to_string(),
+            current_sink: "Media".to_string(),
+            ..Default::default()
+        },
+    );
+    {
+        let mut inputs = backend.inputs.lock().unwrap();
+        inputs.push(discord_stream(80));
+        inputs.push(SinkInput {
+            id: 81,
+            sink: Some(57),
+            properties: HashMap::from([("application.name".to_string(), "Spotify".to_string())]),
+            ..Default::default()
+        });
+    }
+
+    controller.rescan().await.unwrap();
+
+    let cache_read = cache.read().await;
+    // The voice stream joins Discord rather than making an app of its own
+    let discord = cache_read.apps.get("Discord").unwrap().clone();
+    assert!(discord.active);
+    assert_eq!(discord.sink_input_ids, vec![80]);
+    assert!(cache_read.apps.get("WEBRTC VoiceEngine").is_none());
+    // Denied apps stay out of the cache
+    assert!(cache_read.apps.get("Spotify").is_none());
+    let firefox = cache_read.apps.get("Firefox").unwrap().clone();
+    assert_eq!((firefox.sink_input_ids, firefox.current_sink), (vec![71], "Game".to_string()));
+}
+
+#[tokio::test]
+async fn test_adopt_stream_takes_in_only_that_stream() {
+    let (controller, backend, cache) = fake_controller();
+    // Stale, but only a rescan would notice
+    cache.read().await.update_app(
+        "Old".to_string(),
+        AppInfo {
+            display_name: "Old".to_string(),
+            current_sink: "Media".to_string(),
+            active: true,
+            sink_input_ids: vec![6],
+            ..Default::default()
+        },
+    );
+    backend.inputs.lock().unwrap().push(discord_stream(80));
+
+    assert_eq!(controller.adopt_stream(80, &Config::default().routing).await.unwrap(), None);
+
+    let cache_read = cache.read().await;
+    let discord = cache_read.apps.get("Discord").unwrap().clone();
+    assert_eq!((discord.sink_input_ids, discord.current_sink), (vec![80], "Media".to_string()));
+    let old = cache_read.apps.get("Old").unwrap().clone();
+    assert!(old.active && old.sink_input_ids == [6]);
+    assert!(cache_read.apps.get("Firefox").is_none());
+}
+
+#[tokio::test]
+async fn test_resume_rescans_and_reapplies_routing() {
+    let (controller, backend, cache) = fake_controller();
+    let controller = Arc::new(controller);
+    {
+        // Ids from before the suspend; PipeWire put Firefox back on Game
+        let cache_write = cache.write().await;
+        for (name, id) in [("Game", 10), ("Media", 11)] {
+            cache_write.update_sink(
+                name.to_string(),
+                SinkInfo {
+                    id,
+                    name: name.to_string(),
+                    volume: 1.0,
+                    pipewire_id: id,
+                    applied_percent: 100,
+                    ..Default::default()
+                },
+            );
+        }
+        cache_write.update_app(
+            "Firefox".to_string(),
+            AppInfo {
+                display_name: "Firefox".to_string(),
+                binary_name: "firefox".to_string(),
+                stream_names: vec!["Firefox".to_string()],
+                current_sink: "Media".to_string(),
+                active: true,
+                sink_input_ids: vec![5],
+                pipewire_id: 5,
+                ..Default::default()
+            },
+        );
+        cache_write.routing_rules.insert("Firefox".to_string(), "Media".to_string());
+        cache_write.set_auto_routing(true);
+    }
+    let watcher = ResumeWatcher::new(controller.clone(), Config::default().routing);
+
+    // Going to sleep leaves everything alone
+    watcher.watch(futures_util::stream::iter([true])).await;
+    assert_eq!(cache.read().await.sinks.get("Game").unwrap().pipewire_id, 10);
+
+    watcher.watch(futures_util::stream::iter([true, false])).await;
+    let cache_read = cache.read().await;
+    assert_eq!(cache_read.sinks.get("Game").unwrap().pipewire_id, 56);
+    assert_eq!(cache_read.sinks.get("Media").unwrap().pipewire_id, 57);
+    assert_eq!(cache_read.apps.get("Firefox").unwrap().current_sink, "Media");
+    let inputs = backend.inputs.lock().unwrap();
+    assert_eq!(inputs.iter().find(|input| input.id == 71).unwrap().sink, Some(57));
+}
+
+#[tokio::test]
+async fn test_overlapping_sink_input_lookups_share_one_listing() {
+    let (controller, backend, cache) = fake_controller();
+    cache.read().await.update_sink(
+        "Media".to_string(),
+        SinkInfo {
+            id: 57,
+            name: "Media".to_string(),
+            volume: 1.0,
+            pipewire_id: 57,
+            applied_percent: 100,
+            ..Default::default()
+        },
+    );
+
+    let (first, second) =
+        tokio::join!(controller.list_sink_inputs(), controller.list_sink_inputs());
+    assert_eq!(first.unwrap(), second.unwrap());
+    assert_eq!(*backend.list_calls.lock().unwrap(), 1);
+
+    // A move makes the next lookup see where the stream went
+    controller.route_sink_input(71, "Media").await.unwrap();
+    let calls = *backend.list_calls.lock().unwrap();
+    let inputs = controller.list_sink_inputs().await.unwrap();
+    assert_eq!(inputs.iter().find(|input| input.id == 71).unwrap().sink, Some(57));
+    assert_eq!(*backend.list_calls.lock().unwrap(), calls + 1);
+
+    // The listing expires on its own too
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    controller.list_sink_inputs().await.unwrap();
+    assert_eq!(*backend.list_calls.lock().unwrap(), calls + 2);
+}
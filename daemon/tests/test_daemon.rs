@@ -0,0 +1,861 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use pipewire_volume_mixer_daemon::backend::{Node, PipeWireBackend, SinkEntry};
+use pipewire_volume_mixer_daemon::cache::{ImportMode, SinkInfo};
+use pipewire_volume_mixer_daemon::config::{AppMappings, Config, StaleRulePolicy};
+use pipewire_volume_mixer_daemon::dbus_service::{coalesce_changes, STATE_CHANGED_WINDOW};
+use pipewire_volume_mixer_daemon::events::EventKind;
+use pipewire_volume_mixer_daemon::ipc::{MAX_IMPORT_LEN, MAX_LINE_LEN};
+use pipewire_volume_mixer_daemon::ipc_binary::{read_frame, write_frame, Request, Response};
+use pipewire_volume_mixer_daemon::mock_backend::MockBackend;
+use pipewire_volume_mixer_daemon::shared_memory::SharedMemoryReader;
+use pipewire_volume_mixer_daemon::sink_inputs::SinkInput;
+use pipewire_volume_mixer_daemon::Daemon;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tempfile::tempdir;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+
+/// Records volume and mute changes and loaded modules; there are no streams to move
+#[derive(Default, Clone)]
+struct FakeBackend {
+    volumes: Arc<Mutex<HashMap<Node, u32>>>,
+    mutes: Arc<Mutex<HashMap<Node, bool>>>,
+    modules: Arc<Mutex<Vec<u32>>>, // Loaded module ids, whoever loaded them
+    unloaded: Arc<Mutex<Vec<u32>>>,
+}
+
+#[async_trait]
+impl PipeWireBackend for FakeBackend {
+    async fn set_volume(&self, node: Node, percent: u32) -> Result<()> {
+        self.volumes.lock().unwrap().insert(node, percent);
+        Ok(())
+    }
+
+    async fn set_mute(&self, node: Node, muted: bool) -> Result<()> {
+        self.mutes.lock().unwrap().insert(node, muted);
+        Ok(())
+    }
+
+    async fn move_sink_input(&self, _sink_input_id: u32, _sink_name: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn list_sink_inputs(&self) -> Result<Vec<SinkInput>> {
+        Ok(vec![])
+    }
+
+    async fn combine_sinks(&self, _sink_name: &str, _targets: &[String]) -> Result<Option<u32>> {
+        Ok(None)
+    }
+
+    async fn list_sinks(&self) -> Result<Vec<SinkEntry>> {
+        Ok(vec![SinkEntry { id: 34, name: "Game".to_string() }])
+    }
+
+    async fn create_virtual_sink(
+        &self,
+        _sink_name: &str,
+        _description: &str,
+        _loopback_name: &str,
+    ) -> Result<Vec<u32>> {
+        let mut modules = self.modules.lock().unwrap();
+        let next = modules.iter().max().map_or(1, |id| id + 1);
+        let ids = vec![next, next + 1];
+        modules.extend(&ids);
+        Ok(ids)
+    }
+
+    async fn unload_module(&self, module_id: u32) -> Result<()> {
+        self.modules.lock().unwrap().retain(|id| *id != module_id);
+        self.unloaded.lock().unwrap().push(module_id);
+        Ok(())
+    }
+
+    async fn server_version(&self) -> Result<String> {
+        Ok("1.2.3".to_string())
+    }
+
+    async fn default_sink(&self) -> Result<String> {
+        Ok("Game".to_string())
+    }
+}
+
+async fn connect(daemon: &Daemon) -> UnixStream {
+    for _ in 0..100 {
+        if let Ok(stream) = UnixStream::connect(daemon.socket_path()).await {
+            return stream;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("daemon never bound {}", daemon.socket_path().display());
+}
+
+async fn request(reader: &mut BufReader<UnixStream>, command: &str) -> String {
+    reader.get_mut().write_all(format!("{command}\n").as_bytes()).await.unwrap();
+    let mut line = String::new();
+    reader.read_line(&mut line).await.unwrap();
+    line.trim_end().to_string()
+}
+
+async fn exchange(reader: &mut BufReader<UnixStream>, request: Request) -> Response {
+    write_frame(reader.get_mut(), &request.encode()).await.unwrap();
+    Response::decode(&read_frame(reader).await.unwrap().unwrap()).unwrap()
+}
+
+/// A daemon with only its IPC, shared memory and cleanup tasks, keeping files in `dir`
+async fn embedded_daemon(dir: &Path, backend: FakeBackend) -> Arc<Daemon> {
+    embedded_daemon_with_config(dir, backend, Config::default()).await
+}
+
+async fn embedded_daemon_with_config(
+    dir: &Path,
+    backend: FakeBackend,
+    config: Config,
+) -> Arc<Daemon> {
+    let daemon = Daemon::builder(config)
+        .with_socket_path(dir.join("daemon.sock"))
+        .with_shm_path(dir.join("daemon.shm"))
+        .with_backend(Box::new(backend))
+        .with_app_mappings(AppMappings::default())
+        .with_dbus(false)
+        .with_monitor(false)
+        .build();
+    daemon.cache().read().await.update_sink(
+        "Game".to_string(),
+        SinkInfo {
+            id: 34,
+            name: "Game".to_string(),
+            volume: 0.3,
+            muted: true,
+            pipewire_id: 34,
+            applied_percent: 30,
+            ..Default::default()
+        },
+    );
+    Arc::new(daemon)
+}
+
+#[tokio::test]
+async fn test_embedded_daemon_serves_ipc_through_its_backend() {
+    let dir = tempdir().unwrap();
+    let backend = FakeBackend::default();
+    let daemon = embedded_daemon(dir.path(), backend.clone()).await;
+
+    let running = daemon.clone();
+    let handle = tokio::spawn(async move { running.run().await });
+    let mut reader = BufReader::new(connect(&daemon).await);
+
+    assert_eq!(request(&mut reader, "PING").await, "OK PONG");
+    assert!(request(&mut reader, "HEALTH").await.starts_with("OK sinks=1 "));
+    assert_eq!(request(&mut reader, "RESET_VOLUME Game").await, "OK Reset Game volume to 1");
+
+    assert_eq!(backend.volumes.lock().unwrap().get(&Node::Sink(34)), Some(&100));
+    assert_eq!(backend.mutes.lock().unwrap().get(&Node::Sink(34)), Some(&false));
+    assert_eq!(daemon.cache().read().await.server_version(), Some("1.2.3"));
+
+    daemon.shutdown();
+    tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap().unwrap();
+    assert!(!daemon.socket_path().exists());
+}
+
+#[tokio::test]
+async fn test_oversized_lines_close_the_connection() {
+    let dir = tempdir().unwrap();
+    let daemon = embedded_daemon(dir.path(), FakeBackend::default()).await;
+    let running = daemon.clone();
+    let handle = tokio::spawn(async move { running.run().await });
+
+    // Failed commands leave the connection open
+    let mut reader = BufReader::new(connect(&daemon).await);
+    assert!(request(&mut reader, "BOGUS").await.starts_with("ERROR UNKNOWN_COMMAND "));
+    assert_eq!(request(&mut reader, "PING").await, "OK PONG");
+
+    // A line without an end is cut off at the limit
+    let oversized = vec![b'A'; MAX_LINE_LEN + 1];
+    reader.get_mut().write_all(&oversized).await.unwrap();
+    let mut line = String::new();
+    reader.read_line(&mut line).await.unwrap();
+    assert!(line.starts_with("ERROR BAD_ARGS "), "{line}");
+    line.clear();
+    assert_eq!(reader.read_line(&mut line).await.unwrap(), 0);
+
+    // A client leaving mid-line gets nothing run on its behalf
+    let mut reader = BufReader::new(connect(&daemon).await);
+    reader.get_mut().write_all(b"RESET_VOLUME Game").await.unwrap();
+    reader.get_mut().shutdown().await.unwrap();
+    assert_eq!(reader.read_line(&mut line).await.unwrap(), 0);
+    assert_eq!(daemon.cache().read().await.sinks.get("Game").unwrap().volume, 0.3);
+
+    // The daemon keeps serving other clients
+    let mut reader = BufReader::new(connect(&daemon).await);
+    let longest = format!("PING{}", " ".repeat(MAX_LINE_LEN - 5));
+    assert_eq!(request(&mut reader, &longest).await, "OK PONG");
+
+    daemon.shutdown();
+    tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_binary_clients_share_the_text_handlers() {
+    let dir = tempdir().unwrap();
+    let backend = FakeBackend::default();
+    let daemon = embedded_daemon(dir.path(), backend.clone()).await;
+
+    let running = daemon.clone();
+    let handle = tokio::spawn(async move { running.run().await });
+    let mut reader = BufReader::new(connect(&daemon).await);
+    assert_eq!(request(&mut reader, "PROTOCOL binary").await, "OK binary");
+
+    assert_eq!(exchange(&mut reader, Request::Ping).await, Response::Ok("PONG".to_string()));
+    assert_eq!(
+        exchange(&mut reader, Request::Text("RESET_VOLUME Game".to_string())).await,
+        Response::Ok("Reset Game volume to 1".to_string())
+    );
+    assert_eq!(
+        exchange(&mut reader, Request::GetVolume { sink: "Game".to_string() }).await,
+        Response::Ok("volume=1 volume_db=0.0 applied_percent=100 muted=false".to_string())
+    );
+    match exchange(&mut reader, Request::GetVolume { sink: "Nope".to_string() }).await {
+        Response::Error { code, .. } => assert_eq!(code, "UNKNOWN_SINK"),
+        other => panic!("expected an error, got {other:?}"),
+    }
+    assert_eq!(backend.volumes.lock().unwrap().get(&Node::Sink(34)), Some(&100));
+
+    daemon.shutdown();
+    tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_daemon_publishes_snapshots_to_shared_memory() {
+    let dir = tempdir().unwrap();
+    let daemon = embedded_daemon(dir.path(), FakeBackend::default()).await;
+    let running = daemon.clone();
+    let handle = tokio::spawn(async move { running.run().await });
+
+    let reader = SharedMemoryReader::with_path(dir.path().join("daemon.shm"));
+    let mut snapshot = None;
+    for _ in 0..100 {
+        if let Ok(read) = reader.try_read() {
+            snapshot = Some(read);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let snapshot = snapshot.expect("daemon never wrote a shared memory snapshot");
+    assert_eq!(snapshot.generation, daemon.cache().read().await.get_generation());
+    assert_eq!(snapshot.sinks.len(), 1);
+    assert_eq!(snapshot.sinks[0].name, "Game");
+    assert!(snapshot.sinks[0].muted);
+
+    daemon.shutdown();
+    tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap().unwrap();
+    assert!(!dir.path().join("daemon.shm").exists());
+}
+
+#[tokio::test]
+async fn test_daemon_unloads_only_modules_it_created() {
+    let dir = tempdir().unwrap();
+    let backend = FakeBackend::default();
+    // A module the user loaded themselves, e.g. for an existing Game sink
+    backend.modules.lock().unwrap().push(7);
+    let config = Config { auto_create_sinks: true, ..Config::default() };
+    let daemon = embedded_daemon_with_config(dir.path(), backend.clone(), config).await;
+
+    let running = daemon.clone();
+    let handle = tokio::spawn(async move { running.run().await });
+    // Sinks are created once the IPC socket is up
+    let mut reader = BufReader::new(connect(&daemon).await);
+    assert_eq!(request(&mut reader, "PING").await, "OK PONG");
+    for _ in 0..100 {
+        if !daemon.created_modules().is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    // Game exists already; Chat and Media each get a null sink and a loopback
+    let created = daemon.created_modules();
+    assert_eq!(created, vec![8, 9, 10, 11]);
+
+    daemon.shutdown();
+    tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap().unwrap();
+    assert_eq!(*backend.unloaded.lock().unwrap(), vec![11, 10, 9, 8]);
+    assert_eq!(*backend.modules.lock().unwrap(), vec![7]);
+    assert!(daemon.created_modules().is_empty());
+}
+
+#[tokio::test]
+async fn test_export_import_restores_and_saves_rules() {
+    let dir = tempdir().unwrap();
+    let mappings_file = dir.path().join("app-mappings.toml");
+    let daemon = Daemon::builder(Config::default())
+        .with_socket_path(dir.path().join("daemon.sock"))
+        .with_shm_path(dir.path().join("daemon.shm"))
+        .with_backend(Box::new(FakeBackend::default()))
+        .with_app_mappings(AppMappings::load_from(&mappings_file).unwrap())
+        .with_dbus(false)
+        .with_monitor(false)
+        .build();
+    let cache = daemon.cache().clone();
+    {
+        let cache = cache.read().await;
+        for (name, id) in [("Game", 34), ("Chat", 35)] {
+            cache.update_sink(
+                name.to_string(),
+                SinkInfo {
+                    id,
+                    name: name.to_string(),
+                    volume: 1.0,
+                    pipewire_id: id,
+                    applied_percent: 100,
+                    ..Default::default()
+                },
+            );
+        }
+        cache.routing_rules.insert("Firefox".to_string(), "Game".to_string());
+        cache.routing_rules.insert("Discord".to_string(), "Chat".to_string());
+    }
+    daemon.import_config(r#"{"default_volumes": {"Game": 0.7}}"#, ImportMode::Merge).await.unwrap();
+
+    let exported = daemon.export_config().await.unwrap();
+    daemon.import_config("{}", ImportMode::Replace).await.unwrap();
+    assert!(cache.read().await.routing_rules.is_empty());
+    assert_eq!(cache.read().await.default_volume("Game"), 1.0);
+
+    daemon.import_config(&exported, ImportMode::Replace).await.unwrap();
+    {
+        let cache = cache.read().await;
+        assert_eq!(cache.routing_rules.get("Firefox").unwrap().as_str(), "Game");
+        assert_eq!(cache.routing_rules.get("Discord").unwrap().as_str(), "Chat");
+        assert!((cache.default_volume("Game") - 0.7).abs() < 1e-6);
+    }
+    let saved = AppMappings::load_from(&mappings_file).unwrap();
+    assert_eq!(saved.get("Firefox").map(String::as_str), Some("Game"));
+    assert_eq!(saved.default_volumes.get("Game"), Some(&0.7));
+
+    // An import naming a missing sink changes nothing
+    let err = daemon.import_config(r#"{"rules": {"Steam": "Media"}}"#, ImportMode::Replace).await;
+    assert!(err.unwrap_err().to_string().contains("Media"));
+    assert_eq!(cache.read().await.routing_rules.len(), 2);
+    assert!(daemon.import_config("not json", ImportMode::Merge).await.is_err());
+}
+
+#[tokio::test]
+async fn test_large_export_imports_over_the_socket() {
+    let dir = tempdir().unwrap();
+    let daemon = Arc::new(
+        Daemon::builder(Config::default())
+            .with_socket_path(dir.path().join("daemon.sock"))
+            .with_shm_path(dir.path().join("daemon.shm"))
+            .with_backend(Box::new(FakeBackend::default()))
+            .with_app_mappings(
+                AppMappings::load_from(dir.path().join("app-mappings.toml")).unwrap(),
+            )
+            .with_dbus(false)
+            .with_monitor(false)
+            .build(),
+    );
+    let cache = daemon.cache().clone();
+    {
+        let cache = cache.read().await;
+        cache.update_sink(
+            "Game".to_string(),
+            SinkInfo { id: 34, name: "Game".to_string(), volume: 1.0, ..Default::default() },
+        );
+        for i in 0..500 {
+            cache.routing_rules.insert(format!("Some long application name {i}"), "Game".into());
+        }
+    }
+    let running = daemon.clone();
+    let handle = tokio::spawn(async move { running.run().await });
+    let mut reader = BufReader::new(connect(&daemon).await);
+
+    let exported = request(&mut reader, "EXPORT").await;
+    let exported = exported.strip_prefix("OK ").unwrap().to_string();
+    assert!(exported.len() > MAX_LINE_LEN);
+    cache.read().await.routing_rules.clear();
+    let response = request(&mut reader, &format!("IMPORT replace {exported}")).await;
+    assert!(response.starts_with("OK Imported 500 rules "), "{response}");
+    assert_eq!(cache.read().await.routing_rules.len(), 500);
+
+    // Past its own limit an IMPORT is refused like any other oversized line
+    let oversized = format!("IMPORT replace {}", " ".repeat(MAX_IMPORT_LEN));
+    let response = request(&mut reader, &oversized).await;
+    assert!(response.starts_with("ERROR BAD_ARGS "), "{response}");
+
+    daemon.shutdown();
+    tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_pinned_app_is_routed_when_it_appears_and_the_pin_is_saved() {
+    let dir = tempdir().unwrap();
+    let mappings_file = dir.path().join("app-mappings.toml");
+    let backend = MockBackend::new().with_sink("Game").with_sink("Media");
+    let mut config = Config::default();
+    config.routing.enable_auto_routing = false;
+    let daemon = Arc::new(
+        Daemon::builder(config)
+            .with_socket_path(dir.path().join("daemon.sock"))
+            .with_backend(Box::new(backend.clone()))
+            .with_app_mappings(AppMappings::load_from(&mappings_file).unwrap())
+            .with_dbus(false)
+            .with_monitor(false)
+            .build(),
+    );
+    let cache = daemon.cache().clone();
+    for name in ["Game", "Media"] {
+        let id = backend.sink_id(name).unwrap();
+        cache.read().await.update_sink(
+            name.to_string(),
+            SinkInfo {
+                id,
+                name: name.to_string(),
+                volume: 1.0,
+                pipewire_id: id,
+                applied_percent: 100,
+                ..Default::default()
+            },
+        );
+    }
+    let running = daemon.clone();
+    let handle = tokio::spawn(async move { running.run().await });
+    let mut reader = BufReader::new(connect(&daemon).await);
+
+    assert_eq!(request(&mut reader, "PIN Spotify Media").await, "OK Pinned Spotify to Media");
+    let mut saved = AppMappings::default();
+    for _ in 0..100 {
+        saved = AppMappings::load_from(&mappings_file).unwrap();
+        if !saved.pins.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(saved.pins.get("Spotify").map(String::as_str), Some("Media"));
+
+    // Auto-routing is off, the pin moves it all the same
+    let stream = backend.add_stream("Game", &[("application.name", "Spotify")]).unwrap();
+    for _ in 0..100 {
+        if backend.stream_sink(stream).as_deref() == Some("Media") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(backend.stream_sink(stream).as_deref(), Some("Media"));
+
+    // Routes made by the daemon itself save the app's recent sinks too
+    for _ in 0..100 {
+        saved = AppMappings::load_from(&mappings_file).unwrap();
+        if saved.recent_sinks.contains_key("Spotify") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(saved.recent_sinks.get("Spotify"), Some(&vec!["Media".to_string()]));
+
+    assert_eq!(request(&mut reader, "UNPIN Spotify").await, "OK Unpinned Spotify");
+    assert!(request(&mut reader, "UNPIN Spotify").await.starts_with("ERROR UNKNOWN_APP "));
+
+    daemon.shutdown();
+    handle.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_default_sink_is_tracked_as_it_changes() {
+    let dir = tempdir().unwrap();
+    let backend = MockBackend::new().with_sink("Game").with_sink("Media");
+    backend.set_default_sink("Media");
+    let daemon = Arc::new(
+        Daemon::builder(Config::default())
+            .with_socket_path(dir.path().join("daemon.sock"))
+            .with_backend(Box::new(backend.clone()))
+            .with_app_mappings(AppMappings::default())
+            .with_dbus(false)
+            .with_monitor(false)
+            .build(),
+    );
+    let cache = daemon.cache().clone();
+    let running = daemon.clone();
+    let handle = tokio::spawn(async move { running.run().await });
+    let mut reader = BufReader::new(connect(&daemon).await);
+
+    assert_eq!(request(&mut reader, "GET_DEFAULT_SINK").await, "OK Media");
+
+    // Picked up by the next poll, with a new generation for clients to notice
+    let generation = cache.read().await.get_generation();
+    backend.set_default_sink("Game");
+    for _ in 0..150 {
+        if cache.read().await.default_sink().as_deref() == Some("Game") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(request(&mut reader, "GET_DEFAULT_SINK").await, "OK Game");
+    assert!(cache.read().await.get_generation() > generation);
+
+    daemon.shutdown();
+    handle.await.unwrap().unwrap();
+}
+
+/// A daemon on `backend` with no D-Bus or monitor, keeping its mappings in `dir`
+async fn start_mock_daemon(
+    dir: &Path,
+    backend: &MockBackend,
+) -> (Arc<Daemon>, tokio::task::JoinHandle<anyhow::Result<()>>) {
+    let daemon = Arc::new(
+        Daemon::builder(Config::default())
+            .with_socket_path(dir.join("daemon.sock"))
+            .with_backend(Box::new(backend.clone()))
+            .with_app_mappings(AppMappings::load_from(dir.join("app-mappings.toml")).unwrap())
+            .with_dbus(false)
+            .with_monitor(false)
+            .build(),
+    );
+    let running = daemon.clone();
+    let handle = tokio::spawn(async move { running.run().await });
+    connect(&daemon).await;
+    (daemon, handle)
+}
+
+#[tokio::test]
+async fn test_app_volume_and_mute_are_saved_and_restored_on_relaunch() {
+    let dir = tempdir().unwrap();
+    let backend = MockBackend::new().with_sink("Game");
+    let spotify = [("application.name", "Spotify")];
+
+    let (daemon, handle) = start_mock_daemon(dir.path(), &backend).await;
+    let first = backend.add_stream("Game", &spotify).unwrap();
+    for _ in 0..100 {
+        if daemon.cache().read().await.apps.contains_key("Spotify") {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let mut reader = BufReader::new(connect(&daemon).await);
+    assert_eq!(
+        request(&mut reader, "SET_APP_VOLUME Spotify 0.4").await,
+        "OK Set Spotify volume to 0.4"
+    );
+    assert_eq!(request(&mut reader, "MUTE_APP Spotify true").await, "OK Set Spotify mute to true");
+    assert_eq!(backend.volume(Node::SinkInput(first)), Some(40));
+
+    let mappings_file = dir.path().join("app-mappings.toml");
+    let mut saved = AppMappings::default();
+    for _ in 0..100 {
+        saved = AppMappings::load_from(&mappings_file).unwrap();
+        if saved.app_settings.get("Spotify").is_some_and(|settings| settings.muted.is_some()) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let settings = saved.app_settings["Spotify"];
+    assert_eq!((settings.volume, settings.muted), (Some(0.4), Some(true)));
+    daemon.shutdown();
+    handle.await.unwrap().unwrap();
+
+    // Relaunched under a new daemon, the app's new stream gets its settings back
+    backend.remove_stream(first);
+    let (daemon, handle) = start_mock_daemon(dir.path(), &backend).await;
+    let second = backend.add_stream("Game", &spotify).unwrap();
+    for _ in 0..100 {
+        if backend.muted(Node::SinkInput(second)).is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(backend.volume(Node::SinkInput(second)), Some(40));
+    assert_eq!(backend.muted(Node::SinkInput(second)), Some(true));
+    assert!(daemon.cache().read().await.apps.get("Spotify").unwrap().muted);
+
+    daemon.shutdown();
+    handle.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_mock_backend_stream_is_routed_by_rule_end_to_end() {
+    let dir = tempdir().unwrap();
+    let backend = MockBackend::new().with_sink("Game").with_sink("Media");
+    let mut app_mappings = AppMappings::load_from(dir.path().join("app-mappings.toml")).unwrap();
+    app_mappings.update_and_save("Firefox".to_string(), "Media".to_string()).unwrap();
+    let daemon = Arc::new(
+        Daemon::builder(Config::default())
+            .with_socket_path(dir.path().join("daemon.sock"))
+            .with_backend(Box::new(backend.clone()))
+            .with_app_mappings(app_mappings)
+            .with_dbus(false)
+            .with_monitor(false)
+            .build(),
+    );
+    let cache = daemon.cache().clone();
+    for name in ["Game", "Media"] {
+        let id = backend.sink_id(name).unwrap();
+        cache.read().await.update_sink(
+            name.to_string(),
+            SinkInfo {
+                id,
+                name: name.to_string(),
+                volume: 1.0,
+                pipewire_id: id,
+                applied_percent: 100,
+                ..Default::default()
+            },
+        );
+    }
+
+    // What the D-Bus service turns into StateChanged signals
+    let (tx, mut state_changed) = tokio::sync::mpsc::unbounded_channel();
+    let changes = cache.read().await.subscribe_changes();
+    tokio::spawn(coalesce_changes(changes, STATE_CHANGED_WINDOW, move |generation| {
+        let tx = tx.clone();
+        async move {
+            let _ = tx.send(generation);
+        }
+    }));
+    let running = daemon.clone();
+    let handle = tokio::spawn(async move { running.run().await });
+    connect(&daemon).await;
+
+    let stream = backend.add_stream("Game", &[("application.name", "Firefox")]).unwrap();
+    let routed = EventKind::RouteApplied { app: "Firefox".to_string(), sink: "Media".to_string() };
+    for _ in 0..100 {
+        if cache.read().await.recent_events(10).iter().any(|event| event.kind == routed) {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(backend.stream_sink(stream).as_deref(), Some("Media"));
+    let firefox = cache.read().await.apps.get("Firefox").unwrap().clone();
+    assert_eq!((firefox.sink_input_ids, firefox.current_sink), (vec![stream], "Media".into()));
+
+    let generation = cache.read().await.get_generation();
+    let mut signalled = 0;
+    while signalled < generation {
+        signalled = tokio::time::timeout(STATE_CHANGED_WINDOW * 10, state_changed.recv())
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    daemon.shutdown();
+    tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_auto_routed_target_is_saved_as_a_mapping() {
+    let dir = tempdir().unwrap();
+    let mappings_file = dir.path().join("app-mappings.toml");
+    let backend = MockBackend::new().with_sink("Game").with_sink("Media");
+    let mut config = Config::default();
+    config.routing.default_sink = "Media".to_string();
+    config.routing.route_unmatched_to_default = true;
+    let mut app_mappings = AppMappings::load_from(&mappings_file).unwrap();
+    app_mappings.mappings.insert("Zoom".to_string(), "Game".to_string());
+    app_mappings.save().unwrap();
+    let daemon = Arc::new(
+        Daemon::builder(config)
+            .with_socket_path(dir.path().join("daemon.sock"))
+            .with_backend(Box::new(backend.clone()))
+            .with_app_mappings(AppMappings::load_from(&mappings_file).unwrap())
+            .with_dbus(false)
+            .with_monitor(false)
+            .build(),
+    );
+    let cache = daemon.cache().clone();
+    {
+        // A saved rule the cache dropped, and one it has only for now
+        let cache = cache.read().await;
+        cache.routing_rules.remove("Zoom");
+        cache.routing_rules.insert("Discord".to_string(), "Game".to_string());
+    }
+    for name in ["Game", "Media"] {
+        let id = backend.sink_id(name).unwrap();
+        cache.read().await.update_sink(
+            name.to_string(),
+            SinkInfo {
+                id,
+                name: name.to_string(),
+                volume: 1.0,
+                pipewire_id: id,
+                applied_percent: 100,
+                ..Default::default()
+            },
+        );
+    }
+    let running = daemon.clone();
+    let handle = tokio::spawn(async move { running.run().await });
+    connect(&daemon).await;
+
+    let stream = backend.add_stream("Game", &[("application.name", "Spotify")]).unwrap();
+    let mut saved = AppMappings::default();
+    for _ in 0..100 {
+        saved = AppMappings::load_from(&mappings_file).unwrap();
+        if saved.get("Spotify").is_some() && backend.stream_sink(stream).as_deref() == Some("Media")
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(saved.get("Spotify").map(String::as_str), Some("Media"));
+    assert_eq!(backend.stream_sink(stream).as_deref(), Some("Media"));
+    // Only the learned rule is added, and the dropped one is gone
+    assert_eq!(saved.mappings.len(), 1);
+
+    daemon.shutdown();
+    handle.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_sink_label_set_over_ipc_is_saved_when_persisting_labels() {
+    let dir = tempdir().unwrap();
+    let mappings_file = dir.path().join("app-mappings.toml");
+    let backend = MockBackend::new().with_sink("Game");
+    let config = Config { persist_sink_labels: true, ..Config::default() };
+    let daemon = Arc::new(
+        Daemon::builder(config)
+            .with_socket_path(dir.path().join("daemon.sock"))
+            .with_backend(Box::new(backend.clone()))
+            .with_app_mappings(AppMappings::load_from(&mappings_file).unwrap())
+            .with_dbus(false)
+            .with_monitor(false)
+            .build(),
+    );
+    let id = backend.sink_id("Game").unwrap();
+    daemon.cache().read().await.update_sink(
+        "Game".to_string(),
+        SinkInfo {
+            id,
+            name: "Game".to_string(),
+            volume: 1.0,
+            pipewire_id: id,
+            applied_percent: 100,
+            ..Default::default()
+        },
+    );
+    let running = daemon.clone();
+    let handle = tokio::spawn(async move { running.run().await });
+    let mut reader = BufReader::new(connect(&daemon).await);
+
+    let response = request(&mut reader, "SET_SINK_LABEL Game Raid Night").await;
+    assert_eq!(response, "OK Labeled Game as Raid Night");
+    let mut saved = AppMappings::default();
+    for _ in 0..100 {
+        saved = AppMappings::load_from(&mappings_file).unwrap();
+        if !saved.sink_labels.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(saved.sink_labels.get("Game").map(String::as_str), Some("Raid Night"));
+
+    // Given its configured label again, the sink's saved label is dropped from the file
+    let response = request(&mut reader, "SET_SINK_LABEL Game Game").await;
+    assert_eq!(response, "OK Labeled Game as Game");
+    for _ in 0..100 {
+        saved = AppMappings::load_from(&mappings_file).unwrap();
+        if saved.sink_labels.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(saved.sink_labels.is_empty());
+
+    daemon.shutdown();
+    handle.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_ipc_import_saves_only_what_it_brought() {
+    let dir = tempdir().unwrap();
+    let mappings_file = dir.path().join("app-mappings.toml");
+    let backend = MockBackend::new().with_sink("Game").with_sink("Chat");
+    let mut config = Config::default();
+    config.virtual_sinks[0].default_volume = Some(0.4);
+    let daemon = Arc::new(
+        Daemon::builder(config)
+            .with_socket_path(dir.path().join("daemon.sock"))
+            .with_backend(Box::new(backend.clone()))
+            .with_app_mappings(AppMappings::load_from(&mappings_file).unwrap())
+            .with_dbus(false)
+            .with_monitor(false)
+            .build(),
+    );
+    let cache = daemon.cache().clone();
+    for name in ["Game", "Chat"] {
+        let id = backend.sink_id(name).unwrap();
+        cache.read().await.update_sink(
+            name.to_string(),
+            SinkInfo {
+                id,
+                name: name.to_string(),
+                volume: 1.0,
+                pipewire_id: id,
+                applied_percent: 100,
+                ..Default::default()
+            },
+        );
+    }
+    let running = daemon.clone();
+    let handle = tokio::spawn(async move { running.run().await });
+    let mut reader = BufReader::new(connect(&daemon).await);
+
+    let import = r#"IMPORT merge {"rules": {"Discord": "Chat"}, "default_volumes": {"Chat": 0.6}}"#;
+    assert_eq!(request(&mut reader, import).await, "OK Imported 1 rules and 1 default volumes");
+    let saved = AppMappings::load_from(&mappings_file).unwrap();
+    assert_eq!(saved.get("Discord").map(String::as_str), Some("Chat"));
+    assert_eq!(saved.default_volumes.get("Chat"), Some(&0.6));
+    // The configured volume stays in the config file
+    assert!(!saved.default_volumes.contains_key("Game"));
+    assert!((cache.read().await.default_volume("Game") - 0.4).abs() < 1e-6);
+
+    daemon.shutdown();
+    handle.await.unwrap().unwrap();
+}
+
+#[tokio::test]
+async fn test_only_rules_for_sinks_removed_from_the_config_are_pruned() {
+    let dir = tempdir().unwrap();
+    let mappings_file = dir.path().join("app-mappings.toml");
+    let mut app_mappings = AppMappings::load_from(&mappings_file).unwrap();
+    app_mappings.virtual_sinks = vec!["Game".to_string(), "Chat".to_string(), "Media".to_string()];
+    app_mappings.mappings.insert("Firefox".to_string(), "Media".to_string());
+    app_mappings.mappings.insert("mpv".to_string(), "alsa_output.usb_headset".to_string());
+    app_mappings.save().unwrap();
+    let mut config = Config::default();
+    config.routing.stale_rules = StaleRulePolicy::Prune;
+    config.virtual_sinks.retain(|sink| sink.name != "Media");
+    let daemon = Arc::new(
+        Daemon::builder(config)
+            .with_socket_path(dir.path().join("daemon.sock"))
+            .with_backend(Box::new(MockBackend::new()))
+            .with_app_mappings(app_mappings)
+            .with_dbus(false)
+            .with_monitor(false)
+            .build(),
+    );
+    let cache = daemon.cache().clone();
+    let running = daemon.clone();
+    let handle = tokio::spawn(async move { running.run().await });
+    connect(&daemon).await;
+    cache.read().await.mark_ready();
+
+    let mut saved = AppMappings::default();
+    for _ in 0..100 {
+        saved = AppMappings::load_from(&mappings_file).unwrap();
+        if saved.virtual_sinks.len() == 2 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    // Media left the config; the headset is only unplugged
+    assert_eq!(saved.virtual_sinks, vec!["Game".to_string(), "Chat".to_string()]);
+    assert!(saved.get("Firefox").is_none());
+    assert_eq!(saved.get("mpv").map(String::as_str), Some("alsa_output.usb_headset"));
+    assert!(cache.read().await.routing_rules.get("Firefox").is_none());
+
+    daemon.shutdown();
+    handle.await.unwrap().unwrap();
+}
@@ -1,9 +1,20 @@
-use pipewire_volume_mixer_daemon::cache::{AppInfo, AudioCache, SinkInfo};
-use pipewire_volume_mixer_daemon::config::AppMappings;
-use pipewire_volume_mixer_daemon::dbus_service::start_dbus_service;
+use pipewire_volume_mixer_daemon::cache::{AppInfo, AudioCache, SinkEvent, SinkInfo};
+use pipewire_volume_mixer_daemon::command::CommandExecutor;
+use pipewire_volume_mixer_daemon::config::{AppMappings, Config};
+use pipewire_volume_mixer_daemon::dbus_service::{
+    announce_ready, coalesce_changes, forward_app_changes, forward_sink_events, start_dbus_service,
+    AppState, DBusService, MethodError,
+};
+use pipewire_volume_mixer_daemon::ipc::process_command_with;
 use pipewire_volume_mixer_daemon::pipewire_controller::PipeWireController;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ExitStatus, Output};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::RwLock;
+use zbus::zvariant::{OwnedValue, Value};
+use zbus::DBusError;
 
 #[tokio::test]
 async fn test_dbus_service_starts() {
@@ -19,8 +30,8 @@ async fn test_dbus_service_starts() {
                 id: 1,
                 name: "TestSink".to_string(),
                 volume: 0.75,
-                muted: false,
                 pipewire_id: 1,
+                ..Default::default()
             },
         );
 
@@ -34,7 +45,7 @@ async fn test_dbus_service_starts() {
                 active: true,
                 sink_input_ids: vec![100],
                 pipewire_id: 100,
-                inactive_since: None,
+                ..Default::default()
             },
         );
     }
@@ -72,3 +83,676 @@ async fn test_dbus_properties_accessible() {
     }
     // If service is already running, skip this test
 }
+
+#[tokio::test]
+async fn test_get_apps_for_sink() {
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    {
+        let cache_write = cache.write().await;
+        for (name, sink) in [("Firefox", "Chat"), ("Discord", "Game"), ("Spotify", "Media")] {
+            cache_write.update_app(
+                name.to_string(),
+                AppInfo {
+                    display_name: name.to_string(),
+                    binary_name: name.to_lowercase(),
+                    stream_names: vec![name.to_string()],
+                    current_sink: sink.to_string(),
+                    active: true,
+                    ..Default::default()
+                },
+            );
+        }
+
+        // Route two apps onto the same sink
+        assert!(cache_write.set_app_sink("Firefox", "Game"));
+        assert!(cache_write.set_app_sink("Spotify", "Game"));
+    }
+
+    let controller = Arc::new(PipeWireController::new(cache.clone()));
+    let app_mappings = Arc::new(RwLock::new(AppMappings::default()));
+    let service = DBusService::new(cache.clone(), controller, app_mappings);
+
+    assert_eq!(
+        service.get_apps_for_sink("Game".to_string()).await,
+        vec!["Discord".to_string(), "Firefox".to_string(), "Spotify".to_string()]
+    );
+    assert!(service.get_apps_for_sink("Chat".to_string()).await.is_empty());
+    assert!(service.get_apps_for_sink("Unknown".to_string()).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_rapid_changes_coalesce_into_one_state_changed() {
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    let changes = cache.read().await.subscribe_changes();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let window = Duration::from_millis(50);
+    let handle = tokio::spawn(coalesce_changes(changes, window, move |generation| {
+        let tx = tx.clone();
+        async move {
+            tx.send(generation).unwrap();
+        }
+    }));
+
+    for _ in 0..20 {
+        cache.read().await.increment_generation();
+    }
+
+    // One emission, carrying the newest generation
+    let first = tokio::time::timeout(window * 4, rx.recv()).await.unwrap().unwrap();
+    assert_eq!(first, 20);
+    tokio::time::sleep(window * 2).await;
+    assert!(rx.try_recv().is_err());
+
+    // A later change opens a new window
+    cache.read().await.increment_generation();
+    let second = tokio::time::timeout(window * 4, rx.recv()).await.unwrap().unwrap();
+    assert_eq!(second, 21);
+
+    handle.abort();
+}
+
+#[tokio::test]
+async fn test_only_watched_apps_send_app_state_changed() {
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    for (app_name, sink_name) in [("Firefox", "Game"), ("Spotify", "Media")] {
+        cache.read().await.update_app(
+            app_name.to_string(),
+            AppInfo {
+                display_name: app_name.to_string(),
+                binary_name: app_name.to_lowercase(),
+                stream_names: vec![app_name.to_string()],
+                current_sink: sink_name.to_string(),
+                active: true,
+                playing: true,
+                volume: Some(0.5),
+                ..Default::default()
+            },
+        );
+    }
+    let controller = Arc::new(PipeWireController::new(cache.clone()));
+    let app_mappings = Arc::new(RwLock::new(AppMappings::default()));
+    let service = DBusService::new(cache.clone(), controller, app_mappings);
+    service.watch_app("Firefox".to_string()).await;
+
+    let changes = cache.read().await.subscribe_changes();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let window = Duration::from_millis(20);
+    let handle = tokio::spawn(forward_app_changes(
+        changes,
+        cache.clone(),
+        service.watched_apps(),
+        window,
+        move |state| {
+            let tx = tx.clone();
+            async move {
+                tx.send(state).unwrap();
+            }
+        },
+    ));
+
+    // The first change reports the watched app as it is
+    cache.read().await.set_app_mute("Spotify", true);
+    let state = tokio::time::timeout(window * 10, rx.recv()).await.unwrap().unwrap();
+    assert_eq!(state.app_name, "Firefox");
+
+    // After that, changes to other apps send nothing
+    cache.read().await.set_app_mute("Spotify", false);
+    tokio::time::sleep(window * 4).await;
+    assert!(rx.try_recv().is_err());
+
+    cache.read().await.set_app_mute("Firefox", true);
+    let state = tokio::time::timeout(window * 10, rx.recv()).await.unwrap().unwrap();
+    assert_eq!(
+        state,
+        AppState {
+            app_name: "Firefox".to_string(),
+            current_sink: "Game".to_string(),
+            active: true,
+            volume: 0.5,
+            muted: true,
+        }
+    );
+
+    service.unwatch_app("Firefox".to_string()).await;
+    cache.read().await.set_app_mute("Firefox", false);
+    tokio::time::sleep(window * 4).await;
+    assert!(rx.try_recv().is_err());
+
+    handle.abort();
+}
+
+#[tokio::test]
+async fn test_ready_announced_once_after_initial_scan() {
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    let ready = cache.read().await.subscribe_ready();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let announced = cache.clone();
+    let handle = tokio::spawn(announce_ready(ready, move || async move {
+        tx.send(announced.read().await.get_generation()).unwrap();
+    }));
+
+    // The monitor seeds the cache before reporting its first scan complete
+    {
+        let cache = cache.read().await;
+        cache.update_sink(
+            "Game".to_string(),
+            SinkInfo {
+                id: 1,
+                name: "Game".to_string(),
+                volume: 1.0,
+                pipewire_id: 1,
+                applied_percent: 100,
+                ..Default::default()
+            },
+        );
+        assert!(!cache.is_ready());
+    }
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(rx.try_recv().is_err());
+
+    let seeded = cache.read().await.get_generation();
+    assert!(cache.read().await.mark_ready());
+    let generation = tokio::time::timeout(Duration::from_secs(1), rx.recv()).await.unwrap();
+    assert_eq!(generation, Some(seeded + 1));
+    assert!(cache.read().await.is_ready());
+
+    // Later scans don't announce again
+    assert!(!cache.read().await.mark_ready());
+    tokio::time::timeout(Duration::from_secs(1), handle).await.unwrap().unwrap();
+    assert_eq!(rx.recv().await, None);
+}
+
+#[tokio::test]
+async fn test_full_state_matches_its_generation() {
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    let game = |n: u32| SinkInfo {
+        id: 1,
+        name: "Game".to_string(),
+        volume: 1.0,
+        pipewire_id: 1,
+        applied_percent: n,
+        ..Default::default()
+    };
+    cache.read().await.update_sink("Game".to_string(), game(0));
+    let base = cache.read().await.get_generation() as u32;
+
+    let controller = Arc::new(PipeWireController::new(cache.clone()));
+    let app_mappings = Arc::new(RwLock::new(AppMappings::default()));
+    let service = DBusService::new(cache.clone(), controller, app_mappings);
+
+    // Each update bumps the generation by one, so applied_percent tracks the generation
+    for n in 1..=3 {
+        cache.read().await.update_sink("Game".to_string(), game(n));
+        let state = service.get_full_state().await;
+        let Some(Value::U32(generation)) = state.get("generation") else {
+            panic!("generation missing from state");
+        };
+        let Some(Value::Dict(sinks)) = state.get("sinks") else {
+            panic!("sinks missing from state");
+        };
+        let sinks: HashMap<String, HashMap<String, OwnedValue>> = sinks.clone().try_into().unwrap();
+        let applied = u32::try_from(sinks["Game"]["applied_percent"].clone()).unwrap();
+        assert_eq!((applied, generation - base), (n, n));
+    }
+}
+
+#[tokio::test]
+async fn test_physical_sink_plug_and_unplug_are_forwarded() {
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    let events = cache.read().await.subscribe_sink_events();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let handle = tokio::spawn(forward_sink_events(events, move |event| {
+        let tx = tx.clone();
+        async move {
+            tx.send(event).unwrap();
+        }
+    }));
+
+    let usb_dac = "alsa_output.usb-DAC-00.analog-stereo";
+    {
+        let cache = cache.read().await;
+        assert!(cache.add_physical_sink(usb_dac, "USB DAC"));
+        // Seeing the same device again is not a new connection
+        assert!(!cache.add_physical_sink(usb_dac, "USB DAC"));
+        assert!(cache.remove_physical_sink(usb_dac));
+        assert!(!cache.remove_physical_sink(usb_dac));
+        assert!(cache.physical_sinks.is_empty());
+    }
+
+    let timeout = Duration::from_secs(1);
+    let added = tokio::time::timeout(timeout, rx.recv()).await.unwrap().unwrap();
+    assert_eq!(
+        added,
+        SinkEvent::Added { sink_name: usb_dac.to_string(), display_name: "USB DAC".to_string() }
+    );
+    let removed = tokio::time::timeout(timeout, rx.recv()).await.unwrap().unwrap();
+    assert_eq!(removed, SinkEvent::Removed { sink_name: usb_dac.to_string() });
+    assert!(rx.try_recv().is_err());
+
+    // Forwarding stops once the cache is gone
+    drop(cache);
+    tokio::time::timeout(timeout, handle).await.unwrap().unwrap();
+}
+
+/// Lets every pactl call succeed with no output
+struct SucceedingExecutor;
+
+impl CommandExecutor for SucceedingExecutor {
+    fn execute(&self, _program: &str, _args: &[&str]) -> std::io::Result<Output> {
+        Ok(Output { status: ExitStatus::from_raw(0), stdout: vec![], stderr: vec![] })
+    }
+
+    fn execute_shell(&self, _cmd: &str) -> std::io::Result<Output> {
+        Ok(Output { status: ExitStatus::from_raw(0), stdout: vec![], stderr: vec![] })
+    }
+}
+
+#[tokio::test]
+async fn test_sink_state_change_carries_every_field() {
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    cache.read().await.update_sink(
+        "Game".to_string(),
+        SinkInfo {
+            id: 34,
+            name: "Game".to_string(),
+            volume: 1.0,
+            muted: true,
+            pipewire_id: 34,
+            applied_percent: 100,
+            ..Default::default()
+        },
+    );
+    let events = cache.read().await.subscribe_sink_events();
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    tokio::spawn(forward_sink_events(events, move |event| {
+        let tx = tx.clone();
+        async move {
+            tx.send(event).unwrap();
+        }
+    }));
+
+    let controller = PipeWireController::with_executor(cache.clone(), Arc::new(SucceedingExecutor));
+    controller.set_sink_volume("Game", 0.5).await.unwrap();
+    // Reapplying the same value changes nothing, so nothing is announced
+    controller.set_sink_volume("Game", 0.5).await.unwrap();
+    controller.set_sink_mute("Game", false).await.unwrap();
+    // The monitor noticing a change made outside the daemon
+    cache.read().await.update_sink(
+        "Game".to_string(),
+        SinkInfo {
+            id: 34,
+            name: "Game".to_string(),
+            volume: 0.8,
+            pipewire_id: 34,
+            applied_percent: 80,
+            ..Default::default()
+        },
+    );
+    assert!(cache.read().await.remove_sink("Game"));
+
+    let state = |volume: f32, muted: bool, present: bool| SinkEvent::StateChanged {
+        sink_name: "Game".to_string(),
+        volume,
+        muted,
+        present,
+    };
+    let timeout = Duration::from_secs(1);
+    for expected in [
+        state(0.5, true, true),
+        state(0.5, false, true),
+        state(0.8, false, true),
+        state(0.8, false, false),
+    ] {
+        assert_eq!(tokio::time::timeout(timeout, rx.recv()).await.unwrap().unwrap(), expected);
+    }
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_sink_display_name_changes_label_but_not_routing_name() {
+    let cache = Arc::new(RwLock::new(
+        AudioCache::new().with_sink_labels([("Game".to_string(), "Game".to_string())]),
+    ));
+    cache.read().await.update_sink(
+        "Game".to_string(),
+        SinkInfo {
+            id: 56,
+            name: "Game".to_string(),
+            volume: 1.0,
+            pipewire_id: 56,
+            applied_percent: 100,
+            ..Default::default()
+        },
+    );
+    let controller = Arc::new(PipeWireController::new(cache.clone()));
+    let app_mappings = Arc::new(RwLock::new(AppMappings::default()));
+    let service = DBusService::new(cache.clone(), controller, app_mappings.clone());
+    let generation = cache.read().await.get_generation();
+
+    assert!(service
+        .set_sink_display_name("Game".to_string(), "Raid Night".to_string())
+        .await
+        .unwrap());
+    assert!(matches!(
+        service.set_sink_display_name("Missing".to_string(), "Label".to_string()).await,
+        Err(MethodError::UnknownSink(_))
+    ));
+    assert!(matches!(
+        service.set_sink_display_name("Game".to_string(), " ".to_string()).await,
+        Err(MethodError::InvalidArgs(_))
+    ));
+    assert_eq!(cache.read().await.get_generation(), generation + 1);
+
+    let state = service.get_full_state().await;
+    let Some(Value::Dict(sinks)) = state.get("sinks") else {
+        panic!("sinks missing from state");
+    };
+    let sinks: HashMap<String, HashMap<String, OwnedValue>> = sinks.clone().try_into().unwrap();
+    // Still keyed and routed by the node name
+    assert_eq!(sinks.keys().collect::<Vec<_>>(), vec!["Game"]);
+    assert_eq!(String::try_from(sinks["Game"]["display_name"].clone()).unwrap(), "Raid Night");
+    assert_eq!(cache.read().await.sinks.get("Game").unwrap().name, "Game");
+
+    // Setting the same label again changes nothing, and labels aren't saved by default
+    assert!(service
+        .set_sink_display_name("Game".to_string(), "Raid Night".to_string())
+        .await
+        .unwrap());
+    assert_eq!(cache.read().await.get_generation(), generation + 1);
+    assert!(app_mappings.read().await.sink_labels.is_empty());
+}
+
+/// Answers pactl with one Firefox stream, playing on the Media sink
+struct FirefoxOnMediaExecutor;
+
+impl CommandExecutor for FirefoxOnMediaExecutor {
+    fn execute(&self, _program: &str, args: &[&str]) -> std::io::Result<Output> {
+        let stdout = match args {
+            ["list", "sink-inputs"] => {
+                "Sink Input #71\n\tSink: 57\n\tProperties:\n\t\tapplication.name = \"Firefox\"\n"
+            }
+            ["list", "sinks", "short"] => "56\tGame\tPipeWire\n57\tMedia\tPipeWire\n",
+            _ => "",
+        };
+        Ok(Output {
+            status: ExitStatus::from_raw(0),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: vec![],
+        })
+    }
+
+    fn execute_shell(&self, _cmd: &str) -> std::io::Result<Output> {
+        Ok(Output { status: ExitStatus::from_raw(0), stdout: vec![], stderr: vec![] })
+    }
+}
+
+/// Records every call, answering pactl with the Game sink's loopback stream
+#[derive(Default)]
+struct RecordingExecutor {
+    calls: Mutex<Vec<String>>,
+}
+
+impl CommandExecutor for RecordingExecutor {
+    fn execute(&self, program: &str, args: &[&str]) -> std::io::Result<Output> {
+        self.calls.lock().unwrap().push(format!("{program} {}", args.join(" ")));
+        let stdout = match args {
+            ["list", "sink-inputs"] => {
+                "Sink Input #80\n\tSink: 1\n\tProperties:\n\t\tnode.name = \"Game_to_Speaker\"\n"
+            }
+            _ => "",
+        };
+        Ok(Output {
+            status: ExitStatus::from_raw(0),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: vec![],
+        })
+    }
+
+    fn execute_shell(&self, cmd: &str) -> std::io::Result<Output> {
+        self.calls.lock().unwrap().push(cmd.to_string());
+        Ok(Output { status: ExitStatus::from_raw(0), stdout: vec![], stderr: vec![] })
+    }
+}
+
+/// A cache with a muted Game sink and a controller recording what it runs
+async fn recorded_game_sink(
+) -> (Arc<RwLock<AudioCache>>, PipeWireController, Arc<RecordingExecutor>) {
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    cache.read().await.update_sink(
+        "Game".to_string(),
+        SinkInfo {
+            id: 34,
+            name: "Game".to_string(),
+            volume: 1.0,
+            muted: true,
+            pipewire_id: 34,
+            applied_percent: 100,
+            ..Default::default()
+        },
+    );
+    let executor = Arc::new(RecordingExecutor::default());
+    let controller = PipeWireController::with_executor(cache.clone(), executor.clone());
+    (cache, controller, executor)
+}
+
+#[tokio::test]
+async fn test_ipc_and_dbus_set_volume_run_the_same_commands() {
+    let (ipc_cache, ipc_controller, ipc_calls) = recorded_game_sink().await;
+    process_command_with("SET_VOLUME Game 0.5", &ipc_cache, &ipc_controller).await.unwrap();
+
+    let (dbus_cache, dbus_controller, dbus_calls) = recorded_game_sink().await;
+    let service = DBusService::new(
+        dbus_cache.clone(),
+        Arc::new(dbus_controller),
+        Arc::new(RwLock::new(AppMappings::default())),
+    );
+    assert!(service.set_sink_volume("Game".to_string(), 0.5).await.unwrap());
+
+    let calls = ipc_calls.calls.lock().unwrap().clone();
+    assert_eq!(calls, *dbus_calls.calls.lock().unwrap());
+    assert!(calls.contains(&"pactl set-sink-input-volume 80 50%".to_string()), "{calls:#?}");
+    // Raising the volume of the muted sink unmutes it and its loopback
+    assert!(calls.contains(&"pactl set-sink-input-mute 80 0".to_string()), "{calls:#?}");
+    for cache in [ipc_cache, dbus_cache] {
+        let cache = cache.read().await;
+        let game = cache.sinks.get("Game").unwrap();
+        assert_eq!((game.volume, game.muted), (0.5, false));
+    }
+}
+
+#[tokio::test]
+async fn test_ipc_and_dbus_mute_run_the_same_commands() {
+    let (ipc_cache, ipc_controller, ipc_calls) = recorded_game_sink().await;
+    process_command_with("MUTE Game false", &ipc_cache, &ipc_controller).await.unwrap();
+
+    let (dbus_cache, dbus_controller, dbus_calls) = recorded_game_sink().await;
+    let service = DBusService::new(
+        dbus_cache.clone(),
+        Arc::new(dbus_controller),
+        Arc::new(RwLock::new(AppMappings::default())),
+    );
+    assert!(service.set_sink_mute("Game".to_string(), false).await.unwrap());
+
+    let calls = ipc_calls.calls.lock().unwrap().clone();
+    assert_eq!(calls, *dbus_calls.calls.lock().unwrap());
+    assert!(calls.contains(&"pactl set-sink-input-mute 80 0".to_string()), "{calls:#?}");
+    for cache in [ipc_cache, dbus_cache] {
+        assert!(!cache.read().await.sinks.get("Game").unwrap().muted);
+    }
+}
+
+/// Game and Media sinks, with Firefox playing on Game
+async fn firefox_on_game(cache: AudioCache) -> Arc<RwLock<AudioCache>> {
+    let cache = Arc::new(RwLock::new(cache));
+    {
+        let cache = cache.read().await;
+        for (name, id) in [("Game", 56), ("Media", 57)] {
+            cache.update_sink(
+                name.to_string(),
+                SinkInfo {
+                    id,
+                    name: name.to_string(),
+                    volume: 1.0,
+                    pipewire_id: id,
+                    applied_percent: 100,
+                    ..Default::default()
+                },
+            );
+        }
+        cache.update_app(
+            "Firefox".to_string(),
+            AppInfo {
+                display_name: "Firefox".to_string(),
+                binary_name: "firefox".to_string(),
+                stream_names: vec!["Firefox".to_string()],
+                current_sink: "Game".to_string(),
+                active: true,
+                sink_input_ids: vec![71],
+                pipewire_id: 71,
+                ..Default::default()
+            },
+        );
+    }
+    cache
+}
+
+#[tokio::test]
+async fn test_route_without_refresh_delay_leaves_cache_routed() {
+    let cache = firefox_on_game(AudioCache::new().with_route_refresh_delay(Duration::ZERO)).await;
+    let dir = tempfile::tempdir().unwrap();
+    let app_mappings = Arc::new(RwLock::new(
+        AppMappings::load_from(dir.path().join("app-mappings.toml")).unwrap(),
+    ));
+    let controller = Arc::new(PipeWireController::with_executor(
+        cache.clone(),
+        Arc::new(FirefoxOnMediaExecutor),
+    ));
+    let service = DBusService::new(cache.clone(), controller, app_mappings.clone());
+    let generation = cache.read().await.get_generation();
+
+    let started = std::time::Instant::now();
+    service.route_and_save("Firefox", "Media").await.unwrap();
+    service.refresh_after_route().await;
+    assert!(started.elapsed() < Duration::from_millis(300));
+
+    let cache = cache.read().await;
+    assert_eq!(cache.apps.get("Firefox").unwrap().current_sink, "Media");
+    assert_eq!(cache.apps_for_sink("Media"), vec!["Firefox".to_string()]);
+    assert_eq!(cache.routing_rules.get("Firefox").unwrap().as_str(), "Media");
+    assert!(cache.get_generation() > generation);
+    assert_eq!(app_mappings.read().await.get("Firefox").map(String::as_str), Some("Media"));
+}
+
+#[tokio::test]
+async fn test_ipc_route_goes_through_the_controller() {
+    let cache = firefox_on_game(AudioCache::new()).await;
+    let controller =
+        PipeWireController::with_executor(cache.clone(), Arc::new(FirefoxOnMediaExecutor));
+
+    let response = process_command_with("ROUTE Firefox Media", &cache, &controller).await.unwrap();
+    assert_eq!(response, "Routed Firefox to Media");
+
+    let cache = cache.read().await;
+    assert_eq!(cache.apps.get("Firefox").unwrap().current_sink, "Media");
+    assert_eq!(cache.apps_for_sink("Media"), vec!["Firefox".to_string()]);
+    assert_eq!(cache.routing_rules.get("Firefox").unwrap().as_str(), "Media");
+    assert_eq!(cache.recent_sinks("Firefox"), vec!["Media".to_string()]);
+    assert_eq!(cache.latency_summaries()["route"].count, 1);
+}
+
+#[tokio::test]
+async fn test_dbus_set_app_volume_sets_and_remembers_the_apps_volume() {
+    let cache = firefox_on_game(AudioCache::new()).await;
+    let controller = Arc::new(PipeWireController::with_executor(
+        cache.clone(),
+        Arc::new(FirefoxOnMediaExecutor),
+    ));
+    let service =
+        DBusService::new(cache.clone(), controller, Arc::new(RwLock::new(AppMappings::default())));
+
+    assert!(service.set_app_volume("Firefox".to_string(), 0.4).await.unwrap());
+    assert_eq!(cache.read().await.saved_app_settings("Firefox").unwrap().volume, Some(0.4));
+
+    assert!(matches!(
+        service.set_app_volume("Spotify".to_string(), 0.4).await,
+        Err(MethodError::UnknownApp(_))
+    ));
+    assert!(matches!(
+        service.set_app_volume("Firefox".to_string(), f64::NAN).await,
+        Err(MethodError::InvalidArgs(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_route_to_unknown_sink_is_a_dbus_error() {
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    let dir = tempfile::tempdir().unwrap();
+    let app_mappings = Arc::new(RwLock::new(
+        AppMappings::load_from(dir.path().join("app-mappings.toml")).unwrap(),
+    ));
+    let controller = Arc::new(PipeWireController::with_executor(
+        cache.clone(),
+        Arc::new(FirefoxOnMediaExecutor),
+    ));
+    let service = DBusService::new(cache.clone(), controller, app_mappings.clone());
+
+    let err = service.route_and_save("Firefox", "Nowhere").await.unwrap_err();
+    assert!(matches!(err, MethodError::UnknownSink(_)));
+    assert_eq!(err.name().as_str(), "org.gnome.PipewireVolumeMixer.Error.UnknownSink");
+    assert_eq!(err.description(), Some("Unknown sink: Nowhere"));
+
+    // Nothing was remembered for the failed route
+    assert!(cache.read().await.routing_rules.is_empty());
+    assert!(app_mappings.read().await.get("Firefox").is_none());
+}
+
+#[tokio::test]
+async fn test_virtual_sinks_are_reported_in_config_order() {
+    let mut config = Config::default();
+    for (sink, order) in config.virtual_sinks.iter_mut().zip([2, 0, 1]) {
+        sink.order = Some(order);
+    }
+    let mut expected: Vec<(u32, String)> =
+        config.sink_order().into_iter().map(|(name, order)| (order, name)).collect();
+    expected.sort();
+    let expected: Vec<String> = expected.into_iter().map(|(_, name)| name).collect();
+
+    let cache = Arc::new(RwLock::new(AudioCache::new().with_sink_order(config.sink_order())));
+    {
+        let cache_read = cache.read().await;
+        for (id, name) in (56..)
+            .zip(config.virtual_sinks.iter().map(|sink| sink.name.as_str()).chain(["Speakers"]))
+        {
+            cache_read.update_sink(
+                name.to_string(),
+                SinkInfo {
+                    id,
+                    name: name.to_string(),
+                    volume: 1.0,
+                    pipewire_id: id,
+                    applied_percent: 100,
+                    ..Default::default()
+                },
+            );
+        }
+    }
+    let controller = Arc::new(PipeWireController::new(cache.clone()));
+    let app_mappings = Arc::new(RwLock::new(AppMappings::default()));
+    let service = DBusService::new(cache.clone(), controller, app_mappings);
+
+    // Sinks that aren't virtual have no place in the order
+    assert_eq!(service.get_virtual_sinks().await, expected);
+
+    let state = service.get_full_state().await;
+    let Some(Value::Dict(sinks)) = state.get("sinks") else {
+        panic!("sinks missing from state");
+    };
+    let sinks: HashMap<String, HashMap<String, OwnedValue>> = sinks.clone().try_into().unwrap();
+    for (order, name) in (0..).zip(&expected) {
+        assert_eq!(u32::try_from(sinks[name]["order"].clone()).unwrap(), order);
+    }
+    assert!(!sinks["Speakers"].contains_key("order"));
+}
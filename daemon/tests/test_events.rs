@@ -0,0 +1,54 @@
+use pipewire_volume_mixer_daemon::cache::AudioCache;
+use pipewire_volume_mixer_daemon::events::{Event, EventKind, EventLog};
+use pipewire_volume_mixer_daemon::ipc::process_command;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+fn sink_added(sink: &str) -> EventKind {
+    EventKind::SinkAdded { sink: sink.to_string() }
+}
+
+#[test]
+fn test_event_log_keeps_only_the_newest_events() {
+    let log = EventLog::new(3);
+    for sink in ["A", "B", "C", "D"] {
+        log.record(sink_added(sink));
+    }
+
+    let kinds = |events: Vec<Event>| events.into_iter().map(|event| event.kind).collect::<Vec<_>>();
+    assert_eq!(kinds(log.recent(10)), ["B", "C", "D"].map(sink_added));
+    assert_eq!(kinds(log.recent(2)), ["C", "D"].map(sink_added));
+    assert!(log.recent(0).is_empty());
+
+    let disabled = EventLog::new(0);
+    disabled.record(sink_added("A"));
+    assert!(disabled.recent(10).is_empty());
+}
+
+#[tokio::test]
+async fn test_events_command_returns_latest_entries_as_json() {
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    {
+        let cache = cache.read().await;
+        cache.record_event(sink_added("Game"));
+        cache.record_event(EventKind::RouteApplied {
+            app: "Firefox".to_string(),
+            sink: "Game".to_string(),
+        });
+    }
+
+    let events: Vec<serde_json::Value> =
+        serde_json::from_str(&process_command("EVENTS 1", &cache).await.unwrap()).unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0]["event"], "route_applied");
+    assert_eq!(events[0]["app"], "Firefox");
+    assert_eq!(events[0]["sink"], "Game");
+    assert!(events[0]["timestamp_ms"].as_u64().unwrap() > 0);
+
+    let events: Vec<serde_json::Value> =
+        serde_json::from_str(&process_command("EVENTS", &cache).await.unwrap()).unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0]["event"], "sink_added");
+
+    assert!(process_command("EVENTS many", &cache).await.is_err());
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use common::FakeTools;
+use pipewire_volume_mixer_daemon::cache::{AppInfo, AudioCache, SinkInfo};
+use pipewire_volume_mixer_daemon::pipewire_controller::PipeWireController;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+const SINKS: &str = "56\tGame\tPipeWire\tfloat32le 2ch 48000Hz\tIDLE\n\
+                     57\tMedia\tPipeWire\tfloat32le 2ch 48000Hz\tIDLE\n";
+
+fn firefox_streams(sink: u32) -> String {
+    format!(
+        "Sink Input #71\n\tDriver: PipeWire\n\tSink: {sink}\n\tProperties:\n\
+         \t\tapplication.name = \"Firefox\"\n\t\tapplication.process.binary = \"firefox\"\n\n\
+         Sink Input #90\n\tDriver: PipeWire\n\tSink: 1\n\tProperties:\n\
+         \t\tnode.name = \"Game_to_Speaker\"\n"
+    )
+}
+
+fn sink(name: &str, id: u32) -> SinkInfo {
+    SinkInfo {
+        id,
+        name: name.to_string(),
+        volume: 1.0,
+        muted: false,
+        pipewire_id: id,
+        applied_percent: 100,
+        ..Default::default()
+    }
+}
+
+/// A controller using the system pactl, with Firefox playing on the Game sink
+async fn controller() -> (Arc<RwLock<AudioCache>>, PipeWireController) {
+    let cache = AudioCache::new();
+    cache.update_sink("Game".to_string(), sink("Game", 56));
+    cache.update_sink("Media".to_string(), sink("Media", 57));
+    cache.update_app(
+        "Firefox".to_string(),
+        AppInfo {
+            display_name: "Firefox".to_string(),
+            binary_name: "firefox".to_string(),
+            stream_names: vec!["Firefox".to_string()],
+            current_sink: "Game".to_string(),
+            active: true,
+            sink_input_ids: vec![71],
+            pipewire_id: 71,
+            ..Default::default()
+        },
+    );
+    let cache = Arc::new(RwLock::new(cache));
+    (cache.clone(), PipeWireController::new(cache))
+}
+
+#[tokio::test]
+async fn test_route_app_runs_pactl() {
+    let tools = FakeTools::builder()
+        .respond("pactl", &["list", "sink-inputs"], &firefox_streams(56))
+        .respond("pactl", &["list", "sink-inputs"], &firefox_streams(57))
+        .respond("pactl", &["list", "sinks", "short"], SINKS)
+        .install();
+    let (cache, controller) = controller().await;
+
+    controller.route_app("Firefox", "Media").await.unwrap();
+
+    tools.assert_called("pactl", &["move-sink-input", "71", "Media"]);
+    tools.assert_not_called("pactl", &["move-sink-input", "90"]);
+    tools.assert_not_called("pactl", &["load-module"]);
+    let cache = cache.read().await;
+    assert_eq!(cache.apps.get("Firefox").unwrap().current_sink, "Media");
+    assert_eq!(cache.remembered_apps.get("Firefox").unwrap().as_str(), "Media");
+}
+
+#[tokio::test]
+async fn test_set_sink_volume_runs_pactl_for_sink_and_loopback() {
+    let tools = FakeTools::builder()
+        .respond("pactl", &["list", "sink-inputs"], &firefox_streams(56))
+        .install();
+    let (cache, controller) = controller().await;
+
+    controller.set_sink_volume("Game", 0.5).await.unwrap();
+
+    assert_eq!(
+        tools.calls(),
+        vec![
+            "pactl set-sink-volume 56 50%",
+            "pactl list sink-inputs",
+            "pactl set-sink-input-volume 90 50%",
+        ]
+    );
+    assert_eq!(cache.read().await.sinks.get("Game").unwrap().applied_percent, 50);
+}
+
+#[tokio::test]
+async fn test_set_sink_volume_still_sets_loopback_when_sink_fails() {
+    let tools = FakeTools::builder()
+        .fail("pactl", &["set-sink-volume", "56", "30%"], "Failure: No such entity")
+        .respond("pactl", &["list", "sink-inputs"], &firefox_streams(56))
+        .install();
+    let (_cache, controller) = controller().await;
+
+    controller.set_sink_volume("Game", 0.3).await.unwrap();
+
+    tools.assert_called("pactl", &["set-sink-volume", "56", "30%"]);
+    tools.assert_called("pactl", &["set-sink-input-volume", "90", "30%"]);
+}
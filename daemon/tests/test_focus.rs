@@ -0,0 +1,97 @@
+use pipewire_volume_mixer_daemon::app_name_detector::{
+    AppNameConfig, AppNameDetector, CommandExecutor,
+};
+use pipewire_volume_mixer_daemon::focus::focused_app;
+use pipewire_volume_mixer_daemon::sink_inputs::SinkInput;
+use std::collections::HashMap;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ExitStatus, Output};
+
+/// Answers the active window query and `ps` parent lookups from fixed tables
+struct FakeDesktop {
+    focused_pid: Option<u32>,
+    parents: HashMap<u32, u32>,
+}
+
+fn output(success: bool, stdout: String) -> std::io::Result<Output> {
+    Ok(Output {
+        status: ExitStatus::from_raw(if success { 0 } else { 1 << 8 }),
+        stdout: stdout.into_bytes(),
+        stderr: vec![],
+    })
+}
+
+impl CommandExecutor for FakeDesktop {
+    fn execute(&self, program: &str, args: &[&str]) -> std::io::Result<Output> {
+        assert_eq!(program, "ps");
+        let pid: u32 = args.last().unwrap().parse().unwrap();
+        match self.parents.get(&pid) {
+            Some(ppid) => output(true, format!("{ppid}\n")),
+            None => output(false, String::new()),
+        }
+    }
+
+    fn execute_shell(&self, cmd: &str) -> std::io::Result<Output> {
+        assert!(cmd.contains("getactivewindow"), "unexpected command {cmd}");
+        match self.focused_pid {
+            Some(pid) => output(true, format!("{pid}\n")),
+            None => output(false, String::new()),
+        }
+    }
+}
+
+fn detector(focused_pid: Option<u32>, parents: &[(u32, u32)]) -> AppNameDetector {
+    AppNameDetector::new(
+        Box::new(FakeDesktop { focused_pid, parents: parents.iter().copied().collect() }),
+        AppNameConfig::default(),
+    )
+}
+
+fn stream(id: u32, pid: u32) -> SinkInput {
+    SinkInput {
+        id,
+        sink: Some(56),
+        volume: None,
+        corked: false,
+        properties: HashMap::from([("application.process.id".to_string(), pid.to_string())]),
+    }
+}
+
+fn apps() -> Vec<(String, Vec<u32>)> {
+    vec![("Firefox".to_string(), vec![71, 72]), ("Spotify".to_string(), vec![80])]
+}
+
+#[test]
+fn test_focused_window_process_maps_to_its_app() {
+    let inputs = vec![stream(71, 1000), stream(80, 2000)];
+    assert_eq!(
+        focused_app(&detector(Some(2000), &[]), &inputs, &apps()).as_deref(),
+        Some("Spotify")
+    );
+    assert_eq!(
+        focused_app(&detector(Some(1000), &[]), &inputs, &apps()).as_deref(),
+        Some("Firefox")
+    );
+}
+
+#[test]
+fn test_stream_from_child_process_counts_for_focused_window() {
+    // Firefox plays audio from a content process two levels below the window's process
+    let inputs = vec![stream(72, 1200), stream(80, 2000)];
+    let parents = [(1200, 1100), (1100, 1000), (1000, 1)];
+    assert_eq!(
+        focused_app(&detector(Some(1000), &parents), &inputs, &apps()).as_deref(),
+        Some("Firefox")
+    );
+}
+
+#[test]
+fn test_no_focused_app_without_window_or_audio() {
+    let inputs = vec![stream(71, 1000)];
+    // Nothing focused, or the query failed
+    assert_eq!(focused_app(&detector(None, &[]), &inputs, &apps()), None);
+    // A focused terminal that isn't playing anything
+    assert_eq!(focused_app(&detector(Some(3000), &[(1000, 1)]), &inputs, &apps()), None);
+    // A stream that isn't cached under any app
+    assert_eq!(focused_app(&detector(Some(4000), &[]), &[stream(99, 4000)], &apps()), None);
+}
@@ -0,0 +1,67 @@
+use pipewire_volume_mixer_daemon::cache::{AppInfo, AudioCache, SinkInfo};
+use pipewire_volume_mixer_daemon::inspect::{format_json, format_table, StateDump};
+
+fn seeded_cache() -> AudioCache {
+    let cache = AudioCache::new();
+    cache.update_sink(
+        "Game".to_string(),
+        SinkInfo {
+            id: 34,
+            name: "Game".to_string(),
+            volume: 0.75,
+            pipewire_id: 34,
+            ..Default::default()
+        },
+    );
+    cache.update_app(
+        "Firefox".to_string(),
+        AppInfo {
+            display_name: "Firefox".to_string(),
+            binary_name: "firefox".to_string(),
+            stream_names: vec!["Firefox".to_string(), "AudioIPC".to_string()],
+            current_sink: "Game".to_string(),
+            active: true,
+            sink_input_ids: vec![100],
+            pipewire_id: 100,
+            ..Default::default()
+        },
+    );
+    cache.routing_rules.insert("Firefox".to_string(), "Game".to_string());
+    cache
+}
+
+#[test]
+fn test_format_table() {
+    let state = StateDump::from_cache(&seeded_cache());
+    let table = format_table(&state);
+
+    assert!(table.contains(&format!("Generation: {}", state.generation)));
+    assert!(table.contains("Sinks (1):"));
+    assert!(table.lines().any(|l| l.contains("Game") && l.contains("34") && l.contains("75%")));
+    assert!(table.contains("Apps (1):"));
+    assert!(table.lines().any(|l| l.contains("Firefox") && l.contains("Firefox, AudioIPC")));
+    assert!(table.contains("Routing rules (1):"));
+    assert!(table.contains("  Firefox -> Game"));
+}
+
+#[test]
+fn test_format_json_round_trip() {
+    let state = StateDump::from_cache(&seeded_cache());
+    let json = format_json(&state).unwrap();
+
+    let parsed: StateDump = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed.generation, state.generation);
+    assert_eq!(parsed.sinks["Game"].volume, 0.75);
+    assert_eq!(parsed.apps["Firefox"].sink_input_ids, vec![100]);
+    assert_eq!(parsed.routing_rules["Firefox"], "Game");
+}
+
+#[test]
+fn test_empty_state_table() {
+    let state = StateDump::from_cache(&AudioCache::new());
+    let table = format_table(&state);
+
+    assert!(table.contains("Sinks (0):"));
+    assert!(table.contains("Apps (0):"));
+    assert!(table.contains("Routing rules (0):"));
+}
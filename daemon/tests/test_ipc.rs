@@ -1,6 +1,13 @@
-use pipewire_volume_mixer_daemon::cache::AudioCache;
-use pipewire_volume_mixer_daemon::ipc::IpcServer;
+use nix::unistd::Uid;
+use pipewire_volume_mixer_daemon::cache::{AudioCache, SinkInfo};
+use pipewire_volume_mixer_daemon::ipc::{is_read_only, IpcServer};
+use pipewire_volume_mixer_daemon::ipc_binary::{read_frame, write_frame, Request, Response};
+use pipewire_volume_mixer_daemon::pipewire_controller::PipeWireController;
+use std::path::Path;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
 use tokio::sync::RwLock;
 
 #[tokio::test]
@@ -41,3 +48,80 @@ async fn test_command_format() {
         }
     }
 }
+
+#[test]
+fn test_read_only_commands() {
+    for command in
+        ["PING", "HEALTH", "GET_VOLUME Game", "DUMP_STATE", "DUMP_GRAPH", "EVENTS 10", "GET_APP x"]
+    {
+        assert!(is_read_only(command), "{command}");
+    }
+    for command in ["SET_VOLUME Game 0.5", "MUTE Game true", "ROUTE Firefox Media", "PAUSE", ""] {
+        assert!(!is_read_only(command), "{command}");
+    }
+}
+
+/// Serve IPC on a socket in `dir`, letting only `control_uid` change state
+async fn serve(dir: &Path, control_uid: Option<u32>) -> BufReader<UnixStream> {
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    cache.read().await.update_sink(
+        "Game".to_string(),
+        SinkInfo {
+            id: 56,
+            name: "Game".to_string(),
+            volume: 0.4,
+            pipewire_id: 56,
+            applied_percent: 40,
+            ..Default::default()
+        },
+    );
+    let controller = Arc::new(PipeWireController::new(cache.clone()));
+    let socket_path = dir.join("ipc.sock");
+    let server =
+        IpcServer::bind(cache, controller, &socket_path).unwrap().with_control_uid(control_uid);
+    tokio::spawn(server.run());
+    BufReader::new(UnixStream::connect(&socket_path).await.unwrap())
+}
+
+async fn request(reader: &mut BufReader<UnixStream>, command: &str) -> String {
+    reader.get_mut().write_all(format!("{command}\n").as_bytes()).await.unwrap();
+    let mut line = String::new();
+    tokio::time::timeout(Duration::from_secs(5), reader.read_line(&mut line))
+        .await
+        .unwrap()
+        .unwrap();
+    line.trim_end().to_string()
+}
+
+#[tokio::test]
+async fn test_other_users_may_only_read() {
+    let dir = tempfile::tempdir().unwrap();
+    // Pretend the daemon runs as someone else, so this test's process is the other user
+    let other_uid = Uid::current().as_raw().wrapping_add(1);
+    let mut reader = serve(dir.path(), Some(other_uid)).await;
+
+    assert_eq!(request(&mut reader, "PING").await, "OK PONG");
+    assert!(request(&mut reader, "GET_VOLUME Game").await.starts_with("OK volume=0.4 "));
+    for command in ["SET_VOLUME Game 0.9", "MUTE Game true", "ROUTE Firefox Game", "PAUSE"] {
+        let reply = request(&mut reader, command).await;
+        assert!(reply.starts_with("ERROR PERMISSION_DENIED "), "{command}: {reply}");
+    }
+    assert!(request(&mut reader, "GET_VOLUME Game").await.starts_with("OK volume=0.4 "));
+
+    // Binary frames are held to the same policy
+    assert_eq!(request(&mut reader, "PROTOCOL binary").await, "OK binary");
+    let set_volume = Request::SetVolume { sink: "Game".to_string(), volume: 0.9 };
+    write_frame(reader.get_mut(), &set_volume.encode()).await.unwrap();
+    let response = Response::decode(&read_frame(&mut reader).await.unwrap().unwrap()).unwrap();
+    assert!(matches!(response, Response::Error { code, .. } if code == "PERMISSION_DENIED"));
+}
+
+#[tokio::test]
+async fn test_own_user_may_change_state() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut reader = serve(dir.path(), Some(Uid::current().as_raw())).await;
+
+    // Gets past the access check to the command itself
+    let reply = request(&mut reader, "MUTE Nope true").await;
+    assert!(reply.starts_with("ERROR UNKNOWN_SINK "), "{reply}");
+}
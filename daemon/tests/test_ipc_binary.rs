@@ -0,0 +1,115 @@
+use pipewire_volume_mixer_daemon::ipc::IpcError;
+use pipewire_volume_mixer_daemon::ipc_binary::{
+    read_frame, write_frame, Request, Response, MAX_FRAME_LEN,
+};
+
+#[test]
+fn test_set_volume_frame_round_trip() {
+    let request = Request::SetVolume { sink: "Game".to_string(), volume: 0.75 };
+    let payload = request.encode();
+
+    // Opcode, u16 name length, name bytes, little-endian f32
+    let mut expected = vec![0x01, 4, 0];
+    expected.extend_from_slice(b"Game");
+    expected.extend_from_slice(&0.75f32.to_le_bytes());
+    assert_eq!(payload, expected);
+
+    let decoded = Request::decode(&payload).unwrap();
+    assert_eq!(decoded, request);
+    assert_eq!(decoded.to_command(), "SET_VOLUME Game 0.75");
+}
+
+#[test]
+fn test_every_request_round_trips() {
+    let requests = vec![
+        Request::Text("EVENTS 5".to_string()),
+        Request::SetVolume { sink: "Chat".to_string(), volume: 0.1 },
+        Request::SetVolumeDb { sink: "Media".to_string(), db: -6.5 },
+        Request::Mute { sink: "Game".to_string(), muted: true },
+        Request::Route { app: "Firefox".to_string(), sink: "Media".to_string() },
+        Request::GetVolume { sink: "Game".to_string() },
+        Request::Ping,
+    ];
+    for request in requests {
+        assert_eq!(Request::decode(&request.encode()).unwrap(), request);
+    }
+}
+
+#[test]
+fn test_text_commands_match_the_text_protocol() {
+    let cases = [
+        (Request::SetVolumeDb { sink: "Media".to_string(), db: -6.5 }, "SET_VOLUME_DB Media -6.5"),
+        (Request::Mute { sink: "Chat".to_string(), muted: false }, "MUTE Chat false"),
+        (
+            Request::Route { app: "Discord".to_string(), sink: "Chat".to_string() },
+            "ROUTE Discord Chat",
+        ),
+        (Request::GetVolume { sink: "Game".to_string() }, "GET_VOLUME Game"),
+        (Request::Ping, "PING"),
+    ];
+    for (request, command) in cases {
+        assert_eq!(request.to_command(), command);
+    }
+}
+
+#[test]
+fn test_malformed_requests_are_rejected() {
+    let payload = Request::SetVolume { sink: "Game".to_string(), volume: 0.5 }.encode();
+
+    // Cut short, with trailing bytes, or an opcode that does not exist
+    assert!(matches!(Request::decode(&payload[..payload.len() - 1]), Err(IpcError::BadArgs(_))));
+    let mut trailing = payload.clone();
+    trailing.push(0);
+    assert!(matches!(Request::decode(&trailing), Err(IpcError::BadArgs(_))));
+    assert!(matches!(Request::decode(&[0x7f]), Err(IpcError::UnknownCommand(_))));
+    assert!(matches!(Request::decode(&[]), Err(IpcError::BadArgs(_))));
+
+    // Names must stay a single text protocol argument
+    let spaced = Request::GetVolume { sink: "Game Sink".to_string() }.encode();
+    assert!(matches!(Request::decode(&spaced), Err(IpcError::BadArgs(_))));
+    let empty = Request::GetVolume { sink: String::new() }.encode();
+    assert!(matches!(Request::decode(&empty), Err(IpcError::BadArgs(_))));
+
+    let invalid_utf8 = [0x05, 2, 0, 0xff, 0xfe];
+    assert!(matches!(Request::decode(&invalid_utf8), Err(IpcError::BadArgs(_))));
+}
+
+#[test]
+fn test_response_round_trip() {
+    let responses = vec![
+        Response::Ok("Set Game volume to 0.75".to_string()),
+        Response::Error {
+            code: "UNKNOWN_SINK".to_string(),
+            message: "Unknown sink: Nope".to_string(),
+        },
+    ];
+    for response in responses {
+        assert_eq!(Response::decode(&response.encode()).unwrap(), response);
+    }
+    assert!(Response::decode(&[0x09]).is_err());
+}
+
+#[tokio::test]
+async fn test_frames_carry_a_little_endian_length_prefix() {
+    let payload = Request::Ping.encode();
+    let mut wire = Vec::new();
+    write_frame(&mut wire, &payload).await.unwrap();
+    write_frame(&mut wire, &payload).await.unwrap();
+    assert_eq!(&wire[..5], &[1, 0, 0, 0, 0x06]);
+
+    let mut reader = wire.as_slice();
+    assert_eq!(read_frame(&mut reader).await.unwrap(), Some(payload.clone()));
+    assert_eq!(read_frame(&mut reader).await.unwrap(), Some(payload));
+    assert_eq!(read_frame(&mut reader).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_oversized_frames_are_refused() {
+    let oversized = ((MAX_FRAME_LEN + 1) as u32).to_le_bytes();
+    let mut reader = oversized.as_slice();
+    assert!(read_frame(&mut reader).await.is_err());
+
+    let mut wire = Vec::new();
+    assert!(write_frame(&mut wire, &vec![0; MAX_FRAME_LEN + 1]).await.is_err());
+    assert!(wire.is_empty());
+}
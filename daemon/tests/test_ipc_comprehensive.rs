@@ -1,5 +1,16 @@
-use pipewire_volume_mixer_daemon::cache::{AppInfo, AudioCache, SinkInfo};
-use std::sync::Arc;
+use pipewire_volume_mixer_daemon::cache::{
+    AppInfo, AppRecord, AudioCache, GraphNode, KnownApp, RuleMatch, RuleTest, SinkInfo,
+};
+use pipewire_volume_mixer_daemon::command::CommandExecutor;
+use pipewire_volume_mixer_daemon::config::Config;
+use pipewire_volume_mixer_daemon::ipc::{
+    error_code, process_command, process_command_with, IpcError, PROTOCOL_VERSION,
+};
+use pipewire_volume_mixer_daemon::pipewire_controller::PipeWireController;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{ExitStatus, Output};
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use tempfile::tempdir;
 use tokio::sync::RwLock;
@@ -22,8 +33,8 @@ async fn setup_test_ipc() -> (Arc<RwLock<AudioCache>>, String) {
                 id: 34,
                 name: "Game Audio".to_string(),
                 volume: 1.0,
-                muted: false,
                 pipewire_id: 34,
+                ..Default::default()
             },
         );
         cache_write.update_sink(
@@ -32,8 +43,8 @@ async fn setup_test_ipc() -> (Arc<RwLock<AudioCache>>, String) {
                 id: 39,
                 name: "Chat Audio".to_string(),
                 volume: 0.57,
-                muted: false,
                 pipewire_id: 39,
+                ..Default::default()
             },
         );
         cache_write.update_sink(
@@ -42,8 +53,8 @@ async fn setup_test_ipc() -> (Arc<RwLock<AudioCache>>, String) {
                 id: 44,
                 name: "Media Audio".to_string(),
                 volume: 0.71,
-                muted: false,
                 pipewire_id: 44,
+                ..Default::default()
             },
         );
     }
@@ -121,8 +132,7 @@ async fn test_ipc_health_command() {
                 current_sink: "Game".to_string(),
                 active: true,
                 sink_input_ids: vec![1, 2],
-                pipewire_id: 0,
-                inactive_since: None,
+                ..Default::default()
             },
         );
     }
@@ -250,8 +260,8 @@ async fn test_ipc_invalid_commands() {
         "SET_VOLUME",              // Missing parameters
         "SET_VOLUME Game",         // Missing volume parameter
         "SET_VOLUME Game invalid", // Invalid volume value
-        "SET_VOLUME Game 2.0",     // Volume out of range
-        "SET_VOLUME Game -0.5",    // Negative volume
+        "SET_VOLUME Game NaN",     // Not a number
+        "SET_VOLUME Game inf",     // Infinite volume
         "MUTE",                    // Missing parameters
         "MUTE Chat",               // Missing mute state
         "MUTE Chat maybe",         // Invalid mute state
@@ -264,13 +274,13 @@ async fn test_ipc_invalid_commands() {
         // Verify the command would be rejected
         let should_fail = parts.is_empty()
             || match parts[0] {
-                "ROUTE" => parts.len() != 3,
+                "ROUTE" => parts.len() < 3,
                 "SET_VOLUME" => {
                     parts.len() != 3
                         || parts
                             .get(2)
                             .and_then(|v| v.parse::<f32>().ok())
-                            .map_or(true, |v| !(0.0..=1.0).contains(&v))
+                            .map_or(true, |v| !v.is_finite())
                 }
                 "MUTE" => {
                     parts.len() != 3 || !["true", "false"].contains(parts.get(2).unwrap_or(&""))
@@ -323,7 +333,7 @@ async fn test_ipc_stress_test() {
                         active: i % 2 == 0,
                         sink_input_ids: vec![i as u32],
                         pipewire_id: i as u32,
-                        inactive_since: None,
+                        ..Default::default()
                     },
                 );
             }
@@ -418,3 +428,446 @@ async fn test_ipc_command_with_special_characters() {
         assert!(cache_read.routing_rules.contains_key(app), "Failed to handle app name: {app}");
     }
 }
+
+/// Records every call, answering pactl with the Chat sink's loopback stream
+#[derive(Default)]
+struct RecordingExecutor {
+    calls: Mutex<Vec<String>>,
+}
+
+impl CommandExecutor for RecordingExecutor {
+    fn execute(&self, program: &str, args: &[&str]) -> std::io::Result<Output> {
+        self.calls.lock().unwrap().push(format!("{program} {}", args.join(" ")));
+        let stdout = match args {
+            ["list", "sink-inputs"] => {
+                "Sink Input #90\n\tSink: 1\n\tProperties:\n\t\tnode.name = \"Chat_to_Speaker\"\n"
+            }
+            _ => "",
+        };
+        Ok(Output {
+            status: ExitStatus::from_raw(0),
+            stdout: stdout.as_bytes().to_vec(),
+            stderr: vec![],
+        })
+    }
+
+    fn execute_shell(&self, cmd: &str) -> std::io::Result<Output> {
+        self.calls.lock().unwrap().push(cmd.to_string());
+        Ok(Output { status: ExitStatus::from_raw(0), stdout: vec![], stderr: vec![] })
+    }
+}
+
+#[tokio::test]
+async fn test_ipc_get_volume_reports_applied_percent() {
+    let (cache, _socket_path) = setup_test_ipc().await;
+    let executor = Arc::new(RecordingExecutor::default());
+    let controller = PipeWireController::with_executor(cache.clone(), executor.clone());
+
+    process_command_with("SET_VOLUME Chat 0.5", &cache, &controller).await.unwrap();
+    let calls = executor.calls.lock().unwrap().clone();
+    assert!(calls.contains(&"pactl set-sink-input-volume 90 50%".to_string()), "{calls:#?}");
+
+    let response = process_command_with("GET_VOLUME Chat", &cache, &controller).await.unwrap();
+    assert_eq!(response, "volume=0.5 volume_db=-6.0 applied_percent=50 muted=false");
+
+    assert!(process_command("GET_VOLUME Missing", &cache).await.is_err());
+    assert!(process_command("GET_VOLUME", &cache).await.is_err());
+}
+
+#[tokio::test]
+async fn test_ipc_ping_and_version() {
+    let (cache, _socket_path) = setup_test_ipc().await;
+
+    assert_eq!(process_command("PING", &cache).await.unwrap(), "PONG");
+
+    let version = process_command("VERSION", &cache).await.unwrap();
+    assert_eq!(
+        version,
+        format!(
+            "version={} protocol={PROTOCOL_VERSION} pipewire=unknown",
+            env!("CARGO_PKG_VERSION")
+        )
+    );
+
+    cache.read().await.set_server_version("1.0.5".to_string());
+    let version = process_command("VERSION", &cache).await.unwrap();
+    assert!(version.ends_with(" pipewire=1.0.5"));
+}
+
+#[tokio::test]
+async fn test_ipc_health_reports_sink_uptimes() {
+    let (cache, _socket_path) = setup_test_ipc().await;
+
+    let health = process_command("HEALTH", &cache).await.unwrap();
+    assert!(health.starts_with("sinks=3 apps=0 generation="));
+    assert!(
+        health.contains(" active_apps=0 inactive_apps=0 rules=0 remembered_apps=0 indexed_apps=0 ")
+    );
+    assert!(health.contains(" uptime_seconds=Chat:0,Game:0,Media:0 "));
+    assert!(health.ends_with(" dropped_updates=0 status=OK"));
+}
+
+#[tokio::test]
+async fn test_ipc_health_reports_dropped_updates() {
+    let (cache, _socket_path) = setup_test_ipc().await;
+    cache.read().await.dropped_update_counter().fetch_add(3, Ordering::Relaxed);
+
+    let health = process_command("HEALTH", &cache).await.unwrap();
+    assert!(health.ends_with(" dropped_updates=3 status=DEGRADED"), "{health}");
+}
+
+#[tokio::test]
+async fn test_ipc_list_known_apps_merges_sources() {
+    let (cache, _socket_path) = setup_test_ipc().await;
+    {
+        let cache_write = cache.write().await;
+        cache_write.update_app(
+            "Firefox".to_string(),
+            AppInfo {
+                display_name: "Firefox".to_string(),
+                binary_name: "firefox".to_string(),
+                stream_names: vec!["Firefox".to_string()],
+                current_sink: "Media".to_string(),
+                active: true,
+                sink_input_ids: vec![100],
+                pipewire_id: 100,
+                ..Default::default()
+            },
+        );
+        // Running apps are remembered too, and may also have a rule
+        cache_write.routing_rules.insert("Firefox".to_string(), "Game".to_string());
+        cache_write.remembered_apps.insert("Spotify".to_string(), "Media".to_string());
+        cache_write.routing_rules.insert("Discord".to_string(), "Chat".to_string());
+    }
+
+    let response = process_command("LIST_KNOWN_APPS", &cache).await.unwrap();
+    let apps: Vec<KnownApp> = serde_json::from_str(&response).unwrap();
+    let names: Vec<&str> = apps.iter().map(|app| app.name.as_str()).collect();
+    assert_eq!(names, ["Discord", "Firefox", "Spotify"]);
+
+    let flags = |app: &KnownApp| (app.running, app.remembered, app.has_rule, app.sink.clone());
+    assert_eq!(flags(&apps[0]), (false, false, true, Some("Chat".to_string())));
+    assert_eq!(flags(&apps[1]), (true, true, true, Some("Media".to_string())));
+    assert_eq!(flags(&apps[2]), (false, true, false, Some("Media".to_string())));
+}
+
+#[tokio::test]
+async fn test_ipc_dump_graph() {
+    let (cache, _socket_path) = setup_test_ipc().await;
+    // What the monitor records for a stream it couldn't name
+    cache.read().await.graph_nodes().insert(71, GraphNode { id: 71, serial: 171, app_name: None });
+
+    let graph: serde_json::Value =
+        serde_json::from_str(&process_command("DUMP_GRAPH", &cache).await.unwrap()).unwrap();
+    assert_eq!(graph["nodes"][0]["serial"], 171);
+    assert!(graph["nodes"][0]["cache_app"].is_null());
+    let sinks: Vec<&String> = graph["sinks"].as_object().unwrap().keys().collect();
+    assert_eq!(sinks, ["Chat", "Game", "Media"]);
+}
+
+#[tokio::test]
+async fn test_ipc_get_app_returns_full_record() {
+    let (cache, _socket_path) = setup_test_ipc().await;
+    cache.write().await.update_app(
+        "WEBRTC VoiceEngine".to_string(),
+        AppInfo {
+            display_name: "Discord".to_string(),
+            binary_name: "discord".to_string(),
+            stream_names: vec!["WEBRTC VoiceEngine".to_string()],
+            current_sink: "Chat".to_string(),
+            active: false,
+            pipewire_id: 120,
+            media_role: Some("Communication".to_string()),
+            inactive_since: Some(Instant::now() - Duration::from_secs(90)),
+            ..Default::default()
+        },
+    );
+
+    let response = process_command("GET_APP WEBRTC VoiceEngine", &cache).await.unwrap();
+    let json: serde_json::Value = serde_json::from_str(&response).unwrap();
+    assert_eq!(json["display_name"], "Discord");
+    assert_eq!(json["binary_name"], "discord");
+    assert_eq!(json["current_sink"], "Chat");
+    assert_eq!(json["active"], false);
+    assert_eq!(json["sink_input_ids"], serde_json::json!([]));
+    assert_eq!(json["media_role"], "Communication");
+
+    let record: AppRecord = serde_json::from_str(&response).unwrap();
+    assert_eq!(record.name, "WEBRTC VoiceEngine");
+    assert_eq!(record.inactive_seconds, Some(90));
+    assert_eq!(record.volume, Some(0.57));
+
+    let err = process_command("GET_APP Spotify", &cache).await.unwrap_err();
+    assert_eq!(error_code(&err), "UNKNOWN_APP");
+    let err = process_command("GET_APP", &cache).await.unwrap_err();
+    assert_eq!(error_code(&err), "BAD_ARGS");
+}
+
+/// An app whose streams Game picked, as seen before any rule moved it
+fn unrouted_app(
+    binary_name: &str,
+    media_role: Option<&str>,
+    window_title: Option<&str>,
+) -> AppInfo {
+    AppInfo {
+        display_name: binary_name.to_string(),
+        binary_name: binary_name.to_string(),
+        stream_names: vec![binary_name.to_string()],
+        current_sink: "Game".to_string(),
+        current_sinks: vec![],
+        stream_labels: vec![],
+        window_title: window_title.map(str::to_string),
+        active: true,
+        playing: true,
+        muted: false,
+        sink_input_ids: vec![7],
+        pipewire_id: 70,
+        media_role: media_role.map(str::to_string),
+        volume: None,
+        inactive_since: None,
+    }
+}
+
+#[tokio::test]
+async fn test_ipc_test_rule_reports_each_precedence_tier() {
+    let mut routing = Config::default().routing;
+    routing.default_sink = "Game".to_string();
+    routing.route_unmatched_to_default = true;
+    routing.fuzzy_matching = true;
+    routing.role_rules.insert("Music".to_string(), "Media".to_string());
+    let cache = AudioCache::new().with_routing(routing);
+    for (app_name, sink_name) in [
+        ("Discord", "Chat"),
+        ("firefox", "Media"),
+        ("Elite - Dangerous (CLIENT)", "Game"),
+        ("steam", "Chat"),
+    ] {
+        cache.routing_rules.insert(app_name.to_string(), sink_name.to_string());
+    }
+    cache.update_app("Firefox".to_string(), unrouted_app("firefox", None, None));
+    cache.update_app(
+        "wine64-preloader".to_string(),
+        unrouted_app("wine64-preloader", None, Some("Elite - Dangerous (CLIENT)")),
+    );
+    cache.update_app("Spotify".to_string(), unrouted_app("spotify", Some("Music"), None));
+    let cache = Arc::new(RwLock::new(cache));
+
+    let test_rule = |app_name: &'static str| {
+        let cache = cache.clone();
+        async move {
+            let response = process_command(&format!("TEST_RULE {app_name}"), &cache).await;
+            let test: RuleTest = serde_json::from_str(&response.unwrap()).unwrap();
+            assert_eq!(test.app_name, app_name);
+            (test.rule, test.sink)
+        }
+    };
+    let matched = |rule, sink: &str| (Some(rule), Some(sink.to_string()));
+
+    assert_eq!(test_rule("Discord").await, matched(RuleMatch::Exact, "Chat"));
+    assert_eq!(test_rule("Firefox").await, matched(RuleMatch::Binary, "Media"));
+    assert_eq!(test_rule("wine64-preloader").await, matched(RuleMatch::Window, "Game"));
+    assert_eq!(test_rule("steamwebhelper").await, matched(RuleMatch::Fuzzy, "Chat"));
+    assert_eq!(test_rule("Spotify").await, matched(RuleMatch::Role, "Media"));
+    // Apps that never ran are tested by name alone
+    assert_eq!(test_rule("Audacious Player").await, matched(RuleMatch::Default, "Game"));
+
+    // Testing moves nothing and doesn't remember the result as a rule
+    let cache_read = cache.read().await;
+    assert_eq!(cache_read.apps.get("Spotify").unwrap().current_sink, "Game");
+    assert!(cache_read.routing_rules.get("Spotify").is_none());
+}
+
+#[tokio::test]
+async fn test_ipc_pin_and_unpin() {
+    let (cache, _socket_path) = setup_test_ipc().await;
+
+    // Spotify isn't running, so it's only routed once it appears
+    let response = process_command("PIN Spotify Media", &cache).await.unwrap();
+    assert_eq!(response, "Pinned Spotify to Media");
+    let test = process_command("TEST_RULE Spotify", &cache).await.unwrap();
+    let test: RuleTest = serde_json::from_str(&test).unwrap();
+    assert_eq!((test.rule, test.sink.as_deref()), (Some(RuleMatch::Pinned), Some("Media")));
+
+    assert_eq!(process_command("UNPIN Spotify", &cache).await.unwrap(), "Unpinned Spotify");
+    assert!(cache.read().await.pins().is_empty());
+
+    // Stream-derived names may contain spaces, the sink is the last argument
+    let response = process_command("PIN Audacious Player Media", &cache).await.unwrap();
+    assert_eq!(response, "Pinned Audacious Player to Media");
+    let response = process_command("UNPIN Audacious Player", &cache).await.unwrap();
+    assert_eq!(response, "Unpinned Audacious Player");
+    assert!(cache.read().await.pins().is_empty());
+
+    // ROUTE reads its arguments the same way
+    let response = process_command("ROUTE Audacious Player Media", &cache).await.unwrap();
+    assert_eq!(response, "Routed Audacious Player to Media");
+    assert_eq!(cache.read().await.apps.get("Audacious Player").unwrap().current_sink, "Media");
+
+    // An app that isn't playing keeps every target it's duplicated to
+    let response = process_command("ROUTE Spotify Media,Game", &cache).await.unwrap();
+    assert_eq!(response, "Routed Spotify to Media,Game");
+    let spotify = cache.read().await.apps.get("Spotify").unwrap().clone();
+    assert_eq!(spotify.current_sink, "Media");
+    assert_eq!(spotify.current_sinks, vec!["Media".to_string(), "Game".to_string()]);
+
+    for (command, code) in [
+        ("PIN Spotify Nowhere", "UNKNOWN_SINK"),
+        ("PIN Spotify", "BAD_ARGS"),
+        ("UNPIN Spotify", "UNKNOWN_APP"),
+        ("UNPIN", "BAD_ARGS"),
+    ] {
+        let err = process_command(command, &cache).await.unwrap_err();
+        assert_eq!(error_code(&err), code, "{command}");
+    }
+    assert!(cache.read().await.pins().is_empty());
+}
+
+#[tokio::test]
+async fn test_ipc_test_rule_without_a_match() {
+    let (cache, _socket_path) = setup_test_ipc().await;
+
+    let response = process_command("TEST_RULE mpv", &cache).await.unwrap();
+    let test: RuleTest = serde_json::from_str(&response).unwrap();
+    assert_eq!(test, RuleTest { app_name: "mpv".to_string(), rule: None, sink: None });
+    assert!(response.contains(r#""rule":null"#));
+
+    let err = process_command("TEST_RULE", &cache).await.unwrap_err();
+    assert_eq!(error_code(&err), "BAD_ARGS");
+}
+
+#[tokio::test]
+async fn test_ipc_errors_carry_stable_codes() {
+    let (cache, _socket_path) = setup_test_ipc().await;
+
+    let cases = [
+        ("", "UNKNOWN_COMMAND"),
+        ("FROBNICATE", "UNKNOWN_COMMAND"),
+        ("ROUTE Firefox", "BAD_ARGS"),
+        ("SET_VOLUME Game loud", "BAD_ARGS"),
+        ("SET_VOLUME Game NaN", "BAD_ARGS"),
+        ("SET_VOLUME Game inf", "BAD_ARGS"),
+        ("SET_VOLUME Game -inf", "BAD_ARGS"),
+        ("SET_VOLUME_DB Game 3", "BAD_ARGS"),
+        ("MUTE Game maybe", "BAD_ARGS"),
+        ("ROUTE_PID abc Game", "BAD_ARGS"),
+        ("ROUTE_INPUT abc Game", "BAD_ARGS"),
+        ("ROUTE_INPUT 71", "BAD_ARGS"),
+        ("MUTE_APP Firefox", "BAD_ARGS"),
+        ("MUTE_APP Firefox maybe", "BAD_ARGS"),
+        ("MUTE_APP Missing App true", "UNKNOWN_APP"),
+        ("CROSSFADE Game Game 0.5", "BAD_ARGS"),
+        ("GET_VOLUME Missing", "UNKNOWN_SINK"),
+        ("SET_VOLUME Missing 0.5", "UNKNOWN_SINK"),
+        ("MUTE Missing true", "UNKNOWN_SINK"),
+        ("ROUTE_PID 42 Missing", "UNKNOWN_SINK"),
+        ("ROUTE_INPUT 71 Missing", "UNKNOWN_SINK"),
+        ("CROSSFADE Game Missing 0.5", "UNKNOWN_SINK"),
+        ("SOLO Missing", "UNKNOWN_SINK"),
+        ("RESET_VOLUME", "BAD_ARGS"),
+        ("RESET_VOLUME Missing", "UNKNOWN_SINK"),
+    ];
+    for (command, code) in cases {
+        let err = process_command(command, &cache).await.unwrap_err();
+        assert_eq!(error_code(&err), code, "wrong code for {command:?}: {err:#}");
+    }
+}
+
+#[test]
+fn test_ipc_error_codes() {
+    assert_eq!(IpcError::UnknownApp("Unknown app: foo".to_string()).code(), "UNKNOWN_APP");
+    assert_eq!(IpcError::NoActiveStreams("idle".to_string()).code(), "NO_ACTIVE_STREAMS");
+
+    // Anything not classified by a command is a backend failure
+    assert_eq!(error_code(&anyhow::anyhow!("pactl exploded")), "BACKEND");
+    let backend = anyhow::Error::new(IpcError::Backend("wpctl failed".to_string()));
+    assert_eq!(error_code(&backend.context("while muting")), "BACKEND");
+    let unknown = anyhow::Error::new(IpcError::UnknownSink("Unknown sink: X".to_string()));
+    assert_eq!(error_code(&unknown.context("Failed to route")), "UNKNOWN_SINK");
+}
+
+#[tokio::test]
+async fn test_ipc_set_follow_focus() {
+    let (cache, _socket_path) = setup_test_ipc().await;
+    assert!(process_command("SET_FOLLOW_FOCUS true", &cache).await.is_err());
+
+    let cache = Arc::new(RwLock::new(AudioCache::new().with_focus_sink(Some("Chat".to_string()))));
+    assert_eq!(
+        process_command("SET_FOLLOW_FOCUS true", &cache).await.unwrap(),
+        "Following focus to Chat"
+    );
+    assert!(cache.read().await.follow_focus_enabled());
+    assert_eq!(
+        process_command("SET_FOLLOW_FOCUS false", &cache).await.unwrap(),
+        "Stopped following focus"
+    );
+    assert!(!cache.read().await.follow_focus_enabled());
+    assert!(process_command("SET_FOLLOW_FOCUS maybe", &cache).await.is_err());
+}
+
+#[tokio::test]
+async fn test_ipc_set_sink_label() {
+    let (cache, _socket_path) = setup_test_ipc().await;
+
+    assert_eq!(
+        process_command("SET_SINK_LABEL Chat Voice Comms", &cache).await.unwrap(),
+        "Labeled Chat as Voice Comms"
+    );
+    assert_eq!(cache.read().await.sink_label("Chat"), "Voice Comms");
+    assert!(cache.read().await.sinks.contains_key("Chat"));
+
+    let err = process_command("SET_SINK_LABEL Missing Label", &cache).await.unwrap_err();
+    assert_eq!(error_code(&err), "UNKNOWN_SINK");
+    assert!(process_command("SET_SINK_LABEL Chat", &cache).await.is_err());
+}
+
+#[tokio::test]
+async fn test_ipc_export_import_round_trip() {
+    let (cache, _socket_path) = setup_test_ipc().await;
+    {
+        let cache_read = cache.read().await;
+        cache_read.routing_rules.insert("Firefox".to_string(), "Media".to_string());
+        cache_read.routing_rules.insert("OBS Studio".to_string(), "Game, Chat".to_string());
+        cache_read.remembered_apps.insert("Firefox".to_string(), "Media".to_string());
+    }
+    let exported = process_command("EXPORT", &cache).await.unwrap();
+
+    assert_eq!(
+        process_command("IMPORT replace {}", &cache).await.unwrap(),
+        "Imported 0 rules and 0 default volumes"
+    );
+    assert!(cache.read().await.routing_rules.is_empty());
+    assert!(cache.read().await.remembered_apps.is_empty());
+
+    assert_eq!(
+        process_command(&format!("IMPORT replace {exported}"), &cache).await.unwrap(),
+        "Imported 2 rules and 0 default volumes"
+    );
+    assert_eq!(process_command("EXPORT", &cache).await.unwrap(), exported);
+    assert_eq!(cache.read().await.routing_rules.get("OBS Studio").unwrap().as_str(), "Game, Chat");
+
+    // Merging keeps what the import doesn't mention
+    let merge = r#"IMPORT merge {"rules": {"Discord": "Chat"}, "default_volumes": {"Chat": 0.6}}"#;
+    process_command(merge, &cache).await.unwrap();
+    let cache_read = cache.read().await;
+    assert_eq!(cache_read.routing_rules.len(), 3);
+    assert!((cache_read.default_volume("Chat") - 0.6).abs() < 1e-6);
+}
+
+#[tokio::test]
+async fn test_ipc_import_validates_before_applying() {
+    let (cache, _socket_path) = setup_test_ipc().await;
+    cache.read().await.routing_rules.insert("Firefox".to_string(), "Media".to_string());
+
+    let err = process_command(r#"IMPORT replace {"rules": {"Steam": "Game, Nowhere"}}"#, &cache)
+        .await
+        .unwrap_err();
+    assert_eq!(error_code(&err), "UNKNOWN_SINK");
+    assert!(err.to_string().contains("Nowhere"));
+    // Nothing was replaced
+    assert_eq!(cache.read().await.routing_rules.len(), 1);
+
+    for command in ["IMPORT", "IMPORT replace", "IMPORT overwrite {}", "IMPORT merge {rules"] {
+        let err = process_command(command, &cache).await.unwrap_err();
+        assert_eq!(error_code(&err), "BAD_ARGS", "{command}");
+    }
+}
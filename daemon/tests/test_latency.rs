@@ -0,0 +1,71 @@
+use pipewire_volume_mixer_daemon::cache::AudioCache;
+use pipewire_volume_mixer_daemon::ipc::process_command;
+use pipewire_volume_mixer_daemon::latency::{LatencyHistogram, LatencySummary, Operation};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+#[test]
+fn test_percentiles_report_bucket_bounds() {
+    let histogram = LatencyHistogram::default();
+    assert_eq!(histogram.summary(), LatencySummary::default());
+
+    // 90 fast samples, 9 around 3ms and one slow outlier
+    for _ in 0..90 {
+        histogram.record(Duration::from_micros(40));
+    }
+    for _ in 0..9 {
+        histogram.record(Duration::from_micros(3_000));
+    }
+    histogram.record(Duration::from_millis(20));
+
+    let summary = histogram.summary();
+    assert_eq!(summary.count, 100);
+    assert_eq!(summary.p50_us, 50);
+    assert_eq!(summary.p90_us, 50);
+    assert_eq!(summary.p99_us, 5_000);
+    assert_eq!(summary.max_us, 20_000);
+    assert!(summary.p50_us <= summary.p90_us && summary.p90_us <= summary.p99_us);
+    assert!(summary.p99_us <= summary.max_us);
+}
+
+#[test]
+fn test_percentiles_never_exceed_the_slowest_sample() {
+    let histogram = LatencyHistogram::default();
+    histogram.record(Duration::from_micros(300));
+    assert_eq!(histogram.summary().p99_us, 300);
+
+    // Beyond the last bucket the maximum is all there is to report
+    histogram.record(Duration::from_secs(3));
+    assert_eq!(histogram.summary().p99_us, 3_000_000);
+}
+
+#[test]
+fn test_old_samples_decay() {
+    let histogram = LatencyHistogram::default();
+    for _ in 0..2000 {
+        histogram.record(Duration::from_millis(50));
+    }
+    for _ in 0..3000 {
+        histogram.record(Duration::from_micros(20));
+    }
+    let summary = histogram.summary();
+    assert!(summary.count <= 1024, "kept {} samples", summary.count);
+    assert_eq!(summary.p90_us, 25);
+}
+
+#[tokio::test]
+async fn test_metrics_and_health_report_latencies() {
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    cache.read().await.record_latency(Operation::Route, Duration::from_millis(4));
+
+    let metrics: serde_json::Value =
+        serde_json::from_str(&process_command("METRICS", &cache).await.unwrap()).unwrap();
+    assert_eq!(metrics["route"]["count"], 1);
+    assert_eq!(metrics["route"]["p99_us"], 4_000);
+    assert_eq!(metrics["cache_update"]["count"], 0);
+    assert_eq!(metrics["snapshot"]["p50_us"], 0);
+
+    let health = process_command("HEALTH", &cache).await.unwrap();
+    assert!(health.contains(" p99_us=cache_update:0,route:4000,snapshot:0 "), "{health}");
+}
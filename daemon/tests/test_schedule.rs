@@ -0,0 +1,94 @@
+use pipewire_volume_mixer_daemon::schedule::{should_log_failure, AdaptiveInterval, Backoff};
+use std::time::Duration;
+
+const MIN: Duration = Duration::from_millis(50);
+const MAX: Duration = Duration::from_millis(500);
+
+#[test]
+fn test_interval_stays_at_minimum_while_busy() {
+    let mut schedule = AdaptiveInterval::new(MIN, MAX, 3);
+    assert_eq!(schedule.current(), MIN);
+    for _ in 0..10 {
+        assert_eq!(schedule.tick(true), MIN);
+    }
+}
+
+#[test]
+fn test_interval_backs_off_when_idle_up_to_maximum() {
+    let mut schedule = AdaptiveInterval::new(MIN, MAX, 3);
+    let intervals: Vec<u64> = (0..9).map(|_| schedule.tick(false).as_millis() as u64).collect();
+    assert_eq!(intervals, vec![50, 50, 50, 100, 200, 400, 500, 500, 500]);
+}
+
+#[test]
+fn test_burst_after_idle_returns_to_minimum() {
+    let mut schedule = AdaptiveInterval::new(MIN, MAX, 0);
+    for _ in 0..10 {
+        schedule.tick(false);
+    }
+    assert_eq!(schedule.current(), MAX);
+
+    assert_eq!(schedule.tick(true), MIN);
+    // The idle count starts over after a change
+    assert_eq!(schedule.tick(false), MIN * 2);
+}
+
+#[test]
+fn test_occasional_changes_keep_interval_short() {
+    let mut schedule = AdaptiveInterval::new(MIN, MAX, 3);
+    for tick in 0..20 {
+        schedule.tick(tick % 3 == 0);
+        assert_eq!(schedule.current(), MIN);
+    }
+}
+
+#[test]
+fn test_reset_returns_to_minimum() {
+    let mut schedule = AdaptiveInterval::new(MIN, MAX, 0);
+    schedule.tick(false);
+    schedule.tick(false);
+    schedule.reset();
+    assert_eq!(schedule.current(), MIN);
+}
+
+#[test]
+fn test_maximum_below_minimum_gives_fixed_interval() {
+    let mut schedule = AdaptiveInterval::new(MAX, MIN, 0);
+    for _ in 0..5 {
+        assert_eq!(schedule.tick(false), MAX);
+    }
+}
+
+#[test]
+fn test_backoff_doubles_up_to_the_cap() {
+    let backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(60));
+    let delays: Vec<u64> = (0..9).map(|failures| backoff.delay(failures).as_secs()).collect();
+    assert_eq!(delays, vec![0, 1, 2, 4, 8, 16, 32, 60, 60]);
+
+    // Long failure streaks neither overflow nor pass the cap
+    assert_eq!(backoff.delay(40), Duration::from_secs(60));
+    assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(60));
+}
+
+#[test]
+fn test_backoff_counts_failures_until_reset() {
+    let mut backoff = Backoff::new(MIN, MAX);
+    assert_eq!(backoff.fail(), MIN);
+    assert_eq!(backoff.fail(), MIN * 2);
+    assert_eq!(backoff.failures(), 2);
+
+    backoff.reset();
+    assert_eq!(backoff.failures(), 0);
+    assert_eq!(backoff.fail(), MIN);
+
+    // A cap below the base keeps the delay fixed
+    let mut fixed = Backoff::new(MAX, MIN);
+    assert_eq!((fixed.fail(), fixed.fail()), (MAX, MAX));
+}
+
+#[test]
+fn test_failures_are_logged_ever_less_often() {
+    let logged: Vec<u32> = (1..=100).filter(|&failures| should_log_failure(failures)).collect();
+    assert_eq!(logged, vec![1, 2, 4, 8, 16, 32, 64]);
+    assert!(!should_log_failure(0));
+}
@@ -0,0 +1,258 @@
+use pipewire_volume_mixer_daemon::cache::{AppInfo, AudioCache, SinkInfo};
+use pipewire_volume_mixer_daemon::shared_memory::{
+    crc32, decode, slot_offset, SharedMemoryReader, SharedMemoryWriter, ShmError, SLOT_HEADER_SIZE,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::tempdir;
+use tokio::sync::RwLock;
+
+fn populated_cache() -> Arc<RwLock<AudioCache>> {
+    let cache = AudioCache::new();
+    cache.update_sink(
+        "Game".to_string(),
+        SinkInfo {
+            id: 34,
+            name: "Game".to_string(),
+            volume: 0.8,
+            pipewire_id: 34,
+            ..Default::default()
+        },
+    );
+    cache.update_sink(
+        "Chat".to_string(),
+        SinkInfo {
+            id: 39,
+            name: "Chat".to_string(),
+            volume: 0.5,
+            muted: true,
+            pipewire_id: 39,
+            ..Default::default()
+        },
+    );
+    cache.update_app(
+        "Firefox".to_string(),
+        AppInfo {
+            display_name: "Firefox".to_string(),
+            binary_name: "firefox".to_string(),
+            stream_names: vec!["Firefox".to_string()],
+            current_sink: "Game".to_string(),
+            active: true,
+            sink_input_ids: vec![100],
+            pipewire_id: 100,
+            ..Default::default()
+        },
+    );
+    Arc::new(RwLock::new(cache))
+}
+
+#[test]
+fn test_crc32_known_value() {
+    // Standard check value for CRC-32/ISO-HDLC
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert_eq!(crc32(b""), 0);
+}
+
+#[tokio::test]
+async fn test_write_and_read_snapshot() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("shm");
+    let cache = populated_cache();
+
+    let mut writer = SharedMemoryWriter::with_path(cache.clone(), &path).unwrap();
+    let snapshot = cache.read().await.get_snapshot();
+    writer.write_snapshot(&snapshot).unwrap();
+
+    let read = SharedMemoryReader::with_path(&path).read().unwrap();
+    assert_eq!(read.generation, snapshot.generation);
+    assert_eq!(read.sinks.len(), 2);
+    assert_eq!(read.apps.len(), 1);
+
+    let chat = read.sinks.iter().find(|s| s.name == "Chat").unwrap();
+    assert_eq!(chat.id, 39);
+    assert!(chat.muted);
+    assert_eq!(read.apps[0].name, "Firefox");
+    assert_eq!(read.apps[0].current_sink, "Game");
+    assert!(read.apps[0].active);
+}
+
+#[tokio::test]
+async fn test_corrupted_byte_is_rejected() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("shm");
+    let cache = populated_cache();
+
+    let mut writer = SharedMemoryWriter::with_path(cache.clone(), &path).unwrap();
+    let snapshot = cache.read().await.get_snapshot();
+    writer.write_snapshot(&snapshot).unwrap();
+
+    let mut data = std::fs::read(&path).unwrap();
+    assert!(decode(&data).is_ok());
+
+    // Flip one byte inside the active slot's payload
+    let sequence = u64::from_le_bytes(data[8..16].try_into().unwrap());
+    let payload_start = slot_offset((sequence % 2) as usize) + SLOT_HEADER_SIZE;
+    data[payload_start + 6] ^= 0xFF;
+    let err = decode(&data).unwrap_err();
+    assert!(err.to_string().contains("Checksum mismatch"));
+
+    // The reader retries, then reports the buffer as invalid
+    std::fs::write(&path, &data).unwrap();
+    assert!(SharedMemoryReader::with_path(&path).read().is_err());
+}
+
+#[tokio::test]
+async fn test_writes_alternate_slots() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("shm");
+    let cache = populated_cache();
+    let mut writer = SharedMemoryWriter::with_path(cache.clone(), &path).unwrap();
+    let reader = SharedMemoryReader::with_path(&path);
+
+    // Nothing has been published yet
+    assert!(reader.try_read().is_err());
+
+    for expected_slot in [1usize, 0, 1] {
+        let snapshot = cache.read().await.get_snapshot();
+        writer.write_snapshot(&snapshot).unwrap();
+
+        let data = std::fs::read(&path).unwrap();
+        let sequence = u64::from_le_bytes(data[8..16].try_into().unwrap());
+        assert_eq!((sequence % 2) as usize, expected_slot);
+        assert_eq!(reader.try_read().unwrap().generation, snapshot.generation);
+        cache.read().await.increment_generation();
+    }
+}
+
+#[test]
+fn test_concurrent_reader_never_sees_partial_snapshot() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("shm");
+    let cache = Arc::new(RwLock::new(AudioCache::new()));
+    let mut writer = SharedMemoryWriter::with_path(cache.clone(), &path).unwrap();
+    let done = Arc::new(AtomicBool::new(false));
+
+    let writer_done = done.clone();
+    let writer_cache = cache.clone();
+    let writer_thread = std::thread::spawn(move || {
+        for round in 0..500u32 {
+            // Every sink in a snapshot shares the same volume, and the sink count
+            // varies, so a torn read would show up as a mix of two rounds
+            let cache = writer_cache.blocking_read();
+            cache.sinks.clear();
+            for i in 0..(round % 7 + 1) {
+                cache.update_sink(
+                    format!("Sink_{i}"),
+                    SinkInfo {
+                        id: i,
+                        name: format!("Sink_{i}"),
+                        volume: round as f32,
+                        pipewire_id: i,
+                        ..Default::default()
+                    },
+                );
+            }
+            let snapshot = cache.get_snapshot();
+            drop(cache);
+            writer.write_snapshot(&snapshot).unwrap();
+            std::thread::sleep(Duration::from_micros(200));
+        }
+        writer_done.store(true, Ordering::SeqCst);
+    });
+
+    let reader = SharedMemoryReader::with_path(&path);
+    let mut successful_reads = 0;
+    while !done.load(Ordering::SeqCst) {
+        // A read racing the writer may be refused, but must never return a mix
+        let Ok(snapshot) = reader.try_read() else {
+            continue;
+        };
+        let round = snapshot.sinks[0].volume;
+        assert_eq!(snapshot.sinks.len() as u32, round as u32 % 7 + 1);
+        assert!(snapshot.sinks.iter().all(|s| s.volume == round));
+        successful_reads += 1;
+    }
+
+    writer_thread.join().unwrap();
+    assert!(successful_reads > 0);
+}
+
+#[tokio::test]
+async fn test_writer_loop_skips_writes_while_paused() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("shm");
+    let cache = populated_cache();
+    let writer = SharedMemoryWriter::with_path(cache.clone(), &path).unwrap();
+    let reader = SharedMemoryReader::with_path(&path);
+
+    cache.read().await.pause();
+    let handle = tokio::spawn(writer.run());
+
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    cache.read().await.increment_generation();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(reader.try_read().is_err(), "no snapshot should be written while paused");
+
+    // Resuming publishes the current state without waiting for a change
+    cache.read().await.resume();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let generation = cache.read().await.get_generation();
+    assert_eq!(reader.try_read().unwrap().generation, generation);
+
+    handle.abort();
+}
+
+#[tokio::test]
+async fn test_writer_loop_rewrites_changes_made_after_the_last_bump() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("shm");
+    let cache = populated_cache();
+    let writer = SharedMemoryWriter::with_path(cache.clone(), &path)
+        .unwrap()
+        .with_intervals(Duration::from_millis(100), Duration::from_millis(100));
+    let reader = SharedMemoryReader::with_path(&path);
+    let handle = tokio::spawn(writer.run());
+
+    // A burst of bumps, the last of which is written before its change lands
+    for _ in 0..5 {
+        cache.read().await.increment_generation();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    let generation = cache.read().await.get_generation();
+    let mut seen = false;
+    for _ in 0..100 {
+        if reader.try_read().ok().map(|s| s.generation) == Some(generation) {
+            seen = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    assert!(seen, "the burst's last generation should be written");
+    cache.read().await.sinks.get_mut("Game").unwrap().volume = 0.2;
+
+    // The generation is stable now, and the next snapshot still picks the change up
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let snapshot = reader.try_read().unwrap();
+    assert_eq!(snapshot.generation, generation);
+    let game = snapshot.sinks.iter().find(|s| s.name == "Game").unwrap();
+    assert!((game.volume - 0.2).abs() < f32::EPSILON);
+
+    handle.abort();
+}
+
+#[test]
+fn test_unusable_shm_path_is_an_error() {
+    let dir = tempdir().unwrap();
+    let cache = populated_cache();
+
+    // A missing parent directory, as when /dev/shm doesn't exist
+    let missing = dir.path().join("no-shm").join("mixer");
+    let err = SharedMemoryWriter::with_path(cache.clone(), &missing).err().unwrap();
+    assert!(matches!(err, ShmError::Open { .. }), "{err}");
+    assert_eq!(err.path(), missing);
+
+    // A path that can't be opened for writing
+    let err = SharedMemoryWriter::with_path(cache, dir.path()).err().unwrap();
+    assert!(matches!(err, ShmError::Open { .. }), "{err}");
+}
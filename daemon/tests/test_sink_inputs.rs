@@ -0,0 +1,245 @@
+use pipewire_volume_mixer_daemon::sink_inputs::{
+    find_sink_input, parse_sink_inputs, parse_sinks, sink_inputs_for_pid,
+};
+
+const TWO_FIREFOX_PROCESSES: &str = r#"Sink Input #101
+	Driver: PipeWire
+	Owner Module: n/a
+	Client: 80
+	Sink: 56
+	Sample Specification: float32le 2ch 48000Hz
+	Mute: no
+	Properties:
+		application.name = "Firefox"
+		application.process.id = "4242"
+		application.process.binary = "firefox"
+		media.name = "AudioStream"
+
+Sink Input #102
+	Driver: PipeWire
+	Owner Module: n/a
+	Client: 81
+	Sink: 57
+	Mute: no
+	Properties:
+		application.name = "Firefox"
+		application.process.id = "5151"
+		application.process.binary = "firefox"
+		media.name = "AudioStream"
+
+Sink Input #103
+	Driver: PipeWire
+	Client: 80
+	Sink: 56
+	Properties:
+		application.name = "Firefox"
+		application.process.id = "4242"
+		application.process.binary = "firefox"
+		media.name = "Notification"
+
+Sink Input #104
+	Driver: PipeWire
+	Sink: 58
+	Properties:
+		node.name = "Game_to_Speaker"
+"#;
+
+#[test]
+fn test_parse_sink_inputs() {
+    let inputs = parse_sink_inputs(TWO_FIREFOX_PROCESSES);
+    assert_eq!(inputs.len(), 4);
+
+    assert_eq!(inputs[0].id, 101);
+    assert_eq!(inputs[0].sink, Some(56));
+    assert_eq!(inputs[0].property("application.name"), Some("Firefox"));
+    assert_eq!(inputs[0].process_id(), Some(4242));
+
+    assert_eq!(inputs[3].id, 104);
+    assert_eq!(inputs[3].process_id(), None);
+    assert_eq!(inputs[3].property("node.name"), Some("Game_to_Speaker"));
+}
+
+#[test]
+fn test_pid_matches_only_that_process() {
+    let inputs = parse_sink_inputs(TWO_FIREFOX_PROCESSES);
+
+    assert_eq!(sink_inputs_for_pid(&inputs, 4242), vec![101, 103]);
+    assert_eq!(sink_inputs_for_pid(&inputs, 5151), vec![102]);
+    assert!(sink_inputs_for_pid(&inputs, 9999).is_empty());
+}
+
+#[test]
+fn test_parse_empty_output() {
+    assert!(parse_sink_inputs("").is_empty());
+}
+
+// pactl indices deliberately differ from the PipeWire object serials
+const MISMATCHED_IDS: &str = r#"Sink Input #7
+	Driver: PipeWire
+	Sink: 56
+	Properties:
+		application.name = "Firefox"
+		node.name = "Firefox"
+		object.serial = "131"
+
+Sink Input #131
+	Driver: PipeWire
+	Sink: 57
+	Properties:
+		application.name = "Spotify"
+		node.name = "spotify"
+		object.serial = "245"
+
+Sink Input #9
+	Driver: PipeWire
+	Sink: 58
+	Properties:
+		application.name = "mpv"
+		node.name = "mpv"
+"#;
+
+#[test]
+fn test_find_sink_input_by_object_serial() {
+    let inputs = parse_sink_inputs(MISMATCHED_IDS);
+
+    // Serial 131 is Firefox's node, not pactl's sink input #131
+    let firefox = find_sink_input(&inputs, 131, "Firefox").unwrap();
+    assert_eq!(firefox.id, 7);
+    assert_eq!(firefox.sink, Some(56));
+
+    let spotify = find_sink_input(&inputs, 245, "").unwrap();
+    assert_eq!(spotify.id, 131);
+}
+
+#[test]
+fn test_find_sink_input_falls_back_to_node_name() {
+    let inputs = parse_sink_inputs(MISMATCHED_IDS);
+
+    let mpv = find_sink_input(&inputs, 512, "mpv").unwrap();
+    assert_eq!(mpv.id, 9);
+    assert_eq!(mpv.sink, Some(58));
+}
+
+#[test]
+fn test_find_sink_input_does_not_trust_index_when_serial_differs() {
+    let inputs = parse_sink_inputs(MISMATCHED_IDS);
+
+    // #131 reports serial 245, so it must not be returned for serial 131 of an unknown node
+    assert!(find_sink_input(&inputs, 999, "unknown").is_none());
+    assert_eq!(find_sink_input(&inputs, 131, "unknown").unwrap().id, 7);
+
+    // Entries without a serial can still be matched by index
+    assert_eq!(find_sink_input(&inputs, 9, "").unwrap().id, 9);
+}
+
+#[test]
+fn test_media_role_is_captured() {
+    let output = r#"Sink Input #120
+	Driver: PipeWire
+	Sink: 56
+	Properties:
+		application.name = "Steam"
+		media.role = "Game"
+
+Sink Input #121
+	Driver: PipeWire
+	Sink: 56
+	Properties:
+		application.name = "Firefox"
+"#;
+    let inputs = parse_sink_inputs(output);
+    assert_eq!(inputs[0].media_role(), Some("Game"));
+    assert_eq!(inputs[1].media_role(), None);
+}
+
+const WITH_VOLUMES: &str = r#"Sink Input #201
+	Driver: PipeWire
+	Sink: 56
+	Mute: no
+	Volume: front-left: 32768 /  50% / -18.06 dB,   front-right: 65536 / 100% / 0.00 dB
+	        balance 0.50
+	Properties:
+		application.name = "Spotify"
+
+Sink Input #202
+	Driver: PipeWire
+	Sink: 56
+	Volume: mono: 49152 /  75% / -7.50 dB
+	        balance 0.00
+	Properties:
+		application.name = "Mono"
+
+Sink Input #203
+	Driver: PipeWire
+	Sink: 56
+	Volume: (invalid)
+	Properties:
+		application.name = "Broken"
+"#;
+
+#[test]
+fn test_parse_stream_volume_averages_channels() {
+    let inputs = parse_sink_inputs(WITH_VOLUMES);
+    assert_eq!(inputs.len(), 3);
+    assert_eq!(inputs[0].volume, Some(0.75));
+    assert_eq!(inputs[1].volume, Some(0.75));
+    assert_eq!(inputs[2].volume, None);
+    assert_eq!(inputs[0].property("application.name"), Some("Spotify"));
+
+    assert_eq!(parse_sink_inputs(TWO_FIREFOX_PROCESSES)[0].volume, None);
+}
+
+#[test]
+fn test_corked_streams_are_flagged() {
+    let output = r#"Sink Input #301
+	Driver: PipeWire
+	Sink: 57
+	Corked: yes
+	Mute: no
+	Properties:
+		application.name = "Spotify"
+
+Sink Input #302
+	Driver: PipeWire
+	Sink: 57
+	Corked: no
+	Mute: no
+	Properties:
+		application.name = "Firefox"
+"#;
+    let inputs = parse_sink_inputs(output);
+    assert!(inputs[0].corked);
+    assert!(!inputs[1].corked);
+
+    // Output without the field is taken as running
+    assert!(!parse_sink_inputs(WITH_VOLUMES)[0].corked);
+}
+
+#[test]
+fn test_parse_sinks() {
+    let output = r#"Sink #56
+	State: RUNNING
+	Name: Game
+	Description: Game Audio
+	Mute: no
+	Volume: front-left: 32768 /  50% / -18.06 dB,   front-right: 32768 /  50% / -18.06 dB
+	        balance 0.00
+	Base Volume: 65536 / 100% / 0.00 dB
+	Properties:
+		node.name = "Game"
+		device.description = "Name: not the sink's"
+
+Sink #60
+	Name: alsa_output.usb-headset
+	Mute: yes
+"#;
+    let sinks = parse_sinks(output);
+
+    assert_eq!(sinks.len(), 2);
+    assert_eq!((sinks[0].id, sinks[0].name.as_str()), (56, "Game"));
+    assert_eq!(sinks[0].description.as_deref(), Some("Game Audio"));
+    assert_eq!(sinks[0].volume, Some(0.5));
+    assert!(!sinks[0].muted);
+    assert_eq!((sinks[1].id, sinks[1].name.as_str()), (60, "alsa_output.usb-headset"));
+    assert_eq!((sinks[1].volume, sinks[1].muted), (None, true));
+}
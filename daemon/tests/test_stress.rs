@@ -31,7 +31,7 @@ async fn test_memory_leak_detection() {
                     active: true,
                     sink_input_ids: vec![i],
                     pipewire_id: i,
-                    inactive_since: None,
+                    ..Default::default()
                 },
             );
         }
@@ -75,8 +75,8 @@ async fn test_cpu_usage_under_load() {
                             id: (thread_id * 10 + i) as u32,
                             name: format!("Sink_{i}"),
                             volume: 0.5,
-                            muted: false,
                             pipewire_id: (thread_id * 10 + i) as u32,
+                            ..Default::default()
                         },
                     );
                     drop(cache_write);
@@ -122,7 +122,7 @@ async fn test_update_latency_under_stress() {
                     active: i % 2 == 0,
                     sink_input_ids: vec![i as u32],
                     pipewire_id: i as u32,
-                    inactive_since: None,
+                    ..Default::default()
                 },
             );
         }
@@ -141,8 +141,8 @@ async fn test_update_latency_under_stress() {
                 id: 1,
                 name: "TestSink".to_string(),
                 volume: 0.5,
-                muted: false,
                 pipewire_id: 1,
+                ..Default::default()
             },
         );
         drop(cache_write);
@@ -187,6 +187,7 @@ async fn test_large_scale_app_handling() {
                     } else {
                         None
                     },
+                    ..Default::default()
                 },
             );
         }
@@ -228,8 +229,8 @@ async fn test_snapshot_generation_performance() {
                     id: i as u32,
                     name: format!("Audio Sink {i}"),
                     volume: 0.5,
-                    muted: false,
                     pipewire_id: i as u32,
+                    ..Default::default()
                 },
             );
         }
@@ -246,7 +247,7 @@ async fn test_snapshot_generation_performance() {
                     active: true,
                     sink_input_ids: vec![i as u32 * 2, i as u32 * 2 + 1],
                     pipewire_id: i as u32,
-                    inactive_since: None,
+                    ..Default::default()
                 },
             );
         }
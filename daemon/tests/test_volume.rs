@@ -0,0 +1,86 @@
+use pipewire_volume_mixer_daemon::volume::{
+    crossfade_volumes, db_to_linear, linear_to_db, ramp_percents, sanitize_volume,
+    volume_to_percent, MIN_DB,
+};
+use std::time::Duration;
+
+#[test]
+fn test_known_values() {
+    assert_eq!(linear_to_db(1.0), 0.0);
+    assert!((linear_to_db(0.5) - -6.0206).abs() < 1e-3);
+    assert_eq!(db_to_linear(0.0), 1.0);
+    assert!((db_to_linear(-20.0) - 0.1).abs() < 1e-6);
+}
+
+#[test]
+fn test_round_trip() {
+    for linear in [0.01f32, 0.1, 0.25, 0.5, 0.75, 1.0] {
+        let back = db_to_linear(linear_to_db(linear));
+        assert!((back - linear).abs() < 1e-5, "{linear} came back as {back}");
+    }
+    for db in [-59.0f32, -40.0, -12.5, -3.0, 0.0] {
+        let back = linear_to_db(db_to_linear(db));
+        assert!((back - db).abs() < 1e-3, "{db} dB came back as {back}");
+    }
+}
+
+#[test]
+fn test_clamping_to_floor() {
+    assert_eq!(linear_to_db(0.0), MIN_DB);
+    assert_eq!(linear_to_db(-1.0), MIN_DB);
+    assert_eq!(linear_to_db(1e-6), MIN_DB);
+    assert_eq!(db_to_linear(MIN_DB), 0.0);
+    assert_eq!(db_to_linear(-120.0), 0.0);
+    assert_eq!(db_to_linear(f32::NEG_INFINITY), 0.0);
+}
+
+#[test]
+fn test_crossfade_volumes() {
+    assert_eq!(crossfade_volumes(0.0), (1.0, 0.0));
+    assert_eq!(crossfade_volumes(0.5), (0.5, 0.5));
+    assert_eq!(crossfade_volumes(1.0), (0.0, 1.0));
+
+    // Out of range positions are clamped
+    assert_eq!(crossfade_volumes(-0.5), (1.0, 0.0));
+    assert_eq!(crossfade_volumes(2.0), (0.0, 1.0));
+}
+
+#[test]
+fn test_sanitize_volume_rejects_non_finite_and_clamps() {
+    assert_eq!(sanitize_volume(f32::NAN), None);
+    assert_eq!(sanitize_volume(f32::INFINITY), None);
+    assert_eq!(sanitize_volume(f32::NEG_INFINITY), None);
+    assert_eq!(sanitize_volume(-1.0), Some(0.0));
+    assert_eq!(sanitize_volume(5.0), Some(1.0));
+    assert_eq!(sanitize_volume(0.3), Some(0.3));
+    // What reaches pactl stays within 0-100%
+    for volume in [-1.0, 5.0, f32::MAX] {
+        let percent = volume_to_percent(sanitize_volume(volume).unwrap());
+        assert!(percent <= 100, "{volume} became {percent}%");
+    }
+}
+
+#[test]
+fn test_ramp_percents_step_evenly_to_the_target() {
+    let steps = ramp_percents(0.2, 0.9, Duration::from_millis(50));
+    assert_eq!(steps.len(), 5);
+    assert!(steps.windows(2).all(|pair| pair[0] < pair[1]), "{steps:?}");
+    assert!(steps[0] > 20);
+    assert_eq!(steps.last(), Some(&90));
+
+    let down = ramp_percents(0.9, 0.2, Duration::from_millis(50));
+    assert!(down.windows(2).all(|pair| pair[0] > pair[1]), "{down:?}");
+    assert_eq!(down.last(), Some(&20));
+}
+
+#[test]
+fn test_ramp_percents_always_reach_the_target() {
+    // Shorter than one step, and between equal levels
+    assert_eq!(ramp_percents(0.2, 0.9, Duration::from_millis(3)), vec![90]);
+    assert_eq!(ramp_percents(0.5, 0.5, Duration::from_millis(50)), vec![50]);
+    // Ten steps over two percent repeat most percentages, which are sent once
+    let steps = ramp_percents(0.5, 0.52, Duration::from_millis(100));
+    assert!(steps.len() <= 3, "{steps:?}");
+    assert!(steps.windows(2).all(|pair| pair[0] < pair[1]), "{steps:?}");
+    assert_eq!(steps.last(), Some(&volume_to_percent(0.52)));
+}
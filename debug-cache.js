@@ -11,17 +11,24 @@ const file = Gio.File.new_for_path(shmPath);
 try {
     const [success, contents] = file.load_contents(null);
     if (success) {
-        // Read binary header
-        const view = new DataView(contents.buffer);
-        const version = view.getUint32(0, true);
-        const generation = Number(view.getBigUint64(4, true));
-        const timestamp = Number(view.getBigUint64(12, true));
+        // Read global header; the active slot is sequence % 2
+        const header = new DataView(contents.buffer);
+        const version = header.getUint32(0, true);
+        const sequence = Number(header.getBigUint64(8, true));
+        const slotSize = (contents.length - 32) / 2;
+        const slot = 32 + (sequence % 2) * slotSize;
+
+        // Slot header: generation, timestamp, payload length, CRC32
+        const view = new DataView(contents.buffer, slot);
+        const generation = Number(view.getBigUint64(0, true));
+        const timestamp = Number(view.getBigUint64(8, true));
         
         print(`Version: ${version}`);
+        print(`Active slot: ${sequence % 2}`);
         print(`Generation: ${generation}`);
         print(`Timestamp: ${new Date(timestamp)}`);
         
-        // Skip to data section (32 bytes header)
+        // Skip to payload (32 byte slot header)
         let offset = 32;
         
         // Read sinks
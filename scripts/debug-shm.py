@@ -2,6 +2,7 @@
 import os
 import struct
 import sys
+import zlib
 from datetime import datetime
 
 def read_string(data, offset):
@@ -30,19 +31,37 @@ def parse_shared_memory(filename):
             print("File too small to contain valid data")
             return
         
-        # Parse header (32 bytes)
+        # Parse global header (32 bytes)
         version = struct.unpack('<I', data[0:4])[0]
-        generation = struct.unpack('<Q', data[4:12])[0]
-        timestamp = struct.unpack('<Q', data[12:20])[0]
+        sequence = struct.unpack('<Q', data[8:16])[0]
+        if version != 3:
+            print(f"Unsupported shared memory version: {version}")
+            return
+        if sequence == 0:
+            print("No snapshot has been written yet")
+            return
+
+        # Two double-buffered slots follow the header; the active one is sequence % 2
+        slot_size = (len(data) - 32) // 2
+        slot = 32 + (sequence % 2) * slot_size
+        generation = struct.unpack('<Q', data[slot:slot+8])[0]
+        timestamp = struct.unpack('<Q', data[slot+8:slot+16])[0]
+        payload_len = struct.unpack('<I', data[slot+16:slot+20])[0]
+        checksum = struct.unpack('<I', data[slot+20:slot+24])[0]
         
         print(f"Shared Memory Cache Debug")
         print(f"========================")
         print(f"Version: {version}")
+        print(f"Active slot: {sequence % 2} (sequence {sequence})")
         print(f"Generation: {generation}")
         print(f"Timestamp: {datetime.fromtimestamp(timestamp/1000)}")
+        payload = data[slot + 32:slot + 32 + payload_len]
+        valid = zlib.crc32(payload) == checksum
+        print(f"Checksum: {checksum:08x} ({'valid' if valid else 'INVALID'})")
         print()
         
-        offset = 32
+        data = payload
+        offset = 0
         
         # Read sinks
         if offset + 4 > len(data):